@@ -1,16 +1,36 @@
-use std::fmt::Debug;
+use std::{cell::RefCell, fmt::Debug};
 
 use crate::{
-    common::SourceLocation,
-    token::{Token, TokenKind},
+    common::{drop_iteratively, SourceLocation},
+    grammar::{get_binary_precedence, get_unary_precedence},
+    lexer::quote_string,
+    token::{FloatToken, IntegerToken, NameToken, StringToken, Token, TokenKind},
 };
 
 // is there a better name for this?
 pub trait AstTrait: Debug + Clone + PartialEq {
     fn get_location(&self) -> SourceLocation;
+    /// Where the first token of the node starts
+    fn get_start(&self) -> SourceLocation;
+    /// Just past the last token of the node
+    fn get_end(&self) -> SourceLocation;
+    /// The direct sub expressions of this node, in source order
+    fn children(&self) -> Vec<&Ast>;
     fn pretty_print(&self, indent: usize) -> String;
 }
 
+// the tokens that can end a node are all ascii and never span lines, so the column moves
+// along with the position
+fn get_token_end(location: &SourceLocation, end: usize) -> SourceLocation {
+    SourceLocation {
+        filepath: location.filepath.clone(),
+        position: end,
+        line: location.line,
+        column: location.column + (end - location.position),
+        synthetic: location.synthetic.clone(),
+    }
+}
+
 fn get_indent(indent: usize) -> String {
     let mut result = String::new();
     for _ in 0..indent {
@@ -19,123 +39,182 @@ fn get_indent(indent: usize) -> String {
     result
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Ast {
-    File(AstFile),
-    Block(AstBlock),
-    Export(AstExport),
-    Let(AstLet),
-    Unary(AstUnary),
-    Binary(AstBinary),
-    Name(AstName),
-    Integer(AstInteger),
-    Call(AstCall),
-}
-
-impl Ast {
-    pub fn unwrap_file(&self) -> &AstFile {
-        if let Ast::File(file) = self {
-            file
-        } else {
-            unreachable!()
+// declares `Ast` with its as and unwrap methods and a `TryFrom` for each node, and
+// `dispatch_ast!` which runs the same code on whichever node an `Ast` holds, `$d` is a `$`
+// so the generated macro can have its own fragments
+macro_rules! define_ast {
+    ($d:tt $($variant:ident($node:ident) $as:ident $unwrap:ident,)*) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum Ast {
+            $($variant($node),)*
         }
-    }
 
-    pub fn unwrap_block(&self) -> &AstBlock {
-        if let Ast::Block(block) = self {
-            block
-        } else {
-            unreachable!()
-        }
-    }
+        impl Ast {
+            $(
+                pub fn $as(&self) -> Option<&$node> {
+                    if let Ast::$variant(node) = self {
+                        Some(node)
+                    } else {
+                        None
+                    }
+                }
 
-    pub fn unwrap_export(&self) -> &AstExport {
-        if let Ast::Export(export) = self {
-            export
-        } else {
-            unreachable!()
+                /// Panics if it is another kind of node
+                pub fn $unwrap(&self) -> &$node {
+                    self.$as()
+                        .expect(concat!("expected ", stringify!($node)))
+                }
+            )*
         }
-    }
 
-    pub fn unwrap_let(&self) -> &AstLet {
-        if let Ast::Let(lett) = self {
-            lett
-        } else {
-            unreachable!()
-        }
-    }
+        $(
+            /// Gives the node back if it is another kind
+            impl TryFrom<Ast> for $node {
+                type Error = Ast;
 
-    pub fn unwrap_unary(&self) -> &AstUnary {
-        if let Ast::Unary(unary) = self {
-            unary
-        } else {
-            unreachable!()
-        }
-    }
+                fn try_from(node: Ast) -> Result<Self, Self::Error> {
+                    match node {
+                        Ast::$variant(node) => Ok(node),
+                        node => Err(node),
+                    }
+                }
+            }
 
-    pub fn unwrap_binary(&self) -> &AstBinary {
-        if let Ast::Binary(binary) = self {
-            binary
-        } else {
-            unreachable!()
-        }
-    }
+            impl<'a> TryFrom<&'a Ast> for &'a $node {
+                type Error = &'a Ast;
 
-    pub fn unwrap_name(&self) -> &AstName {
-        if let Ast::Name(name) = self {
-            name
-        } else {
-            unreachable!()
-        }
-    }
+                fn try_from(node: &'a Ast) -> Result<Self, Self::Error> {
+                    node.$as().ok_or(node)
+                }
+            }
+        )*
 
-    pub fn unwrap_integer(&self) -> &AstInteger {
-        if let Ast::Integer(integer) = self {
-            integer
-        } else {
-            unreachable!()
+        /// Matches on every kind of node, binding it to `$node` for `$body`
+        macro_rules! dispatch_ast {
+            ($d ast:expr, $d node:ident => $d body:expr) => {
+                match $d ast {
+                    $($crate::ast::Ast::$variant($d node) => $d body,)*
+                }
+            };
         }
-    }
+        pub(crate) use dispatch_ast;
+    };
+}
 
-    pub fn unwrap_call(&self) -> &AstCall {
-        if let Ast::Call(call) = self {
-            call
-        } else {
-            unreachable!()
-        }
-    }
+// adding a kind of node only takes a line here, and implementing `AstTrait` and
+// `BindingTrait` for it
+define_ast! {
+    $
+    File(AstFile) as_file unwrap_file,
+    Block(AstBlock) as_block unwrap_block,
+    Export(AstExport) as_export unwrap_export,
+    Let(AstLet) as_let unwrap_let,
+    Destructure(AstDestructure) as_destructure unwrap_destructure,
+    Unary(AstUnary) as_unary unwrap_unary,
+    Binary(AstBinary) as_binary unwrap_binary,
+    Name(AstName) as_name unwrap_name,
+    Integer(AstInteger) as_integer unwrap_integer,
+    Float(AstFloat) as_float unwrap_float,
+    String(AstString) as_string unwrap_string,
+    None(AstNone) as_none unwrap_none,
+    Call(AstCall) as_call unwrap_call,
+    Parenthesized(AstParenthesized) as_parenthesized unwrap_parenthesized,
+    MemberAccess(AstMemberAccess) as_member_access unwrap_member_access,
+    Assignment(AstAssignment) as_assignment unwrap_assignment,
+    Match(AstMatch) as_match unwrap_match,
+    Enum(AstEnum) as_enum unwrap_enum,
+    Freeze(AstFreeze) as_freeze unwrap_freeze,
+    Spawn(AstSpawn) as_spawn unwrap_spawn,
+    Join(AstJoin) as_join unwrap_join,
 }
 
 impl AstTrait for Ast {
     fn get_location(&self) -> SourceLocation {
-        match self {
-            Ast::File(file) => file.get_location(),
-            Ast::Block(block) => block.get_location(),
-            Ast::Export(export) => export.get_location(),
-            Ast::Let(lett) => lett.get_location(),
-            Ast::Unary(unary) => unary.get_location(),
-            Ast::Binary(binary) => binary.get_location(),
-            Ast::Name(name) => name.get_location(),
-            Ast::Integer(integer) => integer.get_location(),
-            Ast::Call(call) => call.get_location(),
-        }
+        dispatch_ast!(self, node => node.get_location())
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        dispatch_ast!(self, node => node.get_start())
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        dispatch_ast!(self, node => node.get_end())
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        dispatch_ast!(self, node => node.children())
     }
 
     fn pretty_print(&self, indent: usize) -> String {
-        match self {
-            Ast::File(file) => file.pretty_print(indent),
-            Ast::Block(block) => block.pretty_print(indent),
-            Ast::Export(export) => export.pretty_print(indent),
-            Ast::Let(lett) => lett.pretty_print(indent),
-            Ast::Unary(unary) => unary.pretty_print(indent),
-            Ast::Binary(binary) => binary.pretty_print(indent),
-            Ast::Name(name) => name.pretty_print(indent),
-            Ast::Integer(integer) => integer.pretty_print(indent),
-            Ast::Call(call) => call.pretty_print(indent),
-        }
+        dispatch_ast!(self, node => node.pretty_print(indent))
     }
 }
 
+thread_local! {
+    static DROP_QUEUE: RefCell<Option<Vec<Ast>>> = const { RefCell::new(None) };
+}
+
+// takes the children that have children of their own out of a node that is being dropped,
+// so a long chain like `1 + 1 + ...` is not dropped by recursing down it
+fn drop_children<'a>(children: impl IntoIterator<Item = &'a mut Ast>) {
+    let nodes = children
+        .into_iter()
+        .filter(|child| !child.children().is_empty())
+        .map(|child| std::mem::replace(child, empty_node()))
+        .collect();
+    drop_iteratively(&DROP_QUEUE, nodes);
+}
+
+// what is left in the place of a child that was taken out, it owns nothing
+fn empty_node() -> Ast {
+    Ast::None(AstNone {
+        none_token: Token {
+            kind: TokenKind::None,
+            location: SourceLocation {
+                filepath: String::new(),
+                position: 0,
+                line: 0,
+                column: 0,
+                synthetic: None,
+            },
+            end: 0,
+        },
+    })
+}
+
+macro_rules! drop_children_iteratively {
+    ($($node:ident($name:ident) => $children:expr,)*) => {
+        $(
+            impl Drop for $node {
+                fn drop(&mut self) {
+                    let $name = self;
+                    drop_children($children);
+                }
+            }
+        )*
+    };
+}
+
+drop_children_iteratively! {
+    AstFile(node) => &mut node.expressions,
+    AstBlock(node) => &mut node.expressions,
+    AstExport(node) => [&mut *node.value],
+    AstLet(node) => node.value.as_deref_mut(),
+    AstDestructure(node) => [&mut *node.value],
+    AstUnary(node) => [&mut *node.operand],
+    AstBinary(node) => [&mut *node.left, &mut *node.right],
+    AstCall(node) => std::iter::once(&mut *node.operand).chain(&mut node.arguments),
+    AstParenthesized(node) => [&mut *node.expression],
+    AstMemberAccess(node) => [&mut *node.operand],
+    AstAssignment(node) => [&mut *node.value],
+    AstMatch(node) => {
+        std::iter::once(&mut *node.value).chain(node.arms.iter_mut().map(|arm| &mut arm.value))
+    },
+    AstFreeze(node) => [&mut *node.value],
+    AstSpawn(node) => [&mut *node.value],
+    AstJoin(node) => [&mut *node.operand],
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstFile {
     pub expressions: Vec<Ast>,
@@ -144,7 +223,22 @@ pub struct AstFile {
 
 impl AstTrait for AstFile {
     fn get_location(&self) -> SourceLocation {
-        self.end_of_file_token.location.clone()
+        self.get_start()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        match self.expressions.first() {
+            Some(expression) => expression.get_start(),
+            None => self.end_of_file_token.location.clone(),
+        }
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.end_of_file_token.location, self.end_of_file_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        self.expressions.iter().collect()
     }
 
     fn pretty_print(&self, indent: usize) -> String {
@@ -171,6 +265,18 @@ impl AstTrait for AstBlock {
         self.open_brace_token.location.clone()
     }
 
+    fn get_start(&self) -> SourceLocation {
+        self.open_brace_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.close_brace_token.location, self.close_brace_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        self.expressions.iter().collect()
+    }
+
     fn pretty_print(&self, indent: usize) -> String {
         let mut result = String::new();
         result.push('{');
@@ -189,7 +295,7 @@ impl AstTrait for AstBlock {
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstExport {
     pub export_token: Token,
-    pub name_token: Token,
+    pub name_token: NameToken,
     pub equals_token: Token,
     pub value: Box<Ast>,
 }
@@ -199,14 +305,22 @@ impl AstTrait for AstExport {
         self.name_token.location.clone()
     }
 
+    fn get_start(&self) -> SourceLocation {
+        self.export_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.value.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.value]
+    }
+
     fn pretty_print(&self, indent: usize) -> String {
         let mut result = String::new();
         result += "export ";
-        result += if let TokenKind::Name(name) = &self.name_token.kind {
-            name
-        } else {
-            unreachable!()
-        };
+        result += &self.name_token.name;
         result += " = ";
         result += &self.value.pretty_print(indent);
         result
@@ -216,7 +330,7 @@ impl AstTrait for AstExport {
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstLet {
     pub let_token: Token,
-    pub name_token: Token,
+    pub name_token: NameToken,
     pub equal_token: Option<Token>,
     pub value: Option<Box<Ast>>,
 }
@@ -226,14 +340,25 @@ impl AstTrait for AstLet {
         self.name_token.location.clone()
     }
 
+    fn get_start(&self) -> SourceLocation {
+        self.let_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        match &self.value {
+            Some(value) => value.get_end(),
+            None => get_token_end(&self.name_token.location, self.name_token.end),
+        }
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        self.value.iter().map(|value| value as &Ast).collect()
+    }
+
     fn pretty_print(&self, indent: usize) -> String {
         let mut result = String::new();
         result += "let ";
-        result += if let TokenKind::Name(name) = &self.name_token.kind {
-            name
-        } else {
-            unreachable!()
-        };
+        result += &self.name_token.name;
         if let Some(value) = &self.value {
             result += " = ";
             result += &value.pretty_print(indent);
@@ -242,6 +367,44 @@ impl AstTrait for AstLet {
     }
 }
 
+/// `let { x, y } = value`, a let for each of the named members of the block
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstDestructure {
+    pub let_token: Token,
+    pub open_brace_token: Token,
+    pub names: Vec<NameToken>,
+    pub close_brace_token: Token,
+    pub equal_token: Token,
+    pub value: Box<Ast>,
+}
+
+impl AstTrait for AstDestructure {
+    fn get_location(&self) -> SourceLocation {
+        self.open_brace_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.let_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.value.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.value]
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let names: Vec<&str> = self.names.iter().map(|name| name.name.as_str()).collect();
+        format!(
+            "let {{ {} }} = {}",
+            names.join(", "),
+            self.value.pretty_print(indent)
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstUnary {
     pub operator_token: Token,
@@ -253,6 +416,18 @@ impl AstTrait for AstUnary {
         self.operator_token.location.clone()
     }
 
+    fn get_start(&self) -> SourceLocation {
+        self.operator_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.operand.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.operand]
+    }
+
     fn pretty_print(&self, indent: usize) -> String {
         let mut result = String::new();
         result += &self.operator_token.kind.to_string();
@@ -270,7 +445,19 @@ pub struct AstBinary {
 
 impl AstTrait for AstBinary {
     fn get_location(&self) -> SourceLocation {
-        self.operator_token.location.clone()
+        self.get_start()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.left.get_start()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.right.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.left, &self.right]
     }
 
     fn pretty_print(&self, indent: usize) -> String {
@@ -286,7 +473,7 @@ impl AstTrait for AstBinary {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstName {
-    pub name_token: Token,
+    pub name_token: NameToken,
 }
 
 impl AstTrait for AstName {
@@ -294,18 +481,26 @@ impl AstTrait for AstName {
         self.name_token.location.clone()
     }
 
+    fn get_start(&self) -> SourceLocation {
+        self.name_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.name_token.location, self.name_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![]
+    }
+
     fn pretty_print(&self, _indent: usize) -> String {
-        if let TokenKind::Name(name) = &self.name_token.kind {
-            name.clone()
-        } else {
-            unreachable!()
-        }
+        self.name_token.name.clone()
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstInteger {
-    pub integer_token: Token,
+    pub integer_token: IntegerToken,
 }
 
 impl AstTrait for AstInteger {
@@ -313,12 +508,102 @@ impl AstTrait for AstInteger {
         self.integer_token.location.clone()
     }
 
+    fn get_start(&self) -> SourceLocation {
+        self.integer_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.integer_token.location, self.integer_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![]
+    }
+
     fn pretty_print(&self, _indent: usize) -> String {
-        if let TokenKind::Integer(integer) = &self.integer_token.kind {
-            integer.to_string()
-        } else {
-            unreachable!()
-        }
+        self.integer_token.value.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstFloat {
+    pub float_token: FloatToken,
+}
+
+impl AstTrait for AstFloat {
+    fn get_location(&self) -> SourceLocation {
+        self.float_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.float_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.float_token.location, self.float_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![]
+    }
+
+    // debug formatting always has a `.` or an exponent, so it lexes back as a float
+    fn pretty_print(&self, _indent: usize) -> String {
+        format!("{:?}", self.float_token.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstString {
+    pub string_token: StringToken,
+}
+
+impl AstTrait for AstString {
+    fn get_location(&self) -> SourceLocation {
+        self.string_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.string_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.string_token.location, self.string_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![]
+    }
+
+    fn pretty_print(&self, _indent: usize) -> String {
+        quote_string(&self.string_token.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstNone {
+    pub none_token: Token,
+}
+
+impl AstTrait for AstNone {
+    fn get_location(&self) -> SourceLocation {
+        self.none_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.none_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.none_token.location, self.none_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![]
+    }
+
+    fn pretty_print(&self, _indent: usize) -> String {
+        "none".to_string()
     }
 }
 
@@ -335,6 +620,23 @@ impl AstTrait for AstCall {
         self.open_parenthesis_token.location.clone()
     }
 
+    fn get_start(&self) -> SourceLocation {
+        self.operand.get_start()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(
+            &self.close_parenthesis_token.location,
+            self.close_parenthesis_token.end,
+        )
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        std::iter::once(&self.operand as &Ast)
+            .chain(self.arguments.iter())
+            .collect()
+    }
+
     fn pretty_print(&self, indent: usize) -> String {
         let mut result = String::new();
         result += &self.operand.pretty_print(indent);
@@ -349,3 +651,616 @@ impl AstTrait for AstCall {
         result
     }
 }
+
+/// Parentheses are kept so the source can be reproduced, they don't mean anything after parsing
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstParenthesized {
+    pub open_parenthesis_token: Token,
+    pub expression: Box<Ast>,
+    pub close_parenthesis_token: Token,
+}
+
+impl AstTrait for AstParenthesized {
+    fn get_location(&self) -> SourceLocation {
+        self.expression.get_location()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.open_parenthesis_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(
+            &self.close_parenthesis_token.location,
+            self.close_parenthesis_token.end,
+        )
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.expression]
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut result = String::new();
+        result.push('(');
+        result += &self.expression.pretty_print(indent);
+        result.push(')');
+        result
+    }
+}
+
+/// Reads an export out of a block, like `a.b`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstMemberAccess {
+    pub operand: Box<Ast>,
+    pub dot_token: Token,
+    pub name_token: NameToken,
+}
+
+impl AstTrait for AstMemberAccess {
+    fn get_location(&self) -> SourceLocation {
+        self.name_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.operand.get_start()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.name_token.location, self.name_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.operand]
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut result = String::new();
+        result += &self.operand.pretty_print(indent);
+        result.push('.');
+        result += &self.name_token.name;
+        result
+    }
+}
+
+/// Changes the value of a name that was already defined by a `let` or `export`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstAssignment {
+    pub name_token: NameToken,
+    pub equal_token: Token,
+    pub value: Box<Ast>,
+}
+
+impl AstTrait for AstAssignment {
+    fn get_location(&self) -> SourceLocation {
+        self.name_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.name_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.value.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.value]
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut result = String::new();
+        result += &self.name_token.name;
+        result += " = ";
+        result += &self.value.pretty_print(indent);
+        result
+    }
+}
+
+/// Picks the arm whose integer is the value, or the `_` arm if none of them are
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstMatch {
+    pub match_token: Token,
+    pub value: Box<Ast>,
+    pub open_brace_token: Token,
+    pub arms: Vec<AstMatchArm>,
+    pub close_brace_token: Token,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstMatchArm {
+    pub pattern: AstPattern,
+    pub arrow_token: Token,
+    pub value: Ast,
+}
+
+/// What the value of a `match` is compared against
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstPattern {
+    /// An integer literal, with a `-` in front of it for negative ones
+    Integer {
+        minus_token: Option<Token>,
+        integer_token: IntegerToken,
+    },
+    /// A variant of an enum, like `Color.Red`
+    Variant(AstMemberAccess),
+    /// `_`, which matches every value
+    Default(NameToken),
+}
+
+impl AstPattern {
+    pub fn get_location(&self) -> SourceLocation {
+        match self {
+            AstPattern::Integer {
+                minus_token: Some(minus_token),
+                ..
+            } => minus_token.location.clone(),
+            AstPattern::Integer { integer_token, .. } => integer_token.location.clone(),
+            AstPattern::Variant(variant) => variant.get_start(),
+            AstPattern::Default(name_token) => name_token.location.clone(),
+        }
+    }
+
+    pub fn get_end(&self) -> SourceLocation {
+        match self {
+            AstPattern::Integer { integer_token, .. } => {
+                get_token_end(&integer_token.location, integer_token.end)
+            }
+            AstPattern::Variant(variant) => variant.get_end(),
+            AstPattern::Default(name_token) => get_token_end(&name_token.location, name_token.end),
+        }
+    }
+
+    /// The pattern the way it is written in the source
+    pub fn pretty_print(&self) -> String {
+        match self {
+            AstPattern::Integer {
+                minus_token,
+                integer_token,
+            } => format!(
+                "{}{}",
+                if minus_token.is_some() { "-" } else { "" },
+                integer_token.value
+            ),
+            AstPattern::Variant(variant) => variant.pretty_print(0),
+            AstPattern::Default(_) => "_".to_string(),
+        }
+    }
+}
+
+impl AstTrait for AstMatch {
+    fn get_location(&self) -> SourceLocation {
+        self.match_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.match_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.close_brace_token.location, self.close_brace_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        std::iter::once(&self.value as &Ast)
+            .chain(self.arms.iter().map(|arm| &arm.value))
+            .collect()
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut result = String::new();
+        result += "match ";
+        result += &self.value.pretty_print(indent);
+        result += " {";
+        for arm in &self.arms {
+            result.push('\n');
+            result += &get_indent(indent + 1);
+            result += &arm.pattern.pretty_print();
+            result += " -> ";
+            result += &arm.value.pretty_print(indent + 1);
+        }
+        result.push('\n');
+        result += &get_indent(indent);
+        result.push('}');
+        result
+    }
+}
+
+/// `enum { Red, Green, Blue }`, a block with a member for each variant
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstEnum {
+    pub enum_token: Token,
+    pub open_brace_token: Token,
+    pub variants: Vec<NameToken>,
+    pub close_brace_token: Token,
+}
+
+impl AstTrait for AstEnum {
+    fn get_location(&self) -> SourceLocation {
+        self.enum_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.enum_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        get_token_end(&self.close_brace_token.location, self.close_brace_token.end)
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![]
+    }
+
+    fn pretty_print(&self, _indent: usize) -> String {
+        let names: Vec<&str> = self
+            .variants
+            .iter()
+            .map(|variant| variant.name.as_str())
+            .collect();
+        if names.is_empty() {
+            return "enum {}".to_string();
+        }
+        format!("enum {{ {} }}", names.join(", "))
+    }
+}
+
+/// `freeze value`, the block it gives can't be assigned over afterwards
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstFreeze {
+    pub freeze_token: Token,
+    pub value: Box<Ast>,
+}
+
+impl AstTrait for AstFreeze {
+    fn get_location(&self) -> SourceLocation {
+        self.freeze_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.freeze_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.value.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.value]
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut result = String::new();
+        result += "freeze ";
+        result += &self.value.pretty_print(indent);
+        result
+    }
+}
+
+/// `spawn value`, a task that works out `value` in between the rest of the program, or after
+/// it if the task is never joined
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstSpawn {
+    pub spawn_token: Token,
+    pub value: Box<Ast>,
+}
+
+impl AstTrait for AstSpawn {
+    fn get_location(&self) -> SourceLocation {
+        self.spawn_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.spawn_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.value.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.value]
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut result = String::new();
+        result += "spawn ";
+        result += &self.value.pretty_print(indent);
+        result
+    }
+}
+
+/// `join task`, waits for the task to finish and gives its value
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstJoin {
+    pub join_token: Token,
+    pub operand: Box<Ast>,
+}
+
+impl AstTrait for AstJoin {
+    fn get_location(&self) -> SourceLocation {
+        self.join_token.location.clone()
+    }
+
+    fn get_start(&self) -> SourceLocation {
+        self.join_token.location.clone()
+    }
+
+    fn get_end(&self) -> SourceLocation {
+        self.operand.get_end()
+    }
+
+    fn children(&self) -> Vec<&Ast> {
+        vec![&self.operand]
+    }
+
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut result = String::new();
+        result += "join";
+        // `join(a)` reads better than `join (a)`
+        if !matches!(&self.operand as &Ast, Ast::Parenthesized(_)) {
+            result.push(' ');
+        }
+        result += &self.operand.pretty_print(indent);
+        result
+    }
+}
+
+// builders for making nodes in code instead of parsing them, every token they make has a
+// synthetic location and no length
+
+/// What the nodes made by the builders say made them, their filepath is `<generated>`
+pub const GENERATED_NAME: &str = "generated";
+
+fn generated_location() -> SourceLocation {
+    SourceLocation::synthetic(GENERATED_NAME, None)
+}
+
+fn generated_token(kind: TokenKind) -> Token {
+    Token {
+        kind,
+        location: generated_location(),
+        end: 0,
+    }
+}
+
+fn generated_name(name: &str) -> NameToken {
+    NameToken {
+        location: generated_location(),
+        end: 0,
+        name: name.to_string(),
+    }
+}
+
+// how tightly the node holds together when it is printed, so the builders know when an
+// operand needs parentheses to come back as the same tree after `pretty_print`
+fn get_printed_precedence(ast: &Ast) -> usize {
+    match ast {
+        Ast::Binary(binary) => get_binary_precedence(&binary.operator_token.kind),
+        Ast::Unary(unary) => get_unary_precedence(&unary.operator_token.kind),
+        // these swallow everything after them
+        Ast::Let(_)
+        | Ast::Destructure(_)
+        | Ast::Export(_)
+        | Ast::Assignment(_)
+        | Ast::Freeze(_)
+        | Ast::Spawn(_) => 0,
+        // tighter than every operator, but the calls and member accesses after it are part
+        // of its operand
+        Ast::Join(_) => usize::MAX - 1,
+        _ => usize::MAX,
+    }
+}
+
+fn parenthesize_below(ast: Ast, precedence: usize) -> Ast {
+    if get_printed_precedence(&ast) < precedence {
+        parenthesized(ast)
+    } else {
+        ast
+    }
+}
+
+pub fn file(expressions: Vec<Ast>) -> Ast {
+    Ast::File(AstFile {
+        expressions,
+        end_of_file_token: generated_token(TokenKind::EndOfFile),
+    })
+}
+
+pub fn block(expressions: Vec<Ast>) -> Ast {
+    Ast::Block(AstBlock {
+        open_brace_token: generated_token(TokenKind::OpenBrace),
+        expressions,
+        close_brace_token: generated_token(TokenKind::CloseBrace),
+    })
+}
+
+pub fn export(name: &str, value: Ast) -> Ast {
+    Ast::Export(AstExport {
+        export_token: generated_token(TokenKind::Export),
+        name_token: generated_name(name),
+        equals_token: generated_token(TokenKind::Equal),
+        value: Box::new(value),
+    })
+}
+
+/// `let name = value`, or just `let name` without a value
+pub fn lett(name: &str, value: Option<Ast>) -> Ast {
+    Ast::Let(AstLet {
+        let_token: generated_token(TokenKind::Let),
+        name_token: generated_name(name),
+        equal_token: value.as_ref().map(|_| generated_token(TokenKind::Equal)),
+        value: value.map(Box::new),
+    })
+}
+
+/// `let { names } = value`
+pub fn destructure(names: &[&str], value: Ast) -> Ast {
+    Ast::Destructure(AstDestructure {
+        let_token: generated_token(TokenKind::Let),
+        open_brace_token: generated_token(TokenKind::OpenBrace),
+        names: names.iter().map(|name| generated_name(name)).collect(),
+        close_brace_token: generated_token(TokenKind::CloseBrace),
+        equal_token: generated_token(TokenKind::Equal),
+        value: Box::new(value),
+    })
+}
+
+/// Panics if `operator` is not a unary operator
+pub fn unary(operator: TokenKind, operand: Ast) -> Ast {
+    let precedence = get_unary_precedence(&operator);
+    assert!(precedence > 0, "{:?} is not a unary operator", operator);
+    Ast::Unary(AstUnary {
+        operator_token: generated_token(operator),
+        operand: Box::new(parenthesize_below(operand, precedence)),
+    })
+}
+
+/// Panics if `operator` is not a binary operator, operands that would be split up by it
+/// when printed are put in parentheses
+pub fn binary(left: Ast, operator: TokenKind, right: Ast) -> Ast {
+    let precedence = get_binary_precedence(&operator);
+    assert!(precedence > 0, "{:?} is not a binary operator", operator);
+    Ast::Binary(AstBinary {
+        left: Box::new(parenthesize_below(left, precedence)),
+        operator_token: generated_token(operator),
+        // every operator is left associative, so the same precedence on the right needs them
+        right: Box::new(parenthesize_below(right, precedence + 1)),
+    })
+}
+
+pub fn name(name: &str) -> Ast {
+    Ast::Name(AstName {
+        name_token: generated_name(name),
+    })
+}
+
+pub fn integer(value: u128) -> Ast {
+    Ast::Integer(AstInteger {
+        integer_token: IntegerToken {
+            location: generated_location(),
+            end: 0,
+            value,
+        },
+    })
+}
+
+pub fn float(value: f64) -> Ast {
+    Ast::Float(AstFloat {
+        float_token: FloatToken {
+            location: generated_location(),
+            end: 0,
+            value,
+        },
+    })
+}
+
+pub fn string(value: &str) -> Ast {
+    Ast::String(AstString {
+        string_token: StringToken {
+            location: generated_location(),
+            end: 0,
+            value: value.to_string(),
+        },
+    })
+}
+
+pub fn none() -> Ast {
+    Ast::None(AstNone {
+        none_token: generated_token(TokenKind::None),
+    })
+}
+
+pub fn call(operand: Ast, arguments: Vec<Ast>) -> Ast {
+    Ast::Call(AstCall {
+        operand: Box::new(parenthesize_below(operand, usize::MAX)),
+        open_parenthesis_token: generated_token(TokenKind::OpenParenthesis),
+        arguments,
+        close_parenthesis_token: generated_token(TokenKind::CloseParenthesis),
+    })
+}
+
+pub fn parenthesized(expression: Ast) -> Ast {
+    Ast::Parenthesized(AstParenthesized {
+        open_parenthesis_token: generated_token(TokenKind::OpenParenthesis),
+        expression: Box::new(expression),
+        close_parenthesis_token: generated_token(TokenKind::CloseParenthesis),
+    })
+}
+
+pub fn member_access(operand: Ast, name: &str) -> Ast {
+    Ast::MemberAccess(AstMemberAccess {
+        operand: Box::new(parenthesize_below(operand, usize::MAX)),
+        dot_token: generated_token(TokenKind::Dot),
+        name_token: generated_name(name),
+    })
+}
+
+pub fn assignment(name: &str, value: Ast) -> Ast {
+    Ast::Assignment(AstAssignment {
+        name_token: generated_name(name),
+        equal_token: generated_token(TokenKind::Equal),
+        value: Box::new(value),
+    })
+}
+
+pub fn enumm(variants: &[&str]) -> Ast {
+    Ast::Enum(AstEnum {
+        enum_token: generated_token(TokenKind::Enum),
+        open_brace_token: generated_token(TokenKind::OpenBrace),
+        variants: variants
+            .iter()
+            .map(|variant| generated_name(variant))
+            .collect(),
+        close_brace_token: generated_token(TokenKind::CloseBrace),
+    })
+}
+
+pub fn freeze(value: Ast) -> Ast {
+    Ast::Freeze(AstFreeze {
+        freeze_token: generated_token(TokenKind::Freeze),
+        value: Box::new(value),
+    })
+}
+
+pub fn spawn(value: Ast) -> Ast {
+    Ast::Spawn(AstSpawn {
+        spawn_token: generated_token(TokenKind::Spawn),
+        value: Box::new(value),
+    })
+}
+
+pub fn join(operand: Ast) -> Ast {
+    Ast::Join(AstJoin {
+        join_token: generated_token(TokenKind::Join),
+        operand: Box::new(parenthesize_below(operand, usize::MAX)),
+    })
+}
+
+/// An arm with `None` as its pattern is the `_` arm
+pub fn matchh(value: Ast, arms: Vec<(Option<i64>, Ast)>) -> Ast {
+    Ast::Match(AstMatch {
+        match_token: generated_token(TokenKind::Match),
+        value: Box::new(value),
+        open_brace_token: generated_token(TokenKind::OpenBrace),
+        arms: arms
+            .into_iter()
+            .map(|(pattern, value)| AstMatchArm {
+                pattern: match pattern {
+                    Some(integer) => AstPattern::Integer {
+                        minus_token: (integer < 0).then(|| generated_token(TokenKind::Minus)),
+                        integer_token: IntegerToken {
+                            location: generated_location(),
+                            end: 0,
+                            value: integer.unsigned_abs() as u128,
+                        },
+                    },
+                    None => AstPattern::Default(generated_name("_")),
+                },
+                arrow_token: generated_token(TokenKind::RightArrow),
+                value,
+            })
+            .collect(),
+        close_brace_token: generated_token(TokenKind::CloseBrace),
+    })
+}
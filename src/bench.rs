@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bytecode::{Bytecode, BytecodeValue},
+    common::RuntimeError,
+    execute::Vm,
+    program::Program,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: String,
+    pub iterations: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    /// The number of instructions executed by one iteration
+    pub instruction_count: u64,
+}
+
+fn measure(
+    name: String,
+    iterations: usize,
+    mut run: impl FnMut(&mut Vm) -> Result<(), RuntimeError>,
+) -> Result<BenchResult, RuntimeError> {
+    let mut times = vec![];
+    let mut instruction_count = 0;
+    for _ in 0..iterations.max(1) {
+        let mut vm = Vm::new();
+        let start = Instant::now();
+        run(&mut vm)?;
+        times.push(start.elapsed());
+        instruction_count = vm.instruction_count;
+    }
+    Ok(BenchResult {
+        name,
+        iterations: times.len(),
+        min: times.iter().copied().min().unwrap(),
+        mean: times.iter().sum::<Duration>() / times.len() as u32,
+        max: times.iter().copied().max().unwrap(),
+        instruction_count,
+    })
+}
+
+/// Runs every exported `bench_*` procedure that takes no arguments,
+/// or the whole program if there are none
+pub fn bench_program(
+    program: &mut Program,
+    iterations: usize,
+) -> Result<Vec<BenchResult>, RuntimeError> {
+    let bench_names: Vec<String> = program
+        .get_proc_names()
+        .into_iter()
+        .filter(|name| name.starts_with("bench_"))
+        .collect();
+
+    if bench_names.is_empty() {
+        return Ok(vec![measure("<program>".to_string(), iterations, |vm| {
+            program.run_with(vm)
+        })?]);
+    }
+
+    program.run()?;
+    let mut results = vec![];
+    for name in bench_names {
+        let handle = program.get_proc(&name).unwrap();
+        if !handle.get_type().parameter_types.is_empty() {
+            continue;
+        }
+        results.push(measure(name, iterations, |vm| {
+            handle.call_with(vm, &[]).map(|_| ())
+        })?);
+    }
+    Ok(results)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmBenchResult {
+    pub family: &'static str,
+    pub instruction_count: u64,
+    pub elapsed: Duration,
+}
+
+impl VmBenchResult {
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instruction_count as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+// the counter of `counted_loop`, the bodies never use it
+const COUNTER: &str = "bench_counter";
+
+// runs the body `count` times in a loop, so the program is the same size for every count
+fn counted_loop(
+    setup: Vec<Bytecode>,
+    body: &[Bytecode],
+    count: usize,
+    teardown: Vec<Bytecode>,
+) -> Vec<Bytecode> {
+    let mut bytecode = setup;
+    bytecode.push(Bytecode::Push(BytecodeValue::Integer(count as i64)));
+    bytecode.push(Bytecode::Store(COUNTER.to_string()));
+    let start = bytecode.len();
+    // the loop ends at the jump below the body, the counting and the jump back
+    let end = start + 2 + body.len() + 4;
+    bytecode.push(Bytecode::Load(COUNTER.to_string()));
+    bytecode.push(Bytecode::JumpIfZero(end));
+    bytecode.extend_from_slice(body);
+    bytecode.push(Bytecode::Load(COUNTER.to_string()));
+    bytecode.push(Bytecode::AddConstInteger(-1));
+    bytecode.push(Bytecode::Store(COUNTER.to_string()));
+    bytecode.push(Bytecode::Jump(start));
+    bytecode.extend(teardown);
+    bytecode.push(Bytecode::Exit);
+    bytecode
+}
+
+/// Synthetic programs that each stress one family of opcodes
+pub fn vm_bench_programs(body_count: usize) -> Vec<(&'static str, Vec<Bytecode>)> {
+    vec![
+        (
+            "arithmetic",
+            counted_loop(
+                vec![Bytecode::Push(BytecodeValue::Integer(1))],
+                &[
+                    Bytecode::Push(BytecodeValue::Integer(3)),
+                    Bytecode::MulInteger,
+                    Bytecode::Push(BytecodeValue::Integer(2)),
+                    Bytecode::AddInteger,
+                    Bytecode::Push(BytecodeValue::Integer(3)),
+                    Bytecode::DivInteger,
+                ],
+                body_count,
+                vec![Bytecode::Pop],
+            ),
+        ),
+        (
+            "stack",
+            counted_loop(
+                vec![Bytecode::Push(BytecodeValue::Integer(1))],
+                &[Bytecode::Dup, Bytecode::Pop],
+                body_count,
+                vec![Bytecode::Pop],
+            ),
+        ),
+        (
+            "load/store",
+            counted_loop(
+                vec![
+                    Bytecode::Push(BytecodeValue::Integer(1)),
+                    Bytecode::Store("a".to_string()),
+                ],
+                &[
+                    Bytecode::Load("a".to_string()),
+                    Bytecode::Store("a".to_string()),
+                ],
+                body_count,
+                vec![],
+            ),
+        ),
+        (
+            "call",
+            counted_loop(
+                vec![
+                    Bytecode::Push(BytecodeValue::Procedure(Rc::from([Bytecode::Return]))),
+                    Bytecode::Store("f".to_string()),
+                ],
+                &[
+                    Bytecode::Load("f".to_string()),
+                    Bytecode::Call { argument_count: 0 },
+                    Bytecode::Pop,
+                ],
+                body_count,
+                vec![],
+            ),
+        ),
+    ]
+}
+
+pub fn vm_bench(body_count: usize, iterations: usize) -> Result<Vec<VmBenchResult>, RuntimeError> {
+    let mut results = vec![];
+    for (family, bytecode) in vm_bench_programs(body_count) {
+        let mut vm = Vm::new();
+        let start = Instant::now();
+        for _ in 0..iterations.max(1) {
+            vm.execute(&bytecode, Vec::new(), &mut HashMap::new())?;
+        }
+        results.push(VmBenchResult {
+            family,
+            instruction_count: vm.instruction_count,
+            elapsed: start.elapsed(),
+        });
+    }
+    Ok(results)
+}
@@ -1,71 +1,325 @@
 use std::{
-    collections::HashMap,
-    rc::{Rc, Weak},
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    sync::{Arc, Weak},
 };
 
 use crate::{
     ast::{
-        Ast, AstBinary, AstBlock, AstCall, AstExport, AstFile, AstInteger, AstLet, AstName,
+        dispatch_ast, Ast, AstAssignment, AstBinary, AstBlock, AstCall, AstDestructure, AstEnum,
+        AstExport, AstFile, AstFloat, AstFreeze, AstInteger, AstJoin, AstLet, AstMatch,
+        AstMemberAccess, AstName, AstNone, AstParenthesized, AstPattern, AstSpawn, AstString,
         AstTrait, AstUnary,
     },
     bound_nodes::{
-        BinaryOperator, BinaryOperatorKind, BoundBinary, BoundBlock, BoundCall, BoundExport,
-        BoundInteger, BoundLet, BoundName, BoundNode, BoundNodeTrait, BoundUnary, UnaryOperator,
+        BinaryOperator, BinaryOperatorKind, BoundAssignment, BoundBinary, BoundBlock, BoundCall,
+        BoundDestructure, BoundEnum, BoundError, BoundExport, BoundFloat, BoundFreeze,
+        BoundInteger, BoundJoin, BoundLet, BoundMatch, BoundMemberAccess, BoundName, BoundNode,
+        BoundNodeTrait, BoundNone, BoundSpawn, BoundString, BoundUnary, UnaryOperator,
         UnaryOperatorKind,
     },
-    common::{CompileError, CompileNote},
-    token::TokenKind,
-    types::{BlockType, Type},
+    common::{
+        CancellationToken, CompileError, CompileNote, Diagnostics, ErrorKind, SourceLocation,
+        Suggestion,
+    },
+    constant::evaluate_constant,
+    ice::{track, Phase},
+    parsing::DEFAULT_MAX_NESTING_DEPTH,
+    token::{NameToken, TokenKind},
+    types::{BlockType, EnumType, Type},
 };
 
 trait BindingTrait: AstTrait {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError>;
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode>;
+}
+
+/// What a file is allowed to do that others are not, from the pragmas at its top
+#[derive(Debug, Clone)]
+pub struct BindOptions {
+    pub max_depth: usize,
+    /// A `let` in a nested scope can reuse a name from outside of it, hiding the outer one
+    /// until the scope ends
+    pub allow_shadowing: bool,
+    /// Checked before every expression, once it is cancelled the rest of them become errors
+    /// and only the cancellation is reported
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl Default for BindOptions {
+    fn default() -> Self {
+        BindOptions {
+            max_depth: DEFAULT_MAX_NESTING_DEPTH,
+            allow_shadowing: false,
+            cancellation: None,
+        }
+    }
+}
+
+// the names an expression can see, along with which of them the innermost scope defined
+// itself, those are the ones that can not be shadowed
+#[derive(Clone)]
+struct Scope {
+    names: HashMap<String, Weak<BoundNode>>,
+    locals: HashSet<String>,
+    allow_shadowing: bool,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Scope {
+    fn new(names: HashMap<String, Weak<BoundNode>>, allow_shadowing: bool) -> Scope {
+        Scope {
+            names,
+            locals: HashSet::new(),
+            allow_shadowing,
+            cancellation: None,
+        }
+    }
+
+    // the scope of a block in this one, it sees everything this one does
+    fn nested(&self) -> Scope {
+        Scope {
+            cancellation: self.cancellation.clone(),
+            ..Scope::new(self.names.clone(), self.allow_shadowing)
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    fn define(&mut self, name: String, expression: Weak<BoundNode>) {
+        self.locals.insert(name.clone());
+        self.names.insert(name, expression);
+    }
+}
+
+impl Deref for Scope {
+    type Target = HashMap<String, Weak<BoundNode>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.names
+    }
 }
 
 pub fn bind_ast(
     ast: &Ast,
     names: &mut HashMap<String, Weak<BoundNode>>,
-) -> Result<Rc<BoundNode>, CompileError> {
-    ast.bind(names)
+) -> Result<Arc<BoundNode>, CompileError> {
+    bind_ast_with_max_depth(ast, names, DEFAULT_MAX_NESTING_DEPTH)
+}
+
+/// Binds the ast, failing with the first error or if it is nested deeper than `max_depth`
+///
+/// The binder and the bytecode compiler both recurse over the tree, so the depth is
+/// checked up front to give an error instead of overflowing the stack
+pub fn bind_ast_with_max_depth(
+    ast: &Ast,
+    names: &mut HashMap<String, Weak<BoundNode>>,
+    max_depth: usize,
+) -> Result<Arc<BoundNode>, CompileError> {
+    check_depth(ast, max_depth)?;
+
+    // names are only kept if the whole ast binds
+    let mut new_names = Scope::new(names.clone(), false);
+    let mut diagnostics = Diagnostics::new();
+    let bound_ast = ast.bind(&mut new_names, &mut diagnostics);
+    if let Some(error) = diagnostics.errors.into_iter().next() {
+        return Err(error);
+    }
+    *names = new_names.names;
+    Ok(bound_ast)
+}
+
+/// Binds the ast, reporting every error instead of stopping at the first one
+///
+/// Expressions that fail to bind have the type `Type::Error`, which is accepted everywhere
+/// without any more errors so one mistake does not cascade
+pub fn bind_ast_with_diagnostics(
+    ast: &Ast,
+    names: &mut HashMap<String, Weak<BoundNode>>,
+    diagnostics: &mut Diagnostics,
+) -> Arc<BoundNode> {
+    bind_ast_with_diagnostics_and_max_depth(ast, names, diagnostics, DEFAULT_MAX_NESTING_DEPTH)
+}
+
+pub fn bind_ast_with_diagnostics_and_max_depth(
+    ast: &Ast,
+    names: &mut HashMap<String, Weak<BoundNode>>,
+    diagnostics: &mut Diagnostics,
+    max_depth: usize,
+) -> Arc<BoundNode> {
+    let options = BindOptions {
+        max_depth,
+        ..BindOptions::default()
+    };
+    bind_ast_with_options(ast, names, diagnostics, &options)
+}
+
+pub fn bind_ast_with_options(
+    ast: &Ast,
+    names: &mut HashMap<String, Weak<BoundNode>>,
+    diagnostics: &mut Diagnostics,
+    options: &BindOptions,
+) -> Arc<BoundNode> {
+    if let Err(error) = check_depth(ast, options.max_depth) {
+        diagnostics.push(error);
+        return error_node(ast.get_location());
+    }
+    let mut scope = Scope::new(std::mem::take(names), options.allow_shadowing);
+    scope.cancellation = options.cancellation.clone();
+    let bound_ast = ast.bind(&mut scope, diagnostics);
+    *names = scope.names;
+    if let Some(cancellation) = &options.cancellation {
+        if let Err(error) = cancellation.check_compile(&ast.get_location()) {
+            diagnostics.push(error);
+        }
+    }
+    bound_ast
+}
+
+fn error_node(location: SourceLocation) -> Arc<BoundNode> {
+    Arc::new(BoundNode::Error(BoundError { location }))
+}
+
+// the first pass over a file or block, its exports are in scope from the start of it so a
+// name can be told apart from one that does not exist. each export gets a `BoundError` at its
+// name until it is bound, the returned nodes keep them alive while the scope is bound
+fn declare_exports(expressions: &[Ast], names: &mut Scope) -> Vec<Arc<BoundNode>> {
+    let mut declarations = vec![];
+    for expression in expressions {
+        let Ast::Export(export) = expression else {
+            continue;
+        };
+        // a name that is already defined is reported when the export is bound
+        if !names.contains_key(&export.name_token.name) {
+            let declaration = error_node(export.name_token.location.clone());
+            names.define(export.name_token.name.clone(), Arc::downgrade(&declaration));
+            declarations.push(declaration);
+        }
+    }
+    declarations
+}
+
+// whether the name is an export that has been declared but not bound yet, nothing else that
+// is in scope failed to bind
+fn is_declaration(expression: &Weak<BoundNode>) -> bool {
+    expression
+        .upgrade()
+        .is_some_and(|node| matches!(&node as &BoundNode, BoundNode::Error(_)))
+}
+
+// exports cannot be used before they run yet, that needs procedures that are only called later
+fn used_before_definition(
+    name_token: &NameToken,
+    declaration: &Weak<BoundNode>,
+    diagnostics: &mut Diagnostics,
+) -> Arc<BoundNode> {
+    diagnostics.push(CompileError {
+        location: name_token.location.clone(),
+        end_location: None,
+        kind: ErrorKind::UsedBeforeDefinition,
+        id: "used-before-definition",
+        arguments: vec![name_token.name.to_string()],
+        message: format!("{} cannot be used before it is defined", name_token.name),
+        notes: vec![CompileNote {
+            location: Some(declaration.upgrade().unwrap().get_location()),
+            message: format!("{} is defined here", name_token.name),
+        }],
+        suggestions: vec![],
+    });
+    error_node(name_token.location.clone())
+}
+
+fn check_depth(ast: &Ast, max_depth: usize) -> Result<(), CompileError> {
+    // the binder and the compiler recurse down every node, so a chain like `1 + 1 + ...`
+    // that the parser builds without nesting still can only be so long
+    let max_tree_depth = max_depth.saturating_mul(4);
+    // a file is just the container for the top level expressions, so it does not count
+    let start_depth = if let Ast::File(_) = ast { 0 } else { 1 };
+    let mut stack = vec![(ast, start_depth, start_depth)];
+    while let Some((ast, nesting, depth)) = stack.pop() {
+        if nesting > max_depth {
+            return Err(depth_error(
+                ast,
+                "nested-too-deeply",
+                max_depth,
+                format!(
+                    "Expression is nested too deeply, the maximum depth is {}",
+                    max_depth,
+                ),
+            ));
+        }
+        if depth > max_tree_depth {
+            return Err(depth_error(
+                ast,
+                "expression-too-long",
+                max_tree_depth,
+                format!(
+                    "Expression is too long, the maximum depth of its tree is {}",
+                    max_tree_depth,
+                ),
+            ));
+        }
+
+        // nested the same way the parser nests, the left side of an operator, call or member
+        // access is parsed at the same depth as the node
+        let left = match ast {
+            Ast::Binary(binary) => Some(&*binary.left),
+            Ast::Call(call) => Some(&*call.operand),
+            Ast::MemberAccess(member_access) => Some(&*member_access.operand),
+            _ => None,
+        };
+        for child in ast.children() {
+            let child_nesting = if left.is_some_and(|left| std::ptr::eq(left, child)) {
+                nesting
+            } else {
+                nesting + 1
+            };
+            stack.push((child, child_nesting, depth + 1));
+        }
+    }
+    Ok(())
+}
+
+fn depth_error(ast: &Ast, id: &'static str, max_depth: usize, message: String) -> CompileError {
+    CompileError {
+        location: ast.get_start(),
+        end_location: Some(ast.get_end()),
+        kind: ErrorKind::NestedTooDeeply,
+        id,
+        arguments: vec![max_depth.to_string()],
+        message,
+        notes: vec![],
+        suggestions: vec![],
+    }
 }
 
 impl BindingTrait for Ast {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        match self {
-            Ast::File(file) => file.bind(names),
-            Ast::Block(block) => block.bind(names),
-            Ast::Export(export) => export.bind(names),
-            Ast::Let(lett) => lett.bind(names),
-            Ast::Unary(unary) => unary.bind(names),
-            Ast::Binary(binary) => binary.bind(names),
-            Ast::Name(name) => name.bind(names),
-            Ast::Integer(integer) => integer.bind(names),
-            Ast::Call(call) => call.bind(names),
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let _context = track(Phase::Binding, self.get_location());
+        // the cancellation itself is reported once the whole tree has been walked
+        if names.is_cancelled() {
+            return error_node(self.get_location());
         }
+        dispatch_ast!(self, node => node.bind(names, diagnostics))
     }
 }
 
 impl BindingTrait for AstFile {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let mut new_names = names.clone();
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let mut new_names = names.nested();
+        let _declarations = declare_exports(&self.expressions, &mut new_names);
 
         let mut expressions = vec![];
         let mut exported_expressions = HashMap::new();
         for expression in &self.expressions {
-            let bound_expression = expression.bind(&mut new_names)?;
+            let bound_expression = expression.bind(&mut new_names, diagnostics);
             expressions.push(bound_expression.clone());
 
             if let BoundNode::Export(export) = &bound_expression as &BoundNode {
-                exported_expressions.insert(export.name.clone(), Rc::downgrade(&bound_expression));
+                exported_expressions.insert(export.name.clone(), Arc::downgrade(&bound_expression));
             }
         }
 
@@ -74,30 +328,29 @@ impl BindingTrait for AstFile {
             exported_types.insert(name.clone(), expression.upgrade().unwrap().get_type());
         }
 
-        Ok(Rc::new(BoundNode::Block(BoundBlock {
+        Arc::new(BoundNode::Block(BoundBlock {
             location: self.get_location(),
             expressions,
             exported_expressions,
             block_type: Type::Block(BlockType { exported_types }),
-        })))
+            evaluates_to_last: false,
+        }))
     }
 }
 
 impl BindingTrait for AstBlock {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let mut new_names = names.clone();
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let mut new_names = names.nested();
+        let _declarations = declare_exports(&self.expressions, &mut new_names);
 
         let mut expressions = vec![];
         let mut exported_expressions = HashMap::new();
         for expression in &self.expressions {
-            let bound_expression = expression.bind(&mut new_names)?;
+            let bound_expression = expression.bind(&mut new_names, diagnostics);
             expressions.push(bound_expression.clone());
 
             if let BoundNode::Export(export) = &bound_expression as &BoundNode {
-                exported_expressions.insert(export.name.clone(), Rc::downgrade(&bound_expression));
+                exported_expressions.insert(export.name.clone(), Arc::downgrade(&bound_expression));
             }
         }
 
@@ -106,84 +359,213 @@ impl BindingTrait for AstBlock {
             exported_types.insert(name.clone(), expression.upgrade().unwrap().get_type());
         }
 
-        Ok(Rc::new(BoundNode::Block(BoundBlock {
+        // a block that exports nothing is the value of its last expression, like the value
+        // of a file in the repl
+        let evaluates_to_last = exported_expressions.is_empty() && !expressions.is_empty();
+        let block_type = if evaluates_to_last {
+            expressions.last().unwrap().get_type()
+        } else {
+            Type::Block(BlockType { exported_types })
+        };
+        Arc::new(BoundNode::Block(BoundBlock {
             location: self.get_location(),
             expressions,
             exported_expressions,
-            block_type: Type::Block(BlockType { exported_types }),
-        })))
+            block_type,
+            evaluates_to_last,
+        }))
     }
 }
 
 impl BindingTrait for AstExport {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let name = if let TokenKind::Name(name) = &self.name_token.kind {
-            name.clone()
-        } else {
-            unreachable!()
-        };
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let name = self.name_token.name.clone();
 
-        let value = self.value.bind(names)?;
+        let value = self.value.bind(names, diagnostics);
 
-        if let Some(expression) = names.get(&name.clone()) {
-            Err(CompileError {
+        // only the declaration of this scope is the export itself, an outer export clashes
+        // with it whether or not it has been bound yet, so the order they are in does not matter
+        if let Some(expression) = names
+            .get(&name)
+            .filter(|expression| !is_declaration(expression) || !names.locals.contains(&name))
+        {
+            diagnostics.push(CompileError {
                 location: self.get_location(),
+                end_location: None,
+                kind: ErrorKind::AlreadyDefined,
+                id: "already-defined",
+                arguments: vec![name.to_string()],
                 message: format!("{} is already defined", name),
                 notes: vec![CompileNote {
                     location: Some(expression.upgrade().unwrap().get_location()),
                     message: format!("{} was previously defined here", name),
                 }],
-            })
+                suggestions: vec![],
+            });
+            error_node(self.get_location())
         } else {
-            let export = Rc::new(BoundNode::Export(BoundExport {
+            let export = Arc::new(BoundNode::Export(BoundExport {
                 location: self.get_location(),
                 name: name.clone(),
                 value,
             }));
-            names.insert(name, Rc::downgrade(&export));
-            Ok(export)
+            names.define(name, Arc::downgrade(&export));
+            export
         }
     }
 }
 
 impl BindingTrait for AstLet {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let name = if let TokenKind::Name(name) = &self.name_token.kind {
-            name.clone()
-        } else {
-            unreachable!()
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let name = self.name_token.name.clone();
+
+        let value = self
+            .value
+            .as_ref()
+            .map(|value| value.bind(names, diagnostics));
+
+        let Ok(shadowed) = check_redefinition(&self.name_token, names, diagnostics) else {
+            return error_node(self.get_location());
         };
+        let lett = Arc::new(BoundNode::Let(BoundLet {
+            location: self.get_location(),
+            name: name.clone(),
+            value,
+            shadowed,
+        }));
+        names.define(name, Arc::downgrade(&lett));
+        lett
+    }
+}
 
-        let value = if let Some(value) = &self.value {
-            Some(value.bind(names)?)
-        } else {
-            None
+// where the definition from an outer scope that a let of the name shadows is, if there is
+// one. anything else that already has the name is an error, unless it is the declaration of
+// an export in the same scope, which reports the clash itself
+fn check_redefinition(
+    name_token: &NameToken,
+    names: &Scope,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<SourceLocation>, ()> {
+    let name = &name_token.name;
+    let Some(expression) = names
+        .get(name)
+        .filter(|expression| !is_declaration(expression) || !names.locals.contains(name))
+    else {
+        return Ok(None);
+    };
+    let location = expression.upgrade().unwrap().get_location();
+    let outer = !names.locals.contains(name);
+    if outer && names.allow_shadowing {
+        return Ok(Some(location));
+    }
+
+    let mut notes = vec![CompileNote {
+        location: Some(location),
+        message: format!("{} was previously defined here", name),
+    }];
+    if outer {
+        notes.push(CompileNote {
+            location: None,
+            message: "A file that starts with //! shadowing can reuse the names of outer scopes"
+                .to_string(),
+        });
+    }
+    diagnostics.push(CompileError {
+        location: name_token.location.clone(),
+        end_location: None,
+        kind: ErrorKind::AlreadyDefined,
+        id: "already-defined",
+        arguments: vec![name.to_string()],
+        message: format!("{} is already defined", name),
+        notes,
+        suggestions: vec![],
+    });
+    Err(())
+}
+
+impl BindingTrait for AstDestructure {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let value = self.value.bind(names, diagnostics);
+        let value_type = value.get_type();
+        let block_type = match &value_type {
+            Type::Block(block_type) => Some(block_type),
+            Type::Error => None,
+            _ => {
+                diagnostics.push(CompileError {
+                    location: self.get_location(),
+                    end_location: Some(self.get_end()),
+                    kind: ErrorKind::NotABlock,
+                    id: "destructure-non-block",
+                    arguments: vec![],
+                    message: "Cannot destructure a non block".to_string(),
+                    notes: vec![CompileNote {
+                        location: Some(value.get_location()),
+                        message: format!("The type was {:?}", value_type),
+                    }],
+                    suggestions: vec![],
+                });
+                None
+            }
         };
 
-        if let Some(expression) = names.get(&name.clone()) {
-            Err(CompileError {
-                location: self.get_location(),
-                message: format!("{} is already defined", name),
-                notes: vec![CompileNote {
-                    location: Some(expression.upgrade().unwrap().get_location()),
-                    message: format!("{} was previously defined here", name),
-                }],
-            })
-        } else {
-            let lett = Rc::new(BoundNode::Let(BoundLet {
-                location: self.get_location(),
-                name: name.clone(),
-                value,
+        let pattern: Vec<&str> = self.names.iter().map(|name| name.name.as_str()).collect();
+        let value = Arc::new(BoundNode::Let(BoundLet {
+            location: SourceLocation::synthetic("destructuring", Some(&self.get_location())),
+            name: format!("{{{}}}", pattern.join(", ")),
+            value: Some(value),
+            shadowed: None,
+        }));
+
+        let mut bindings = vec![];
+        for name_token in &self.names {
+            let Ok(shadowed) = check_redefinition(name_token, names, diagnostics) else {
+                continue;
+            };
+            let location = SourceLocation::synthetic("destructuring", Some(&name_token.location));
+            // a name that is not a member is still defined, so its uses are not errors too
+            let member = match block_type {
+                Some(block_type) => match block_type.exported_types.get(&name_token.name) {
+                    Some(member_type) => Arc::new(BoundNode::MemberAccess(BoundMemberAccess {
+                        location: location.clone(),
+                        operand: Arc::new(BoundNode::Name(BoundName {
+                            location,
+                            name: value.unwrap_let().name.clone(),
+                            resolved_expression: Arc::downgrade(&value),
+                        })),
+                        name: name_token.name.clone(),
+                        member_type: member_type.clone(),
+                    })),
+                    None => {
+                        diagnostics.push(CompileError {
+                            location: name_token.location.clone(),
+                            end_location: None,
+                            kind: ErrorKind::UnknownMember,
+                            id: "unknown-member",
+                            arguments: vec![name_token.name.to_string()],
+                            message: format!("The block does not export {}", name_token.name),
+                            notes: vec![],
+                            suggestions: did_you_mean(name_token, block_type.exported_types.keys()),
+                        });
+                        error_node(location)
+                    }
+                },
+                None => error_node(location),
+            };
+            let lett = Arc::new(BoundNode::Let(BoundLet {
+                location: name_token.location.clone(),
+                name: name_token.name.clone(),
+                value: Some(member),
+                shadowed,
             }));
-            names.insert(name, Rc::downgrade(&lett));
-            Ok(lett)
+            names.define(name_token.name.clone(), Arc::downgrade(&lett));
+            bindings.push(lett);
         }
+
+        Arc::new(BoundNode::Destructure(BoundDestructure {
+            location: self.get_location(),
+            value,
+            bindings,
+        }))
     }
 }
 
@@ -204,16 +586,48 @@ static UNARY_OPERATORS: &[(TokenKind, UnaryOperator)] = &[
             result: Type::Integer,
         },
     ),
+    (
+        TokenKind::Plus,
+        UnaryOperator {
+            kind: UnaryOperatorKind::Identity,
+            operand: Type::Float,
+            result: Type::Float,
+        },
+    ),
+    (
+        TokenKind::Minus,
+        UnaryOperator {
+            kind: UnaryOperatorKind::Negation,
+            operand: Type::Float,
+            result: Type::Float,
+        },
+    ),
+    (
+        TokenKind::Tilde,
+        UnaryOperator {
+            kind: UnaryOperatorKind::BitwiseNot,
+            operand: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
 ];
 
 impl BindingTrait for AstUnary {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let operand = self.operand.bind(names)?;
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let operand = self.operand.bind(names, diagnostics);
+        if operand.get_type() == Type::Error {
+            return error_node(self.get_location());
+        }
 
         let mut operator = None;
+        // `?` works on every type, so it is not in the table
+        if self.operator_token.kind == TokenKind::QuestionMark {
+            operator = Some(UnaryOperator {
+                kind: UnaryOperatorKind::MakeOptional,
+                operand: operand.get_type(),
+                result: Type::optional(operand.get_type()),
+            });
+        }
         for (kind, unary_operator) in UNARY_OPERATORS {
             if &self.operator_token.kind == kind && unary_operator.operand == operand.get_type() {
                 operator = Some(unary_operator.clone());
@@ -222,22 +636,32 @@ impl BindingTrait for AstUnary {
         }
 
         if let Some(operator) = operator {
-            Ok(Rc::new(BoundNode::Unary(BoundUnary {
+            let unary = Arc::new(BoundNode::Unary(BoundUnary {
                 location: self.get_location(),
                 operator,
                 operand,
-            })))
+            }));
+            check_constant(unary, self, diagnostics)
         } else {
             // TODO: Print type properly
-            Err(CompileError {
-                location: self.get_location(),
+            diagnostics.push(CompileError {
+                location: self.get_start(),
+                end_location: Some(self.get_end()),
+                kind: ErrorKind::UnknownOperator,
+                id: "unknown-unary-operator",
+                arguments: vec![
+                    self.operator_token.kind.to_string(),
+                    format!("{:?}", operand.get_type()),
+                ],
                 message: format!(
                     "Unable to find unary operator {} for type {:?}",
                     self.operator_token.kind.to_string(),
                     operand.get_type(),
                 ),
                 notes: vec![],
-            })
+                suggestions: vec![],
+            });
+            error_node(self.get_location())
         }
     }
 }
@@ -279,17 +703,129 @@ static BINARY_OPERATORS: &[(TokenKind, BinaryOperator)] = &[
             result: Type::Integer,
         },
     ),
+    // floats follow ieee 754, so dividing by zero gives an infinity or nan instead of an error
+    (
+        TokenKind::Plus,
+        BinaryOperator {
+            kind: BinaryOperatorKind::Addition,
+            left: Type::Float,
+            right: Type::Float,
+            result: Type::Float,
+        },
+    ),
+    (
+        TokenKind::Minus,
+        BinaryOperator {
+            kind: BinaryOperatorKind::Subtraction,
+            left: Type::Float,
+            right: Type::Float,
+            result: Type::Float,
+        },
+    ),
+    (
+        TokenKind::Asterisk,
+        BinaryOperator {
+            kind: BinaryOperatorKind::Multiplication,
+            left: Type::Float,
+            right: Type::Float,
+            result: Type::Float,
+        },
+    ),
+    (
+        TokenKind::Slash,
+        BinaryOperator {
+            kind: BinaryOperatorKind::Division,
+            left: Type::Float,
+            right: Type::Float,
+            result: Type::Float,
+        },
+    ),
+    (
+        TokenKind::LessThanLessThan,
+        BinaryOperator {
+            kind: BinaryOperatorKind::ShiftLeft,
+            left: Type::Integer,
+            right: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
+    (
+        TokenKind::GreaterThanGreaterThan,
+        BinaryOperator {
+            kind: BinaryOperatorKind::ShiftRight,
+            left: Type::Integer,
+            right: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
+    (
+        TokenKind::Ampersand,
+        BinaryOperator {
+            kind: BinaryOperatorKind::BitwiseAnd,
+            left: Type::Integer,
+            right: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
+    (
+        TokenKind::Pipe,
+        BinaryOperator {
+            kind: BinaryOperatorKind::BitwiseOr,
+            left: Type::Integer,
+            right: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
+    (
+        TokenKind::Caret,
+        BinaryOperator {
+            kind: BinaryOperatorKind::BitwiseXor,
+            left: Type::Integer,
+            right: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
+    // there are no booleans yet, so these treat any integer other than 0 as true and
+    // give 0 or 1
+    (
+        TokenKind::AmpersandAmpersand,
+        BinaryOperator {
+            kind: BinaryOperatorKind::LogicalAnd,
+            left: Type::Integer,
+            right: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
+    (
+        TokenKind::PipePipe,
+        BinaryOperator {
+            kind: BinaryOperatorKind::LogicalOr,
+            left: Type::Integer,
+            right: Type::Integer,
+            result: Type::Integer,
+        },
+    ),
 ];
 
 impl BindingTrait for AstBinary {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let left = self.left.bind(names)?;
-        let right = self.right.bind(names)?;
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let left = self.left.bind(names, diagnostics);
+        let right = self.right.bind(names, diagnostics);
+        if left.get_type() == Type::Error || right.get_type() == Type::Error {
+            return error_node(self.get_location());
+        }
 
         let mut operator = None;
+        if self.operator_token.kind == TokenKind::QuestionMarkQuestionMark {
+            operator = unwrap_or_operator(&left.get_type(), &right.get_type());
+        }
+        if let Type::Enum(_) = left.get_type() {
+            operator = enum_comparison_operator(
+                &self.operator_token.kind,
+                &left.get_type(),
+                &right.get_type(),
+            );
+        }
         for (kind, binary_operator) in BINARY_OPERATORS {
             if &self.operator_token.kind == kind
                 && binary_operator.left == left.get_type()
@@ -301,16 +837,40 @@ impl BindingTrait for AstBinary {
         }
 
         if let Some(operator) = operator {
-            Ok(Rc::new(BoundNode::Binary(BoundBinary {
+            if matches!(operator.kind, BinaryOperatorKind::Division)
+                && evaluate_constant(&right) == Ok(Some(0))
+            {
+                diagnostics.push(CompileError {
+                    location: self.right.get_start(),
+                    end_location: Some(self.right.get_end()),
+                    kind: ErrorKind::InvalidConstant,
+                    id: "division-by-zero",
+                    arguments: vec![],
+                    message: "Division by zero".to_string(),
+                    notes: vec![],
+                    suggestions: vec![],
+                });
+                return error_node(self.get_location());
+            }
+            let binary = Arc::new(BoundNode::Binary(BoundBinary {
                 location: self.get_location(),
                 left,
                 operator,
                 right,
-            })))
+            }));
+            check_constant(binary, self, diagnostics)
         } else {
             // TODO: Print type properly
-            Err(CompileError {
-                location: self.get_location(),
+            diagnostics.push(CompileError {
+                location: self.get_start(),
+                end_location: Some(self.get_end()),
+                kind: ErrorKind::UnknownOperator,
+                id: "unknown-binary-operator",
+                arguments: vec![
+                    self.operator_token.kind.to_string(),
+                    format!("{:?}", left.get_type()),
+                    format!("{:?}", right.get_type()),
+                ],
                 message: format!(
                     "Unable to find binary operator {} for types {:?} and {:?}",
                     self.operator_token.kind.to_string(),
@@ -318,117 +878,997 @@ impl BindingTrait for AstBinary {
                     right.get_type(),
                 ),
                 notes: vec![],
-            })
+                suggestions: vec![],
+            });
+            error_node(self.get_location())
         }
     }
 }
 
+// `??` works on every optional, so it is not in the table. the default can be a value of the
+// inner type, which unwraps the optional, or another optional
+fn unwrap_or_operator(left: &Type, right: &Type) -> Option<BinaryOperator> {
+    let result = match left {
+        Type::Optional(inner) if **inner == *right => right.clone(),
+        Type::Optional(_) if left.accepts(right) => left.clone(),
+        Type::None => right.clone(),
+        _ => return None,
+    };
+    Some(BinaryOperator {
+        kind: BinaryOperatorKind::UnwrapOr,
+        left: left.clone(),
+        right: right.clone(),
+        result,
+    })
+}
+
+// every enum is its own type, so they can't be in the table either. variants can only be
+// compared with variants of the same enum
+fn enum_comparison_operator(kind: &TokenKind, left: &Type, right: &Type) -> Option<BinaryOperator> {
+    let kind = match kind {
+        TokenKind::EqualEqual => BinaryOperatorKind::Equal,
+        TokenKind::ExclamationMarkEqual => BinaryOperatorKind::NotEqual,
+        _ => return None,
+    };
+    if left != right {
+        return None;
+    }
+    Some(BinaryOperator {
+        kind,
+        left: left.clone(),
+        right: right.clone(),
+        result: Type::Integer,
+    })
+}
+
 impl BindingTrait for AstName {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let name = if let TokenKind::Name(name) = &self.name_token.kind {
-            name.clone()
-        } else {
-            unreachable!()
-        };
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let name = self.name_token.name.clone();
 
         if let Some(expression) = names.get(&name) {
-            Ok(Rc::new(BoundNode::Name(BoundName {
+            if is_declaration(expression) {
+                return used_before_definition(&self.name_token, expression, diagnostics);
+            }
+            Arc::new(BoundNode::Name(BoundName {
                 location: self.get_location(),
                 name,
                 resolved_expression: expression.clone(),
-            })))
+            }))
         } else {
-            Err(CompileError {
-                location: self.get_location(),
+            diagnostics.push(CompileError {
+                location: self.get_start(),
+                end_location: Some(self.get_end()),
+                kind: ErrorKind::UnresolvedName,
+                id: "unresolved-name",
+                arguments: vec![name.to_string()],
                 message: format!("Unable to find {}", name),
                 notes: vec![],
-            })
+                suggestions: did_you_mean(&self.name_token, names.keys()),
+            });
+            error_node(self.get_location())
         }
     }
 }
 
 impl BindingTrait for AstInteger {
-    fn bind(
-        &self,
-        _names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let value = if let TokenKind::Integer(value) = self.integer_token.kind {
-            value
-        } else {
-            unreachable!()
-        };
+    fn bind(&self, _names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let value = self.integer_token.value;
 
         if value > i64::MAX as u128 {
-            Err(CompileError {
-                location: self.integer_token.location.clone(),
+            diagnostics.push(CompileError {
+                location: self.get_start(),
+                end_location: Some(self.get_end()),
+                kind: ErrorKind::IntegerTooBig,
+                id: "integer-too-big",
+                arguments: vec![value.to_string()],
                 message: format!("Integer {} is too big for a 64 bit signed integer", value),
                 notes: vec![],
-            })
+                suggestions: vec![],
+            });
+            error_node(self.get_location())
         } else {
-            Ok(Rc::new(BoundNode::Integer(BoundInteger {
+            Arc::new(BoundNode::Integer(BoundInteger {
                 location: self.get_location(),
                 value,
-            })))
+            }))
         }
     }
 }
 
+impl BindingTrait for AstFloat {
+    fn bind(&self, _names: &mut Scope, _diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        Arc::new(BoundNode::Float(BoundFloat {
+            location: self.get_location(),
+            value: self.float_token.value,
+        }))
+    }
+}
+
+impl BindingTrait for AstString {
+    fn bind(&self, _names: &mut Scope, _diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        Arc::new(BoundNode::String(BoundString {
+            location: self.get_location(),
+            value: self.string_token.value.clone(),
+        }))
+    }
+}
+
+impl BindingTrait for AstNone {
+    fn bind(&self, _names: &mut Scope, _diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        Arc::new(BoundNode::None(BoundNone {
+            location: self.get_location(),
+        }))
+    }
+}
+
 impl BindingTrait for AstCall {
-    fn bind(
-        &self,
-        names: &mut HashMap<String, Weak<BoundNode>>,
-    ) -> Result<Rc<BoundNode>, CompileError> {
-        let operand = self.operand.bind(names)?;
-        let proc_type = if let Type::Proc(proc_type) = operand.get_type() {
-            proc_type
-        } else {
-            return Err(CompileError {
-                location: self.close_parenthesis_token.location.clone(),
-                message: format!("Cannot call a non procedure"),
-                notes: vec![CompileNote {
-                    location: Some(operand.get_location()),
-                    message: format!("The type was {:?}", operand.get_type()),
-                }],
-            });
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let mut arguments = vec![];
+        let operand = match self.operand.as_member_access() {
+            Some(member) => {
+                let receiver = member.operand.bind(names, diagnostics);
+                match find_function_for(&receiver.get_type(), &member.name_token, names) {
+                    // `x.f(a)` is `f(x, a)` when x has no member f to call
+                    Some(function) => {
+                        arguments.push(receiver);
+                        function
+                    }
+                    None => bind_member_access(member, receiver, diagnostics),
+                }
+            }
+            None => self.operand.bind(names, diagnostics),
+        };
+        arguments.extend(
+            self.arguments
+                .iter()
+                .map(|argument| argument.bind(names, diagnostics)),
+        );
+
+        let proc_type = match operand.get_type() {
+            Type::Proc(proc_type) => proc_type,
+            Type::Error => return error_node(self.get_location()),
+            // calling a member of a block is a method call, so the member gets the blame
+            operand_type => {
+                let error = match self.operand.as_member_access() {
+                    Some(member) => CompileError {
+                        location: member.name_token.location.clone(),
+                        end_location: Some(self.get_end()),
+                        kind: ErrorKind::NotAProcedure,
+                        id: "member-not-a-procedure",
+                        arguments: vec![
+                            member.name_token.name.to_string(),
+                            format!("{:?}", operand_type),
+                        ],
+                        message: format!(
+                            "Cannot call {} because it is not a procedure, it has type {:?}",
+                            member.name_token.name, operand_type
+                        ),
+                        notes: definition_note(&operand),
+                        suggestions: vec![],
+                    },
+                    None => CompileError {
+                        location: self.close_parenthesis_token.location.clone(),
+                        end_location: None,
+                        kind: ErrorKind::NotAProcedure,
+                        id: "not-a-procedure",
+                        arguments: vec![],
+                        message: "Cannot call a non procedure".to_string(),
+                        notes: vec![CompileNote {
+                            location: Some(operand.get_location()),
+                            message: format!("The type was {:?}", operand_type),
+                        }],
+                        suggestions: vec![],
+                    },
+                };
+                diagnostics.push(error);
+                return error_node(self.get_location());
+            }
         };
 
-        if proc_type.parameter_types.len() != self.arguments.len() {
-            return Err(CompileError {
+        if proc_type.parameter_types.len() != arguments.len() {
+            diagnostics.push(CompileError {
                 location: self.close_parenthesis_token.location.clone(),
+                end_location: None,
+                kind: ErrorKind::WrongArgumentCount,
+                id: "wrong-argument-count",
+                arguments: vec![
+                    proc_type.parameter_types.len().to_string(),
+                    arguments.len().to_string(),
+                ],
                 message: format!(
                     "Invalid number of arguments for procedure, expected {} arguments but got {}",
                     proc_type.parameter_types.len(),
-                    self.arguments.len(),
+                    arguments.len(),
                 ),
                 notes: vec![],
+                suggestions: vec![],
             });
+            return error_node(self.get_location());
         }
 
-        let mut arguments = vec![];
-        for (i, expression) in self.arguments.iter().enumerate() {
-            let argument = expression.bind(names)?;
-            if argument.get_type() != proc_type.parameter_types[i] {
-                return Err(CompileError {
+        let mut valid = true;
+        for (i, argument) in arguments.iter().enumerate() {
+            if argument.get_type() == Type::Error {
+                valid = false;
+            } else if !proc_type.parameter_types[i].accepts(&argument.get_type()) {
+                diagnostics.push(CompileError {
                     location: self.close_parenthesis_token.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::WrongArgumentType,
+                    id: "wrong-argument-type",
+                    arguments: vec![
+                        format!("{:?}", proc_type.parameter_types[i]),
+                        format!("{:?}", argument.get_type()),
+                    ],
                     message: format!(
                         "Wrong argument type for procedure, expected type {:?} but got type {:?}",
                         proc_type.parameter_types[i],
                         argument.get_type(),
                     ),
                     notes: vec![],
+                    suggestions: vec![],
                 });
+                valid = false;
             }
-            arguments.push(argument);
+        }
+        if !valid {
+            return error_node(self.get_location());
         }
 
-        Ok(Rc::new(BoundNode::Call(BoundCall {
+        Arc::new(BoundNode::Call(BoundCall {
             location: self.get_location(),
             operand,
             arguments,
             proc_type: Type::Proc(proc_type),
+        }))
+    }
+}
+
+impl BindingTrait for AstParenthesized {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        self.expression.bind(names, diagnostics)
+    }
+}
+
+// where the name a call found in scope was defined, `x.f()` can find `f` far from the call
+fn definition_note(operand: &BoundNode) -> Vec<CompileNote> {
+    let BoundNode::Name(name) = operand else {
+        return vec![];
+    };
+    name.resolved_expression
+        .upgrade()
+        .map(|definition| CompileNote {
+            location: Some(definition.get_location()),
+            message: format!("{} was defined here", name.name),
+        })
+        .into_iter()
+        .collect()
+}
+
+// the procedure that `receiver.name(...)` calls with the receiver as its first argument, when
+// the receiver has no member called `name`. a name in scope is used first, otherwise the
+// builtin namespaces are searched for a procedure that takes the receiver. a name in scope
+// that is not a procedure is still returned, so the call reports it instead of the member
+fn find_function_for(
+    receiver_type: &Type,
+    name_token: &NameToken,
+    names: &HashMap<String, Weak<BoundNode>>,
+) -> Option<Arc<BoundNode>> {
+    match receiver_type {
+        Type::Block(block_type) if block_type.exported_types.contains_key(&name_token.name) => {
+            return None
+        }
+        Type::Error => return None,
+        _ => {}
+    }
+    let takes_receiver = |function_type: &Type| match function_type {
+        Type::Proc(proc_type) => proc_type
+            .parameter_types
+            .first()
+            .is_some_and(|parameter_type| parameter_type.accepts(receiver_type)),
+        _ => false,
+    };
+
+    if let Some(function) = names.get(&name_token.name).and_then(Weak::upgrade) {
+        let function_type = function.get_type();
+        if matches!(function_type, Type::Proc(_)) && !takes_receiver(&function_type) {
+            return None;
+        }
+        return Some(Arc::new(BoundNode::Name(BoundName {
+            location: name_token.location.clone(),
+            name: name_token.name.clone(),
+            resolved_expression: Arc::downgrade(&function),
+        })));
+    }
+
+    // sorted so the same namespace is picked every time
+    let mut namespaces: Vec<_> = names
+        .iter()
+        .filter_map(|(name, node)| {
+            let node = node.upgrade()?;
+            match &node as &BoundNode {
+                BoundNode::Block(block) if block.is_namespace() => Some((name, node.clone())),
+                _ => None,
+            }
+        })
+        .collect();
+    namespaces.sort_by_key(|(name, _)| *name);
+    namespaces.into_iter().find_map(|(namespace, node)| {
+        let Type::Block(block_type) = node.get_type() else {
+            return None;
+        };
+        let member_type = block_type.exported_types.get(&name_token.name)?.clone();
+        if !takes_receiver(&member_type) {
+            return None;
+        }
+        Some(Arc::new(BoundNode::MemberAccess(BoundMemberAccess {
+            location: name_token.location.clone(),
+            // the namespace is not written anywhere, it is filled in from the method name
+            operand: Arc::new(BoundNode::Name(BoundName {
+                location: SourceLocation::synthetic("method call", Some(&name_token.location)),
+                name: namespace.clone(),
+                resolved_expression: Arc::downgrade(&node),
+            })),
+            name: name_token.name.clone(),
+            member_type,
         })))
+    })
+}
+
+impl BindingTrait for AstMemberAccess {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let operand = self.operand.bind(names, diagnostics);
+        bind_member_access(self, operand, diagnostics)
+    }
+}
+
+// the operand is bound separately so a call can look at its type first
+fn bind_member_access(
+    member: &AstMemberAccess,
+    operand: Arc<BoundNode>,
+    diagnostics: &mut Diagnostics,
+) -> Arc<BoundNode> {
+    let name = member.name_token.name.clone();
+
+    let block_type = match operand.get_type() {
+        Type::Block(block_type) => block_type,
+        Type::Error => return error_node(member.get_location()),
+        _ => {
+            diagnostics.push(CompileError {
+                location: member.name_token.location.clone(),
+                end_location: Some(member.get_end()),
+                kind: ErrorKind::NotABlock,
+                id: "member-of-non-block",
+                arguments: vec![name.to_string()],
+                message: format!("Cannot access {} of a non block", name),
+                notes: vec![CompileNote {
+                    location: Some(operand.get_location()),
+                    message: format!("The type was {:?}", operand.get_type()),
+                }],
+                suggestions: vec![],
+            });
+            return error_node(member.get_location());
+        }
+    };
+
+    let Some(member_type) = block_type.exported_types.get(&name) else {
+        diagnostics.push(CompileError {
+            location: member.name_token.location.clone(),
+            end_location: Some(member.get_end()),
+            kind: ErrorKind::UnknownMember,
+            id: "unknown-member",
+            arguments: vec![name.to_string()],
+            message: format!("The block does not export {}", name),
+            notes: vec![],
+            suggestions: did_you_mean(&member.name_token, block_type.exported_types.keys()),
+        });
+        return error_node(member.get_location());
+    };
+
+    Arc::new(BoundNode::MemberAccess(BoundMemberAccess {
+        location: member.get_location(),
+        operand,
+        name,
+        member_type: member_type.clone(),
+    }))
+}
+
+impl BindingTrait for AstAssignment {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let name = self.name_token.name.clone();
+        let value = self.value.bind(names, diagnostics);
+
+        let Some(resolved_expression) = names.get(&name) else {
+            diagnostics.push(CompileError {
+                location: self.name_token.location.clone(),
+                end_location: None,
+                kind: ErrorKind::UnresolvedName,
+                id: "unresolved-name",
+                arguments: vec![name.to_string()],
+                message: format!("Unable to find {}", name),
+                notes: vec![],
+                suggestions: did_you_mean(&self.name_token, names.keys()),
+            });
+            return error_node(self.get_location());
+        };
+        if is_declaration(resolved_expression) {
+            return used_before_definition(&self.name_token, resolved_expression, diagnostics);
+        }
+        let definition = resolved_expression.upgrade().unwrap();
+
+        let is_builtin = match &definition as &BoundNode {
+            BoundNode::Native(_) => true,
+            BoundNode::Block(block) => block.is_namespace(),
+            _ => false,
+        };
+        if is_builtin {
+            diagnostics.push(CompileError {
+                location: self.get_start(),
+                end_location: Some(self.get_end()),
+                kind: ErrorKind::InvalidAssignment,
+                id: "assign-to-builtin",
+                arguments: vec![name.to_string()],
+                message: format!("Cannot assign to the builtin {}", name),
+                notes: vec![],
+                suggestions: vec![],
+            });
+            return error_node(self.get_location());
+        }
+
+        let (target_type, value_type) = (definition.get_type(), value.get_type());
+        if target_type == Type::Error || value_type == Type::Error {
+            return error_node(self.get_location());
+        }
+        if !target_type.accepts(&value_type) {
+            diagnostics.push(CompileError {
+                location: self.get_start(),
+                end_location: Some(self.get_end()),
+                kind: ErrorKind::InvalidAssignment,
+                id: "assign-wrong-type",
+                arguments: vec![
+                    format!("{:?}", value_type),
+                    name.to_string(),
+                    format!("{:?}", target_type),
+                ],
+                message: format!(
+                    "Cannot assign a value of type {:?} to {}, which has type {:?}",
+                    value_type, name, target_type,
+                ),
+                notes: vec![CompileNote {
+                    location: Some(definition.get_location()),
+                    message: format!("{} was defined here", name),
+                }],
+                suggestions: vec![],
+            });
+            return error_node(self.get_location());
+        }
+
+        Arc::new(BoundNode::Assignment(BoundAssignment {
+            location: self.get_location(),
+            name,
+            resolved_expression: resolved_expression.clone(),
+            value,
+        }))
+    }
+}
+
+impl BindingTrait for AstMatch {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let value = self.value.bind(names, diagnostics);
+        let value_type = value.get_type();
+        let mut failed = value_type == Type::Error;
+        if !failed && !matches!(value_type, Type::Integer | Type::Enum(_)) {
+            diagnostics.push(CompileError {
+                location: self.value.get_start(),
+                end_location: Some(self.value.get_end()),
+                kind: ErrorKind::InvalidMatch,
+                id: "match-wrong-type",
+                arguments: vec![format!("{:?}", value_type)],
+                message: format!(
+                    "Unable to match on a value of type {:?}, only integers and enums can be matched",
+                    value_type,
+                ),
+                notes: vec![],
+                suggestions: vec![],
+            });
+            failed = true;
+        }
+
+        let mut arms: Vec<(i64, Arc<BoundNode>, &AstPattern)> = vec![];
+        let mut default = None;
+        // the type of the match comes from the first arm, and the arm it came from
+        let mut match_type: Option<(Type, &Ast)> = None;
+        for arm in &self.arms {
+            // only one arm runs, so what an arm defines is not in scope after the match
+            let bound_arm = arm.value.bind(&mut names.clone(), diagnostics);
+
+            let arm_type = bound_arm.get_type();
+            match &match_type {
+                _ if arm_type == Type::Error => failed = true,
+                None => match_type = Some((arm_type, &arm.value)),
+                Some((typ, _)) if typ.accepts(&arm_type) => {}
+                Some((typ, _)) if arm_type.accepts(typ) => {
+                    match_type = Some((arm_type, &arm.value))
+                }
+                Some((typ, first_arm)) => {
+                    diagnostics.push(CompileError {
+                        location: arm.value.get_start(),
+                        end_location: Some(arm.value.get_end()),
+                        kind: ErrorKind::MismatchedArmTypes,
+                        id: "mismatched-arm-types",
+                        arguments: vec![format!("{:?}", arm_type), format!("{:?}", typ)],
+                        message: format!(
+                            "Every arm of a match needs the same type, this one has type {:?} but an earlier one has type {:?}",
+                            arm_type, typ,
+                        ),
+                        notes: vec![CompileNote {
+                            location: Some(first_arm.get_location()),
+                            message: format!("This arm has type {:?}", typ),
+                        }],
+                        suggestions: vec![],
+                    });
+                    failed = true;
+                }
+            }
+
+            if default.is_some() {
+                diagnostics.push(CompileError {
+                    location: arm.pattern.get_location(),
+                    end_location: Some(arm.pattern.get_end()),
+                    kind: ErrorKind::UnreachableArm,
+                    id: "arm-after-wildcard",
+                    arguments: vec![],
+                    message: "This arm can never run because it comes after the _ arm".to_string(),
+                    notes: vec![],
+                    suggestions: vec![],
+                });
+                failed = true;
+                continue;
+            }
+            if let AstPattern::Default(_) = &arm.pattern {
+                default = Some(bound_arm);
+                continue;
+            }
+            let Some(integer) = bind_pattern(&arm.pattern, &value_type, names, diagnostics) else {
+                failed = true;
+                continue;
+            };
+            if let Some((_, _, earlier)) = arms.iter().find(|(other, _, _)| *other == integer) {
+                let pattern = arm.pattern.pretty_print();
+                diagnostics.push(CompileError {
+                    location: arm.pattern.get_location(),
+                    end_location: Some(arm.pattern.get_end()),
+                    kind: ErrorKind::UnreachableArm,
+                    id: "arm-already-matched",
+                    arguments: vec![pattern.to_string()],
+                    message: format!(
+                        "This arm can never run because {} is already matched by an earlier arm",
+                        pattern,
+                    ),
+                    notes: vec![CompileNote {
+                        location: Some(earlier.get_location()),
+                        message: format!("{} is matched here", pattern),
+                    }],
+                    suggestions: vec![],
+                });
+                failed = true;
+                continue;
+            }
+            arms.push((integer, bound_arm, &arm.pattern));
+        }
+
+        // a match with an arm for every variant of an enum does not need a `_` arm, the last
+        // arm is run for whatever the others don't match
+        let covers_enum = match &value_type {
+            Type::Enum(enum_type) => arms.len() == enum_type.variants.len(),
+            _ => false,
+        };
+        let default = match default {
+            Some(default) => default,
+            None if covers_enum && !failed => arms.pop().unwrap().1,
+            None => {
+                let notes = match &value_type {
+                    Type::Enum(enum_type) => {
+                        let missing: Vec<&str> = (0..enum_type.variants.len())
+                            .filter(|index| {
+                                arms.iter().all(|(integer, _, _)| *integer != *index as i64)
+                            })
+                            .map(|index| enum_type.variants[index].as_str())
+                            .collect();
+                        vec![CompileNote {
+                            location: None,
+                            message: format!("No arm matches {}", missing.join(", ")),
+                        }]
+                    }
+                    _ => vec![],
+                };
+                diagnostics.push(CompileError {
+                    location: self.get_start(),
+                    end_location: Some(self.get_end()),
+                    kind: ErrorKind::InvalidMatch,
+                    id: "match-without-wildcard",
+                    arguments: vec![],
+                    message: "A match needs a _ arm at the end for the values no other arm matches"
+                        .to_string(),
+                    notes,
+                    suggestions: vec![],
+                });
+                return error_node(self.get_location());
+            }
+        };
+        if failed {
+            return error_node(self.get_location());
+        }
+
+        Arc::new(BoundNode::Match(BoundMatch {
+            location: self.get_location(),
+            value,
+            arms: arms
+                .into_iter()
+                .map(|(integer, arm, _)| (integer, arm))
+                .collect(),
+            default,
+            match_type: match_type.map_or(Type::Void, |(typ, _)| typ),
+        }))
+    }
+}
+
+// the integer a pattern that is not `_` matches, variants are their index. `None` when the
+// error has already been reported
+fn bind_pattern(
+    pattern: &AstPattern,
+    value_type: &Type,
+    names: &mut Scope,
+    diagnostics: &mut Diagnostics,
+) -> Option<i64> {
+    let wrong_type = |pattern_type: &Type, diagnostics: &mut Diagnostics| {
+        diagnostics.push(CompileError {
+            location: pattern.get_location(),
+            end_location: Some(pattern.get_end()),
+            kind: ErrorKind::InvalidMatch,
+            id: "pattern-wrong-type",
+            arguments: vec![format!("{:?}", pattern_type), format!("{:?}", value_type)],
+            message: format!(
+                "Unable to match a pattern of type {:?} against a value of type {:?}",
+                pattern_type, value_type,
+            ),
+            notes: vec![],
+            suggestions: vec![],
+        });
+    };
+
+    match pattern {
+        AstPattern::Integer {
+            minus_token,
+            integer_token,
+        } => {
+            if let Type::Enum(_) = value_type {
+                wrong_type(&Type::Integer, diagnostics);
+                return None;
+            }
+            let integer = if minus_token.is_some() {
+                0i64.checked_sub_unsigned(u64::try_from(integer_token.value).unwrap_or(u64::MAX))
+            } else {
+                i64::try_from(integer_token.value).ok()
+            };
+            if integer.is_none() {
+                diagnostics.push(CompileError {
+                    location: pattern.get_location(),
+                    end_location: Some(pattern.get_end()),
+                    kind: ErrorKind::IntegerTooBig,
+                    id: "integer-too-big",
+                    arguments: vec![pattern.pretty_print()],
+                    message: format!(
+                        "Integer {} is too big for a 64 bit signed integer",
+                        pattern.pretty_print(),
+                    ),
+                    notes: vec![],
+                    suggestions: vec![],
+                });
+            }
+            integer
+        }
+        AstPattern::Variant(variant) => {
+            let bound_variant = variant.bind(names, diagnostics);
+            match (bound_variant.get_type(), value_type) {
+                (Type::Error, _) | (_, Type::Error) => None,
+                (Type::Enum(enum_type), Type::Enum(_))
+                    if bound_variant.get_type() == *value_type =>
+                {
+                    enum_type
+                        .get_index(&variant.name_token.name)
+                        .map(|index| index as i64)
+                }
+                (pattern_type, _) => {
+                    wrong_type(&pattern_type, diagnostics);
+                    None
+                }
+            }
+        }
+        AstPattern::Default(_) => unreachable!("the _ arm has no integer"),
+    }
+}
+
+impl BindingTrait for AstEnum {
+    fn bind(&self, _names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        if self.variants.is_empty() {
+            diagnostics.push(CompileError {
+                location: self.get_start(),
+                end_location: Some(self.get_end()),
+                kind: ErrorKind::InvalidEnum,
+                id: "empty-enum",
+                arguments: vec![],
+                message: "An enum needs at least one variant".to_string(),
+                notes: vec![],
+                suggestions: vec![],
+            });
+            return error_node(self.get_location());
+        }
+
+        let mut failed = false;
+        for (i, variant) in self.variants.iter().enumerate() {
+            if let Some(earlier) = self.variants[..i]
+                .iter()
+                .find(|earlier| earlier.name == variant.name)
+            {
+                diagnostics.push(CompileError {
+                    location: variant.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::InvalidEnum,
+                    id: "duplicate-variant",
+                    arguments: vec![variant.name.to_string()],
+                    message: format!("{} is already a variant of the enum", variant.name),
+                    notes: vec![CompileNote {
+                        location: Some(earlier.location.clone()),
+                        message: format!("{} is defined here", variant.name),
+                    }],
+                    suggestions: vec![],
+                });
+                failed = true;
+            }
+        }
+        if failed {
+            return error_node(self.get_location());
+        }
+
+        Arc::new(BoundNode::Enum(BoundEnum {
+            location: self.get_location(),
+            enum_type: EnumType {
+                variants: self
+                    .variants
+                    .iter()
+                    .map(|variant| variant.name.clone())
+                    .collect(),
+                location: self.get_location(),
+            },
+        }))
+    }
+}
+
+// only blocks have an identity at runtime, everything else is copied into each variable so
+// there is nothing to freeze
+impl BindingTrait for AstFreeze {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let value = self.value.bind(names, diagnostics);
+        match value.get_type() {
+            Type::Block(_) => {}
+            Type::Error => return error_node(self.get_location()),
+            value_type => {
+                diagnostics.push(CompileError {
+                    location: self.value.get_start(),
+                    end_location: Some(self.value.get_end()),
+                    kind: ErrorKind::InvalidFreeze,
+                    id: "freeze-non-block",
+                    arguments: vec![format!("{:?}", value_type)],
+                    message: format!("Only blocks can be frozen, this has type {:?}", value_type),
+                    notes: vec![],
+                    suggestions: vec![],
+                });
+                return error_node(self.get_location());
+            }
+        }
+
+        Arc::new(BoundNode::Freeze(BoundFreeze {
+            location: self.get_location(),
+            value,
+        }))
+    }
+}
+
+impl BindingTrait for AstSpawn {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        // what the task defines is only in scope inside of it
+        let value = self.value.bind(&mut names.nested(), diagnostics);
+        if value.get_type() == Type::Error {
+            return error_node(self.get_location());
+        }
+
+        let mut captures = vec![];
+        find_captures(&value, &definitions_in(&value), &mut captures);
+        // the task has its own copies, assigning to one would not change the name outside
+        let mut failed = false;
+        for assignment in assignments_in(&value) {
+            if let Some((name, definition)) = captures
+                .iter()
+                .find(|(_, definition)| definition.ptr_eq(&assignment.resolved_expression))
+            {
+                diagnostics.push(CompileError {
+                    location: assignment.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::InvalidAssignment,
+                    id: "assign-in-spawn",
+                    arguments: vec![name.to_string()],
+                    message: format!(
+                        "Cannot assign to {} inside a spawn, the task only has a copy of it",
+                        name,
+                    ),
+                    notes: vec![CompileNote {
+                        location: Some(definition.upgrade().unwrap().get_location()),
+                        message: format!("{} was defined here", name),
+                    }],
+                    suggestions: vec![],
+                });
+                failed = true;
+            }
+        }
+        if failed {
+            return error_node(self.get_location());
+        }
+
+        Arc::new(BoundNode::Spawn(BoundSpawn {
+            location: self.get_location(),
+            value,
+            captures,
+        }))
+    }
+}
+
+// the lets and exports in the tree, by address
+fn definitions_in(node: &Arc<BoundNode>) -> HashSet<*const BoundNode> {
+    let mut definitions = HashSet::new();
+    let mut pending = vec![node];
+    while let Some(node) = pending.pop() {
+        if let BoundNode::Let(_) | BoundNode::Export(_) = node as &BoundNode {
+            definitions.insert(Arc::as_ptr(node));
+        }
+        pending.extend(node.children());
+    }
+    definitions
+}
+
+fn assignments_in(node: &Arc<BoundNode>) -> Vec<&BoundAssignment> {
+    let mut assignments = vec![];
+    let mut pending = vec![node];
+    while let Some(node) = pending.pop() {
+        if let BoundNode::Assignment(assignment) = node as &BoundNode {
+            assignments.push(assignment);
+        }
+        pending.extend(node.children());
+    }
+    assignments
+}
+
+// the names used in the tree that are not defined in it, in the order they are first used.
+// recursive so the order is the order of the source
+fn find_captures(
+    node: &Arc<BoundNode>,
+    definitions: &HashSet<*const BoundNode>,
+    captures: &mut Vec<(String, Weak<BoundNode>)>,
+) {
+    let used = match node as &BoundNode {
+        BoundNode::Name(name) => Some((&name.name, &name.resolved_expression)),
+        BoundNode::Assignment(assignment) => {
+            Some((&assignment.name, &assignment.resolved_expression))
+        }
+        _ => None,
+    };
+    if let Some((name, resolved_expression)) = used {
+        let is_outside = !definitions.contains(&resolved_expression.as_ptr());
+        let is_new = !captures
+            .iter()
+            .any(|(_, capture)| capture.ptr_eq(resolved_expression));
+        if is_outside && is_new {
+            captures.push((name.clone(), resolved_expression.clone()));
+        }
+    }
+    for child in node.children() {
+        find_captures(child, definitions, captures);
+    }
+}
+
+impl BindingTrait for AstJoin {
+    fn bind(&self, names: &mut Scope, diagnostics: &mut Diagnostics) -> Arc<BoundNode> {
+        let operand = self.operand.bind(names, diagnostics);
+        match operand.get_type() {
+            Type::Task(_) => {}
+            Type::Error => return error_node(self.get_location()),
+            operand_type => {
+                diagnostics.push(CompileError {
+                    location: self.operand.get_start(),
+                    end_location: Some(self.operand.get_end()),
+                    kind: ErrorKind::InvalidJoin,
+                    id: "join-non-task",
+                    arguments: vec![format!("{:?}", operand_type)],
+                    message: format!("Only tasks can be joined, this has type {:?}", operand_type),
+                    notes: vec![],
+                    suggestions: vec![],
+                });
+                return error_node(self.get_location());
+            }
+        }
+
+        Arc::new(BoundNode::Join(BoundJoin {
+            location: self.get_location(),
+            operand,
+        }))
+    }
+}
+
+// operators with constant operands that would overflow at runtime are reported now instead,
+// the operands were checked when they were bound so only this operator can be the problem
+fn check_constant(
+    node: Arc<BoundNode>,
+    ast: &impl AstTrait,
+    diagnostics: &mut Diagnostics,
+) -> Arc<BoundNode> {
+    match evaluate_constant(&node) {
+        Ok(_) => node,
+        Err(message) => {
+            diagnostics.push(CompileError {
+                location: ast.get_start(),
+                end_location: Some(ast.get_end()),
+                kind: ErrorKind::InvalidConstant,
+                // the message is the one the vm would have failed with, which has no id
+                id: "constant-fails",
+                arguments: vec![message.clone()],
+                message,
+                notes: vec![],
+                suggestions: vec![],
+            });
+            error_node(node.get_location())
+        }
+    }
+}
+
+// replaces the name with the closest one that could have been meant
+fn did_you_mean<'a>(
+    name_token: &NameToken,
+    names: impl Iterator<Item = &'a String>,
+) -> Vec<Suggestion> {
+    find_similar_name(&name_token.name, names)
+        .map(|similar| Suggestion {
+            location: name_token.location.clone(),
+            length: name_token.length(),
+            replacement: similar.clone(),
+            message: format!("Did you mean {}?", similar),
+        })
+        .into_iter()
+        .collect()
+}
+
+/// The closest of the names that is only a typo or two away, if there is one
+fn find_similar_name<'a>(
+    name: &str,
+    names: impl Iterator<Item = &'a String>,
+) -> Option<&'a String> {
+    names
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= 2 && distance < name.chars().count())
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+// the levenshtein distance, in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
     }
+    previous[b.len()]
 }
@@ -1,142 +1,202 @@
 use std::{
+    cell::RefCell,
     collections::HashMap,
     fmt::Debug,
-    rc::{Rc, Weak},
+    sync::{Arc, Weak},
 };
 
 use crate::{
-    common::SourceLocation,
-    types::{ProcType, Type},
+    bytecode::NativeId,
+    common::{drop_iteratively, SourceLocation},
+    natives::get_native,
+    types::{EnumType, Type},
 };
 
 pub trait BoundNodeTrait: Debug + Clone {
     fn get_location(&self) -> SourceLocation;
     fn get_type(&self) -> Type;
+    /// The direct sub expressions of this node, in source order
+    fn children(&self) -> Vec<&Arc<BoundNode>>;
 }
 
-#[derive(Debug, Clone)]
-pub enum BoundNode {
-    Block(BoundBlock),
-    Export(BoundExport),
-    Let(BoundLet),
-    Unary(BoundUnary),
-    Binary(BoundBinary),
-    Name(BoundName),
-    Integer(BoundInteger),
-    Call(BoundCall),
-    PrintInteger(BoundPrintInteger),
-}
-
-impl BoundNode {
-    pub fn unwrap_block(&self) -> &BoundBlock {
-        if let BoundNode::Block(block) = self {
-            block
-        } else {
-            unreachable!()
+// declares `BoundNode` the same way `define_ast!` declares `Ast`, along with
+// `dispatch_bound_node!`
+macro_rules! define_bound_nodes {
+    ($d:tt $($variant:ident($node:ident) $as:ident $unwrap:ident,)*) => {
+        #[derive(Debug, Clone)]
+        pub enum BoundNode {
+            $($variant($node),)*
         }
-    }
 
-    pub fn unwrap_export(&self) -> &BoundExport {
-        if let BoundNode::Export(export) = self {
-            export
-        } else {
-            unreachable!()
+        impl BoundNode {
+            $(
+                pub fn $as(&self) -> Option<&$node> {
+                    if let BoundNode::$variant(node) = self {
+                        Some(node)
+                    } else {
+                        None
+                    }
+                }
+
+                /// Panics if it is another kind of node
+                pub fn $unwrap(&self) -> &$node {
+                    self.$as()
+                        .expect(concat!("expected ", stringify!($node)))
+                }
+            )*
         }
-    }
 
-    pub fn unwrap_let(&self) -> &BoundLet {
-        if let BoundNode::Let(lett) = self {
-            lett
-        } else {
-            unreachable!()
+        $(
+            /// Gives the node back if it is another kind
+            impl TryFrom<BoundNode> for $node {
+                type Error = BoundNode;
+
+                fn try_from(node: BoundNode) -> Result<Self, Self::Error> {
+                    match node {
+                        BoundNode::$variant(node) => Ok(node),
+                        node => Err(node),
+                    }
+                }
+            }
+
+            impl<'a> TryFrom<&'a BoundNode> for &'a $node {
+                type Error = &'a BoundNode;
+
+                fn try_from(node: &'a BoundNode) -> Result<Self, Self::Error> {
+                    node.$as().ok_or(node)
+                }
+            }
+        )*
+
+        /// Matches on every kind of bound node, binding it to `$node` for `$body`
+        macro_rules! dispatch_bound_node {
+            ($d bound_node:expr, $d node:ident => $d body:expr) => {
+                match $d bound_node {
+                    $($crate::bound_nodes::BoundNode::$variant($d node) => $d body,)*
+                }
+            };
         }
-    }
+        pub(crate) use dispatch_bound_node;
+    };
+}
 
-    pub fn unwrap_unary(&self) -> &BoundUnary {
-        if let BoundNode::Unary(unary) = self {
-            unary
-        } else {
-            unreachable!()
-        }
-    }
+// adding a kind of bound node only takes a line here, and implementing `BoundNodeTrait` and
+// `Compilable` for it
+define_bound_nodes! {
+    $
+    Block(BoundBlock) as_block unwrap_block,
+    Export(BoundExport) as_export unwrap_export,
+    Let(BoundLet) as_let unwrap_let,
+    Destructure(BoundDestructure) as_destructure unwrap_destructure,
+    Unary(BoundUnary) as_unary unwrap_unary,
+    Binary(BoundBinary) as_binary unwrap_binary,
+    Name(BoundName) as_name unwrap_name,
+    Integer(BoundInteger) as_integer unwrap_integer,
+    Float(BoundFloat) as_float unwrap_float,
+    String(BoundString) as_string unwrap_string,
+    None(BoundNone) as_none unwrap_none,
+    Call(BoundCall) as_call unwrap_call,
+    Native(BoundNative) as_native unwrap_native,
+    MemberAccess(BoundMemberAccess) as_member_access unwrap_member_access,
+    Assignment(BoundAssignment) as_assignment unwrap_assignment,
+    Match(BoundMatch) as_match unwrap_match,
+    Enum(BoundEnum) as_enum unwrap_enum,
+    Freeze(BoundFreeze) as_freeze unwrap_freeze,
+    Spawn(BoundSpawn) as_spawn unwrap_spawn,
+    Join(BoundJoin) as_join unwrap_join,
+    Error(BoundError) as_error unwrap_error,
+}
 
-    pub fn unwrap_binary(&self) -> &BoundBinary {
-        if let BoundNode::Binary(binary) = self {
-            binary
-        } else {
-            unreachable!()
-        }
+impl BoundNodeTrait for BoundNode {
+    fn get_location(&self) -> SourceLocation {
+        dispatch_bound_node!(self, node => node.get_location())
     }
 
-    pub fn unwrap_name(&self) -> &BoundName {
-        if let BoundNode::Name(name) = self {
-            name
-        } else {
-            unreachable!()
-        }
+    fn get_type(&self) -> Type {
+        dispatch_bound_node!(self, node => node.get_type())
     }
 
-    pub fn unwrap_integer(&self) -> &BoundInteger {
-        if let BoundNode::Integer(integer) = self {
-            integer
-        } else {
-            unreachable!()
-        }
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        dispatch_bound_node!(self, node => node.children())
     }
+}
 
-    pub fn unwrap_call(&self) -> &BoundCall {
-        if let BoundNode::Call(call) = self {
-            call
-        } else {
-            unreachable!()
-        }
-    }
+thread_local! {
+    static DROP_QUEUE: RefCell<Option<Vec<Arc<BoundNode>>>> = const { RefCell::new(None) };
+    // what is left in the place of a child that was taken out, it owns nothing
+    static EMPTY_NODE: Arc<BoundNode> = Arc::new(BoundNode::Error(BoundError {
+        location: SourceLocation {
+            filepath: String::new(),
+            position: 0,
+            line: 0,
+            column: 0,
+            synthetic: None,
+        },
+    }));
+}
 
-    pub fn unwrap_print_integer(&self) -> &BoundPrintInteger {
-        if let BoundNode::PrintInteger(print_integer) = self {
-            print_integer
-        } else {
-            unreachable!()
-        }
-    }
+// takes the children that only this node holds out of it while it is being dropped, so a
+// long chain of nodes is not dropped by recursing down it
+fn drop_children<'a>(children: impl IntoIterator<Item = &'a mut Arc<BoundNode>>) {
+    let nodes = children
+        .into_iter()
+        .filter(|child| Arc::strong_count(child) == 1 && !child.children().is_empty())
+        .map(|child| std::mem::replace(child, EMPTY_NODE.with(Arc::clone)))
+        .collect();
+    drop_iteratively(&DROP_QUEUE, nodes);
 }
 
-impl BoundNodeTrait for BoundNode {
-    fn get_location(&self) -> SourceLocation {
-        match self {
-            BoundNode::Block(block) => block.get_location(),
-            BoundNode::Export(export) => export.get_location(),
-            BoundNode::Let(lett) => lett.get_location(),
-            BoundNode::Unary(unary) => unary.get_location(),
-            BoundNode::Binary(binary) => binary.get_location(),
-            BoundNode::Name(name) => name.get_location(),
-            BoundNode::Integer(integer) => integer.get_location(),
-            BoundNode::Call(call) => call.get_location(),
-            BoundNode::PrintInteger(print_integer) => print_integer.get_location(),
-        }
-    }
+macro_rules! drop_children_iteratively {
+    ($($node:ident($name:ident) => $children:expr,)*) => {
+        $(
+            impl Drop for $node {
+                fn drop(&mut self) {
+                    let $name = self;
+                    drop_children($children);
+                }
+            }
+        )*
+    };
+}
 
-    fn get_type(&self) -> Type {
-        match self {
-            BoundNode::Block(block) => block.get_type(),
-            BoundNode::Export(export) => export.get_type(),
-            BoundNode::Let(lett) => lett.get_type(),
-            BoundNode::Unary(unary) => unary.get_type(),
-            BoundNode::Binary(binary) => binary.get_type(),
-            BoundNode::Name(name) => name.get_type(),
-            BoundNode::Integer(integer) => integer.get_type(),
-            BoundNode::Call(call) => call.get_type(),
-            BoundNode::PrintInteger(print_integer) => print_integer.get_type(),
-        }
-    }
+drop_children_iteratively! {
+    BoundBlock(node) => &mut node.expressions,
+    BoundExport(node) => [&mut node.value],
+    BoundLet(node) => &mut node.value,
+    BoundDestructure(node) => std::iter::once(&mut node.value).chain(&mut node.bindings),
+    BoundUnary(node) => [&mut node.operand],
+    BoundBinary(node) => [&mut node.left, &mut node.right],
+    BoundCall(node) => std::iter::once(&mut node.operand).chain(&mut node.arguments),
+    BoundMemberAccess(node) => [&mut node.operand],
+    BoundAssignment(node) => [&mut node.value],
+    BoundMatch(node) => std::iter::once(&mut node.value)
+        .chain(node.arms.iter_mut().map(|(_, arm)| arm))
+        .chain([&mut node.default]),
+    BoundFreeze(node) => [&mut node.value],
+    BoundSpawn(node) => [&mut node.value],
+    BoundJoin(node) => [&mut node.operand],
 }
 
 #[derive(Debug, Clone)]
 pub struct BoundBlock {
     pub location: SourceLocation,
-    pub expressions: Vec<Rc<BoundNode>>,
+    pub expressions: Vec<Arc<BoundNode>>,
     pub exported_expressions: HashMap<String, Weak<BoundNode>>,
     pub block_type: Type,
+    /// Whether the block is the value of its last expression instead of a value holding its
+    /// exports, a block that exports nothing is as long as it has an expression
+    pub evaluates_to_last: bool,
+}
+
+impl BoundBlock {
+    /// Whether this is a namespace of the prelude, which only holds builtins
+    pub fn is_namespace(&self) -> bool {
+        !self.expressions.is_empty()
+            && self
+                .expressions
+                .iter()
+                .all(|expression| matches!(expression as &BoundNode, BoundNode::Native(_)))
+    }
 }
 
 impl BoundNodeTrait for BoundBlock {
@@ -147,13 +207,17 @@ impl BoundNodeTrait for BoundBlock {
     fn get_type(&self) -> Type {
         self.block_type.clone()
     }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        self.expressions.iter().collect()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct BoundExport {
     pub location: SourceLocation,
     pub name: String,
-    pub value: Rc<BoundNode>,
+    pub value: Arc<BoundNode>,
 }
 
 impl BoundNodeTrait for BoundExport {
@@ -164,13 +228,33 @@ impl BoundNodeTrait for BoundExport {
     fn get_type(&self) -> Type {
         self.value.get_type()
     }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.value]
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct BoundLet {
     pub location: SourceLocation,
     pub name: String,
-    pub value: Option<Rc<BoundNode>>,
+    pub value: Option<Arc<BoundNode>>,
+    /// Where the definition from an outer scope that this one shadows is
+    pub shadowed: Option<SourceLocation>,
+}
+
+impl BoundLet {
+    /// The variable the value is kept in, a let that shadows another one needs a variable of
+    /// its own so the outer one still has its value once the scope ends
+    pub fn get_variable(&self) -> String {
+        match &self.shadowed {
+            Some(_) => format!(
+                "{}@{}:{}",
+                self.name, self.location.line, self.location.column
+            ),
+            None => self.name.clone(),
+        }
+    }
 }
 
 impl BoundNodeTrait for BoundLet {
@@ -185,12 +269,45 @@ impl BoundNodeTrait for BoundLet {
             Type::Void
         }
     }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        self.value.iter().collect()
+    }
+}
+
+/// `let { x, y } = value`, the value goes in a variable of its own and each name is a let of
+/// a member of it, so everything that looks at lets sees them
+#[derive(Debug, Clone)]
+pub struct BoundDestructure {
+    pub location: SourceLocation,
+    /// The let that holds the value, its name is the pattern like `{x, y}` so it can't be used
+    /// by name
+    pub value: Arc<BoundNode>,
+    /// A let for each name, in the order they were written
+    pub bindings: Vec<Arc<BoundNode>>,
+}
+
+impl BoundNodeTrait for BoundDestructure {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        self.value.get_type()
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        std::iter::once(&self.value).chain(&self.bindings).collect()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum UnaryOperatorKind {
     Identity,
     Negation,
+    BitwiseNot,
+    /// Makes an optional that has the operand, which is stored the same as the operand
+    MakeOptional,
 }
 
 #[derive(Debug, Clone)]
@@ -204,7 +321,7 @@ pub struct UnaryOperator {
 pub struct BoundUnary {
     pub location: SourceLocation,
     pub operator: UnaryOperator,
-    pub operand: Rc<BoundNode>,
+    pub operand: Arc<BoundNode>,
 }
 
 impl BoundNodeTrait for BoundUnary {
@@ -215,6 +332,10 @@ impl BoundNodeTrait for BoundUnary {
     fn get_type(&self) -> Type {
         self.operator.result.clone()
     }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.operand]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -223,6 +344,23 @@ pub enum BinaryOperatorKind {
     Subtraction,
     Multiplication,
     Division,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    /// Shifts by 64 or more give 0, shifting by a negative amount is an error
+    ShiftLeft,
+    /// Keeps the sign, so shifts by 64 or more give 0 or -1
+    ShiftRight,
+    /// Short circuits, so the right operand only runs if the left one is not 0
+    LogicalAnd,
+    /// Short circuits, so the right operand only runs if the left one is 0
+    LogicalOr,
+    /// Only between two variants of the same enum, gives 0 or 1 like the logical operators
+    Equal,
+    NotEqual,
+    /// The optional on the left unless it is none, then the right operand, which only runs
+    /// if it is needed
+    UnwrapOr,
 }
 
 #[derive(Debug, Clone)]
@@ -236,9 +374,9 @@ pub struct BinaryOperator {
 #[derive(Debug, Clone)]
 pub struct BoundBinary {
     pub location: SourceLocation,
-    pub left: Rc<BoundNode>,
+    pub left: Arc<BoundNode>,
     pub operator: BinaryOperator,
-    pub right: Rc<BoundNode>,
+    pub right: Arc<BoundNode>,
 }
 
 impl BoundNodeTrait for BoundBinary {
@@ -249,6 +387,10 @@ impl BoundNodeTrait for BoundBinary {
     fn get_type(&self) -> Type {
         self.operator.result.clone()
     }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.left, &self.right]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -266,6 +408,10 @@ impl BoundNodeTrait for BoundName {
     fn get_type(&self) -> Type {
         self.resolved_expression.upgrade().unwrap().get_type()
     }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -282,13 +428,76 @@ impl BoundNodeTrait for BoundInteger {
     fn get_type(&self) -> Type {
         Type::Integer
     }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundFloat {
+    pub location: SourceLocation,
+    pub value: f64,
+}
+
+impl BoundNodeTrait for BoundFloat {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        Type::Float
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundString {
+    pub location: SourceLocation,
+    pub value: String,
+}
+
+impl BoundNodeTrait for BoundString {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        Type::String
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundNone {
+    pub location: SourceLocation,
+}
+
+impl BoundNodeTrait for BoundNone {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        Type::None
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct BoundCall {
     pub location: SourceLocation,
-    pub operand: Rc<BoundNode>,
-    pub arguments: Vec<Rc<BoundNode>>,
+    pub operand: Arc<BoundNode>,
+    pub arguments: Vec<Arc<BoundNode>>,
     pub proc_type: Type,
 }
 
@@ -298,24 +507,220 @@ impl BoundNodeTrait for BoundCall {
     }
 
     fn get_type(&self) -> Type {
-        self.proc_type.clone()
+        match &self.proc_type {
+            Type::Proc(proc_type) => *proc_type.return_type.clone(),
+            _ => unreachable!("the binder only allows calling procedures"),
+        }
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        std::iter::once(&self.operand)
+            .chain(self.arguments.iter())
+            .collect()
+    }
+}
+
+/// A builtin implemented in Rust
+#[derive(Debug, Clone)]
+pub struct BoundNative {
+    pub location: SourceLocation,
+    pub id: NativeId,
+}
+
+impl BoundNodeTrait for BoundNative {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        Type::Proc(get_native(self.id).get_type())
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundMemberAccess {
+    pub location: SourceLocation,
+    pub operand: Arc<BoundNode>,
+    pub name: String,
+    pub member_type: Type,
+}
+
+impl BoundNodeTrait for BoundMemberAccess {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        self.member_type.clone()
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.operand]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundAssignment {
+    pub location: SourceLocation,
+    pub name: String,
+    /// The `let` or `export` that defined the name
+    pub resolved_expression: Weak<BoundNode>,
+    pub value: Arc<BoundNode>,
+}
+
+impl BoundNodeTrait for BoundAssignment {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        self.value.get_type()
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.value]
+    }
+}
+
+/// Runs the arm whose integer is the value, or `default` if none of them are
+#[derive(Debug, Clone)]
+pub struct BoundMatch {
+    pub location: SourceLocation,
+    pub value: Arc<BoundNode>,
+    /// In source order, no two of them have the same integer
+    pub arms: Vec<(i64, Arc<BoundNode>)>,
+    /// The `_` arm, which always comes last
+    pub default: Arc<BoundNode>,
+    pub match_type: Type,
+}
+
+impl BoundNodeTrait for BoundMatch {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        self.match_type.clone()
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        std::iter::once(&self.value)
+            .chain(self.arms.iter().map(|(_, arm)| arm))
+            .chain(std::iter::once(&self.default))
+            .collect()
     }
 }
 
+/// Makes the block of an enum, each variant is its index
 #[derive(Debug, Clone)]
-pub struct BoundPrintInteger {
+pub struct BoundEnum {
     pub location: SourceLocation,
+    pub enum_type: EnumType,
 }
 
-impl BoundNodeTrait for BoundPrintInteger {
+impl BoundNodeTrait for BoundEnum {
     fn get_location(&self) -> SourceLocation {
         self.location.clone()
     }
 
     fn get_type(&self) -> Type {
-        Type::Proc(ProcType {
-            parameter_types: vec![Type::Integer],
-            return_type: Box::new(Type::Void),
-        })
+        Type::Block(self.enum_type.get_block_type())
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
+    }
+}
+
+/// Gives the block from `value`, marked so that storing over it in any variable is an error
+#[derive(Debug, Clone)]
+pub struct BoundFreeze {
+    pub location: SourceLocation,
+    pub value: Arc<BoundNode>,
+}
+
+impl BoundNodeTrait for BoundFreeze {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        self.value.get_type()
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.value]
+    }
+}
+
+/// Runs `value` as a task, which starts with a copy of each name that `value` uses from
+/// outside of it
+#[derive(Debug, Clone)]
+pub struct BoundSpawn {
+    pub location: SourceLocation,
+    pub value: Arc<BoundNode>,
+    /// The names and what they resolved to, in the order they are first used
+    pub captures: Vec<(String, Weak<BoundNode>)>,
+}
+
+impl BoundNodeTrait for BoundSpawn {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        Type::Task(Box::new(self.value.get_type()))
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.value]
+    }
+}
+
+/// Waits for the task to finish, giving its value
+#[derive(Debug, Clone)]
+pub struct BoundJoin {
+    pub location: SourceLocation,
+    pub operand: Arc<BoundNode>,
+}
+
+impl BoundNodeTrait for BoundJoin {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        match self.operand.get_type() {
+            Type::Task(value_type) => *value_type,
+            _ => unreachable!("the binder only allows joining tasks"),
+        }
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![&self.operand]
+    }
+}
+
+/// An expression that failed to bind, the error has already been reported
+#[derive(Debug, Clone)]
+pub struct BoundError {
+    pub location: SourceLocation,
+}
+
+impl BoundNodeTrait for BoundError {
+    fn get_location(&self) -> SourceLocation {
+        self.location.clone()
+    }
+
+    fn get_type(&self) -> Type {
+        Type::Error
+    }
+
+    fn children(&self) -> Vec<&Arc<BoundNode>> {
+        vec![]
     }
 }
@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
 
 #[derive(Debug, Clone)]
 pub enum Bytecode {
@@ -6,48 +10,377 @@ pub enum Bytecode {
     Push(BytecodeValue),
     Pop,
     Dup,
-    Call { argument_count: usize },
+    /// Calls the procedure below the top `argument_count` values
+    ///
+    /// The caller pushes the procedure and then the arguments from left to right, all of them
+    /// are replaced by the returned value. The callee starts with its own stack laid out as
+    /// described by `Frame`
+    Call {
+        argument_count: usize,
+    },
+    /// Calls a builtin directly, its arguments are on top of the stack in source order and
+    /// are replaced by the returned value
+    CallNative(NativeId),
     Return,
     Load(String),
     Store(String),
+    /// Replaces the top values, one for each name in order, with a block that exports them
+    MakeBlock(Vec<String>),
+    /// Replaces the block on top of the stack with one of its exports
+    LoadMember(String),
+    /// Replaces the procedure on top of the stack with a closure that starts with the current
+    /// values of these variables, changes to them afterwards are not seen by the closure. The
+    /// body of a `spawn` is made into one of these for its task
+    MakeClosure(Vec<String>),
+    /// Marks the block on top of the stack as frozen, the text says where for the error
+    Freeze(String),
+    /// Fails if the variable holds a frozen block, assignments do this before they store
+    CheckNotFrozen(String),
+    /// Replaces the procedure on top of the stack with the handle of a new task that runs it,
+    /// the task gets its first turn the next time the others do
+    Spawn,
+    /// Replaces the task handle on top of the stack with the value of the task, once the task
+    /// has finished
+    Join,
+    /// Continues at an index into the same bytecode
+    Jump(usize),
+    /// Pops an integer and jumps if it is 0, otherwise continues with the next instruction
+    JumpIfZero(usize),
+    /// Replaces the value on top of the stack with 1 if it is none, otherwise 0
+    IsNone,
     AddInteger,
     SubInteger,
     MulInteger,
     DivInteger,
     NegateInteger,
-    PrintInteger,
+    AndInteger,
+    OrInteger,
+    XorInteger,
+    NotInteger,
+    /// Replaces the two integers on top of the stack with 1 if they are the same, otherwise 0
+    EqualInteger,
+    /// Shifts the integer below the top one by the top one, see `execute::shift_left`
+    ShiftLeftInteger,
+    /// Shifts the integer below the top one by the top one, see `execute::shift_right`
+    ShiftRightInteger,
+    AddFloat,
+    SubFloat,
+    MulFloat,
+    DivFloat,
+    NegateFloat,
+    // superinstructions, see `peephole::fuse_superinstructions`
+    /// The same as `push <integer>` followed by `add`
+    AddConstInteger(i64),
+    /// The same as `load <a>`, `load <b>` and then `add`
+    AddLoadsInteger(String, String),
+    /// The same as `dup` followed by `store <name>`
+    DupStore(String),
+}
+
+/// An index into `natives::NATIVES`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NativeId(pub usize);
+
+/// The layout of the stack a procedure starts with
+///
+/// Index 0 is a void value that is returned if the procedure returns without pushing
+/// anything, then the arguments follow in source order so the last argument is on top
+pub struct Frame;
+
+impl Frame {
+    /// The index of the first argument on a new stack
+    pub const FIRST_ARGUMENT: usize = 1;
 }
 
 #[derive(Debug, Clone)]
 pub enum BytecodeValue {
     Void,
+    /// An optional without a value, an optional with one is just that value
+    None,
     Integer(i64),
-    Procedure(Vec<Bytecode>),
-    Block(HashMap<String, BytecodeValue>),
+    Float(f64),
+    String(Rc<str>),
+    Procedure(Rc<[Bytecode]>),
+    Native(NativeId),
+    /// The members are shared with wherever else their values are, copying a block doesn't
+    /// copy what is nested in it
+    Block(HashMap<String, VmValue>),
+}
+
+/// Integers, strings, builtins and blocks compare by value, procedures compare by identity
+/// because two different procedures can behave the same. Floats compare by their bits so a
+/// nan is still equal to itself
+impl PartialEq for BytecodeValue {
+    fn eq(&self, other: &BytecodeValue) -> bool {
+        match (self, other) {
+            (BytecodeValue::Void, BytecodeValue::Void) => true,
+            (BytecodeValue::None, BytecodeValue::None) => true,
+            (BytecodeValue::Integer(a), BytecodeValue::Integer(b)) => a == b,
+            (BytecodeValue::Float(a), BytecodeValue::Float(b)) => a.to_bits() == b.to_bits(),
+            (BytecodeValue::String(a), BytecodeValue::String(b)) => a == b,
+            (BytecodeValue::Procedure(a), BytecodeValue::Procedure(b)) => Rc::ptr_eq(a, b),
+            (BytecodeValue::Native(a), BytecodeValue::Native(b)) => a == b,
+            (BytecodeValue::Block(a), BytecodeValue::Block(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for BytecodeValue {}
+
+impl Hash for BytecodeValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            BytecodeValue::Void | BytecodeValue::None => {}
+            BytecodeValue::Integer(integer) => integer.hash(state),
+            BytecodeValue::Float(float) => float.to_bits().hash(state),
+            BytecodeValue::String(string) => string.hash(state),
+            BytecodeValue::Procedure(procedure) => Rc::as_ptr(procedure).cast::<()>().hash(state),
+            BytecodeValue::Native(id) => id.hash(state),
+            // members have no order, so only hash what every equal block agrees on
+            BytecodeValue::Block(block) => block.len().hash(state),
+        }
+    }
 }
 
 impl BytecodeValue {
-    pub fn unwrap_integer(&self) -> &i64 {
+    pub fn as_integer(&self) -> Option<&i64> {
         if let BytecodeValue::Integer(integer) = self {
-            integer
+            Some(integer)
         } else {
-            unreachable!()
+            None
         }
     }
 
-    pub fn unwrap_procedure(&self) -> &Vec<Bytecode> {
+    pub fn as_float(&self) -> Option<&f64> {
+        if let BytecodeValue::Float(float) = self {
+            Some(float)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&Rc<str>> {
+        if let BytecodeValue::String(string) = self {
+            Some(string)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_procedure(&self) -> Option<&Rc<[Bytecode]>> {
         if let BytecodeValue::Procedure(procedure) = self {
-            procedure
+            Some(procedure)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_native(&self) -> Option<&NativeId> {
+        if let BytecodeValue::Native(id) = self {
+            Some(id)
         } else {
-            unreachable!()
+            None
         }
     }
 
-    pub fn unwrap_block(&self) -> &HashMap<String, BytecodeValue> {
+    pub fn as_block(&self) -> Option<&HashMap<String, VmValue>> {
         if let BytecodeValue::Block(block) = self {
-            block
+            Some(block)
         } else {
-            unreachable!()
+            None
+        }
+    }
+
+    /// Panics if it is not an integer
+    pub fn unwrap_integer(&self) -> &i64 {
+        self.as_integer().expect("expected an integer")
+    }
+
+    /// Panics if it is not a float
+    pub fn unwrap_float(&self) -> &f64 {
+        self.as_float().expect("expected a float")
+    }
+
+    /// Panics if it is not a string
+    pub fn unwrap_string(&self) -> &Rc<str> {
+        self.as_string().expect("expected a string")
+    }
+
+    /// Panics if it is not a procedure
+    pub fn unwrap_procedure(&self) -> &Rc<[Bytecode]> {
+        self.as_procedure().expect("expected a procedure")
+    }
+
+    /// Panics if it is not a block
+    pub fn unwrap_block(&self) -> &HashMap<String, VmValue> {
+        self.as_block().expect("expected a block")
+    }
+}
+
+// these give the value back if it is something else
+impl TryFrom<BytecodeValue> for i64 {
+    type Error = BytecodeValue;
+
+    fn try_from(value: BytecodeValue) -> Result<Self, Self::Error> {
+        match value {
+            BytecodeValue::Integer(integer) => Ok(integer),
+            value => Err(value),
+        }
+    }
+}
+
+impl TryFrom<BytecodeValue> for f64 {
+    type Error = BytecodeValue;
+
+    fn try_from(value: BytecodeValue) -> Result<Self, Self::Error> {
+        match value {
+            BytecodeValue::Float(float) => Ok(float),
+            value => Err(value),
+        }
+    }
+}
+
+impl TryFrom<BytecodeValue> for Rc<str> {
+    type Error = BytecodeValue;
+
+    fn try_from(value: BytecodeValue) -> Result<Self, Self::Error> {
+        match value {
+            BytecodeValue::String(string) => Ok(string),
+            value => Err(value),
+        }
+    }
+}
+
+impl TryFrom<BytecodeValue> for Rc<[Bytecode]> {
+    type Error = BytecodeValue;
+
+    fn try_from(value: BytecodeValue) -> Result<Self, Self::Error> {
+        match value {
+            BytecodeValue::Procedure(procedure) => Ok(procedure),
+            value => Err(value),
+        }
+    }
+}
+
+impl TryFrom<BytecodeValue> for NativeId {
+    type Error = BytecodeValue;
+
+    fn try_from(value: BytecodeValue) -> Result<Self, Self::Error> {
+        match value {
+            BytecodeValue::Native(id) => Ok(id),
+            value => Err(value),
+        }
+    }
+}
+
+impl TryFrom<BytecodeValue> for HashMap<String, VmValue> {
+    type Error = BytecodeValue;
+
+    fn try_from(value: BytecodeValue) -> Result<Self, Self::Error> {
+        match value {
+            BytecodeValue::Block(block) => Ok(block),
+            value => Err(value),
+        }
+    }
+}
+
+/// How the vm keeps a value on its stacks and in its variables
+///
+/// Void, none, integers, floats and builtins are stored inline so pushing them and doing arithmetic
+/// never allocates, strings, procedures and blocks are shared behind an `Rc`. Values never
+/// change once they are made, so sharing one between stack slots and variables is fine
+#[derive(Debug, Clone)]
+pub enum VmValue {
+    Void,
+    None,
+    Integer(i64),
+    Float(f64),
+    Native(NativeId),
+    /// Only ever a string, procedure or block, `VmValue::from` keeps everything else inline
+    Object(Rc<BytecodeValue>),
+}
+
+/// Compares the same way as `BytecodeValue`
+impl PartialEq for VmValue {
+    fn eq(&self, other: &VmValue) -> bool {
+        match (self, other) {
+            (VmValue::Void, VmValue::Void) => true,
+            (VmValue::None, VmValue::None) => true,
+            (VmValue::Integer(a), VmValue::Integer(b)) => a == b,
+            (VmValue::Float(a), VmValue::Float(b)) => a.to_bits() == b.to_bits(),
+            (VmValue::Native(a), VmValue::Native(b)) => a == b,
+            (VmValue::Object(a), VmValue::Object(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for VmValue {}
+
+impl VmValue {
+    pub fn as_integer(&self) -> Option<i64> {
+        if let VmValue::Integer(integer) = self {
+            Some(*integer)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        if let VmValue::Float(float) = self {
+            Some(*float)
+        } else {
+            None
+        }
+    }
+
+    /// The string, procedure or block, if it is one
+    pub fn as_object(&self) -> Option<&BytecodeValue> {
+        if let VmValue::Object(object) = self {
+            Some(object)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_bytecode_value(&self) -> BytecodeValue {
+        match self {
+            VmValue::Void => BytecodeValue::Void,
+            VmValue::None => BytecodeValue::None,
+            VmValue::Integer(integer) => BytecodeValue::Integer(*integer),
+            VmValue::Float(float) => BytecodeValue::Float(*float),
+            VmValue::Native(id) => BytecodeValue::Native(*id),
+            VmValue::Object(object) => BytecodeValue::clone(object),
+        }
+    }
+}
+
+impl From<BytecodeValue> for VmValue {
+    fn from(value: BytecodeValue) -> Self {
+        match value {
+            BytecodeValue::Void => VmValue::Void,
+            BytecodeValue::None => VmValue::None,
+            BytecodeValue::Integer(integer) => VmValue::Integer(integer),
+            BytecodeValue::Float(float) => VmValue::Float(float),
+            BytecodeValue::Native(id) => VmValue::Native(id),
+            object @ (BytecodeValue::String(_)
+            | BytecodeValue::Procedure(_)
+            | BytecodeValue::Block(_)) => VmValue::Object(Rc::new(object)),
+        }
+    }
+}
+
+impl From<&BytecodeValue> for VmValue {
+    fn from(value: &BytecodeValue) -> Self {
+        match value {
+            BytecodeValue::Void => VmValue::Void,
+            BytecodeValue::None => VmValue::None,
+            BytecodeValue::Integer(integer) => VmValue::Integer(*integer),
+            BytecodeValue::Float(float) => VmValue::Float(*float),
+            BytecodeValue::Native(id) => VmValue::Native(*id),
+            object => VmValue::Object(Rc::new(object.clone())),
         }
     }
 }
@@ -1,120 +1,561 @@
-use std::rc::Rc;
+use std::{
+    collections::HashSet,
+    rc::Rc,
+    sync::{Arc, Weak},
+};
 
 use crate::{
     bound_nodes::{
-        BinaryOperatorKind, BoundBinary, BoundBlock, BoundCall, BoundExport, BoundInteger,
-        BoundLet, BoundName, BoundNode, BoundNodeTrait, BoundPrintInteger, BoundUnary,
+        dispatch_bound_node, BinaryOperatorKind, BoundAssignment, BoundBinary, BoundBlock,
+        BoundCall, BoundDestructure, BoundEnum, BoundError, BoundExport, BoundFloat, BoundFreeze,
+        BoundInteger, BoundJoin, BoundLet, BoundMatch, BoundMemberAccess, BoundName, BoundNative,
+        BoundNode, BoundNodeTrait, BoundNone, BoundSpawn, BoundString, BoundUnary,
         UnaryOperatorKind,
     },
-    bytecode::{Bytecode, BytecodeValue},
+    bytecode::{Bytecode, BytecodeValue, NativeId},
+    common::{CancellationToken, CompileError},
+    constant::evaluate_constant,
+    ice::{track, Phase},
+    peephole::fuse_superinstructions,
+    types::Type,
 };
 
+/// How hard the compiler tries to make the bytecode faster, each level also does everything
+/// the ones below it do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptimizationLevel {
+    /// Every expression is compiled as it is written
+    #[default]
+    None,
+    /// Expressions made of only literals and operators are replaced with their value
+    FoldConstants,
+    /// Common instruction sequences are fused into one instruction, see
+    /// `peephole::fuse_superinstructions`
+    Superinstructions,
+}
+
+impl OptimizationLevel {
+    pub const ALL: [OptimizationLevel; 3] = [
+        OptimizationLevel::None,
+        OptimizationLevel::FoldConstants,
+        OptimizationLevel::Superinstructions,
+    ];
+
+    /// The level for `--opt-level`, which counts up from 0
+    pub fn from_number(number: usize) -> Option<OptimizationLevel> {
+        OptimizationLevel::ALL.get(number).copied()
+    }
+}
+
 trait Compilable: BoundNodeTrait {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>);
+    fn compile(&self, code: &mut Code);
+}
+
+// where the instructions go, along with the line of the node that made each of them
+struct Code<'a> {
+    bytecode: &'a mut Vec<Bytecode>,
+    lines: Option<&'a mut Vec<usize>>,
+    line: usize,
+    optimizations: OptimizationLevel,
+    cancellation: Option<&'a CancellationToken>,
+    /// Every string literal that has been pushed, so identical ones share one allocation
+    strings: &'a mut HashSet<Rc<str>>,
+}
+
+impl Code<'_> {
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .is_some_and(|cancellation| cancellation.is_cancelled())
+    }
+
+    fn push(&mut self, instruction: Bytecode) {
+        self.bytecode.push(instruction);
+        if let Some(lines) = &mut self.lines {
+            lines.push(self.line);
+        }
+    }
+
+    /// Pushes a jump whose target is not known yet, it has to be given one with `patch_jump`
+    fn push_jump(&mut self, jump: fn(usize) -> Bytecode) -> usize {
+        self.push(jump(usize::MAX));
+        self.bytecode.len() - 1
+    }
+
+    /// Points the jump at `index` to the next instruction that gets pushed
+    fn patch_jump(&mut self, index: usize) {
+        let target = self.bytecode.len();
+        match &mut self.bytecode[index] {
+            Bytecode::Jump(jump_target) | Bytecode::JumpIfZero(jump_target) => {
+                *jump_target = target
+            }
+            _ => unreachable!("only jumps can be patched"),
+        }
+    }
+}
+
+/// Compiles a bound tree, the binder has already limited how deeply it can be nested
+///
+/// Expressions are evaluated left to right, the left operand of a binary operator before the
+/// right one and a called procedure before its arguments, which are evaluated in source order.
+/// Anything that rearranges the generated code has to keep that order whenever either side
+/// could print or fail
+pub fn compile_bytecode(node: &Arc<BoundNode>, bytecode: &mut Vec<Bytecode>) {
+    node.compile(&mut Code {
+        bytecode,
+        lines: None,
+        line: 0,
+        optimizations: OptimizationLevel::None,
+        cancellation: None,
+        strings: &mut HashSet::new(),
+    });
+}
+
+/// Also pushes the source line of every instruction to `lines`
+pub fn compile_bytecode_with_lines(
+    node: &Arc<BoundNode>,
+    bytecode: &mut Vec<Bytecode>,
+    lines: &mut Vec<usize>,
+) {
+    compile_optimized_bytecode(node, bytecode, lines, OptimizationLevel::None);
+}
+
+/// `compile_bytecode_with_lines`, also applying the optimizations of `optimizations`
+pub fn compile_optimized_bytecode(
+    node: &Arc<BoundNode>,
+    bytecode: &mut Vec<Bytecode>,
+    lines: &mut Vec<usize>,
+    optimizations: OptimizationLevel,
+) {
+    compile_optimized_bytecode_with_cancellation(
+        node,
+        bytecode,
+        lines,
+        optimizations,
+        &CancellationToken::new(),
+    )
+    .expect("a new token is never cancelled");
 }
 
-pub fn compile_bytecode(node: &Rc<BoundNode>, bytecode: &mut Vec<Bytecode>) {
-    node.compile(bytecode);
+/// `compile_optimized_bytecode`, giving up with an error if `cancellation` is cancelled part
+/// way through, the bytecode is left unfinished then
+pub fn compile_optimized_bytecode_with_cancellation(
+    node: &Arc<BoundNode>,
+    bytecode: &mut Vec<Bytecode>,
+    lines: &mut Vec<usize>,
+    optimizations: OptimizationLevel,
+    cancellation: &CancellationToken,
+) -> Result<(), CompileError> {
+    node.compile(&mut Code {
+        bytecode,
+        lines: Some(lines),
+        line: 0,
+        optimizations,
+        cancellation: Some(cancellation),
+        strings: &mut HashSet::new(),
+    });
+    cancellation.check_compile(&node.get_location())?;
+    if optimizations >= OptimizationLevel::Superinstructions {
+        fuse_superinstructions(bytecode, lines, cancellation);
+    }
+    cancellation.check_compile(&node.get_location())
 }
 
 impl Compilable for BoundNode {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        match self {
-            BoundNode::Block(block) => block.compile(bytecode),
-            BoundNode::Export(export) => export.compile(bytecode),
-            BoundNode::Let(lett) => lett.compile(bytecode),
-            BoundNode::Unary(unary) => unary.compile(bytecode),
-            BoundNode::Binary(binary) => binary.compile(bytecode),
-            BoundNode::Name(name) => name.compile(bytecode),
-            BoundNode::Integer(integer) => integer.compile(bytecode),
-            BoundNode::Call(call) => call.compile(bytecode),
-            BoundNode::PrintInteger(print_integer) => print_integer.compile(bytecode),
+    fn compile(&self, code: &mut Code) {
+        let _context = track(Phase::Compiling, self.get_location());
+        // the bytecode is thrown away once it is cancelled, so the rest is not compiled
+        if code.is_cancelled() {
+            return;
         }
+        let outer_line = code.line;
+        code.line = self.get_location().line;
+        match fold_constant(self, code) {
+            Some(value) => code.push(Bytecode::Push(BytecodeValue::Integer(value))),
+            None => dispatch_bound_node!(self, node => node.compile(code)),
+        }
+        code.line = outer_line;
     }
 }
 
+// literals are already as small as they get, and constants can never print or fail so
+// folding them can't change the order anything happens in
+fn fold_constant(node: &BoundNode, code: &Code) -> Option<i64> {
+    if code.optimizations < OptimizationLevel::FoldConstants
+        || !matches!(node, BoundNode::Unary(_) | BoundNode::Binary(_))
+        || code.is_cancelled()
+    {
+        return None;
+    }
+    // the binder already reported the ones that overflow
+    evaluate_constant(node).ok().flatten()
+}
+
 impl Compilable for BoundBlock {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        for expression in &self.expressions {
-            expression.compile(bytecode);
-            bytecode.push(Bytecode::Pop);
+    fn compile(&self, code: &mut Code) {
+        for (index, expression) in self.expressions.iter().enumerate() {
+            expression.compile(code);
+            if self.evaluates_to_last && index == self.expressions.len() - 1 {
+                // the value of the last expression is the value of the block
+                return;
+            }
+            // the pop belongs to the expression, so it doesn't count as running the block's line
+            let block_line = code.line;
+            code.line = expression.get_location().line;
+            code.push(Bytecode::Pop);
+            code.line = block_line;
         }
+
+        // the exports are still in their variables, so they are loaded back into the block value
+        let mut names: Vec<_> = self.exported_expressions.keys().cloned().collect();
+        names.sort();
+        for name in &names {
+            code.push(Bytecode::Load(name.clone()));
+        }
+        code.push(Bytecode::MakeBlock(names));
     }
 }
 
 impl Compilable for BoundExport {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        self.value.compile(bytecode);
-        bytecode.push(Bytecode::Dup);
-        bytecode.push(Bytecode::Store(self.name.clone()));
+    fn compile(&self, code: &mut Code) {
+        self.value.compile(code);
+        code.push(Bytecode::Dup);
+        code.push(Bytecode::Store(self.name.clone()));
     }
 }
 
 impl Compilable for BoundLet {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
+    fn compile(&self, code: &mut Code) {
         if let Some(value) = &self.value {
-            value.compile(bytecode);
-            bytecode.push(Bytecode::Dup);
+            value.compile(code);
         } else {
-            bytecode.push(Bytecode::Push(BytecodeValue::Void));
+            code.push(Bytecode::Push(BytecodeValue::Void));
+        }
+        code.push(Bytecode::Dup);
+        code.push(Bytecode::Store(self.get_variable()));
+    }
+}
+
+// the variable a name resolved to its definition is kept in, only a let that shadows another
+// one keeps it somewhere other than its name
+fn variable_of(resolved_expression: &Weak<BoundNode>, name: &str) -> String {
+    match resolved_expression.upgrade().as_deref() {
+        Some(BoundNode::Let(lett)) => lett.get_variable(),
+        _ => name.to_string(),
+    }
+}
+
+// the let of the value leaves it on the stack as the result, each binding loads its member
+// from the variable the value was stored in
+impl Compilable for BoundDestructure {
+    fn compile(&self, code: &mut Code) {
+        self.value.compile(code);
+        for binding in &self.bindings {
+            binding.compile(code);
+            code.push(Bytecode::Pop);
         }
-        bytecode.push(Bytecode::Store(self.name.clone()));
     }
 }
 
 impl Compilable for BoundUnary {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        self.operand.compile(bytecode);
+    fn compile(&self, code: &mut Code) {
+        self.operand.compile(code);
         match &self.operator.kind {
             UnaryOperatorKind::Identity => {}
-            UnaryOperatorKind::Negation => bytecode.push(Bytecode::NegateInteger),
+            UnaryOperatorKind::Negation if self.operator.operand == Type::Float => {
+                code.push(Bytecode::NegateFloat)
+            }
+            UnaryOperatorKind::Negation => code.push(Bytecode::NegateInteger),
+            UnaryOperatorKind::BitwiseNot => code.push(Bytecode::NotInteger),
+            UnaryOperatorKind::MakeOptional => {}
         }
     }
 }
 
 impl Compilable for BoundBinary {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        self.left.compile(bytecode);
-        self.right.compile(bytecode);
+    fn compile(&self, code: &mut Code) {
+        if let BinaryOperatorKind::LogicalAnd | BinaryOperatorKind::LogicalOr = self.operator.kind {
+            compile_logical(self, code);
+            return;
+        }
+        if let BinaryOperatorKind::UnwrapOr = self.operator.kind {
+            compile_unwrap_or(self, code);
+            return;
+        }
+
+        self.left.compile(code);
+        self.right.compile(code);
+        // the float operators share their kinds with the integer ones
+        if self.operator.left == Type::Float {
+            match &self.operator.kind {
+                BinaryOperatorKind::Addition => code.push(Bytecode::AddFloat),
+                BinaryOperatorKind::Subtraction => code.push(Bytecode::SubFloat),
+                BinaryOperatorKind::Multiplication => code.push(Bytecode::MulFloat),
+                BinaryOperatorKind::Division => code.push(Bytecode::DivFloat),
+                kind => unreachable!("there is no float operator for {:?}", kind),
+            }
+            return;
+        }
         match &self.operator.kind {
-            BinaryOperatorKind::Addition => bytecode.push(Bytecode::AddInteger),
-            BinaryOperatorKind::Subtraction => bytecode.push(Bytecode::SubInteger),
-            BinaryOperatorKind::Multiplication => bytecode.push(Bytecode::MulInteger),
-            BinaryOperatorKind::Division => bytecode.push(Bytecode::DivInteger),
+            BinaryOperatorKind::Addition => code.push(Bytecode::AddInteger),
+            BinaryOperatorKind::Subtraction => code.push(Bytecode::SubInteger),
+            BinaryOperatorKind::Multiplication => code.push(Bytecode::MulInteger),
+            BinaryOperatorKind::Division => code.push(Bytecode::DivInteger),
+            BinaryOperatorKind::BitwiseAnd => code.push(Bytecode::AndInteger),
+            BinaryOperatorKind::BitwiseOr => code.push(Bytecode::OrInteger),
+            BinaryOperatorKind::BitwiseXor => code.push(Bytecode::XorInteger),
+            BinaryOperatorKind::ShiftLeft => code.push(Bytecode::ShiftLeftInteger),
+            BinaryOperatorKind::ShiftRight => code.push(Bytecode::ShiftRightInteger),
+            BinaryOperatorKind::Equal => code.push(Bytecode::EqualInteger),
+            // there is no instruction for it, so it is whether the result of `==` is 0
+            BinaryOperatorKind::NotEqual => {
+                code.push(Bytecode::EqualInteger);
+                code.push(Bytecode::Push(BytecodeValue::Integer(0)));
+                code.push(Bytecode::EqualInteger);
+            }
+            BinaryOperatorKind::LogicalAnd | BinaryOperatorKind::LogicalOr => {
+                unreachable!("compiled by compile_logical")
+            }
+            BinaryOperatorKind::UnwrapOr => unreachable!("compiled by compile_unwrap_or"),
         }
     }
 }
 
+// the right operand is skipped when the left one already decides the result, which is pushed
+// as 0 or 1 at the end. for `||` a left operand of 0 jumps over the `push 1` to the right one
+fn compile_logical(binary: &BoundBinary, code: &mut Code) {
+    let mut jumps_to_false = vec![];
+    let mut jumps_to_end = vec![];
+
+    binary.left.compile(code);
+    jumps_to_false.push(code.push_jump(Bytecode::JumpIfZero));
+    if let BinaryOperatorKind::LogicalOr = binary.operator.kind {
+        // the jump above goes to the right operand instead
+        let jump_to_right = jumps_to_false.pop().unwrap();
+        code.push(Bytecode::Push(BytecodeValue::Integer(1)));
+        jumps_to_end.push(code.push_jump(Bytecode::Jump));
+        code.patch_jump(jump_to_right);
+    }
+    binary.right.compile(code);
+    jumps_to_false.push(code.push_jump(Bytecode::JumpIfZero));
+    code.push(Bytecode::Push(BytecodeValue::Integer(1)));
+    jumps_to_end.push(code.push_jump(Bytecode::Jump));
+
+    for jump in jumps_to_false {
+        code.patch_jump(jump);
+    }
+    code.push(Bytecode::Push(BytecodeValue::Integer(0)));
+    for jump in jumps_to_end {
+        code.patch_jump(jump);
+    }
+}
+
+// each arm compares a copy of the value with its integer, xor gives 0 when they are the same
+// and can't overflow the way subtracting could. the value is popped once an arm is picked
+impl Compilable for BoundMatch {
+    fn compile(&self, code: &mut Code) {
+        self.value.compile(code);
+        let mut jumps_to_arms = vec![];
+        for (integer, _) in &self.arms {
+            code.push(Bytecode::Dup);
+            code.push(Bytecode::Push(BytecodeValue::Integer(*integer)));
+            code.push(Bytecode::XorInteger);
+            jumps_to_arms.push(code.push_jump(Bytecode::JumpIfZero));
+        }
+
+        let mut jumps_to_end = vec![];
+        code.push(Bytecode::Pop);
+        self.default.compile(code);
+        for (jump, (_, arm)) in jumps_to_arms.into_iter().zip(&self.arms) {
+            jumps_to_end.push(code.push_jump(Bytecode::Jump));
+            code.patch_jump(jump);
+            code.push(Bytecode::Pop);
+            arm.compile(code);
+        }
+        for jump in jumps_to_end {
+            code.patch_jump(jump);
+        }
+    }
+}
+
+// the optional stays on the stack as the result unless it is none, then it is replaced by
+// the right operand
+fn compile_unwrap_or(binary: &BoundBinary, code: &mut Code) {
+    binary.left.compile(code);
+    code.push(Bytecode::Dup);
+    code.push(Bytecode::IsNone);
+    let jump_to_end = code.push_jump(Bytecode::JumpIfZero);
+    code.push(Bytecode::Pop);
+    binary.right.compile(code);
+    code.patch_jump(jump_to_end);
+}
+
 impl Compilable for BoundName {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        bytecode.push(Bytecode::Load(self.name.clone()));
+    fn compile(&self, code: &mut Code) {
+        code.push(Bytecode::Load(variable_of(
+            &self.resolved_expression,
+            &self.name,
+        )));
     }
 }
 
 impl Compilable for BoundInteger {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        bytecode.push(Bytecode::Push(BytecodeValue::Integer(self.value as i64)));
+    fn compile(&self, code: &mut Code) {
+        code.push(Bytecode::Push(BytecodeValue::Integer(self.value as i64)));
+    }
+}
+
+impl Compilable for BoundFloat {
+    fn compile(&self, code: &mut Code) {
+        code.push(Bytecode::Push(BytecodeValue::Float(self.value)));
+    }
+}
+
+impl Compilable for BoundString {
+    fn compile(&self, code: &mut Code) {
+        let string = match code.strings.get(self.value.as_str()) {
+            Some(string) => string.clone(),
+            None => {
+                let string: Rc<str> = self.value.as_str().into();
+                code.strings.insert(string.clone());
+                string
+            }
+        };
+        code.push(Bytecode::Push(BytecodeValue::String(string)));
+    }
+}
+
+impl Compilable for BoundNone {
+    fn compile(&self, code: &mut Code) {
+        code.push(Bytecode::Push(BytecodeValue::None));
     }
 }
 
 impl Compilable for BoundCall {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        self.operand.compile(bytecode);
+    fn compile(&self, code: &mut Code) {
+        // builtins called by name don't need to be loaded first
+        if let Some(id) = direct_native(&self.operand) {
+            for argument in &self.arguments {
+                argument.compile(code);
+            }
+            code.push(Bytecode::CallNative(id));
+            return;
+        }
+
+        self.operand.compile(code);
         for argument in &self.arguments {
-            argument.compile(bytecode);
+            argument.compile(code);
         }
-        bytecode.push(Bytecode::Call {
+        code.push(Bytecode::Call {
             argument_count: self.arguments.len(),
         });
     }
 }
 
-impl Compilable for BoundPrintInteger {
-    fn compile(&self, bytecode: &mut Vec<Bytecode>) {
-        // TODO: Maybe dont create a new function every time print_integer is referenced
-        bytecode.push(Bytecode::Push(BytecodeValue::Procedure(Vec::from([
-            Bytecode::PrintInteger,
-            Bytecode::Return,
-        ]))));
+/// The builtin that calling `operand` always calls, so the call can go straight to it
+pub(crate) fn direct_native(operand: &BoundNode) -> Option<NativeId> {
+    match operand {
+        BoundNode::Native(native) => Some(native.id),
+        BoundNode::Name(name) => match &name.resolved_expression.upgrade()? as &BoundNode {
+            BoundNode::Native(native) => Some(native.id),
+            _ => None,
+        },
+        // a member of a namespace like `io.print_integer`, namespaces can't be reassigned
+        BoundNode::MemberAccess(member) => {
+            let BoundNode::Name(name) = &member.operand as &BoundNode else {
+                return None;
+            };
+            match &name.resolved_expression.upgrade()? as &BoundNode {
+                BoundNode::Block(block) if block.is_namespace() => {
+                    match &block.exported_expressions.get(&member.name)?.upgrade()? as &BoundNode {
+                        BoundNode::Native(native) => Some(native.id),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl Compilable for BoundMemberAccess {
+    fn compile(&self, code: &mut Code) {
+        self.operand.compile(code);
+        code.push(Bytecode::LoadMember(self.name.clone()));
+    }
+}
+
+impl Compilable for BoundAssignment {
+    fn compile(&self, code: &mut Code) {
+        // the same variable as the definition, so it is just overwritten
+        self.value.compile(code);
+        let variable = variable_of(&self.resolved_expression, &self.name);
+        code.push(Bytecode::CheckNotFrozen(variable.clone()));
+        code.push(Bytecode::Dup);
+        code.push(Bytecode::Store(variable));
+    }
+}
+
+impl Compilable for BoundFreeze {
+    fn compile(&self, code: &mut Code) {
+        self.value.compile(code);
+        code.push(Bytecode::Freeze(self.location.to_string()));
+    }
+}
+
+// variants are just their index, so the block of the enum is made like any other
+impl Compilable for BoundEnum {
+    fn compile(&self, code: &mut Code) {
+        for index in 0..self.enum_type.variants.len() {
+            code.push(Bytecode::Push(BytecodeValue::Integer(index as i64)));
+        }
+        code.push(Bytecode::MakeBlock(self.enum_type.variants.clone()));
+    }
+}
+
+impl Compilable for BoundError {
+    fn compile(&self, _code: &mut Code) {
+        unreachable!("programs with errors are never compiled")
+    }
+}
+
+impl Compilable for BoundNative {
+    fn compile(&self, code: &mut Code) {
+        code.push(Bytecode::Push(BytecodeValue::Native(self.id)));
+    }
+}
+
+// the task runs the value as a procedure of its own, starting with copies of what it uses
+impl Compilable for BoundSpawn {
+    fn compile(&self, code: &mut Code) {
+        let mut body = vec![];
+        self.value.compile(&mut Code {
+            bytecode: &mut body,
+            lines: None,
+            line: code.line,
+            optimizations: code.optimizations,
+            cancellation: code.cancellation,
+            strings: &mut *code.strings,
+        });
+        body.push(Bytecode::Return);
+        code.push(Bytecode::Push(BytecodeValue::Procedure(body.into())));
+        if !self.captures.is_empty() {
+            let mut variables: Vec<String> = vec![];
+            for (name, resolved_expression) in &self.captures {
+                let variable = variable_of(resolved_expression, name);
+                if !variables.contains(&variable) {
+                    variables.push(variable);
+                }
+            }
+            code.push(Bytecode::MakeClosure(variables));
+        }
+        code.push(Bytecode::Spawn);
+    }
+}
+
+impl Compilable for BoundJoin {
+    fn compile(&self, code: &mut Code) {
+        self.operand.compile(code);
+        code.push(Bytecode::Join);
     }
 }
@@ -0,0 +1,442 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{
+    bytecode::{Bytecode, BytecodeValue, NativeId},
+    lexer::{quote_string, Lexer},
+    natives::{find_native, get_native},
+    token::{StringToken, TokenKind},
+    types::Type,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BytecodeParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Writes bytecode as text, one instruction per line
+///
+/// Procedures are written as `push proc` followed by their indented body and `end`,
+/// everything after a `#` is a comment. Jump targets are the index of an instruction in
+/// the same procedure, counting from 0. The builtins the bytecode uses are declared at the
+/// top as `builtin <name>: <type>`, so loading it somewhere they are missing fails up front
+pub fn format_bytecode(bytecode: &[Bytecode]) -> String {
+    let mut result = String::new();
+    let mut natives = BTreeSet::new();
+    collect_natives(bytecode, &mut natives);
+    for id in natives {
+        let native = get_native(id);
+        writeln!(
+            result,
+            "builtin {}: {}",
+            native.full_name(),
+            Type::Proc(native.get_type())
+        )
+        .unwrap();
+    }
+    format_instructions(bytecode, 0, &mut result);
+    result
+}
+
+/// Writes the bytecode with the source line that each run of instructions came from above
+/// it, so it shows how the code was lowered
+///
+/// `lines` has the line of every instruction like `Program::get_lines`, the instructions of
+/// line 0 came from no line, like the builtins and the end of the program. Each instruction
+/// starts with its index, procedures are written with their body like in `format_bytecode`
+pub fn explain_bytecode(bytecode: &[Bytecode], lines: &[usize], source: &str) -> String {
+    let source_lines: Vec<&str> = source.lines().collect();
+    let mut result = String::new();
+    let mut current_line = None;
+    for (index, instruction) in bytecode.iter().enumerate() {
+        let line = lines.get(index).copied().unwrap_or(0);
+        if current_line != Some(line) {
+            current_line = Some(line);
+            match line
+                .checked_sub(1)
+                .and_then(|index| source_lines.get(index))
+            {
+                Some(text) => writeln!(result, "# {}: {}", line, text.trim()).unwrap(),
+                None => writeln!(result, "# no source line").unwrap(),
+            }
+        }
+
+        let mut text = String::new();
+        format_instructions(std::slice::from_ref(instruction), 0, &mut text);
+        for (i, text_line) in text.lines().enumerate() {
+            if i == 0 {
+                writeln!(result, "{:>4}  {}", index, text_line).unwrap();
+            } else {
+                writeln!(result, "      {}", text_line).unwrap();
+            }
+        }
+    }
+    result
+}
+
+/// Writes one instruction on one line, a procedure is just `push proc` without its body
+pub fn format_instruction(instruction: &Bytecode) -> String {
+    let mut text = String::new();
+    format_instructions(std::slice::from_ref(instruction), 0, &mut text);
+    text.lines().next().unwrap_or_default().to_string()
+}
+
+fn collect_natives(bytecode: &[Bytecode], natives: &mut BTreeSet<NativeId>) {
+    for instruction in bytecode {
+        match instruction {
+            Bytecode::Push(BytecodeValue::Procedure(procedure)) => {
+                collect_natives(procedure, natives)
+            }
+            Bytecode::Push(BytecodeValue::Native(id)) | Bytecode::CallNative(id) => {
+                natives.insert(*id);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn format_instructions(bytecode: &[Bytecode], indent: usize, result: &mut String) {
+    for instruction in bytecode {
+        for _ in 0..indent {
+            *result += "    ";
+        }
+        match instruction {
+            Bytecode::Exit => *result += "exit",
+            Bytecode::Push(value) => match value {
+                BytecodeValue::Void => *result += "push void",
+                BytecodeValue::None => *result += "push none",
+                BytecodeValue::Integer(integer) => write!(result, "push {}", integer).unwrap(),
+                // debug formatting always has a `.` or an exponent, so it reads back as a float
+                BytecodeValue::Float(float) => write!(result, "push {:?}", float).unwrap(),
+                BytecodeValue::String(string) => {
+                    write!(result, "push {}", quote_string(string)).unwrap()
+                }
+                BytecodeValue::Procedure(procedure) => {
+                    *result += "push proc\n";
+                    format_instructions(procedure, indent + 1, result);
+                    for _ in 0..indent {
+                        *result += "    ";
+                    }
+                    *result += "end";
+                }
+                BytecodeValue::Native(id) => {
+                    write!(result, "push native {}", get_native(*id).full_name()).unwrap()
+                }
+                // the compiler never pushes constant blocks
+                BytecodeValue::Block(_) => *result += "push block # unsupported",
+            },
+            Bytecode::Pop => *result += "pop",
+            Bytecode::Dup => *result += "dup",
+            Bytecode::Call { argument_count } => write!(result, "call {}", argument_count).unwrap(),
+            Bytecode::CallNative(id) => {
+                write!(result, "call_native {}", get_native(*id).full_name()).unwrap()
+            }
+            Bytecode::Return => *result += "return",
+            Bytecode::Load(name) => write!(result, "load {}", name).unwrap(),
+            Bytecode::Store(name) => write!(result, "store {}", name).unwrap(),
+            Bytecode::MakeBlock(names) => {
+                *result += "make_block";
+                for name in names {
+                    write!(result, " {}", name).unwrap();
+                }
+            }
+            Bytecode::LoadMember(name) => write!(result, "load_member {}", name).unwrap(),
+            Bytecode::MakeClosure(names) => {
+                *result += "make_closure";
+                for name in names {
+                    write!(result, " {}", name).unwrap();
+                }
+            }
+            Bytecode::Freeze(site) => write!(result, "freeze {}", quote_string(site)).unwrap(),
+            Bytecode::CheckNotFrozen(name) => write!(result, "check_not_frozen {}", name).unwrap(),
+            Bytecode::Spawn => *result += "spawn",
+            Bytecode::Join => *result += "join",
+            Bytecode::Jump(target) => write!(result, "jump {}", target).unwrap(),
+            Bytecode::JumpIfZero(target) => write!(result, "jump_if_zero {}", target).unwrap(),
+            Bytecode::IsNone => *result += "is_none",
+            Bytecode::AddInteger => *result += "add",
+            Bytecode::SubInteger => *result += "sub",
+            Bytecode::MulInteger => *result += "mul",
+            Bytecode::DivInteger => *result += "div",
+            Bytecode::NegateInteger => *result += "negate",
+            Bytecode::AndInteger => *result += "and",
+            Bytecode::OrInteger => *result += "or",
+            Bytecode::XorInteger => *result += "xor",
+            Bytecode::NotInteger => *result += "not",
+            Bytecode::EqualInteger => *result += "eq",
+            Bytecode::ShiftLeftInteger => *result += "shl",
+            Bytecode::ShiftRightInteger => *result += "shr",
+            Bytecode::AddFloat => *result += "addf",
+            Bytecode::SubFloat => *result += "subf",
+            Bytecode::MulFloat => *result += "mulf",
+            Bytecode::DivFloat => *result += "divf",
+            Bytecode::NegateFloat => *result += "negatef",
+            Bytecode::AddConstInteger(integer) => write!(result, "add_const {}", integer).unwrap(),
+            Bytecode::AddLoadsInteger(a, b) => write!(result, "add_loads {} {}", a, b).unwrap(),
+            Bytecode::DupStore(name) => write!(result, "dup_store {}", name).unwrap(),
+        }
+        result.push('\n');
+    }
+}
+
+/// Reads bytecode written by `format_bytecode`
+pub fn parse_bytecode(text: &str) -> Result<Vec<Bytecode>, BytecodeParseError> {
+    parse_bytecode_module("<bytecode>", text)
+}
+
+/// `parse_bytecode`, naming the bytecode `module` when a builtin it needs is missing or has
+/// a different type in this runtime
+pub fn parse_bytecode_module(
+    module: &str,
+    text: &str,
+) -> Result<Vec<Bytecode>, BytecodeParseError> {
+    let lines: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, strip_comment(line).trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+    // every declared builtin is checked before any instruction, so nothing is half loaded
+    for &(line, text) in &lines {
+        if let Some(declaration) = text.strip_prefix("builtin ") {
+            check_builtin(module, declaration, line)?;
+        }
+    }
+    parse_instructions(&mut lines.into_iter(), module, false)
+}
+
+fn check_builtin(module: &str, declaration: &str, line: usize) -> Result<(), BytecodeParseError> {
+    let error = |message: String| BytecodeParseError { line, message };
+    let (name, declared_type) = declaration
+        .split_once(':')
+        .ok_or_else(|| error("builtin needs a name and a type".to_string()))?;
+    let native = get_native(find_native_or_error(module, name.trim(), line)?);
+    let actual_type = Type::Proc(native.get_type()).to_string();
+    // the type is compared as text, only the spacing can differ
+    let without_spaces = |text: &str| text.split_whitespace().collect::<String>();
+    if without_spaces(declared_type) != without_spaces(&actual_type) {
+        return Err(error(format!(
+            "Builtin {} has type {} but module {} requires {}",
+            native.full_name(),
+            actual_type,
+            module,
+            declared_type.trim()
+        )));
+    }
+    Ok(())
+}
+
+fn parse_instructions(
+    lines: &mut dyn Iterator<Item = (usize, &str)>,
+    module: &str,
+    in_procedure: bool,
+) -> Result<Vec<Bytecode>, BytecodeParseError> {
+    let mut bytecode = vec![];
+    let mut last_line = 0;
+    while let Some((line, text)) = lines.next() {
+        last_line = line;
+        let error = |message: String| BytecodeParseError { line, message };
+
+        if text.starts_with("builtin ") {
+            if in_procedure {
+                return Err(error(
+                    "Builtins can only be declared outside of procedures".to_string(),
+                ));
+            }
+            continue;
+        }
+
+        // strings can contain spaces, so they are lexed instead of split into words
+        let string = text
+            .strip_prefix("push")
+            .map(str::trim_start)
+            .filter(|operand| operand.starts_with('"'));
+        if let Some(string) = string {
+            let string = parse_string(string, line)?;
+            bytecode.push(Bytecode::Push(BytecodeValue::String(string.into())));
+            continue;
+        }
+        if let Some(site) = text.strip_prefix("freeze ") {
+            bytecode.push(Bytecode::Freeze(parse_string(site.trim_start(), line)?));
+            continue;
+        }
+
+        let mut words = text.split_whitespace();
+        let opcode = words.next().unwrap();
+        // `make_block` and `make_closure` take any number of names
+        if opcode == "make_block" {
+            bytecode.push(Bytecode::MakeBlock(words.map(str::to_string).collect()));
+            continue;
+        }
+        if opcode == "make_closure" {
+            bytecode.push(Bytecode::MakeClosure(words.map(str::to_string).collect()));
+            continue;
+        }
+        let operand = words.next();
+        // `push native <name>` and `add_loads <a> <b>` are the only instructions with two
+        // operands
+        let words_after_second =
+            if (opcode == "push" && operand == Some("native")) || opcode == "add_loads" {
+                words.next()
+            } else {
+                None
+            };
+        if let Some(extra) = words.next() {
+            return Err(error(format!("Unexpected '{}'", extra)));
+        }
+        let expect_operand =
+            || operand.ok_or_else(|| error(format!("{} needs an operand", opcode)));
+        let expect_none = |instruction: Bytecode| match operand {
+            Some(operand) => Err(error(format!("Unexpected '{}'", operand))),
+            None => Ok(instruction),
+        };
+
+        bytecode.push(match opcode {
+            "end" if in_procedure => {
+                if let Some(operand) = operand {
+                    return Err(error(format!("Unexpected '{}'", operand)));
+                }
+                return Ok(bytecode);
+            }
+            "exit" => expect_none(Bytecode::Exit)?,
+            "push" if operand == Some("native") => {
+                let name = words_after_second
+                    .ok_or_else(|| error("push native needs the name of a builtin".to_string()))?;
+                Bytecode::Push(BytecodeValue::Native(find_native_or_error(
+                    module, name, line,
+                )?))
+            }
+            "push" => Bytecode::Push(match expect_operand()? {
+                "void" => BytecodeValue::Void,
+                "none" => BytecodeValue::None,
+                "proc" => BytecodeValue::Procedure(parse_instructions(lines, module, true)?.into()),
+                number => parse_number(number, line)?,
+            }),
+            "pop" => expect_none(Bytecode::Pop)?,
+            "dup" => expect_none(Bytecode::Dup)?,
+            "call" => {
+                let argument_count = expect_operand()?;
+                Bytecode::Call {
+                    argument_count: argument_count.parse().map_err(|_| {
+                        error(format!("Invalid argument count '{}'", argument_count))
+                    })?,
+                }
+            }
+            "call_native" => {
+                Bytecode::CallNative(find_native_or_error(module, expect_operand()?, line)?)
+            }
+            "return" => expect_none(Bytecode::Return)?,
+            "load" => Bytecode::Load(expect_operand()?.to_string()),
+            "store" => Bytecode::Store(expect_operand()?.to_string()),
+            "load_member" => Bytecode::LoadMember(expect_operand()?.to_string()),
+            "check_not_frozen" => Bytecode::CheckNotFrozen(expect_operand()?.to_string()),
+            "spawn" => expect_none(Bytecode::Spawn)?,
+            "join" => expect_none(Bytecode::Join)?,
+            "jump" => Bytecode::Jump(parse_target(expect_operand()?, line)?),
+            "jump_if_zero" => Bytecode::JumpIfZero(parse_target(expect_operand()?, line)?),
+            "is_none" => expect_none(Bytecode::IsNone)?,
+            "add" => expect_none(Bytecode::AddInteger)?,
+            "sub" => expect_none(Bytecode::SubInteger)?,
+            "mul" => expect_none(Bytecode::MulInteger)?,
+            "div" => expect_none(Bytecode::DivInteger)?,
+            "negate" => expect_none(Bytecode::NegateInteger)?,
+            "and" => expect_none(Bytecode::AndInteger)?,
+            "or" => expect_none(Bytecode::OrInteger)?,
+            "xor" => expect_none(Bytecode::XorInteger)?,
+            "not" => expect_none(Bytecode::NotInteger)?,
+            "eq" => expect_none(Bytecode::EqualInteger)?,
+            "shl" => expect_none(Bytecode::ShiftLeftInteger)?,
+            "shr" => expect_none(Bytecode::ShiftRightInteger)?,
+            "addf" => expect_none(Bytecode::AddFloat)?,
+            "subf" => expect_none(Bytecode::SubFloat)?,
+            "mulf" => expect_none(Bytecode::MulFloat)?,
+            "divf" => expect_none(Bytecode::DivFloat)?,
+            "negatef" => expect_none(Bytecode::NegateFloat)?,
+            "add_const" => {
+                let integer = expect_operand()?;
+                Bytecode::AddConstInteger(
+                    integer
+                        .parse()
+                        .map_err(|_| error(format!("Invalid integer '{}'", integer)))?,
+                )
+            }
+            "add_loads" => {
+                let b = words_after_second.ok_or_else(|| {
+                    error("add_loads needs the names of two variables".to_string())
+                })?;
+                Bytecode::AddLoadsInteger(expect_operand()?.to_string(), b.to_string())
+            }
+            "dup_store" => Bytecode::DupStore(expect_operand()?.to_string()),
+            _ => return Err(error(format!("Unknown instruction '{}'", opcode))),
+        });
+    }
+    if in_procedure {
+        return Err(BytecodeParseError {
+            line: last_line,
+            message: "Expected 'end' at the end of the procedure".to_string(),
+        });
+    }
+    Ok(bytecode)
+}
+
+// a `#` inside a string does not start a comment
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, chr) in line.char_indices() {
+        match chr {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// anything that is not an integer is tried as a float, which also takes `inf` and `NaN`
+fn parse_number(text: &str, line: usize) -> Result<BytecodeValue, BytecodeParseError> {
+    if let Ok(integer) = text.parse() {
+        Ok(BytecodeValue::Integer(integer))
+    } else if let Ok(float) = text.parse() {
+        Ok(BytecodeValue::Float(float))
+    } else {
+        Err(BytecodeParseError {
+            line,
+            message: format!("Invalid value '{}'", text),
+        })
+    }
+}
+
+fn parse_string(text: &str, line: usize) -> Result<String, BytecodeParseError> {
+    let error = |message: String| BytecodeParseError { line, message };
+    let mut lexer = Lexer::new(String::new(), text);
+    let string = match lexer.next_token() {
+        Ok(token) => StringToken::try_from(token)
+            .map_err(|token| error(format!("Invalid value '{}'", token.kind.to_string())))?,
+        Err(lex_error) => return Err(error(lex_error.message)),
+    };
+    match lexer.next_token() {
+        Ok(token) if token.kind == TokenKind::EndOfFile => Ok(string.value),
+        _ => Err(error(format!("Unexpected '{}'", &text[string.end..]))),
+    }
+}
+
+fn parse_target(target: &str, line: usize) -> Result<usize, BytecodeParseError> {
+    target.parse().map_err(|_| BytecodeParseError {
+        line,
+        message: format!("Invalid jump target '{}'", target),
+    })
+}
+
+fn find_native_or_error(
+    module: &str,
+    name: &str,
+    line: usize,
+) -> Result<NativeId, BytecodeParseError> {
+    find_native(name).ok_or_else(|| BytecodeParseError {
+        line,
+        message: format!("Missing builtin {} required by module {}", name, module),
+    })
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    ast::Ast,
+    common::{CancellationToken, CompileError},
+    lexer::Lexer,
+    parsing::parse_file_with_max_depth,
+    token::{Token, TokenKind},
+};
+
+/// Remembers the tokens and ast of each file along with its source, so hovering, checking
+/// and then running the same file in one process only lexes and parses it once, clones share
+/// the same cache
+#[derive(Debug, Clone, Default)]
+pub struct FrontEndCache {
+    files: Arc<Mutex<HashMap<String, CachedFile>>>,
+}
+
+// only the latest source of each file is kept, so an editor session doesn't keep every
+// version it has seen, the whole source is compared because two sources can share a hash
+#[derive(Debug)]
+struct CachedFile {
+    source: Arc<str>,
+    tokens: Option<Arc<[Token]>>,
+    // along with the max nesting depth it was parsed with
+    ast: Option<(usize, Arc<Ast>)>,
+}
+
+impl FrontEndCache {
+    pub fn new() -> FrontEndCache {
+        FrontEndCache::default()
+    }
+
+    /// Every token of the file up to and including the end of file
+    pub fn lex(
+        &self,
+        filepath: &str,
+        source: &str,
+        cancellation: &CancellationToken,
+    ) -> Result<Arc<[Token]>, CompileError> {
+        if let Some(tokens) = self.get(filepath, source, |file| file.tokens.clone()) {
+            return Ok(tokens);
+        }
+
+        let mut lexer = Lexer::new(filepath.to_string(), source);
+        lexer.set_cancellation(cancellation.clone());
+        let mut tokens = vec![];
+        loop {
+            let token = lexer.next_token()?;
+            let is_end_of_file = token.kind == TokenKind::EndOfFile;
+            tokens.push(token);
+            if is_end_of_file {
+                break;
+            }
+        }
+
+        let tokens: Arc<[Token]> = tokens.into();
+        self.update(filepath, source, |file| file.tokens = Some(tokens.clone()));
+        Ok(tokens)
+    }
+
+    /// The file parsed into an `Ast::File`
+    pub fn parse(
+        &self,
+        filepath: &str,
+        source: &str,
+        max_depth: usize,
+        cancellation: &CancellationToken,
+    ) -> Result<Arc<Ast>, CompileError> {
+        let cached = self.get(filepath, source, |file| match &file.ast {
+            Some((depth, ast)) if *depth == max_depth => Some(ast.clone()),
+            _ => None,
+        });
+        if let Some(ast) = cached {
+            return Ok(ast);
+        }
+
+        let mut lexer = Lexer::new(filepath.to_string(), source);
+        lexer.set_cancellation(cancellation.clone());
+        let ast = Arc::new(Ast::File(parse_file_with_max_depth(&mut lexer, max_depth)?));
+        self.update(filepath, source, |file| {
+            file.ast = Some((max_depth, ast.clone()))
+        });
+        Ok(ast)
+    }
+
+    /// Forgets every file
+    pub fn clear(&self) {
+        self.files.lock().unwrap().clear();
+    }
+
+    fn get<T>(
+        &self,
+        filepath: &str,
+        source: &str,
+        get: impl FnOnce(&CachedFile) -> Option<T>,
+    ) -> Option<T> {
+        let files = self.files.lock().unwrap();
+        files
+            .get(filepath)
+            .filter(|file| &*file.source == source)
+            .and_then(get)
+    }
+
+    // errors are never cached, the source could be fine and only have been cancelled
+    fn update(&self, filepath: &str, source: &str, update: impl FnOnce(&mut CachedFile)) {
+        let mut files = self.files.lock().unwrap();
+        let file = files
+            .entry(filepath.to_string())
+            .or_insert_with(|| CachedFile::new(source));
+        if &*file.source != source {
+            *file = CachedFile::new(source);
+        }
+        update(file);
+    }
+}
+
+impl CachedFile {
+    fn new(source: &str) -> CachedFile {
+        CachedFile {
+            source: source.into(),
+            tokens: None,
+            ast: None,
+        }
+    }
+}
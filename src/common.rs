@@ -1,9 +1,77 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::LocalKey,
+};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct SourceLocation {
     pub filepath: String,
     pub position: usize,
     pub line: usize,
     pub column: usize,
+    /// Set for code that was made by the compiler instead of read from a file
+    pub synthetic: Option<Arc<Synthetic>>,
+}
+
+/// Where code that nobody wrote came from, so errors in it can say so instead of pointing
+/// at a line that does not exist
+#[derive(Debug, Clone, PartialEq)]
+pub struct Synthetic {
+    /// What made the code, like `builtin`
+    pub name: String,
+    /// The code it was made from, if there is any
+    pub expanded_from: Option<SourceLocation>,
+}
+
+impl SourceLocation {
+    /// A location for code that `name` made from the code at `expanded_from`
+    ///
+    /// It has the same place as `expanded_from` so anything that only looks at the line and
+    /// column still points somewhere useful, without it the filepath is `<name>`
+    pub fn synthetic(name: &str, expanded_from: Option<&SourceLocation>) -> SourceLocation {
+        let synthetic = Some(Arc::new(Synthetic {
+            name: name.to_string(),
+            expanded_from: expanded_from.cloned(),
+        }));
+        match expanded_from {
+            Some(location) => SourceLocation {
+                synthetic,
+                ..location.clone()
+            },
+            None => SourceLocation {
+                filepath: format!("<{}>", name),
+                position: 0,
+                line: 1,
+                column: 1,
+                synthetic,
+            },
+        }
+    }
+}
+
+/// `file:line:column`, code without a file is just `<name>` and code made from other code
+/// says what made it
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.synthetic.as_deref() {
+            Some(Synthetic {
+                name,
+                expanded_from: Some(expanded_from),
+            }) => write!(f, "<{}> expanded from {}", name, expanded_from),
+            Some(Synthetic {
+                expanded_from: None,
+                ..
+            }) => write!(f, "{}", self.filepath),
+            None => write!(f, "{}:{}:{}", self.filepath, self.line, self.column),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -12,9 +80,593 @@ pub struct CompileNote {
     pub message: String,
 }
 
+/// A change to the source that fixes an error, `fix` applies these
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// Where the replaced text starts
+    pub location: SourceLocation,
+    /// How many bytes are replaced, 0 to only insert
+    pub length: usize,
+    pub replacement: String,
+    pub message: String,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CompileError {
     pub location: SourceLocation,
+    /// Where the error stops, for errors about a whole expression instead of a single position
+    pub end_location: Option<SourceLocation>,
+    /// Set where the error is made, so nothing has to look at `message` to tell errors apart
+    pub kind: ErrorKind,
+    /// Names the message independently of its wording, so `MessageCatalog` can translate it
+    pub id: &'static str,
+    /// What goes in each `{}` of the message, in order
+    pub arguments: Vec<String>,
     pub message: String,
     pub notes: Vec<CompileNote>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// The kinds of errors the compiler can report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Syntax,
+    AlreadyDefined,
+    UnresolvedName,
+    UsedBeforeDefinition,
+    IntegerTooBig,
+    UnknownOperator,
+    NotAProcedure,
+    WrongArgumentCount,
+    WrongArgumentType,
+    NotABlock,
+    UnknownMember,
+    InvalidAssignment,
+    NestedTooDeeply,
+    InvalidMatch,
+    MismatchedArmTypes,
+    UnreachableArm,
+    InvalidEnum,
+    InvalidFreeze,
+    InvalidJoin,
+    /// An operator with constant operands that would fail at runtime, like dividing by 0
+    InvalidConstant,
+    InvalidPragma,
+    /// Something the compiler can't do with this input, like formatting a file with comments
+    Unsupported,
+    Cancelled,
+    /// Every lint, warnings use the same type as errors
+    Warning,
+    /// A bug in the compiler, see `ice`
+    Internal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+}
+
+const OUTPUT_CLOSED_MESSAGE: &str = "The output was closed";
+const OUT_OF_FUEL_MESSAGE: &str = "Execution ran out of fuel";
+const TIMED_OUT_MESSAGE: &str = "Execution went past its timeout";
+const OUTPUT_LIMIT_MESSAGE: &str = "Execution printed more than its output limit";
+
+impl RuntimeError {
+    /// The error for when stdout has gone away, like when piping into `head`
+    pub fn output_closed() -> RuntimeError {
+        RuntimeError {
+            message: OUTPUT_CLOSED_MESSAGE.to_string(),
+        }
+    }
+
+    pub fn is_output_closed(&self) -> bool {
+        self.message == OUTPUT_CLOSED_MESSAGE
+    }
+
+    /// The error for when `Vm::fuel` has run out
+    pub fn out_of_fuel() -> RuntimeError {
+        RuntimeError {
+            message: OUT_OF_FUEL_MESSAGE.to_string(),
+        }
+    }
+
+    /// The error for when `Vm::deadline` has passed
+    pub fn timed_out() -> RuntimeError {
+        RuntimeError {
+            message: TIMED_OUT_MESSAGE.to_string(),
+        }
+    }
+
+    /// The error for when printing would go past `Vm::max_output`
+    pub fn output_limit_reached() -> RuntimeError {
+        RuntimeError {
+            message: OUTPUT_LIMIT_MESSAGE.to_string(),
+        }
+    }
+
+    /// Whether the program was stopped by one of the limits of the vm rather than failing on
+    /// its own
+    pub fn is_resource_limit(&self) -> bool {
+        [OUT_OF_FUEL_MESSAGE, TIMED_OUT_MESSAGE, OUTPUT_LIMIT_MESSAGE].contains(&&*self.message)
+    }
+}
+
+/// A flag shared with another thread that asks a compilation or execution to stop early
+///
+/// It is checked at phase boundaries, for every expression the binder and the compiler walk
+/// over and inside long loops, so work stops soon after `cancel` rather than immediately
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn check_compile(&self, location: &SourceLocation) -> Result<(), CompileError> {
+        if self.is_cancelled() {
+            Err(CompileError {
+                location: location.clone(),
+                end_location: None,
+                kind: ErrorKind::Cancelled,
+                id: "cancelled",
+                arguments: vec![],
+                message: "Compilation was cancelled".to_string(),
+                notes: vec![],
+                suggestions: vec![],
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn check_runtime(&self) -> Result<(), RuntimeError> {
+        if self.is_cancelled() {
+            Err(RuntimeError {
+                message: "Execution was cancelled".to_string(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Drops `nodes` one at a time instead of by recursion, for trees too deep for the stack.
+/// The `Drop` of a node hands its children to this instead of letting them drop with it,
+/// and `queue` keeps them until the outermost call gets to them
+pub(crate) fn drop_iteratively<T: 'static>(
+    queue: &'static LocalKey<RefCell<Option<Vec<T>>>>,
+    nodes: Vec<T>,
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    let outermost = queue.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        match &mut *queue {
+            Some(pending) => {
+                pending.extend(nodes);
+                false
+            }
+            None => {
+                *queue = Some(nodes);
+                true
+            }
+        }
+    });
+    if outermost {
+        // the borrow has to end before the node drops, since that adds its own children
+        while let Some(node) = queue.with(|queue| queue.borrow_mut().as_mut().and_then(Vec::pop)) {
+            drop(node);
+        }
+        queue.with(|queue| *queue.borrow_mut() = None);
+    }
+}
+
+pub fn write_compile_error(
+    stream: &mut dyn Write,
+    error: &CompileError,
+) -> Result<(), std::io::Error> {
+    write_compile_error_with(stream, error, &DiagnosticStyle::default())
+}
+
+pub fn write_compile_error_with(
+    stream: &mut dyn Write,
+    error: &CompileError,
+    style: &DiagnosticStyle,
+) -> Result<(), std::io::Error> {
+    write_diagnostic(stream, error, "Compile Error", style)
+}
+
+/// Warnings use the same type as errors, they just don't stop the compilation
+pub fn write_warning(
+    stream: &mut dyn Write,
+    warning: &CompileError,
+    style: &DiagnosticStyle,
+) -> Result<(), std::io::Error> {
+    write_diagnostic(stream, warning, "Warning", style)
+}
+
+fn write_diagnostic(
+    stream: &mut dyn Write,
+    error: &CompileError,
+    kind: &str,
+    style: &DiagnosticStyle,
+) -> Result<(), std::io::Error> {
+    if style.verbosity == DiagnosticVerbosity::Terse {
+        return writeln!(
+            stream,
+            "{}: {}",
+            format_error_location(error),
+            style.catalog.translate_error(error),
+        );
+    }
+    writeln!(
+        stream,
+        "{}: {}: {}",
+        format_error_location(error),
+        style.catalog.translate(kind),
+        style.catalog.translate_error(error),
+    )?;
+    for note in &error.notes {
+        if let Some(location) = &note.location {
+            writeln!(stream, "{}: ", location)?;
+        }
+        writeln!(stream, "Note: {}", style.catalog.translate(&note.message))?;
+    }
+    for suggestion in &error.suggestions {
+        writeln!(
+            stream,
+            "Suggestion: {}",
+            style.catalog.translate(&suggestion.message)
+        )?;
+    }
+    Ok(())
+}
+
+// `file:line:column`, or `file:line:column-line:column` when the error covers a range
+fn format_error_location(error: &CompileError) -> String {
+    let location = &error.location;
+    match &error.end_location {
+        Some(end) if end.position > location.position && location.synthetic.is_none() => format!(
+            "{}:{}:{}-{}:{}",
+            location.filepath, location.line, location.column, end.line, end.column,
+        ),
+        _ => location.to_string(),
+    }
+}
+
+/// How much `write_diagnostics_with` writes about each error
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DiagnosticVerbosity {
+    /// One line per error, without notes
+    Terse,
+    #[default]
+    Normal,
+    /// Also says how many errors there were in total at the end
+    Verbose,
+}
+
+impl DiagnosticVerbosity {
+    pub const ALL: [DiagnosticVerbosity; 3] = [
+        DiagnosticVerbosity::Terse,
+        DiagnosticVerbosity::Normal,
+        DiagnosticVerbosity::Verbose,
+    ];
+
+    pub fn from_name(name: &str) -> Option<DiagnosticVerbosity> {
+        DiagnosticVerbosity::ALL
+            .into_iter()
+            .find(|verbosity| verbosity.get_name() == name)
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            DiagnosticVerbosity::Terse => "terse",
+            DiagnosticVerbosity::Normal => "normal",
+            DiagnosticVerbosity::Verbose => "verbose",
+        }
+    }
+}
+
+/// Replaces the english diagnostic messages with other ones, like translations
+///
+/// Errors are translated by their id, the arguments of the error go in place of the `{}`s in
+/// the message for it in order. Text without an id, like notes, is matched against patterns
+/// instead, `{}` in a pattern matches any text, which gets put in place of the `{}`s in the
+/// replacement in the same order. Anything that is not in the catalog is left alone
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageCatalog {
+    messages: HashMap<String, String>,
+    entries: Vec<(String, String)>,
+}
+
+impl MessageCatalog {
+    pub fn new() -> MessageCatalog {
+        MessageCatalog::default()
+    }
+
+    /// Reads a catalog with an `id = message` on each line, empty lines and lines starting
+    /// with `#` are skipped
+    pub fn parse(text: &str) -> Result<MessageCatalog, String> {
+        let mut catalog = MessageCatalog::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((id, message)) if !id.trim().is_empty() => {
+                    catalog.add_message(id.trim(), message.trim());
+                }
+                _ => {
+                    return Err(format!(
+                        "Expected an id, = and a message on line {} of the catalog",
+                        index + 1,
+                    ))
+                }
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// The message for errors with this id, a later message for the same id replaces it
+    pub fn add_message(&mut self, id: &str, message: &str) {
+        self.messages.insert(id.to_string(), message.to_string());
+    }
+
+    /// The message of the error from its id, or its english message translated like any other
+    /// text if the catalog has no message for its id
+    pub fn translate_error(&self, error: &CompileError) -> String {
+        match self.messages.get(error.id) {
+            Some(message) => fill_in(message, error.arguments.iter().map(String::as_str)),
+            None => self.translate(&error.message),
+        }
+    }
+
+    /// Earlier patterns win when more than one matches
+    pub fn add(&mut self, pattern: &str, replacement: &str) {
+        self.entries
+            .push((pattern.to_string(), replacement.to_string()));
+    }
+
+    pub fn translate(&self, message: &str) -> String {
+        for (pattern, replacement) in &self.entries {
+            if let Some(captures) = match_pattern(pattern, message) {
+                return fill_in(replacement, captures.into_iter());
+            }
+        }
+        message.to_string()
+    }
+}
+
+// puts the arguments in place of the `{}`s in order, missing ones are left empty
+fn fill_in<'argument>(
+    message: &str,
+    mut arguments: impl Iterator<Item = &'argument str>,
+) -> String {
+    let mut pieces = message.split("{}");
+    let mut result = pieces.next().unwrap().to_string();
+    for piece in pieces {
+        result += arguments.next().unwrap_or_default();
+        result += piece;
+    }
+    result
+}
+
+// the text each `{}` matched, or `None` if the message does not match the pattern
+fn match_pattern<'message>(pattern: &str, message: &'message str) -> Option<Vec<&'message str>> {
+    let mut pieces = pattern.split("{}");
+    let mut rest = message.strip_prefix(pieces.next().unwrap())?;
+    let mut captures = vec![];
+    let pieces: Vec<&str> = pieces.collect();
+    for (index, piece) in pieces.iter().enumerate() {
+        let end = if index + 1 == pieces.len() {
+            // the last piece has to match the end of the message
+            if !rest.ends_with(piece) {
+                return None;
+            }
+            rest.len() - piece.len()
+        } else {
+            rest.find(piece)?
+        };
+        captures.push(&rest[..end]);
+        rest = &rest[end + piece.len()..];
+    }
+    if pieces.is_empty() && !rest.is_empty() {
+        return None;
+    }
+    Some(captures)
+}
+
+/// How diagnostics are written out
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiagnosticStyle {
+    pub verbosity: DiagnosticVerbosity,
+    pub catalog: MessageCatalog,
+}
+
+/// What a column counts, editors that speak the language server protocol count utf-16 code
+/// units
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ColumnUnit {
+    #[default]
+    Chars,
+    Utf8Bytes,
+    Utf16CodeUnits,
+}
+
+impl ColumnUnit {
+    pub const ALL: [ColumnUnit; 3] = [
+        ColumnUnit::Chars,
+        ColumnUnit::Utf8Bytes,
+        ColumnUnit::Utf16CodeUnits,
+    ];
+
+    pub fn from_name(name: &str) -> Option<ColumnUnit> {
+        ColumnUnit::ALL
+            .into_iter()
+            .find(|unit| unit.get_name() == name)
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            ColumnUnit::Chars => "chars",
+            ColumnUnit::Utf8Bytes => "utf8",
+            ColumnUnit::Utf16CodeUnits => "utf16",
+        }
+    }
+
+    fn get_width(self, chr: char) -> usize {
+        match self {
+            ColumnUnit::Chars => 1,
+            ColumnUnit::Utf8Bytes => chr.len_utf8(),
+            ColumnUnit::Utf16CodeUnits => chr.len_utf16(),
+        }
+    }
+}
+
+/// How the columns in diagnostics are counted, the lexer counts every char as one column and
+/// `recount` changes that afterwards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnPolicy {
+    /// A tab moves to the next multiple of this, 1 counts it like any other char, it can not
+    /// be 0
+    pub tab_width: usize,
+    pub unit: ColumnUnit,
+}
+
+impl Default for ColumnPolicy {
+    fn default() -> Self {
+        ColumnPolicy {
+            tab_width: 1,
+            unit: ColumnUnit::Chars,
+        }
+    }
+}
+
+impl ColumnPolicy {
+    /// The column of the byte `position` in `source`, counting from 1
+    pub fn get_column(&self, source: &str, position: usize) -> usize {
+        let line_start = source[..position].rfind('\n').map_or(0, |index| index + 1);
+        let mut column = 0;
+        for chr in source[line_start..position].chars() {
+            if chr == '\t' {
+                column = (column / self.tab_width + 1) * self.tab_width;
+            } else {
+                column += self.unit.get_width(chr);
+            }
+        }
+        column + 1
+    }
+
+    /// Recounts the columns of every location in the error that points into `source`
+    pub fn recount(&self, error: &mut CompileError, source: &str) {
+        let locations = std::iter::once(&mut error.location)
+            .chain(&mut error.end_location)
+            .chain(
+                error
+                    .notes
+                    .iter_mut()
+                    .filter_map(|note| note.location.as_mut()),
+            )
+            .chain(
+                error
+                    .suggestions
+                    .iter_mut()
+                    .map(|suggestion| &mut suggestion.location),
+            );
+        for location in locations {
+            // synthetic locations have no place in the source, or borrowed the place of one
+            // that is recounted on its own
+            if location.synthetic.is_none() && source.is_char_boundary(location.position) {
+                location.column = self.get_column(source, location.position);
+            }
+        }
+    }
+}
+
+/// The errors of a compilation, with repeats of the same cascaded error dropped
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics {
+    pub errors: Vec<CompileError>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// Adds the error unless one with the same message was already reported,
+    /// so every use of an unresolved name only shows up once
+    pub fn push(&mut self, error: CompileError) {
+        if !self
+            .errors
+            .iter()
+            .any(|existing| existing.message == error.message)
+        {
+            self.errors.push(error);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl From<CompileError> for Diagnostics {
+    fn from(error: CompileError) -> Self {
+        Diagnostics {
+            errors: vec![error],
+        }
+    }
+}
+
+/// Writes at most `max_errors` of the errors followed by a count of the rest
+pub fn write_diagnostics(
+    stream: &mut dyn Write,
+    diagnostics: &Diagnostics,
+    max_errors: usize,
+) -> Result<(), std::io::Error> {
+    write_diagnostics_with(stream, diagnostics, max_errors, &DiagnosticStyle::default())
+}
+
+pub fn write_diagnostics_with(
+    stream: &mut dyn Write,
+    diagnostics: &Diagnostics,
+    max_errors: usize,
+    style: &DiagnosticStyle,
+) -> Result<(), std::io::Error> {
+    for error in diagnostics.errors.iter().take(max_errors) {
+        write_compile_error_with(stream, error, style)?;
+    }
+    let remaining = diagnostics.errors.len().saturating_sub(max_errors);
+    if remaining > 0 {
+        let message = format!(
+            "... and {} more error{}",
+            remaining,
+            if remaining == 1 { "" } else { "s" },
+        );
+        writeln!(stream, "{}", style.catalog.translate(&message))?;
+    }
+    if style.verbosity == DiagnosticVerbosity::Verbose {
+        let total = diagnostics.errors.len();
+        let message = format!(
+            "{} error{} in total",
+            total,
+            if total == 1 { "" } else { "s" }
+        );
+        writeln!(stream, "{}", style.catalog.translate(&message))?;
+    }
+    Ok(())
 }
@@ -0,0 +1,91 @@
+use crate::{
+    bound_nodes::{BinaryOperatorKind, BoundNode, UnaryOperatorKind},
+    execute::{shift_left, shift_right},
+};
+
+/// Evaluates an expression made of only integer literals and operators at compile time,
+/// `Ok(None)` if it is not constant, or the same message the vm would give if it overflows
+///
+/// Names are not looked through, only what is written in the expression itself counts.
+/// Division by zero is left to the binder, which reports it at the zero
+pub fn evaluate_constant(node: &BoundNode) -> Result<Option<i64>, String> {
+    Ok(match node {
+        BoundNode::Integer(integer) => i64::try_from(integer.value).ok(),
+        BoundNode::Unary(unary) => {
+            let Some(operand) = evaluate_constant(&unary.operand)? else {
+                return Ok(None);
+            };
+            match unary.operator.kind {
+                UnaryOperatorKind::Identity => Some(operand),
+                UnaryOperatorKind::Negation => {
+                    Some(operand.checked_neg().ok_or_else(|| {
+                        format!("Unable to negate {} without overflowing", operand)
+                    })?)
+                }
+                UnaryOperatorKind::BitwiseNot => Some(!operand),
+                UnaryOperatorKind::MakeOptional => Some(operand),
+            }
+        }
+        // a constant optional always has a value, so the default is never needed
+        BoundNode::Binary(binary)
+            if matches!(binary.operator.kind, BinaryOperatorKind::UnwrapOr) =>
+        {
+            evaluate_constant(&binary.left)?
+        }
+        BoundNode::Binary(binary) => {
+            let (Some(a), Some(b)) = (
+                evaluate_constant(&binary.left)?,
+                evaluate_constant(&binary.right)?,
+            ) else {
+                return Ok(None);
+            };
+            let result = match binary.operator.kind {
+                BinaryOperatorKind::Addition => a.checked_add(b),
+                BinaryOperatorKind::Subtraction => a.checked_sub(b),
+                BinaryOperatorKind::Multiplication => a.checked_mul(b),
+                BinaryOperatorKind::Division if b == 0 => return Ok(None),
+                BinaryOperatorKind::Division => a.checked_div(b),
+                BinaryOperatorKind::BitwiseAnd => Some(a & b),
+                BinaryOperatorKind::BitwiseOr => Some(a | b),
+                BinaryOperatorKind::BitwiseXor => Some(a ^ b),
+                BinaryOperatorKind::ShiftLeft => shift_left(a, b),
+                BinaryOperatorKind::ShiftRight => shift_right(a, b),
+                BinaryOperatorKind::LogicalAnd => Some((a != 0 && b != 0) as i64),
+                BinaryOperatorKind::LogicalOr => Some((a != 0 || b != 0) as i64),
+                BinaryOperatorKind::Equal => Some((a == b) as i64),
+                BinaryOperatorKind::NotEqual => Some((a != b) as i64),
+                BinaryOperatorKind::UnwrapOr => unreachable!("evaluated without the default"),
+            };
+            Some(result.ok_or_else(|| overflow_message(&binary.operator.kind, a, b))?)
+        }
+        _ => None,
+    })
+}
+
+fn overflow_message(kind: &BinaryOperatorKind, a: i64, b: i64) -> String {
+    match kind {
+        BinaryOperatorKind::Addition => {
+            format!("Unable to add {} and {} without overflowing", a, b)
+        }
+        BinaryOperatorKind::Subtraction => {
+            format!("Unable to subtract {} from {} without overflowing", b, a)
+        }
+        BinaryOperatorKind::Multiplication => {
+            format!("Unable to multiply {} by {} without overflowing", a, b)
+        }
+        BinaryOperatorKind::Division => format!("Unable to divide {} by {}", a, b),
+        BinaryOperatorKind::ShiftLeft | BinaryOperatorKind::ShiftRight => {
+            format!("Unable to shift {} by a negative amount {}", a, b)
+        }
+        BinaryOperatorKind::BitwiseAnd
+        | BinaryOperatorKind::BitwiseOr
+        | BinaryOperatorKind::BitwiseXor
+        | BinaryOperatorKind::LogicalAnd
+        | BinaryOperatorKind::LogicalOr
+        | BinaryOperatorKind::Equal
+        | BinaryOperatorKind::NotEqual
+        | BinaryOperatorKind::UnwrapOr => {
+            unreachable!("bitwise, logical, comparison and optional operators cannot overflow")
+        }
+    }
+}
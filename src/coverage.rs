@@ -0,0 +1,74 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::program::Program;
+
+/// How many times each line of a program ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub filepath: String,
+    /// Only lines that have code on them
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl Coverage {
+    /// `counts` is `Vm::coverage` after running the program
+    pub fn collect(filepath: String, program: &Program, counts: &[u64]) -> Coverage {
+        let mut lines = BTreeMap::new();
+        for (i, &line) in program.get_lines().iter().enumerate() {
+            if line == 0 {
+                continue;
+            }
+            let count = counts.get(i).copied().unwrap_or(0);
+            let line_count = lines.entry(line).or_insert(0);
+            *line_count = count.max(*line_count);
+        }
+        Coverage { filepath, lines }
+    }
+
+    pub fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|&&count| count > 0).count()
+    }
+
+    /// The report in the lcov tracefile format
+    pub fn to_lcov(&self) -> String {
+        let mut result = format!("TN:\nSF:{}\n", self.filepath);
+        for (line, count) in &self.lines {
+            result += &format!("DA:{},{}\n", line, count);
+        }
+        result += &format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            self.lines.len(),
+            self.lines_hit()
+        );
+        result
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hit = self.lines_hit();
+        let percent = if self.lines.is_empty() {
+            100.0
+        } else {
+            hit as f64 * 100.0 / self.lines.len() as f64
+        };
+        writeln!(
+            f,
+            "{}: {} of {} lines ran ({:.0}%)",
+            self.filepath,
+            hit,
+            self.lines.len(),
+            percent,
+        )?;
+        let missed: Vec<_> = self
+            .lines
+            .iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(line, _)| line.to_string())
+            .collect();
+        if !missed.is_empty() {
+            writeln!(f, "Lines that never ran: {}", missed.join(", "))?;
+        }
+        Ok(())
+    }
+}
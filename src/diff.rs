@@ -0,0 +1,249 @@
+use std::fmt::{Display, Write};
+
+use crate::{
+    ast::{Ast, AstTrait},
+    bound_nodes::{BoundNode, BoundNodeTrait},
+    bytecode_text::format_bytecode,
+    common::Diagnostics,
+    emit::Stage,
+    inspect::bind_file,
+    lexer::{quote_string, Lexer},
+    natives::get_native,
+    parsing::parse_file,
+    program::{Prelude, Program},
+    token::TokenKind,
+    types::Type,
+};
+
+/// The result of a stage with one line per token, node or instruction, nested nodes are
+/// indented under their parent
+///
+/// Unlike `compile --emit` there are no source locations in it, so moving code around or
+/// editing one line of a file only changes the lines of what actually changed
+pub fn outline(stage: Stage, filepath: String, source: &str) -> Result<Vec<String>, Diagnostics> {
+    let mut lines = vec![];
+    match stage {
+        Stage::Tokens => {
+            let mut lexer = Lexer::new(filepath, source);
+            loop {
+                let token = lexer.next_token()?;
+                if token.kind == TokenKind::EndOfFile {
+                    break;
+                }
+                lines.push(format!("{:?}", token.kind));
+            }
+        }
+        Stage::Ast => {
+            let file = parse_file(&mut Lexer::new(filepath, source))?;
+            for expression in &file.expressions {
+                outline_ast(expression, 0, &mut lines);
+            }
+        }
+        Stage::Ir => {
+            let prelude = Prelude::new();
+            let bound_file = bind_file(filepath, source, &prelude)?;
+            for expression in &bound_file.unwrap_block().expressions {
+                outline_bound_node(expression, 0, &mut lines);
+            }
+        }
+        Stage::Bytecode => {
+            let program = Program::compile(filepath, source)?;
+            lines.extend(
+                format_bytecode(program.get_bytecode())
+                    .lines()
+                    .map(str::to_string),
+            );
+        }
+    }
+    Ok(lines)
+}
+
+fn outline_ast(ast: &Ast, depth: usize, lines: &mut Vec<String>) {
+    let description = match ast {
+        Ast::File(_) => "file".to_string(),
+        Ast::Block(_) => "block".to_string(),
+        Ast::Export(export) => format!("export {}", export.name_token.name),
+        Ast::Let(lett) => format!("let {}", lett.name_token.name),
+        Ast::Destructure(destructure) => {
+            let names: Vec<&str> = destructure
+                .names
+                .iter()
+                .map(|name| name.name.as_str())
+                .collect();
+            format!("destructure {}", names.join(" "))
+        }
+        Ast::Unary(unary) => format!("unary {}", unary.operator_token.kind.to_string()),
+        Ast::Binary(binary) => format!("binary {}", binary.operator_token.kind.to_string()),
+        Ast::Name(name) => format!("name {}", name.name_token.name),
+        Ast::Integer(integer) => format!("integer {}", integer.integer_token.value),
+        Ast::Float(float) => format!("float {:?}", float.float_token.value),
+        Ast::String(string) => format!("string {}", quote_string(&string.string_token.value)),
+        Ast::None(_) => "none".to_string(),
+        Ast::Call(_) => "call".to_string(),
+        Ast::Parenthesized(_) => "parenthesized".to_string(),
+        Ast::MemberAccess(member) => format!("member {}", member.name_token.name),
+        Ast::Assignment(assignment) => format!("assignment {}", assignment.name_token.name),
+        Ast::Match(matchh) => {
+            let patterns: Vec<String> = matchh
+                .arms
+                .iter()
+                .map(|arm| arm.pattern.pretty_print())
+                .collect();
+            format!("match {}", patterns.join(" "))
+        }
+        Ast::Enum(enumm) => {
+            let names: Vec<&str> = enumm
+                .variants
+                .iter()
+                .map(|variant| variant.name.as_str())
+                .collect();
+            format!("enum {}", names.join(" "))
+        }
+        Ast::Freeze(_) => "freeze".to_string(),
+        Ast::Spawn(_) => "spawn".to_string(),
+        Ast::Join(_) => "join".to_string(),
+    };
+    lines.push(format!("{:indent$}{}", "", description, indent = depth * 4));
+    for child in ast.children() {
+        outline_ast(child, depth + 1, lines);
+    }
+}
+
+fn outline_bound_node(node: &BoundNode, depth: usize, lines: &mut Vec<String>) {
+    let description = match node {
+        BoundNode::Block(_) => "block".to_string(),
+        BoundNode::Export(export) => format!("export {}", export.name),
+        BoundNode::Let(lett) => format!("let {}", lett.name),
+        BoundNode::Destructure(_) => "destructure".to_string(),
+        BoundNode::Unary(unary) => format!("unary {:?}", unary.operator.kind),
+        BoundNode::Binary(binary) => format!("binary {:?}", binary.operator.kind),
+        BoundNode::Name(name) => format!("name {}", name.name),
+        BoundNode::Integer(integer) => format!("integer {}", integer.value),
+        BoundNode::Float(float) => format!("float {:?}", float.value),
+        BoundNode::String(string) => format!("string {}", quote_string(&string.value)),
+        BoundNode::None(_) => "none".to_string(),
+        BoundNode::Call(_) => "call".to_string(),
+        BoundNode::Native(native) => format!("native {}", get_native(native.id).full_name()),
+        BoundNode::MemberAccess(member) => format!("member {}", member.name),
+        BoundNode::Assignment(assignment) => format!("assignment {}", assignment.name),
+        BoundNode::Match(matchh) => {
+            let value_type = matchh.value.get_type();
+            let patterns: Vec<String> = matchh
+                .arms
+                .iter()
+                .map(|(integer, _)| match &value_type {
+                    Type::Enum(enum_type) => enum_type.variants[*integer as usize].clone(),
+                    _ => integer.to_string(),
+                })
+                .chain(std::iter::once("_".to_string()))
+                .collect();
+            format!("match {}", patterns.join(" "))
+        }
+        BoundNode::Enum(enumm) => format!("enum {}", enumm.enum_type.variants.join(" ")),
+        BoundNode::Freeze(_) => "freeze".to_string(),
+        BoundNode::Spawn(spawn) => std::iter::once("spawn")
+            .chain(spawn.captures.iter().map(|(name, _)| name as &str))
+            .collect::<Vec<_>>()
+            .join(" "),
+        BoundNode::Join(_) => "join".to_string(),
+        BoundNode::Error(_) => "error".to_string(),
+    };
+    lines.push(format!(
+        "{:indent$}{}: {}",
+        "",
+        description,
+        node.get_type(),
+        indent = depth * 4
+    ));
+    for child in node.children() {
+        outline_bound_node(child, depth + 1, lines);
+    }
+}
+
+/// A line of `diff_lines`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+    /// A line that was replaced by one at the same depth that starts with the same word, like
+    /// `integer 1` becoming `integer 2`
+    Changed(String, String),
+}
+
+impl Display for DiffLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffLine::Same(line) => write!(f, "  {}", line),
+            DiffLine::Removed(line) => write!(f, "- {}", line),
+            DiffLine::Added(line) => write!(f, "+ {}", line),
+            DiffLine::Changed(old, new) => write!(f, "~ {} -> {}", old, new.trim_start()),
+        }
+    }
+}
+
+/// The smallest set of removed and added lines that turns `old` into `new`, in order
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    // `common[i][j]` is the length of the longest common subsequence of `old[i..]` and
+    // `new[j..]`
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut removed, mut added) = (vec![], vec![]);
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            pair_changes(&mut removed, &mut added, &mut diff);
+            diff.push(DiffLine::Same(old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            added.push(new[j].clone());
+            j += 1;
+        } else {
+            removed.push(old[i].clone());
+            i += 1;
+        }
+    }
+    pair_changes(&mut removed, &mut added, &mut diff);
+    diff
+}
+
+// a run of removed lines followed by a run of added ones, the lines that line up and look
+// like the same kind of thing are shown as changed
+fn pair_changes(removed: &mut Vec<String>, added: &mut Vec<String>, diff: &mut Vec<DiffLine>) {
+    fn kind(line: &str) -> (usize, &str) {
+        let text = line.trim_start();
+        (
+            line.len() - text.len(),
+            text.split([' ', ':']).next().unwrap(),
+        )
+    }
+
+    let mut added_lines = std::mem::take(added).into_iter().peekable();
+    for old in std::mem::take(removed) {
+        match added_lines.next_if(|new| kind(new) == kind(&old)) {
+            Some(new) => diff.push(DiffLine::Changed(old, new)),
+            None => diff.push(DiffLine::Removed(old)),
+        }
+    }
+    diff.extend(added_lines.map(DiffLine::Added));
+}
+
+/// Writes the diff one line at a time, prefixed with two spaces, `-`, `+` or `~`
+pub fn format_diff(diff: &[DiffLine]) -> String {
+    let mut result = String::new();
+    for line in diff {
+        writeln!(result, "{}", line).unwrap();
+    }
+    result
+}
@@ -0,0 +1,53 @@
+use crate::{common::Diagnostics, pipeline::Pipeline};
+
+/// A stage of compilation that `compile --emit` can write out, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Tokens,
+    Ast,
+    Ir,
+    Bytecode,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Tokens, Stage::Ast, Stage::Ir, Stage::Bytecode];
+
+    pub fn from_name(name: &str) -> Option<Stage> {
+        Stage::ALL
+            .into_iter()
+            .find(|stage| stage.get_name() == name)
+    }
+
+    /// The name used on the command line, also used as the file extension
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Ast => "ast",
+            Stage::Ir => "ir",
+            Stage::Bytecode => "bytecode",
+        }
+    }
+}
+
+/// Parses a comma separated list of stages, where `all` means every stage
+pub fn parse_stages(list: &str) -> Result<Vec<Stage>, String> {
+    let mut stages = vec![];
+    for name in list.split(',') {
+        if name == "all" {
+            stages.extend(Stage::ALL);
+        } else if let Some(stage) = Stage::from_name(name) {
+            stages.push(stage);
+        } else {
+            return Err(format!("Unknown stage: '{}'", name));
+        }
+    }
+    stages.dedup();
+    Ok(stages)
+}
+
+/// Compiles the source as far as the stage needs and formats the result of that stage
+pub fn emit(stage: Stage, filepath: String, source: &str) -> Result<String, Diagnostics> {
+    let mut compilation = Pipeline::new().emit(stage).run(filepath, source);
+    compilation.result?;
+    Ok(compilation.emitted.remove(0).1)
+}
@@ -1,88 +1,958 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+#[cfg(feature = "native")]
+use std::io::{ErrorKind, Write};
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    time::Instant,
+};
 
-use crate::bytecode::{Bytecode, BytecodeValue};
+use crate::{
+    bytecode::{Bytecode, BytecodeValue, VmValue},
+    bytecode_text::format_bytecode,
+    common::{CancellationToken, RuntimeError},
+    lexer::quote_string,
+    natives::get_native,
+};
+
+pub type Vars = HashMap<String, VmValue>;
 
 pub fn execute_bytecode(
-    bytecode: &Vec<Bytecode>,
-    mut stack: Vec<Rc<RefCell<BytecodeValue>>>,
-) -> Option<Rc<RefCell<BytecodeValue>>> {
-    let mut ip = 0;
-    let mut vars: HashMap<String, Rc<RefCell<BytecodeValue>>> = HashMap::new();
-    stack.insert(0, Rc::new(RefCell::new(BytecodeValue::Void)));
-    loop {
-        match &bytecode[ip] {
-            Bytecode::Exit => return None,
+    bytecode: &[Bytecode],
+    stack: Vec<VmValue>,
+    vars: &mut Vars,
+) -> Result<Option<VmValue>, RuntimeError> {
+    Vm::new().execute(bytecode, stack, vars)
+}
+
+/// State that lives across every procedure call of an execution
+#[derive(Debug, Clone, Default)]
+pub struct Vm {
+    pub instruction_count: u64,
+    /// The most values on the stacks of every active call at once
+    pub peak_stack_depth: usize,
+    /// The most variables alive in every active call at once
+    pub peak_variable_count: usize,
+    /// When set, printed values are appended here instead of going to stdout
+    pub captured_output: Option<String>,
+    /// Checked every `CANCELLATION_INTERVAL` instructions
+    pub cancellation: Option<CancellationToken>,
+    pub hooks: Hooks,
+    /// Every task that was spawned, the handle of one is its index
+    tasks: Vec<Task>,
+    /// The task whose code is running, `None` for the program itself
+    current_task: Option<usize>,
+    /// Whether the call that is running is the outermost one of a task, only those can pause
+    can_pause: bool,
+    /// `instruction_count` when the code that is running got its turn
+    slice_start: u64,
+    /// The values sent to each channel that haven't been received yet, the handle of one is
+    /// its index
+    channels: Vec<VecDeque<VmValue>>,
+    /// Set by a builtin that has to wait, the task pauses and calls it again on its next turn
+    blocked: bool,
+    /// When set, execution fails once `instruction_count` goes past it
+    pub fuel: Option<u64>,
+    /// When set, counts how many times each instruction of the outermost bytecode runs
+    pub coverage: Option<Vec<u64>>,
+    /// When set, execution fails once it is past, checked every `CANCELLATION_INTERVAL`
+    /// instructions
+    pub deadline: Option<Instant>,
+    /// When set, printing fails once it would take `output_size` past it
+    pub max_output: Option<usize>,
+    /// How many bytes have been printed
+    pub output_size: usize,
+    /// Every block that was frozen by its address, with where it was frozen. The block is
+    /// kept alive so its address can't be reused by a different one. Blocks share their
+    /// members, so one that was frozen is still frozen when it is read back out of another
+    frozen: HashMap<*const BytecodeValue, (Rc<BytecodeValue>, String)>,
+    stack_base: usize,
+    variable_base: usize,
+}
+
+const CANCELLATION_INTERVAL: u64 = 1024;
+
+/// How many instructions run before the other tasks get a turn, see `is_switch_point`
+const TIME_SLICE: u64 = 256;
+
+/// A task made by `spawn`, it takes turns with the program and the other tasks to run its
+/// procedure
+#[derive(Debug, Clone)]
+struct Task {
+    procedure: Rc<[Bytecode]>,
+    state: TaskState,
+}
+
+#[derive(Debug, Clone)]
+enum TaskState {
+    /// Waiting for its next turn, with where to carry on from
+    Paused {
+        ip: usize,
+        stack: Vec<VmValue>,
+        vars: Vars,
+    },
+    /// Further down the rust stack, so it can't be given another turn until it pauses
+    Running,
+    Finished(VmValue),
+}
+
+// how far a call got before `run_frame` gave back control
+enum Step {
+    /// The call returned, `None` if it exited the program
+    Returned(Option<VmValue>),
+    /// The outermost call of a task stopped before the instruction at `ip` to carry on later,
+    /// `blocked` if it is waiting for something instead of giving the others a turn
+    Paused { blocked: bool },
+}
 
-            Bytecode::Push(value) => stack.push(Rc::new(RefCell::new(value.clone()))),
+pub type PrintHook = Rc<dyn Fn(&str)>;
+pub type CallHook = Rc<dyn Fn(&VmValue, usize)>;
+pub type ErrorHook = Rc<dyn Fn(&RuntimeError)>;
+pub type InstructionHook = Rc<dyn Fn(&Bytecode, usize, &[VmValue])>;
 
-            Bytecode::Pop => {
-                stack.pop().unwrap();
+/// Callbacks for programs that embed the vm
+#[derive(Clone, Default)]
+pub struct Hooks {
+    /// Gets everything that would be printed, instead of it being printed
+    pub on_print: Option<PrintHook>,
+    /// Called before every call with the procedure or builtin and the number of arguments
+    pub on_call: Option<CallHook>,
+    /// Called once with any runtime error that stops an execution, before it is returned
+    pub on_error: Option<ErrorHook>,
+    /// Called before every instruction with its index and the stack of the current call, top
+    /// last. Procedures have their own bytecode so their indices start again from 0
+    pub on_instruction: Option<InstructionHook>,
+}
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_print", &self.on_print.is_some())
+            .field("on_call", &self.on_call.is_some())
+            .field("on_error", &self.on_error.is_some())
+            .field("on_instruction", &self.on_instruction.is_some())
+            .finish()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm::default()
+    }
+
+    /// Calls a procedure or builtin value with the arguments in source order
+    pub fn call(
+        &mut self,
+        procedure: &VmValue,
+        arguments: Vec<VmValue>,
+    ) -> Result<Option<VmValue>, RuntimeError> {
+        if let Some(on_call) = self.hooks.on_call.clone() {
+            on_call(procedure, arguments.len());
+        }
+        match procedure {
+            VmValue::Object(object) if object.as_procedure().is_some() => {
+                self.execute(object.unwrap_procedure(), arguments, &mut HashMap::new())
+            }
+            VmValue::Native(id) => {
+                let result = (get_native(*id).function)(self, &arguments)
+                    .inspect_err(|error| self.report_error(error))?;
+                Ok(Some(result))
             }
+            // only reachable from outside, the binder only allows calling procedures
+            other => {
+                let error = RuntimeError {
+                    message: format!("Unable to call {}", describe_vm_value(other)),
+                };
+                self.report_error(&error);
+                Err(error)
+            }
+        }
+    }
 
-            Bytecode::Dup => stack.push(stack.last().unwrap().clone()),
+    // the outermost call of the program, not of a procedure or a task
+    fn is_outermost(&self) -> bool {
+        self.stack_base == 0 && self.current_task.is_none()
+    }
 
-            Bytecode::Call { argument_count } => {
-                let mut new_stack = vec![];
-                for _ in 0..*argument_count {
-                    new_stack.push(stack.pop().unwrap());
-                }
-                let procedure = stack.pop().unwrap();
-                stack.push(
-                    execute_bytecode(&procedure.borrow().unwrap_procedure(), new_stack).unwrap(),
-                );
+    /// The value of the task once it has finished, `None` if the task that is running has
+    /// to pause until it has
+    fn join(&mut self, id: usize) -> Result<Option<VmValue>, RuntimeError> {
+        loop {
+            match &self.tasks[id].state {
+                TaskState::Finished(value) => return Ok(Some(value.clone())),
+                // it is further down the stack waiting for this one, or it is this one
+                TaskState::Running => return Err(deadlock()),
+                TaskState::Paused { .. } if self.can_pause => return Ok(None),
+                TaskState::Paused { .. } => {}
             }
+            if !self.run_tasks()? {
+                return Err(deadlock());
+            }
+        }
+    }
 
-            Bytecode::Return => return Some(stack.pop().unwrap()),
+    /// Makes a new channel with nothing in it and returns its handle
+    pub fn make_channel(&mut self) -> VmValue {
+        self.channels.push(VecDeque::new());
+        VmValue::Integer(self.channels.len() as i64 - 1)
+    }
 
-            Bytecode::Load(name) => stack.push(vars.get(name).unwrap().clone()),
+    /// Sends the value to the channel, channels have no limit so this never waits
+    pub fn send(&mut self, channel: i64, value: VmValue) -> Result<(), RuntimeError> {
+        self.channel(channel)?.push_back(value);
+        Ok(())
+    }
 
-            Bytecode::Store(name) => {
-                vars.insert(name.clone(), stack.pop().unwrap());
+    /// The oldest value in the channel that hasn't been received yet, waiting for one to be
+    /// sent if there isn't one. A task that has to wait is paused and this gives back void
+    pub fn receive(&mut self, channel: i64) -> Result<VmValue, RuntimeError> {
+        loop {
+            if let Some(value) = self.channel(channel)?.pop_front() {
+                return Ok(value);
+            }
+            if self.can_pause {
+                self.blocked = true;
+                return Ok(VmValue::Void);
             }
+            if !self.run_tasks()? {
+                return Err(deadlock());
+            }
+        }
+    }
+
+    fn channel(&mut self, channel: i64) -> Result<&mut VecDeque<VmValue>, RuntimeError> {
+        // like tasks, a session can keep a handle after the vm that made it is gone
+        usize::try_from(channel)
+            .ok()
+            .and_then(|index| self.channels.get_mut(index))
+            .ok_or_else(|| RuntimeError {
+                message: format!("Unable to use channel {}, it is from another run", channel),
+            })
+    }
 
-            Bytecode::AddInteger => {
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(Rc::new(RefCell::new(BytecodeValue::Integer(
-                    a.borrow().unwrap_integer() + b.borrow().unwrap_integer(),
-                ))));
+    // gives every paused task a turn in the order they were spawned, tasks spawned during
+    // the round wait for the next one. Returns whether any of them got anywhere
+    fn run_tasks(&mut self) -> Result<bool, RuntimeError> {
+        let mut progressed = false;
+        for id in 0..self.tasks.len() {
+            if let TaskState::Paused { .. } = self.tasks[id].state {
+                progressed |= self.resume(id)?;
             }
+        }
+        Ok(progressed)
+    }
 
-            Bytecode::SubInteger => {
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(Rc::new(RefCell::new(BytecodeValue::Integer(
-                    a.borrow().unwrap_integer() - b.borrow().unwrap_integer(),
-                ))));
+    // gives the paused tasks turns until all of them have finished
+    fn finish_tasks(&mut self) -> Result<(), RuntimeError> {
+        while self
+            .tasks
+            .iter()
+            .any(|task| matches!(task.state, TaskState::Paused { .. }))
+        {
+            if !self.run_tasks()? {
+                return Err(deadlock());
             }
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self, id: usize) -> Result<bool, RuntimeError> {
+        let TaskState::Paused {
+            mut ip,
+            mut stack,
+            mut vars,
+        } = std::mem::replace(&mut self.tasks[id].state, TaskState::Running)
+        else {
+            unreachable!("only paused tasks are resumed")
+        };
+        let procedure = self.tasks[id].procedure.clone();
+        let outer_task = self.current_task.replace(id);
+        let could_pause = std::mem::replace(&mut self.can_pause, true);
+        let start = self.instruction_count;
+        self.slice_start = start;
+        let result = self.run_frame(&procedure, &mut ip, &mut stack, &mut vars);
+        self.current_task = outer_task;
+        self.can_pause = could_pause;
 
-            Bytecode::MulInteger => {
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(Rc::new(RefCell::new(BytecodeValue::Integer(
-                    a.borrow().unwrap_integer() * b.borrow().unwrap_integer(),
-                ))));
+        let executed = self.instruction_count - start;
+        let (state, progressed) = match result? {
+            Step::Returned(value) => (TaskState::Finished(value.unwrap_or(VmValue::Void)), true),
+            // a task that stopped at the same thing it was waiting for got nowhere
+            Step::Paused { blocked } => (
+                TaskState::Paused { ip, stack, vars },
+                !blocked || executed > 1,
+            ),
+        };
+        self.tasks[id].state = state;
+        Ok(progressed)
+    }
+
+    // errors are reported where they leave the vm, not in every call they pass through
+    fn report_error(&self, error: &RuntimeError) {
+        if self.stack_base == 0 {
+            if let Some(on_error) = &self.hooks.on_error {
+                on_error(error);
             }
+        }
+    }
+
+    /// Prints to `hooks.on_print`, `captured_output` or stdout, whichever is set first, without
+    /// the `native` feature there is no stdout and printing without the others is an error
+    pub fn write_output(&mut self, text: &str) -> Result<(), RuntimeError> {
+        if self
+            .max_output
+            .is_some_and(|max_output| self.output_size + text.len() > max_output)
+        {
+            return Err(RuntimeError::output_limit_reached());
+        }
+        self.output_size += text.len();
+        if let Some(on_print) = &self.hooks.on_print {
+            on_print(text);
+            return Ok(());
+        }
+        if let Some(output) = &mut self.captured_output {
+            *output += text;
+            return Ok(());
+        }
+        self.write_stdout(text)
+    }
 
-            Bytecode::DivInteger => {
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(Rc::new(RefCell::new(BytecodeValue::Integer(
-                    a.borrow().unwrap_integer() / b.borrow().unwrap_integer(),
-                ))));
+    #[cfg(feature = "native")]
+    fn write_stdout(&self, text: &str) -> Result<(), RuntimeError> {
+        std::io::stdout()
+            .write_all(text.as_bytes())
+            .map_err(|error| {
+                if error.kind() == ErrorKind::BrokenPipe {
+                    RuntimeError::output_closed()
+                } else {
+                    RuntimeError {
+                        message: format!("Unable to print: {}", error),
+                    }
+                }
+            })
+    }
+
+    #[cfg(not(feature = "native"))]
+    fn write_stdout(&self, _text: &str) -> Result<(), RuntimeError> {
+        Err(RuntimeError {
+            message: "Unable to print, there is no stdout without the native feature".to_string(),
+        })
+    }
+
+    /// Runs the bytecode with `stack` holding the arguments in source order
+    pub fn execute(
+        &mut self,
+        bytecode: &[Bytecode],
+        stack: Vec<VmValue>,
+        vars: &mut Vars,
+    ) -> Result<Option<VmValue>, RuntimeError> {
+        let outermost = self.is_outermost();
+        self.execute_frame(bytecode, stack, vars)
+            .and_then(|result| {
+                // the tasks that were never joined still get to finish, and their errors are
+                // the program's errors
+                if outermost {
+                    self.finish_tasks()?;
+                }
+                Ok(result)
+            })
+            .inspect_err(|error| self.report_error(error))
+    }
+
+    fn execute_frame(
+        &mut self,
+        bytecode: &[Bytecode],
+        mut stack: Vec<VmValue>,
+        vars: &mut Vars,
+    ) -> Result<Option<VmValue>, RuntimeError> {
+        // the arguments start at `Frame::FIRST_ARGUMENT`
+        stack.insert(0, VmValue::Void);
+        // only the outermost call of a task can pause, the calls it makes have to finish first
+        let could_pause = std::mem::replace(&mut self.can_pause, false);
+        let result = self.run_frame(bytecode, &mut 0, &mut stack, vars);
+        self.can_pause = could_pause;
+        match result? {
+            Step::Returned(result) => Ok(result),
+            Step::Paused { .. } => unreachable!("only the outermost call of a task pauses"),
+        }
+    }
+
+    // runs from `ip` until the call returns, or until the task it is the outermost call of
+    // pauses. Then `ip` and `stack` are where to carry on from
+    fn run_frame(
+        &mut self,
+        bytecode: &[Bytecode],
+        ip: &mut usize,
+        stack: &mut Vec<VmValue>,
+        vars: &mut Vars,
+    ) -> Result<Step, RuntimeError> {
+        // for states the compiler should never be able to produce
+        macro_rules! internal_error {
+            ($($argument:tt)*) => {
+                panic!("{}", dump_state(&format!($($argument)*), bytecode, *ip, stack, vars))
+            };
+        }
+        macro_rules! pop {
+            () => {
+                match stack.pop() {
+                    Some(value) => value,
+                    None => internal_error!("Popped from an empty stack"),
+                }
+            };
+        }
+        macro_rules! pop_integer {
+            () => {{
+                match pop!() {
+                    VmValue::Integer(integer) => integer,
+                    other => {
+                        internal_error!("Expected an integer but got {}", describe_vm_value(&other))
+                    }
+                }
+            }};
+        }
+        macro_rules! pop_float {
+            () => {{
+                match pop!() {
+                    VmValue::Float(float) => float,
+                    other => {
+                        internal_error!("Expected a float but got {}", describe_vm_value(&other))
+                    }
+                }
+            }};
+        }
+        macro_rules! split_arguments {
+            ($count:expr) => {{
+                let count = $count;
+                if stack.len() < count {
+                    internal_error!(
+                        "Expected {} arguments but the stack only has {} values",
+                        count,
+                        stack.len()
+                    );
+                }
+                stack.split_off(stack.len() - count)
+            }};
+        }
+
+        loop {
+            if !self.tasks.is_empty()
+                && self.instruction_count - self.slice_start >= TIME_SLICE
+                && bytecode
+                    .get(*ip)
+                    .is_some_and(|instruction| is_switch_point(instruction, *ip))
+            {
+                if self.can_pause {
+                    return Ok(Step::Paused { blocked: false });
+                }
+                // the calls a task makes can't pause, so the task pauses once they return
+                if self.current_task.is_none() {
+                    self.run_tasks()?;
+                    self.slice_start = self.instruction_count;
+                }
             }
 
-            Bytecode::NegateInteger => {
-                let value = stack.pop().unwrap();
-                stack.push(Rc::new(RefCell::new(BytecodeValue::Integer(
-                    -value.borrow().unwrap_integer(),
-                ))));
+            self.instruction_count += 1;
+            if self.is_outermost() {
+                if let Some(coverage) = &mut self.coverage {
+                    if coverage.len() < bytecode.len() {
+                        coverage.resize(bytecode.len(), 0);
+                    }
+                    // a bad jump is reported as running past the end below
+                    if let Some(count) = coverage.get_mut(*ip) {
+                        *count += 1;
+                    }
+                }
+            }
+            if self.fuel.is_some_and(|fuel| self.instruction_count > fuel) {
+                return Err(RuntimeError::out_of_fuel());
+            }
+            if self.instruction_count.is_multiple_of(CANCELLATION_INTERVAL) {
+                if let Some(cancellation) = &self.cancellation {
+                    cancellation.check_runtime()?;
+                }
+                if self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    return Err(RuntimeError::timed_out());
+                }
             }
+            self.peak_stack_depth = self.peak_stack_depth.max(self.stack_base + stack.len());
+            self.peak_variable_count = self
+                .peak_variable_count
+                .max(self.variable_base + vars.len());
+            let Some(instruction) = bytecode.get(*ip) else {
+                internal_error!("Ran past the end of the bytecode");
+            };
+            if let Some(on_instruction) = &self.hooks.on_instruction {
+                on_instruction(instruction, *ip, stack);
+            }
+            match instruction {
+                Bytecode::Exit => return Ok(Step::Returned(None)),
+
+                Bytecode::Push(value) => stack.push(VmValue::from(value)),
+
+                Bytecode::Pop => {
+                    pop!();
+                }
+
+                Bytecode::Dup => {
+                    let value = pop!();
+                    stack.push(value.clone());
+                    stack.push(value);
+                }
+
+                Bytecode::Call { argument_count } => {
+                    // the arguments keep their order, see `Frame`
+                    let arguments = split_arguments!(*argument_count);
+                    let procedure = pop!();
+                    let is_procedure = procedure
+                        .as_object()
+                        .is_some_and(|object| object.as_procedure().is_some());
+                    if !is_procedure && !matches!(procedure, VmValue::Native(_)) {
+                        internal_error!("Called {}", describe_vm_value(&procedure));
+                    }
+                    self.stack_base += stack.len();
+                    self.variable_base += vars.len();
+                    // a builtin called this way waits where it is instead of pausing the task
+                    let could_pause = std::mem::replace(&mut self.can_pause, false);
+                    let result = self.call(&procedure, arguments);
+                    self.can_pause = could_pause;
+                    self.stack_base -= stack.len();
+                    self.variable_base -= vars.len();
+                    match result? {
+                        Some(result) => stack.push(result),
+                        // exiting inside a call stops the whole program
+                        None => return Ok(Step::Returned(None)),
+                    }
+                }
+
+                Bytecode::CallNative(id) => {
+                    let native = get_native(*id);
+                    if let Some(on_call) = self.hooks.on_call.clone() {
+                        on_call(&VmValue::Native(*id), native.parameter_types.len());
+                    }
+                    let arguments = split_arguments!(native.parameter_types.len());
+                    let result = (native.function)(self, &arguments)?;
+                    if std::mem::take(&mut self.blocked) {
+                        // the arguments are put back so the call happens again on the next turn
+                        stack.extend(arguments);
+                        return Ok(Step::Paused { blocked: true });
+                    }
+                    stack.push(result);
+                }
+
+                Bytecode::Return => return Ok(Step::Returned(Some(pop!()))),
 
-            Bytecode::PrintInteger => {
-                println!("{}", &stack.pop().unwrap().borrow().unwrap_integer());
+                Bytecode::Load(name) => stack.push(
+                    vars.get(name)
+                        .ok_or_else(|| RuntimeError {
+                            message: format!("Unable to find variable {}", name),
+                        })?
+                        .clone(),
+                ),
+
+                Bytecode::Store(name) => {
+                    let value = pop!();
+                    vars.insert(name.clone(), value);
+                }
+
+                Bytecode::Spawn => {
+                    let procedure = pop!();
+                    let Some(body) = procedure.as_object().and_then(BytecodeValue::as_procedure)
+                    else {
+                        internal_error!(
+                            "Expected a procedure but got {}",
+                            describe_vm_value(&procedure)
+                        )
+                    };
+                    let id = self.tasks.len();
+                    self.tasks.push(Task {
+                        procedure: body.clone(),
+                        state: TaskState::Paused {
+                            ip: 0,
+                            stack: vec![VmValue::Void],
+                            vars: Vars::new(),
+                        },
+                    });
+                    stack.push(VmValue::Integer(id as i64));
+                }
+
+                Bytecode::Join => {
+                    let id = match stack.last() {
+                        Some(VmValue::Integer(id)) => *id,
+                        Some(other) => {
+                            internal_error!("Expected a task but got {}", describe_vm_value(other))
+                        }
+                        None => internal_error!("Joined with an empty stack"),
+                    };
+                    // a session can keep a handle after the vm that spawned it is gone
+                    let Some(id) = usize::try_from(id).ok().filter(|&id| id < self.tasks.len())
+                    else {
+                        return Err(RuntimeError {
+                            message: format!("Unable to join task {}, it is from another run", id),
+                        });
+                    };
+                    match self.join(id)? {
+                        Some(value) => {
+                            pop!();
+                            stack.push(value);
+                        }
+                        // the handle stays on the stack for when it tries again
+                        None => return Ok(Step::Paused { blocked: true }),
+                    }
+                }
+
+                Bytecode::MakeBlock(names) => {
+                    let values = split_arguments!(names.len());
+                    let block = names.iter().cloned().zip(values).collect();
+                    stack.push(VmValue::from(BytecodeValue::Block(block)));
+                }
+
+                Bytecode::MakeClosure(names) => {
+                    let procedure = pop!();
+                    let Some(body) = procedure.as_object().and_then(BytecodeValue::as_procedure)
+                    else {
+                        internal_error!(
+                            "Expected a procedure but got {}",
+                            describe_vm_value(&procedure)
+                        )
+                    };
+                    let mut captures = vec![];
+                    for name in names {
+                        let value = vars.get(name).ok_or_else(|| RuntimeError {
+                            message: format!("Unable to find variable {}", name),
+                        })?;
+                        captures.push((name.clone(), value.to_bytecode_value()));
+                    }
+                    let closure = make_closure(body, captures);
+                    stack.push(VmValue::from(BytecodeValue::Procedure(closure.into())));
+                }
+
+                Bytecode::LoadMember(name) => {
+                    let block = pop!();
+                    let Some(members) = block.as_object().and_then(BytecodeValue::as_block) else {
+                        internal_error!("Expected a block but got {}", describe_vm_value(&block))
+                    };
+                    match members.get(name) {
+                        Some(member) => stack.push(member.clone()),
+                        None => {
+                            internal_error!("{} has no member {}", describe_vm_value(&block), name)
+                        }
+                    }
+                }
+
+                Bytecode::Freeze(site) => {
+                    let block = pop!();
+                    let VmValue::Object(object) = &block else {
+                        internal_error!("Expected a block but got {}", describe_vm_value(&block))
+                    };
+                    // a block that is frozen again keeps the site it was first frozen at
+                    self.frozen
+                        .entry(Rc::as_ptr(object))
+                        .or_insert_with(|| (object.clone(), site.clone()));
+                    stack.push(block);
+                }
+
+                Bytecode::CheckNotFrozen(name) => {
+                    if let Some(VmValue::Object(object)) = vars.get(name) {
+                        if let Some((_, site)) = self.frozen.get(&Rc::as_ptr(object)) {
+                            return Err(RuntimeError {
+                                message: format!(
+                                    "Unable to assign to {}, its value was frozen at {}",
+                                    name, site,
+                                ),
+                            });
+                        }
+                    }
+                }
+
+                Bytecode::Jump(target) => {
+                    *ip = *target;
+                    continue;
+                }
+
+                Bytecode::JumpIfZero(target) => {
+                    if pop_integer!() == 0 {
+                        *ip = *target;
+                        continue;
+                    }
+                }
+
+                Bytecode::IsNone => {
+                    let value = pop!();
+                    stack.push(VmValue::Integer((value == VmValue::None) as i64));
+                }
+
+                Bytecode::AddInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    let result = a.checked_add(b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to add {} and {} without overflowing", a, b),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::SubInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    let result = a.checked_sub(b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to subtract {} from {} without overflowing", b, a),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::MulInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    let result = a.checked_mul(b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to multiply {} by {} without overflowing", a, b),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::DivInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    let result = a.checked_div(b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to divide {} by {}", a, b),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::NegateInteger => {
+                    let value = pop_integer!();
+                    let result = value.checked_neg().ok_or_else(|| RuntimeError {
+                        message: format!("Unable to negate {} without overflowing", value),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::AndInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    stack.push(VmValue::Integer(a & b));
+                }
+
+                Bytecode::OrInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    stack.push(VmValue::Integer(a | b));
+                }
+
+                Bytecode::XorInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    stack.push(VmValue::Integer(a ^ b));
+                }
+
+                Bytecode::NotInteger => {
+                    let value = pop_integer!();
+                    stack.push(VmValue::Integer(!value));
+                }
+
+                Bytecode::EqualInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    stack.push(VmValue::Integer((a == b) as i64));
+                }
+
+                Bytecode::ShiftLeftInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    let result = shift_left(a, b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to shift {} by a negative amount {}", a, b),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::ShiftRightInteger => {
+                    let b = pop_integer!();
+                    let a = pop_integer!();
+                    let result = shift_right(a, b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to shift {} by a negative amount {}", a, b),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::AddConstInteger(b) => {
+                    let a = pop_integer!();
+                    let result = a.checked_add(*b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to add {} and {} without overflowing", a, b),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::AddLoadsInteger(a_name, b_name) => {
+                    let load_integer = |name: &String| match vars.get(name) {
+                        Some(VmValue::Integer(integer)) => Ok(*integer),
+                        Some(other) => internal_error!(
+                            "Expected an integer but got {}",
+                            describe_vm_value(other)
+                        ),
+                        None => Err(RuntimeError {
+                            message: format!("Unable to find variable {}", name),
+                        }),
+                    };
+                    let a = load_integer(a_name)?;
+                    let b = load_integer(b_name)?;
+                    let result = a.checked_add(b).ok_or_else(|| RuntimeError {
+                        message: format!("Unable to add {} and {} without overflowing", a, b),
+                    })?;
+                    stack.push(VmValue::Integer(result));
+                }
+
+                Bytecode::DupStore(name) => {
+                    let value = match stack.last() {
+                        Some(value) => value.clone(),
+                        None => internal_error!("Duplicated from an empty stack"),
+                    };
+                    vars.insert(name.clone(), value);
+                }
+
+                // floats never error, they become an infinity or nan instead
+                Bytecode::AddFloat => {
+                    let b = pop_float!();
+                    let a = pop_float!();
+                    stack.push(VmValue::Float(a + b));
+                }
+
+                Bytecode::SubFloat => {
+                    let b = pop_float!();
+                    let a = pop_float!();
+                    stack.push(VmValue::Float(a - b));
+                }
+
+                Bytecode::MulFloat => {
+                    let b = pop_float!();
+                    let a = pop_float!();
+                    stack.push(VmValue::Float(a * b));
+                }
+
+                Bytecode::DivFloat => {
+                    let b = pop_float!();
+                    let a = pop_float!();
+                    stack.push(VmValue::Float(a / b));
+                }
+
+                Bytecode::NegateFloat => {
+                    let value = pop_float!();
+                    stack.push(VmValue::Float(-value));
+                }
             }
+            *ip += 1;
         }
-        ip += 1;
     }
 }
+
+// where the code that is running can give the other tasks a turn, every loop has a call or
+// a jump back in it so none of them can keep the others from running
+fn is_switch_point(instruction: &Bytecode, ip: usize) -> bool {
+    match instruction {
+        Bytecode::Call { .. } | Bytecode::CallNative(_) => true,
+        Bytecode::Jump(target) | Bytecode::JumpIfZero(target) => *target <= ip,
+        _ => false,
+    }
+}
+
+fn deadlock() -> RuntimeError {
+    RuntimeError {
+        message: "Deadlock, every task is waiting for another one".to_string(),
+    }
+}
+
+/// Bits shifted past the top are dropped, so shifting by 64 or more gives 0, `None` if
+/// `amount` is negative
+pub fn shift_left(value: i64, amount: i64) -> Option<i64> {
+    if amount < 0 {
+        return None;
+    }
+    Some(if amount < 64 { value << amount } else { 0 })
+}
+
+/// Keeps the sign, so shifting by 64 or more gives 0 for positive values and -1 for negative
+/// ones, `None` if `amount` is negative
+pub fn shift_right(value: i64, amount: i64) -> Option<i64> {
+    if amount < 0 {
+        return None;
+    }
+    Some(value >> amount.min(63))
+}
+
+fn describe_vm_value(value: &VmValue) -> String {
+    match value {
+        VmValue::Object(object) => describe_value(object),
+        other => describe_value(&other.to_bytecode_value()),
+    }
+}
+
+fn describe_value(value: &BytecodeValue) -> String {
+    match value {
+        BytecodeValue::Void => "void".to_string(),
+        BytecodeValue::None => "none".to_string(),
+        BytecodeValue::Integer(integer) => format!("integer {}", integer),
+        BytecodeValue::Float(float) => format!("float {:?}", float),
+        BytecodeValue::String(string) => format!("string {}", quote_string(string)),
+        BytecodeValue::Procedure(procedure) => {
+            format!("procedure of {} instructions", procedure.len())
+        }
+        BytecodeValue::Native(id) => format!("builtin {}", get_native(*id).full_name()),
+        BytecodeValue::Block(block) => {
+            let mut names: Vec<_> = block.keys().map(|name| name as &str).collect();
+            names.sort();
+            format!("block {{ {} }}", names.join(", "))
+        }
+    }
+}
+
+/// Formats everything the vm knows about the current call, for bug reports
+pub fn dump_state(
+    message: &str,
+    bytecode: &[Bytecode],
+    ip: usize,
+    stack: &[VmValue],
+    vars: &Vars,
+) -> String {
+    let mut result = format!("Internal VM error: {}\n", message);
+    result += "Instructions:\n";
+    let window = ip.saturating_sub(3)..(ip + 4).min(bytecode.len());
+    for i in window {
+        let text = format_bytecode(&bytecode[i..i + 1]);
+        // only the first line of a procedure
+        let text = text.lines().next().unwrap_or_default();
+        let marker = if i == ip { ">" } else { " " };
+        result += &format!("  {} {:4}: {}\n", marker, i, text);
+    }
+    result += "Stack, top last:\n";
+    for (i, value) in stack.iter().enumerate() {
+        result += &format!("    {:4}: {}\n", i, describe_vm_value(value));
+    }
+    result += "Variables:\n";
+    let mut names: Vec<_> = vars.keys().collect();
+    names.sort();
+    for name in names {
+        result += &format!("    {}: {}\n", name, describe_vm_value(&vars[name]));
+    }
+    result
+}
+
+/// The body of a procedure that stores the captured values before running `procedure`, every
+/// call of it starts with them in its variables
+pub fn make_closure(
+    procedure: &[Bytecode],
+    captures: Vec<(String, BytecodeValue)>,
+) -> Vec<Bytecode> {
+    let offset = captures.len() * 2;
+    let mut closure = Vec::with_capacity(offset + procedure.len());
+    for (name, value) in captures {
+        closure.push(Bytecode::Push(value));
+        closure.push(Bytecode::Store(name));
+    }
+    // the jumps of the procedure move along with it, the ones in nested procedures are
+    // relative to those
+    closure.extend(procedure.iter().map(|instruction| match instruction {
+        Bytecode::Jump(target) => Bytecode::Jump(target + offset),
+        Bytecode::JumpIfZero(target) => Bytecode::JumpIfZero(target + offset),
+        instruction => instruction.clone(),
+    }));
+    closure
+}
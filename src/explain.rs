@@ -0,0 +1,105 @@
+use std::{cell::RefCell, fmt::Write, rc::Rc};
+
+use crate::{
+    bytecode_text::{explain_bytecode, format_instruction},
+    common::Diagnostics,
+    diff::outline,
+    emit::Stage,
+    execute::Vm,
+    lexer::{quote_string, Lexer},
+    program::Program,
+    token::TokenKind,
+    value::Value,
+};
+
+/// How many instructions the trace of `explain_pipeline` shows before it gives up, the
+/// programs it is meant for are small and a loop would print forever
+pub const DEFAULT_MAX_STEPS: u64 = 1000;
+
+/// Every stage a file goes through on its way to running, one section per stage
+///
+/// The sections are the tokens, the parse tree, the bound tree with the type of every node,
+/// the bytecode under the source lines it came from and a trace of the execution. The trace
+/// has a line per instruction with the stack before it runs, what the program prints and the
+/// error that stops it, it stops after `max_steps` instructions
+pub fn explain_pipeline(
+    filepath: String,
+    source: &str,
+    max_steps: u64,
+) -> Result<String, Diagnostics> {
+    let mut result = String::new();
+
+    write_section(&mut result, "tokens");
+    let mut lexer = Lexer::new(filepath.clone(), source);
+    loop {
+        let token = lexer.next_token()?;
+        writeln!(
+            result,
+            "{}:{}: {:?}",
+            token.location.line, token.location.column, token.kind
+        )
+        .unwrap();
+        if token.kind == TokenKind::EndOfFile {
+            break;
+        }
+    }
+
+    for (stage, name) in [(Stage::Ast, "parse tree"), (Stage::Ir, "bound tree")] {
+        write_section(&mut result, name);
+        for line in outline(stage, filepath.clone(), source)? {
+            writeln!(result, "{}", line).unwrap();
+        }
+    }
+
+    let mut program = Program::compile(filepath, source)?;
+    write_section(&mut result, "bytecode");
+    result += &explain_bytecode(program.get_bytecode(), program.get_lines(), source);
+
+    write_section(&mut result, "execution");
+    // the hooks outlive the borrow of the vm, so the trace is shared with them
+    let trace = Rc::new(RefCell::new(String::new()));
+    let mut vm = Vm::new();
+    vm.fuel = Some(max_steps);
+    vm.hooks.on_instruction = Some({
+        let trace = trace.clone();
+        Rc::new(move |instruction, ip, stack| {
+            // the first slot of every call is not one of its values
+            let values: Vec<String> = stack
+                .iter()
+                .skip(1)
+                .map(|value| Value::from(value).to_string())
+                .collect();
+            writeln!(
+                trace.borrow_mut(),
+                "{:>4}  {:<24} [{}]",
+                ip,
+                format_instruction(instruction),
+                values.join(", ")
+            )
+            .unwrap();
+        })
+    });
+    vm.hooks.on_print = Some({
+        let trace = trace.clone();
+        Rc::new(move |text| writeln!(trace.borrow_mut(), "printed {}", quote_string(text)).unwrap())
+    });
+    let error = program.run_with(&mut vm).err();
+    result += &trace.borrow();
+    match error {
+        Some(error) => writeln!(result, "error: {}", error.message).unwrap(),
+        None => writeln!(
+            result,
+            "finished after {} instructions",
+            vm.instruction_count
+        )
+        .unwrap(),
+    }
+    Ok(result)
+}
+
+fn write_section(result: &mut String, name: &str) {
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    writeln!(result, "== {} ==", name).unwrap();
+}
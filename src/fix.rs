@@ -0,0 +1,101 @@
+use crate::{
+    common::{Diagnostics, Suggestion},
+    lexer::Lexer,
+    lint::lint_precedence,
+    parsing::parse_file,
+    program::Program,
+};
+
+// fixing one error can uncover the next, like when the parser stops at the first
+// missing newline, so the source is compiled again after every round of fixes
+const MAX_FIX_ROUNDS: usize = 100;
+
+/// Which warnings get fixed too, errors are always fixed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FixOptions {
+    pub lint_precedence: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixResult {
+    pub source: String,
+    /// The suggestions in the order they were applied, each location is in the source
+    /// as it was after the fixes before it
+    pub applied: Vec<Suggestion>,
+    /// The errors that had no suggestion
+    pub remaining: Diagnostics,
+}
+
+/// Applies the suggestions of the errors until the source compiles or there is nothing left to fix
+pub fn fix_source(filepath: String, source: &str) -> FixResult {
+    fix_source_with(filepath, source, FixOptions::default())
+}
+
+pub fn fix_source_with(filepath: String, source: &str, options: FixOptions) -> FixResult {
+    let mut source = source.to_string();
+    let mut applied = vec![];
+    for _ in 0..MAX_FIX_ROUNDS {
+        let diagnostics = Program::compile(filepath.clone(), &source)
+            .err()
+            .unwrap_or_default();
+        let mut suggestions: Vec<Suggestion> = diagnostics
+            .errors
+            .iter()
+            .flat_map(|error| error.suggestions.iter().cloned())
+            .collect();
+        if options.lint_precedence {
+            let mut lexer = Lexer::new(filepath.clone(), &source);
+            if let Ok(file) = parse_file(&mut lexer) {
+                for warning in lint_precedence(&file, &source) {
+                    suggestions.extend(warning.suggestions);
+                }
+            }
+        }
+
+        let round = apply_suggestions(&mut source, suggestions);
+        if round.is_empty() {
+            return FixResult {
+                source,
+                applied,
+                remaining: diagnostics,
+            };
+        }
+        applied.extend(round);
+    }
+
+    let remaining = Program::compile(filepath, &source)
+        .err()
+        .unwrap_or_default();
+    FixResult {
+        source,
+        applied,
+        remaining,
+    }
+}
+
+fn apply_suggestions(source: &mut String, mut suggestions: Vec<Suggestion>) -> Vec<Suggestion> {
+    suggestions.sort_by_key(|suggestion| suggestion.location.position);
+
+    // applied from the end so the positions before them stay the same, ones that
+    // overlap are left for the next round
+    let mut applied = vec![];
+    let mut end = None;
+    for suggestion in suggestions.into_iter().rev() {
+        let mut start = suggestion.location.position;
+        let replaced_end = start + suggestion.length;
+        if let Some(end) = end {
+            if replaced_end > end || start == end {
+                continue;
+            }
+        }
+        if suggestion.replacement.starts_with('\n') {
+            // don't leave trailing whitespace behind when splitting a line
+            start = source[..start].trim_end_matches([' ', '\t']).len();
+        }
+        source.replace_range(start..replaced_end, &suggestion.replacement);
+        end = Some(start);
+        applied.push(suggestion);
+    }
+    applied.reverse();
+    applied
+}
@@ -0,0 +1,31 @@
+use crate::{
+    common::{Diagnostics, RuntimeError},
+    execute::Vm,
+    program::Program,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FuzzOutcome {
+    CompileErrors(Diagnostics),
+    RuntimeError(RuntimeError),
+    Finished,
+}
+
+/// Runs arbitrary input through the whole pipeline, for fuzzers
+///
+/// This must never panic whatever the input is, any panic is a bug. Execution stops after
+/// `fuel` instructions and everything printed is thrown away
+pub fn fuzz_compile_and_run(bytes: &[u8], fuel: u64) -> FuzzOutcome {
+    let source = String::from_utf8_lossy(bytes);
+    let mut program = match Program::compile("fuzz.lang".to_string(), &source) {
+        Ok(program) => program,
+        Err(diagnostics) => return FuzzOutcome::CompileErrors(diagnostics),
+    };
+    let mut vm = Vm::new();
+    vm.captured_output = Some(String::new());
+    vm.fuel = Some(fuel);
+    match program.run_with(&mut vm) {
+        Ok(()) => FuzzOutcome::Finished,
+        Err(error) => FuzzOutcome::RuntimeError(error),
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt::Write;
+
+use crate::{natives::NATIVES, token::TokenKind, types::Type};
+
+/// The words that lex as keywords instead of names
+pub static KEYWORDS: &[(&str, TokenKind)] = &[
+    ("enum", TokenKind::Enum),
+    ("export", TokenKind::Export),
+    ("freeze", TokenKind::Freeze),
+    ("join", TokenKind::Join),
+    ("let", TokenKind::Let),
+    ("match", TokenKind::Match),
+    ("none", TokenKind::None),
+    ("spawn", TokenKind::Spawn),
+];
+
+/// Every token that is always spelled the same way, apart from the keywords, the lexer
+/// takes the longest one that matches
+pub static SYMBOLS: &[(&str, TokenKind)] = &[
+    // Brackets
+    ("(", TokenKind::OpenParenthesis),
+    (")", TokenKind::CloseParenthesis),
+    ("{", TokenKind::OpenBrace),
+    ("}", TokenKind::CloseBrace),
+    // Symbols
+    ("<-", TokenKind::LeftArrow),
+    ("->", TokenKind::RightArrow),
+    (",", TokenKind::Comma),
+    (".", TokenKind::Dot),
+    // Operators
+    ("+", TokenKind::Plus),
+    ("-", TokenKind::Minus),
+    ("*", TokenKind::Asterisk),
+    ("/", TokenKind::Slash),
+    ("!", TokenKind::ExclamationMark),
+    ("?", TokenKind::QuestionMark),
+    ("??", TokenKind::QuestionMarkQuestionMark),
+    // Bitwise Operators
+    ("&", TokenKind::Ampersand),
+    ("|", TokenKind::Pipe),
+    ("^", TokenKind::Caret),
+    ("~", TokenKind::Tilde),
+    ("<<", TokenKind::LessThanLessThan),
+    (">>", TokenKind::GreaterThanGreaterThan),
+    // Comparison Operators
+    ("==", TokenKind::EqualEqual),
+    ("!=", TokenKind::ExclamationMarkEqual),
+    ("<", TokenKind::LessThan),
+    (">", TokenKind::GreaterThan),
+    ("<=", TokenKind::LessThanEqual),
+    (">=", TokenKind::GreaterThanEqual),
+    // Logical Operators
+    ("&&", TokenKind::AmpersandAmpersand),
+    ("||", TokenKind::PipePipe),
+    // Assignment Operators
+    ("=", TokenKind::Equal),
+    ("+=", TokenKind::PlusEqual),
+    ("-=", TokenKind::MinusEqual),
+    ("*=", TokenKind::AsteriskEqual),
+    ("/=", TokenKind::SlashEqual),
+];
+
+/// The prefix operators and their precedence, a higher precedence binds tighter
+pub static UNARY_OPERATORS: &[(TokenKind, usize)] = &[
+    (TokenKind::Plus, 11),
+    (TokenKind::Minus, 11),
+    (TokenKind::ExclamationMark, 11),
+    (TokenKind::Tilde, 11),
+    (TokenKind::QuestionMark, 11),
+];
+
+/// The infix operators and their precedence, they are all left associative
+pub static BINARY_OPERATORS: &[(TokenKind, usize)] = &[
+    (TokenKind::Asterisk, 10),
+    (TokenKind::Slash, 10),
+    (TokenKind::Plus, 9),
+    (TokenKind::Minus, 9),
+    (TokenKind::LessThanLessThan, 8),
+    (TokenKind::GreaterThanGreaterThan, 8),
+    (TokenKind::Ampersand, 7),
+    (TokenKind::Caret, 6),
+    (TokenKind::Pipe, 5),
+    (TokenKind::EqualEqual, 4),
+    (TokenKind::ExclamationMarkEqual, 4),
+    (TokenKind::LessThan, 4),
+    (TokenKind::GreaterThan, 4),
+    (TokenKind::LessThanEqual, 4),
+    (TokenKind::GreaterThanEqual, 4),
+    (TokenKind::AmpersandAmpersand, 3),
+    (TokenKind::PipePipe, 2),
+    (TokenKind::QuestionMarkQuestionMark, 1),
+];
+
+pub fn find_keyword(name: &str) -> Option<TokenKind> {
+    KEYWORDS
+        .iter()
+        .find(|(keyword, _)| *keyword == name)
+        .map(|(_, kind)| kind.clone())
+}
+
+/// 0 if the token is not a unary operator
+pub fn get_unary_precedence(kind: &TokenKind) -> usize {
+    find_precedence(UNARY_OPERATORS, kind)
+}
+
+/// 0 if the token is not a binary operator
+pub fn get_binary_precedence(kind: &TokenKind) -> usize {
+    find_precedence(BINARY_OPERATORS, kind)
+}
+
+fn find_precedence(operators: &[(TokenKind, usize)], kind: &TokenKind) -> usize {
+    operators
+        .iter()
+        .find(|(operator, _)| operator == kind)
+        .map_or(0, |&(_, precedence)| precedence)
+}
+
+/// Describes the keywords, symbols, operators and builtins, for editor plugins and docs
+pub fn dump_grammar() -> String {
+    let mut result = String::new();
+
+    writeln!(result, "keywords").unwrap();
+    for (keyword, _) in KEYWORDS {
+        writeln!(result, "    {}", keyword).unwrap();
+    }
+
+    writeln!(result, "symbols").unwrap();
+    for (symbol, _) in SYMBOLS {
+        writeln!(result, "    {}", symbol).unwrap();
+    }
+
+    writeln!(result, "unary operators").unwrap();
+    for (operator, precedence) in UNARY_OPERATORS {
+        writeln!(
+            result,
+            "    {} precedence {}",
+            operator.to_string(),
+            precedence
+        )
+        .unwrap();
+    }
+
+    writeln!(result, "binary operators, left associative").unwrap();
+    for (operator, precedence) in BINARY_OPERATORS {
+        writeln!(
+            result,
+            "    {} precedence {}",
+            operator.to_string(),
+            precedence
+        )
+        .unwrap();
+    }
+
+    writeln!(result, "builtins").unwrap();
+    for native in NATIVES {
+        writeln!(
+            result,
+            "    {}: {}",
+            native.full_name(),
+            Type::Proc(native.get_type())
+        )
+        .unwrap();
+    }
+
+    result
+}
+
+/// A rule of the grammar, written in EBNF
+#[derive(Debug, Clone, PartialEq)]
+pub struct Production {
+    pub name: String,
+    pub rule: String,
+}
+
+/// The grammar the parser accepts, the operator rules are generated from the operator tables
+/// and the rest has to be kept in sync with `parsing.rs` by hand
+pub fn productions() -> Vec<Production> {
+    fn production(name: &str, rule: &str) -> Production {
+        Production {
+            name: name.to_string(),
+            rule: rule.to_string(),
+        }
+    }
+
+    let precedences = binary_precedences();
+    let mut productions = vec![
+        production(
+            "file",
+            "[ { newline } expression { newline { newline } expression } [ newline ] ] ? the end of file ?",
+        ),
+        // only a name can be assigned to, anything else on the left is an error
+        production(
+            "expression",
+            &format!(
+                "{} [ \"=\" [ newline ] expression ]",
+                binary_production_name(&precedences, 0)
+            ),
+        ),
+    ];
+
+    // every precedence is its own rule, from loosest to tightest
+    for (index, &precedence) in precedences.iter().enumerate() {
+        let next = binary_production_name(&precedences, index + 1);
+        productions.push(production(
+            &binary_production_name(&precedences, index),
+            &format!(
+                "{next} {{ {} [ newline ] {next} }}",
+                operator_alternatives(BINARY_OPERATORS, precedence),
+            ),
+        ));
+    }
+
+    let unary_precedence = UNARY_OPERATORS
+        .iter()
+        .map(|&(_, precedence)| precedence)
+        .max()
+        .unwrap_or(0);
+    // the operand of a unary operator can still contain operators that bind tighter than it
+    let operand = precedences
+        .iter()
+        .position(|&precedence| precedence > unary_precedence)
+        .map_or("unary".to_string(), |index| {
+            binary_production_name(&precedences, index)
+        });
+    productions.push(production(
+        "unary",
+        &format!(
+            "{} [ newline ] {} | postfix",
+            operator_alternatives(UNARY_OPERATORS, unary_precedence),
+            operand,
+        ),
+    ));
+
+    productions.extend([
+        production(
+            "postfix",
+            "primary { \"(\" [ newline ] [ expression { \",\" [ newline ] expression } [ \",\" [ newline ] ] ] \")\" | \".\" name }",
+        ),
+        production(
+            "primary",
+            "name | integer | float | string | \"none\" | block | \"(\" expression \")\" | export | let | match | enum | freeze | spawn | join",
+        ),
+        production(
+            "block",
+            "\"{\" [ { newline } expression { newline { newline } expression } [ newline ] ] \"}\"",
+        ),
+        production("export", "\"export\" name \"=\" [ newline ] expression"),
+        production(
+            "let",
+            "\"let\" ( name [ \"=\" [ newline ] expression ] | \"{\" [ newline ] name { \",\" [ newline ] name } [ \",\" [ newline ] ] \"}\" \"=\" [ newline ] expression )",
+        ),
+        production(
+            "match",
+            "\"match\" expression \"{\" { newline } arm { newline { newline } arm } { newline } \"}\"",
+        ),
+        // the binder checks that there is exactly one _ arm and that it comes last
+        production(
+            "arm",
+            "( [ \"-\" ] integer | name \".\" name | \"_\" ) \"->\" [ newline ] expression",
+        ),
+        production(
+            "enum",
+            "\"enum\" \"{\" [ newline ] [ name { \",\" [ newline ] name } [ \",\" [ newline ] ] ] \"}\"",
+        ),
+        production("freeze", "\"freeze\" [ newline ] expression"),
+        production("spawn", "\"spawn\" [ newline ] expression"),
+        production("join", "\"join\" postfix"),
+        production(
+            "name",
+            "? a letter or _ followed by letters, digits and _, that is not a keyword ?",
+        ),
+        production(
+            "integer",
+            "? digits, with an optional 0b, 0o, 0d or 0x prefix for the base ?",
+        ),
+        production(
+            "float",
+            "? decimal digits with a fractional part like 1.5, an exponent like 1e9, or both ?",
+        ),
+        production(
+            "string",
+            "\"\\\"\" ? any characters but \" and newlines, with \\n \\r \\t \\0 \\\\ and \\\" escapes ? \"\\\"\"",
+        ),
+        production("newline", "? \\n, \\r, \\r\\n or \\n\\r ?"),
+    ]);
+
+    productions
+}
+
+/// Writes out `productions` in EBNF, one rule per line
+pub fn dump_ebnf() -> String {
+    let mut result = String::new();
+    for production in productions() {
+        writeln!(result, "{} = {} ;", production.name, production.rule).unwrap();
+    }
+    result
+}
+
+// the distinct binary precedences, loosest first
+fn binary_precedences() -> Vec<usize> {
+    let mut precedences: Vec<usize> = BINARY_OPERATORS
+        .iter()
+        .map(|&(_, precedence)| precedence)
+        .collect();
+    precedences.sort();
+    precedences.dedup();
+    precedences
+}
+
+fn binary_production_name(precedences: &[usize], index: usize) -> String {
+    match precedences.get(index) {
+        Some(precedence) => format!("binary_{}", precedence),
+        None => "unary".to_string(),
+    }
+}
+
+fn operator_alternatives(operators: &[(TokenKind, usize)], precedence: usize) -> String {
+    let alternatives: Vec<String> = operators
+        .iter()
+        .filter(|&&(_, operator_precedence)| operator_precedence == precedence)
+        .map(|(operator, _)| format!("\"{}\"", operator.to_string()))
+        .collect();
+    format!("( {} )", alternatives.join(" | "))
+}
@@ -0,0 +1,230 @@
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use crate::common::{CompileError, CompileNote, ErrorKind, SourceLocation};
+
+/// Where to report internal compiler errors
+pub const ISSUES_URL: &str = "https://github.com/HomelikeBrick42/TestExprLang/issues";
+
+// how many times the input is compiled again while minimizing it, so a big file doesn't take
+// forever to report
+const MAX_MINIMIZE_ATTEMPTS: usize = 200;
+
+/// The part of compilation a panic happened in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Parsing,
+    Binding,
+    Linting,
+    Compiling,
+}
+
+impl Phase {
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Phase::Parsing => "parsing",
+            Phase::Binding => "binding",
+            Phase::Linting => "linting",
+            Phase::Compiling => "compiling",
+        }
+    }
+}
+
+/// What the compiler was working on
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseContext {
+    pub phase: Phase,
+    pub location: SourceLocation,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Option<PhaseContext>> = const { RefCell::new(None) };
+    // how many `catch_internal_errors` are running, the panic hook stays quiet inside them
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Puts back the context from before `track` when it is dropped
+pub struct ContextGuard {
+    previous: Option<PhaseContext>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        // while unwinding the context is left as it was when the panic happened, so it can
+        // still be reported once the panic is caught
+        if !std::thread::panicking() {
+            CONTEXT.with(|context| *context.borrow_mut() = self.previous.take());
+        }
+    }
+}
+
+/// Records that the compiler is in `phase` working on the code at `location`, until the
+/// guard is dropped
+pub fn track(phase: Phase, location: SourceLocation) -> ContextGuard {
+    let previous = CONTEXT.with(|context| context.replace(Some(PhaseContext { phase, location })));
+    ContextGuard { previous }
+}
+
+/// Makes panics inside `catch_internal_errors` quiet, they are reported as internal compiler
+/// errors instead. Other panics still go to the hook that was there before
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if CATCHING.with(Cell::get) == 0 {
+            previous(info);
+            return;
+        }
+        let location = info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()));
+        PANIC_LOCATION.with(|panic_location| *panic_location.borrow_mut() = location);
+    }));
+}
+
+/// A panic of the compiler, which is always a bug
+#[derive(Debug, Clone, PartialEq)]
+pub struct InternalCompilerError {
+    pub message: String,
+    /// What the compiler was working on, if the panic happened inside a `track`
+    pub context: Option<PhaseContext>,
+    /// The line of the compiler that panicked, only known with `install_panic_hook`
+    pub panic_location: Option<String>,
+    /// The smallest input found that still panics in the same phase
+    pub minimized_input: String,
+}
+
+impl InternalCompilerError {
+    /// The error as a diagnostic at the code the compiler was working on, or at the start of
+    /// the file
+    pub fn to_compile_error(&self, filepath: &str) -> CompileError {
+        let location = match &self.context {
+            Some(context) => context.location.clone(),
+            None => SourceLocation {
+                filepath: filepath.to_string(),
+                position: 0,
+                line: 1,
+                column: 1,
+                synthetic: None,
+            },
+        };
+        let mut notes = vec![];
+        if let Some(context) = &self.context {
+            notes.push(CompileNote {
+                location: None,
+                message: format!("The compiler panicked while {}", context.phase.get_name()),
+            });
+        }
+        if let Some(panic_location) = &self.panic_location {
+            notes.push(CompileNote {
+                location: None,
+                message: format!("The panic was at {}", panic_location),
+            });
+        }
+        notes.push(CompileNote {
+            location: None,
+            message: format!(
+                "This is a bug in the compiler, please report it at {} with this input, which still crashes it:\n{}",
+                ISSUES_URL,
+                self.minimized_input.trim_end(),
+            ),
+        });
+        CompileError {
+            location,
+            end_location: None,
+            kind: ErrorKind::Internal,
+            id: "internal-compiler-error",
+            arguments: vec![self.message.to_string()],
+            message: format!("internal compiler error: {}", self.message),
+            notes,
+            suggestions: vec![],
+        }
+    }
+}
+
+struct Panic {
+    message: String,
+    context: Option<PhaseContext>,
+    panic_location: Option<String>,
+}
+
+// runs `f` with a context of its own, giving back the context of the panic if it panics
+fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Panic> {
+    let outer = CONTEXT.with(|context| context.take());
+    CATCHING.with(|catching| catching.set(catching.get() + 1));
+    let result = catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(catching.get() - 1));
+    let context = CONTEXT.with(|context| context.replace(outer));
+    result.map_err(|payload| Panic {
+        message: panic_message(&*payload),
+        context,
+        panic_location: PANIC_LOCATION.with(|panic_location| panic_location.take()),
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Runs `compile` on `source`, turning a panic into an `InternalCompilerError`
+///
+/// The input of the report is minimized by giving `recompile` the source with lines taken
+/// out, keeping every cut that still panics in the same phase
+pub fn catch_internal_errors<T>(
+    source: &str,
+    compile: impl FnOnce() -> T,
+    mut recompile: impl FnMut(&str),
+) -> Result<T, InternalCompilerError> {
+    let panic = match catch(compile) {
+        Ok(result) => return Ok(result),
+        Err(panic) => panic,
+    };
+    let phase = panic.context.as_ref().map(|context| context.phase);
+    let minimized_input = minimize_input(source, |source| {
+        catch(|| recompile(source))
+            .err()
+            .is_some_and(|other| other.context.map(|context| context.phase) == phase)
+    });
+    Err(InternalCompilerError {
+        message: panic.message,
+        context: panic.context,
+        panic_location: panic.panic_location,
+        minimized_input,
+    })
+}
+
+/// Takes out runs of lines while `still_fails` says the rest still shows the problem, from
+/// half of the lines at a time down to single lines
+pub fn minimize_input(source: &str, mut still_fails: impl FnMut(&str) -> bool) -> String {
+    let mut lines: Vec<&str> = source.lines().collect();
+    let mut attempts = 0;
+    let mut chunk = lines.len().div_ceil(2);
+    while chunk > 0 {
+        let mut start = 0;
+        while start < lines.len() && attempts < MAX_MINIMIZE_ATTEMPTS {
+            let end = (start + chunk).min(lines.len());
+            let candidate: Vec<&str> = lines[..start]
+                .iter()
+                .chain(&lines[end..])
+                .copied()
+                .collect();
+            attempts += 1;
+            if still_fails(&(candidate.join("\n") + "\n")) {
+                lines = candidate;
+            } else {
+                start = end;
+            }
+        }
+        chunk /= 2;
+    }
+    lines.join("\n") + "\n"
+}
@@ -0,0 +1,319 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+    sync::Arc,
+};
+
+use crate::{
+    ast::{Ast, AstTrait},
+    binding::{bind_ast_with_options, BindOptions},
+    bound_nodes::{BoundNode, BoundNodeTrait},
+    bytecode_compilation::direct_native,
+    common::{Diagnostics, SourceLocation},
+    lexer::Lexer,
+    natives::get_native,
+    parsing::parse_file,
+    pragma::parse_attributes,
+    program::Prelude,
+};
+
+/// Parses and binds a file with the builtins in scope
+///
+/// Names that resolve to builtins only stay valid while the prelude is alive
+pub fn bind_file(
+    filepath: String,
+    source: &str,
+    prelude: &Prelude,
+) -> Result<Arc<BoundNode>, Diagnostics> {
+    // a file with a pragma it does not understand still binds, the way it would without it
+    let attributes = parse_attributes(&filepath, source).unwrap_or_default();
+    let mut lexer = Lexer::new(filepath, source);
+    let file = parse_file(&mut lexer)?;
+    let mut diagnostics = Diagnostics::new();
+    let bound_file = bind_ast_with_options(
+        &Ast::File(file),
+        &mut prelude.names(),
+        &mut diagnostics,
+        &BindOptions {
+            allow_shadowing: attributes.shadowing,
+            ..BindOptions::default()
+        },
+    );
+    if diagnostics.is_empty() {
+        Ok(bound_file)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Lists every `let` and `export` with its type and where it is defined
+///
+/// The bindings of a nested block are indented under whatever contains the block
+pub fn dump_types(filepath: String, source: &str) -> Result<String, Diagnostics> {
+    let prelude = Prelude::new();
+    let bound_file = bind_file(filepath, source, &prelude)?;
+    let mut result = String::new();
+    for expression in &bound_file.unwrap_block().expressions {
+        write_types(expression, 0, &mut result);
+    }
+    Ok(result)
+}
+
+fn write_types(node: &BoundNode, indent: usize, result: &mut String) {
+    let (keyword, name) = match node {
+        BoundNode::Let(lett) => ("let", &lett.name),
+        BoundNode::Export(export) => ("export", &export.name),
+        BoundNode::Block(block) => {
+            if block
+                .expressions
+                .iter()
+                .any(|expression| declares(expression))
+            {
+                writeln!(
+                    result,
+                    "{:indent$}block at {}",
+                    "",
+                    block.location,
+                    indent = indent * 4,
+                )
+                .unwrap();
+                for expression in &block.expressions {
+                    write_types(expression, indent + 1, result);
+                }
+            }
+            return;
+        }
+        _ => {
+            for child in node.children() {
+                write_types(child, indent, result);
+            }
+            return;
+        }
+    };
+    writeln!(
+        result,
+        "{:indent$}{} {}: {} at {}",
+        "",
+        keyword,
+        name,
+        node.get_type(),
+        node.get_location(),
+        indent = indent * 4,
+    )
+    .unwrap();
+    // a block directly bound to a name is listed under that name
+    for child in node.children() {
+        if let BoundNode::Block(block) = child as &BoundNode {
+            for expression in &block.expressions {
+                write_types(expression, indent + 1, result);
+            }
+        } else {
+            write_types(child, indent + 1, result);
+        }
+    }
+}
+
+// whether a block has anything to list, directly or in a nested block
+fn declares(node: &BoundNode) -> bool {
+    matches!(node, BoundNode::Let(_) | BoundNode::Export(_))
+        || node.children().into_iter().any(|child| declares(child))
+}
+
+/// Prints the scopes the binder builds and what is defined in each of them
+///
+/// This works from the syntax tree so it still shows the scopes of a file that fails to
+/// bind, names that are already defined and names that cannot be found are marked
+pub fn dump_scopes(filepath: String, source: &str) -> Result<String, Diagnostics> {
+    let mut lexer = Lexer::new(filepath.clone(), source);
+    let file = parse_file(&mut lexer)?;
+
+    let mut result = String::new();
+    let mut names = HashMap::new();
+    writeln!(result, "builtins").unwrap();
+    for (name, node) in &Prelude::new().builtins {
+        let location = node.get_location();
+        writeln!(result, "    {} from {}", name, location).unwrap();
+        names.insert(name.clone(), (location, 0));
+    }
+    writeln!(result, "file {}", filepath).unwrap();
+    for expression in &file.expressions {
+        write_scopes(expression, &mut names, 1, &mut result);
+    }
+    Ok(result)
+}
+
+// `names` maps every visible name to where it is defined and the depth of its scope
+fn write_scopes(
+    ast: &Ast,
+    names: &mut HashMap<String, (SourceLocation, usize)>,
+    depth: usize,
+    result: &mut String,
+) {
+    let indent = depth * 4;
+    match ast {
+        Ast::Block(block) => {
+            writeln!(result, "{:indent$}block at {}", "", block.get_location(),).unwrap();
+            // the same as the binder, a block can see everything outside of it
+            let mut new_names = names.clone();
+            for expression in &block.expressions {
+                write_scopes(expression, &mut new_names, depth + 1, result);
+            }
+        }
+
+        Ast::Let(_) | Ast::Destructure(_) | Ast::Export(_) => {
+            let (keyword, name_tokens) = match ast {
+                Ast::Let(lett) => ("let", vec![&lett.name_token]),
+                Ast::Destructure(destructure) => ("let", destructure.names.iter().collect()),
+                Ast::Export(export) => ("export", vec![&export.name_token]),
+                _ => unreachable!(),
+            };
+            // the value is bound before the name exists
+            for child in ast.children() {
+                write_scopes(child, names, depth, result);
+            }
+
+            for name_token in name_tokens {
+                let name = &name_token.name;
+                write!(
+                    result,
+                    "{:indent$}{} {} at {}",
+                    "", keyword, name, name_token.location,
+                )
+                .unwrap();
+                match names.get(name) {
+                    Some((location, 0)) => {
+                        writeln!(result, ", already defined by the builtin at {}", location)
+                    }
+                    Some((location, defined_depth)) if *defined_depth == depth => {
+                        writeln!(result, ", already defined at {}", location)
+                    }
+                    Some((location, _)) => {
+                        writeln!(result, ", shadows the outer {} at {}", name, location,)
+                    }
+                    None => {
+                        names.insert(name.to_string(), (name_token.location.clone(), depth));
+                        writeln!(result)
+                    }
+                }
+                .unwrap();
+            }
+        }
+
+        Ast::Name(name) => {
+            let name_string = &name.name_token.name;
+            if !names.contains_key(name_string) {
+                writeln!(
+                    result,
+                    "{:indent$}{} at {} cannot be found",
+                    "", name_string, &name.name_token.location,
+                )
+                .unwrap();
+            }
+        }
+
+        _ => {
+            for child in ast.children() {
+                write_scopes(child, names, depth, result);
+            }
+        }
+    }
+}
+
+/// Which definitions call which, a call belongs to the closest `let` or `export` around it,
+/// or to the file if there is none
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallGraph {
+    pub calls: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl CallGraph {
+    pub fn from_file(filepath: &str, bound_file: &BoundNode) -> CallGraph {
+        let mut graph = CallGraph::default();
+        graph.add_calls(bound_file, filepath.to_string());
+        graph
+    }
+
+    fn add_calls(&mut self, node: &BoundNode, caller: String) {
+        let caller = match node {
+            BoundNode::Let(lett) => describe_definition(&lett.name, &lett.location),
+            BoundNode::Export(export) => describe_definition(&export.name, &export.location),
+            BoundNode::Call(call) => {
+                if let Some(callee) = describe_callee(&call.operand) {
+                    self.calls.entry(caller.clone()).or_default().insert(callee);
+                }
+                caller
+            }
+            _ => caller,
+        };
+        for child in node.children() {
+            self.add_calls(child, caller.clone());
+        }
+    }
+
+    /// Every cycle of calls, each starting from its smallest definition
+    ///
+    /// Names can only refer to earlier definitions at the moment so there are never any,
+    /// but anything that inlines calls should still check
+    pub fn recursive_cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles = vec![];
+        for start in self.calls.keys() {
+            let mut path = vec![start.clone()];
+            self.find_cycles(start, &mut path, &mut cycles);
+        }
+        cycles
+    }
+
+    fn find_cycles(&self, start: &String, path: &mut Vec<String>, cycles: &mut Vec<Vec<String>>) {
+        let Some(callees) = self.calls.get(path.last().unwrap()) else {
+            return;
+        };
+        for callee in callees {
+            if callee == start {
+                cycles.push(path.clone());
+            } else if callee > start && !path.contains(callee) {
+                // only following definitions after the start finds each cycle once
+                path.push(callee.clone());
+                self.find_cycles(start, path, cycles);
+                path.pop();
+            }
+        }
+    }
+}
+
+fn describe_definition(name: &str, location: &SourceLocation) -> String {
+    format!("{} at {}", name, location)
+}
+
+fn describe_callee(operand: &BoundNode) -> Option<String> {
+    if let Some(id) = direct_native(operand) {
+        return Some(format!("{} (builtin)", get_native(id).full_name()));
+    }
+    match operand {
+        BoundNode::Name(name) => match &name.resolved_expression.upgrade()? as &BoundNode {
+            BoundNode::Let(lett) => Some(describe_definition(&lett.name, &lett.location)),
+            BoundNode::Export(export) => Some(describe_definition(&export.name, &export.location)),
+            _ => None,
+        },
+        // anything else is only known when it runs
+        _ => None,
+    }
+}
+
+/// Lists what every definition calls, followed by any recursive cycles
+pub fn dump_callgraph(filepath: String, source: &str) -> Result<String, Diagnostics> {
+    let prelude = Prelude::new();
+    let bound_file = bind_file(filepath.clone(), source, &prelude)?;
+    let graph = CallGraph::from_file(&filepath, &bound_file);
+
+    let mut result = String::new();
+    for (caller, callees) in &graph.calls {
+        writeln!(result, "{}", caller).unwrap();
+        for callee in callees {
+            writeln!(result, "    calls {}", callee).unwrap();
+        }
+    }
+    for cycle in graph.recursive_cycles() {
+        writeln!(result, "recursive: {} -> {}", cycle.join(" -> "), cycle[0]).unwrap();
+    }
+    Ok(result)
+}
@@ -0,0 +1,305 @@
+use std::fmt::Display;
+
+/// Just enough json for `serve`, objects keep their keys in the order they were written
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from its keys and values
+    pub fn object<const N: usize>(members: [(&str, Json); N]) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// The value of `key` if this is an object that has it
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            // json has no infinity or nan
+            Json::Number(value) if !value.is_finite() => write!(f, "null"),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write_json_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_json_string(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for chr in value.chars() {
+        match chr {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            chr if chr.is_control() => write!(f, "\\u{:04x}", chr as u32)?,
+            chr => write!(f, "{}", chr)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    /// The byte offset in the text where it went wrong
+    pub position: usize,
+    pub message: String,
+}
+
+/// How deeply arrays and objects can be nested, the parser recurses into each one
+const MAX_DEPTH: usize = 128;
+
+/// Parses a whole json document, anything but whitespace after the value is an error
+pub fn parse_json(text: &str) -> Result<Json, JsonError> {
+    let mut parser = JsonParser { text, position: 0 };
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+        return Err(parser.error("Expected the end of the json"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'text> {
+    text: &'text str,
+    position: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.position = self.text.len()
+            - self
+                .rest()
+                .trim_start_matches([' ', '\t', '\n', '\r'])
+                .len();
+    }
+
+    fn eat(&mut self, expected: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(expected) {
+            self.position += expected.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    // `depth` is how many arrays and objects the value is in
+    fn parse_value(&mut self, depth: usize) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        if depth > MAX_DEPTH {
+            return Err(self.error(&format!(
+                "Json is nested too deeply, the maximum depth is {}",
+                MAX_DEPTH
+            )));
+        }
+        if self.eat("null") {
+            Ok(Json::Null)
+        } else if self.eat("true") {
+            Ok(Json::Bool(true))
+        } else if self.eat("false") {
+            Ok(Json::Bool(false))
+        } else if self.rest().starts_with('"') {
+            Ok(Json::String(self.parse_string()?))
+        } else if self.eat("[") {
+            let mut values = vec![];
+            if !self.eat("]") {
+                loop {
+                    values.push(self.parse_value(depth + 1)?);
+                    if self.eat("]") {
+                        break;
+                    }
+                    if !self.eat(",") {
+                        return Err(self.error("Expected , or ]"));
+                    }
+                }
+            }
+            Ok(Json::Array(values))
+        } else if self.eat("{") {
+            let mut members = vec![];
+            if !self.eat("}") {
+                loop {
+                    self.skip_whitespace();
+                    if !self.rest().starts_with('"') {
+                        return Err(self.error("Expected a string key"));
+                    }
+                    let key = self.parse_string()?;
+                    if !self.eat(":") {
+                        return Err(self.error("Expected :"));
+                    }
+                    members.push((key, self.parse_value(depth + 1)?));
+                    if self.eat("}") {
+                        break;
+                    }
+                    if !self.eat(",") {
+                        return Err(self.error("Expected , or }"));
+                    }
+                }
+            }
+            Ok(Json::Object(members))
+        } else {
+            self.parse_number()
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json, JsonError> {
+        let length = self
+            .rest()
+            .find(|chr: char| !(chr.is_ascii_digit() || "+-.eE".contains(chr)))
+            .unwrap_or(self.rest().len());
+        match self.rest()[..length].parse() {
+            Ok(value) if length > 0 => {
+                self.position += length;
+                Ok(Json::Number(value))
+            }
+            _ => Err(self.error("Expected a json value")),
+        }
+    }
+
+    // the position is on the opening quote
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.position += 1;
+        let mut result = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((offset, chr)) = chars.next() {
+            match chr {
+                '"' => {
+                    self.position += offset + 1;
+                    return Ok(result);
+                }
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        Some((_, '/')) => '/',
+                        Some((_, 'b')) => '\u{8}',
+                        Some((_, 'f')) => '\u{c}',
+                        Some((_, 'n')) => '\n',
+                        Some((_, 'r')) => '\r',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'u')) => {
+                            let digits: String =
+                                chars.by_ref().take(4).map(|(_, chr)| chr).collect();
+                            // surrogate pairs are not put back together, they become the
+                            // replacement character
+                            u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .filter(|_| digits.len() == 4)
+                                .map(|code| {
+                                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                                })
+                                .ok_or_else(|| {
+                                    self.error_at(offset, "Expected 4 hex digits after \\u")
+                                })?
+                        }
+                        _ => return Err(self.error_at(offset, "Unknown escape sequence")),
+                    };
+                    result.push(escaped);
+                }
+                chr => result.push(chr),
+            }
+        }
+        Err(self.error("Expected \" at the end of the string"))
+    }
+
+    fn error_at(&self, offset: usize, message: &str) -> JsonError {
+        JsonError {
+            position: self.position + offset,
+            message: message.to_string(),
+        }
+    }
+}
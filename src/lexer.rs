@@ -1,42 +1,128 @@
-use std::rc::Rc;
+use std::collections::VecDeque;
 
 use crate::{
-    common::{CompileError, SourceLocation},
+    common::{CancellationToken, CompileError, ErrorKind, SourceLocation},
+    grammar::{find_keyword, SYMBOLS},
     token::{Token, TokenKind},
 };
 
+/// How many tokens the parser can see ahead of the current one, enough to tell syntax like
+/// `name: Type` or `proc(...) -> T` apart from an expression without backtracking
+pub const MAX_LOOKAHEAD: usize = 3;
+
+/// The biggest integer literal, one past `i64::MAX` so `-9223372036854775808` can still be
+/// written, the binder rejects it without the minus
+pub const MAX_INTEGER_LITERAL: u128 = i64::MAX as u128 + 1;
+
+/// The characters that are written with a `\` in a string, and the letter that follows it
+pub static ESCAPES: &[(char, char)] = &[
+    ('\n', 'n'),
+    ('\r', 'r'),
+    ('\t', 't'),
+    ('\0', '0'),
+    ('\\', '\\'),
+    ('"', '"'),
+];
+
+/// Writes `value` as a string literal that lexes back to the same string
+pub fn quote_string(value: &str) -> String {
+    let mut result = String::from('"');
+    for chr in value.chars() {
+        match ESCAPES.iter().find(|&&(escaped, _)| escaped == chr) {
+            Some(&(_, name)) => {
+                result.push('\\');
+                result.push(name);
+            }
+            None => result.push(chr),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn unterminated_string(location: SourceLocation) -> CompileError {
+    CompileError {
+        location,
+        end_location: None,
+        kind: ErrorKind::Syntax,
+        id: "unterminated-string",
+        arguments: vec![],
+        message: "Unterminated string, strings have to end on the line they start on".to_string(),
+        notes: vec![],
+        suggestions: vec![],
+    }
+}
+
+/// A saved position of a `Lexer`, it only holds offsets so taking one is cheap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LexerCheckpoint {
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+/// Lexes tokens straight out of the borrowed source, `position` is a byte offset into it
 #[derive(Clone)]
-pub struct Lexer {
+pub struct Lexer<'source> {
     filepath: String,
-    source: Rc<Vec<char>>,
+    source: &'source str,
     position: usize,
     line: usize,
     column: usize,
+    // tokens that have been peeked but not taken yet, at most `MAX_LOOKAHEAD` of them
+    peeked: VecDeque<Result<Token, CompileError>>,
+    // where the first peeked token started lexing, only meaningful while `peeked` is not empty
+    peeked_from: LexerCheckpoint,
+    cancellation: Option<CancellationToken>,
 }
 
-impl Lexer {
-    pub fn new(filepath: String, source: &str) -> Lexer {
+impl<'source> Lexer<'source> {
+    pub fn new(filepath: String, source: &'source str) -> Lexer<'source> {
         Lexer {
             filepath,
-            source: Rc::new(source.chars().into_iter().collect()),
+            source,
             position: 0,
             line: 1,
             column: 1,
+            peeked: VecDeque::new(),
+            peeked_from: LexerCheckpoint {
+                position: 0,
+                line: 1,
+                column: 1,
+            },
+            cancellation: None,
         }
     }
 
-    fn current_char(&self) -> char {
-        if self.position < self.source.len() {
-            self.source[self.position]
-        } else {
-            '\0'
+    /// Makes the parser stop with an error once the token is cancelled
+    pub fn set_cancellation(&mut self, cancellation: CancellationToken) {
+        self.cancellation = Some(cancellation);
+    }
+
+    pub fn check_cancelled(&self) -> Result<(), CompileError> {
+        match &self.cancellation {
+            Some(cancellation) => cancellation.check_compile(&self.get_current_location()),
+            None => Ok(()),
         }
     }
 
+    // a '\0' from `current_char` is only the end of the file when this is true, the source
+    // can contain a literal '\0' as well
+    fn is_at_end(&self) -> bool {
+        self.position >= self.source.len()
+    }
+
+    fn current_char(&self) -> char {
+        self.source[self.position..].chars().next().unwrap_or('\0')
+    }
+
     fn next_char(&mut self) -> char {
         let current = self.current_char();
+        if self.is_at_end() {
+            return current;
+        }
 
-        self.position += 1;
+        self.position += current.len_utf8();
         self.column += 1;
 
         if current == '\n' {
@@ -53,83 +139,156 @@ impl Lexer {
             position: self.position,
             line: self.line,
             column: self.column,
+            synthetic: None,
         }
     }
 
-    fn single_char_token(&mut self, kind: TokenKind) -> Token {
+    /// The longest symbol that the source continues with, from `SYMBOLS`
+    fn lex_symbol(&mut self) -> Option<Token> {
         let start_location = self.get_current_location();
-        self.next_char();
-        Token {
-            kind,
-            length: self.position - start_location.position,
-            location: start_location,
+        let rest = &self.source[self.position..];
+        let (text, kind) = SYMBOLS
+            .iter()
+            .filter(|(text, _)| rest.starts_with(text))
+            .max_by_key(|(text, _)| text.len())?;
+        for _ in text.chars() {
+            self.next_char();
         }
+        Some(Token {
+            kind: kind.clone(),
+            end: self.position,
+            location: start_location,
+        })
     }
 
-    fn double_char_token(
-        &mut self,
-        kind: TokenKind,
-        second_char: char,
-        second_kind: TokenKind,
-    ) -> Token {
+    // strings end on the same line they start on, a newline has to be written as `\n`
+    fn lex_string(&mut self) -> Result<Token, CompileError> {
         let start_location = self.get_current_location();
         self.next_char();
-        if self.current_char() == second_char {
-            self.next_char();
-            Token {
-                kind: second_kind,
-                length: self.position - start_location.position,
-                location: start_location,
+        let mut value = String::new();
+        let mut error = None;
+        loop {
+            let chr_location = self.get_current_location();
+            match self.current_char() {
+                '"' => {
+                    self.next_char();
+                    break;
+                }
+                '\0' if self.is_at_end() => return Err(unterminated_string(start_location)),
+                '\n' | '\r' => return Err(unterminated_string(start_location)),
+                '\\' => {
+                    self.next_char();
+                    // a `\` cannot be used to carry on to the next line
+                    if matches!(self.current_char(), '\n' | '\r') || self.is_at_end() {
+                        return Err(unterminated_string(start_location));
+                    }
+                    let escaped = self.next_char();
+                    match ESCAPES.iter().find(|&&(_, name)| name == escaped) {
+                        Some(&(chr, _)) => value.push(chr),
+                        // the rest of the string is still taken so it is not lexed as code
+                        None => {
+                            error.get_or_insert(CompileError {
+                                location: chr_location,
+                                end_location: None,
+                                kind: ErrorKind::Syntax,
+                                id: "unknown-escape",
+                                arguments: vec![escaped.to_string()],
+                                message: format!("Unknown escape sequence '\\{}'", escaped),
+                                notes: vec![],
+                                suggestions: vec![],
+                            });
+                        }
+                    }
+                }
+                _ => value.push(self.next_char()),
             }
-        } else {
-            Token {
-                kind,
-                length: self.position - start_location.position,
-                location: start_location,
+        }
+
+        if let Some(error) = error {
+            return Err(error);
+        }
+        Ok(Token {
+            kind: TokenKind::String(value),
+            end: self.position,
+            location: start_location,
+        })
+    }
+
+    // a decimal literal with a fractional part or an exponent, `1.` and `1.a` are still an
+    // integer followed by a dot
+    fn is_float_literal(&self) -> bool {
+        let rest = self.source[self.position..]
+            .trim_start_matches(|chr: char| chr.is_ascii_digit() || chr == '_');
+        let starts_with_digit = |text: &str| text.starts_with(|chr: char| chr.is_ascii_digit());
+        match rest.chars().next() {
+            Some('.') => starts_with_digit(&rest[1..]),
+            Some('e' | 'E') => {
+                let exponent = &rest[1..];
+                starts_with_digit(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))
             }
+            _ => false,
         }
     }
 
-    fn double_char_token_2_choice(
-        &mut self,
-        kind: TokenKind,
-        second_char_1: char,
-        second_kind_1: TokenKind,
-        second_char_2: char,
-        second_kind_2: TokenKind,
-    ) -> Token {
+    fn lex_float(&mut self) -> Result<Token, CompileError> {
         let start_location = self.get_current_location();
-        self.next_char();
-        if self.current_char() == second_char_1 {
+        self.skip_digits();
+        if self.current_char() == '.' && self.is_float_literal() {
             self.next_char();
-            Token {
-                kind: second_kind_1.clone(),
-                length: self.position - start_location.position,
-                location: start_location,
-            }
-        } else if self.current_char() == second_char_2 {
+            self.skip_digits();
+        }
+        if matches!(self.current_char(), 'e' | 'E') && self.is_float_literal() {
             self.next_char();
-            Token {
-                kind: second_kind_2.clone(),
-                length: self.position - start_location.position,
-                location: start_location,
+            if matches!(self.current_char(), '+' | '-') {
+                self.next_char();
             }
-        } else {
-            Token {
-                kind,
-                length: self.position - start_location.position,
+            self.skip_digits();
+        }
+
+        let text = self.source[start_location.position..self.position].replace('_', "");
+        let value: f64 = text
+            .parse()
+            .expect("the lexer only takes valid float literals");
+        if value.is_infinite() {
+            return Err(CompileError {
                 location: start_location,
-            }
+                end_location: None,
+                kind: ErrorKind::Syntax,
+                id: "float-too-big",
+                arguments: vec![],
+                message: "Float literal is too big for a 64 bit float".to_string(),
+                notes: vec![],
+                suggestions: vec![],
+            });
+        }
+        Ok(Token {
+            kind: TokenKind::Float(value),
+            end: self.position,
+            location: start_location,
+        })
+    }
+
+    // `_` can be used to split up the digits like in integers
+    fn skip_digits(&mut self) {
+        while self.current_char().is_ascii_digit() || self.current_char() == '_' {
+            self.next_char();
         }
     }
 
     pub fn next_token(&mut self) -> Result<Token, CompileError> {
+        if let Some(peeked) = self.peeked.pop_front() {
+            return peeked;
+        }
+        self.lex_token()
+    }
+
+    fn lex_token(&mut self) -> Result<Token, CompileError> {
         'main_loop: loop {
             let start_location = self.get_current_location();
             return match self.current_char() {
-                '\0' => Ok(Token {
+                '\0' if self.is_at_end() => Ok(Token {
                     kind: TokenKind::EndOfFile,
-                    length: self.position - start_location.position,
+                    end: self.position,
                     location: start_location,
                 }),
 
@@ -145,7 +304,7 @@ impl Lexer {
                     }
                     Ok(Token {
                         kind: TokenKind::Newline,
-                        length: self.position - start_location.position,
+                        end: self.position,
                         location: start_location,
                     })
                 }
@@ -157,7 +316,7 @@ impl Lexer {
                     }
                     Ok(Token {
                         kind: TokenKind::Newline,
-                        length: self.position - start_location.position,
+                        end: self.position,
                         location: start_location,
                     })
                 }
@@ -170,28 +329,18 @@ impl Lexer {
                             _ => break 'name_loop,
                         }
                     }
-                    match &value as &str {
-                        "export" => Ok(Token {
-                            kind: TokenKind::Export,
-                            length: self.position - start_location.position,
-                            location: start_location,
-                        }),
-
-                        "let" => Ok(Token {
-                            kind: TokenKind::Let,
-                            length: self.position - start_location.position,
-                            location: start_location,
-                        }),
-
-                        _ => Ok(Token {
-                            kind: TokenKind::Name(value),
-                            length: self.position - start_location.position,
-                            location: start_location,
-                        }),
-                    }
+                    Ok(Token {
+                        kind: find_keyword(&value).unwrap_or(TokenKind::Name(value)),
+                        end: self.position,
+                        location: start_location,
+                    })
                 }
 
+                '0'..='9' if self.is_float_literal() => self.lex_float(),
+
                 '0'..='9' => {
+                    // with a prefix the 0 is not a digit of the literal
+                    let mut has_digits = false;
                     let base: u128 = if self.current_char() == '0' {
                         self.next_char();
                         match self.current_char() {
@@ -215,134 +364,182 @@ impl Lexer {
                                 16
                             }
 
-                            _ => 10,
+                            _ => {
+                                has_digits = true;
+                                10
+                            }
                         }
                     } else {
                         10
                     };
 
-                    let mut int_value: u128 = 0;
+                    // the whole literal is taken before checking its digits, so a bad digit
+                    // does not leave the rest of the literal behind to be lexed as another token
+                    let mut int_value: Option<u128> = Some(0);
+                    let mut error = None;
                     'int_loop: loop {
-                        match self.current_char() {
-                            '0'..='9' | 'A'..='Z' | 'a'..='z' => {
-                                let value = match self.current_char() {
-                                    '0'..='9' => self.current_char() as u128 - '0' as u128,
-                                    'A'..='Z' => self.current_char() as u128 - 'A' as u128 + 10,
-                                    'a'..='z' => self.current_char() as u128 - 'a' as u128 + 10,
-                                    _ => unreachable!(),
-                                };
-
-                                if value >= base {
-                                    return Err(CompileError {
-                                        location: self.get_current_location(),
-                                        message: format!(
-                                            "Character '{}' is too big for base '{}'",
-                                            self.current_char(),
-                                            base
-                                        ),
-                                        notes: vec![],
-                                    });
-                                }
-
-                                int_value *= base;
-                                int_value += value;
-
-                                self.next_char();
-                            }
-
+                        let digit_location = self.get_current_location();
+                        let digit = self.current_char();
+                        let value = match digit {
+                            '0'..='9' => digit as u128 - '0' as u128,
+                            'A'..='Z' => digit as u128 - 'A' as u128 + 10,
+                            'a'..='z' => digit as u128 - 'a' as u128 + 10,
                             '_' => {
                                 self.next_char();
+                                continue 'int_loop;
                             }
-
                             _ => break 'int_loop,
+                        };
+                        self.next_char();
+                        has_digits = true;
+
+                        if value >= base {
+                            error.get_or_insert(CompileError {
+                                location: digit_location,
+                                end_location: None,
+                                kind: ErrorKind::Syntax,
+                                id: "invalid-digit",
+                                arguments: vec![digit.to_string(), base.to_string()],
+                                message: format!("Digit '{}' is invalid for base {}", digit, base),
+                                notes: vec![],
+                                suggestions: vec![],
+                            });
                         }
+                        int_value = int_value
+                            .and_then(|int_value| int_value.checked_mul(base))
+                            .and_then(|int_value| int_value.checked_add(value))
+                            .filter(|&int_value| int_value <= MAX_INTEGER_LITERAL);
                     }
 
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+                    if !has_digits {
+                        let prefix = &self.source[start_location.position..self.position];
+                        return Err(CompileError {
+                            location: start_location,
+                            end_location: None,
+                            kind: ErrorKind::Syntax,
+                            id: "expected-digit",
+                            arguments: vec![prefix.to_string()],
+                            message: format!("Expected a digit after {}", prefix),
+                            notes: vec![],
+                            suggestions: vec![],
+                        });
+                    }
+                    let Some(int_value) = int_value else {
+                        return Err(CompileError {
+                            location: start_location,
+                            end_location: None,
+                            kind: ErrorKind::IntegerTooBig,
+                            id: "integer-literal-too-big",
+                            arguments: vec![],
+                            message: "Integer literal is too big for a 64 bit signed integer"
+                                .to_string(),
+                            notes: vec![],
+                            suggestions: vec![],
+                        });
+                    };
+
                     Ok(Token {
                         kind: TokenKind::Integer(int_value),
-                        length: self.position - start_location.position,
+                        end: self.position,
                         location: start_location,
                     })
                 }
 
-                '(' => Ok(self.single_char_token(TokenKind::OpenParenthesis)),
-                ')' => Ok(self.single_char_token(TokenKind::CloseParenthesis)),
-                '{' => Ok(self.single_char_token(TokenKind::OpenBrace)),
-                '}' => Ok(self.single_char_token(TokenKind::CloseBrace)),
-
-                ',' => Ok(self.single_char_token(TokenKind::Comma)),
-
-                '+' => Ok(self.double_char_token(TokenKind::Plus, '=', TokenKind::PlusEqual)),
-                '-' => Ok(self.double_char_token_2_choice(
-                    TokenKind::Minus,
-                    '=',
-                    TokenKind::MinusEqual,
-                    '>',
-                    TokenKind::RightArrow,
-                )),
-                '*' => {
-                    Ok(self.double_char_token(TokenKind::Asterisk, '=', TokenKind::AsteriskEqual))
-                }
+                '"' => self.lex_string(),
 
-                '/' => {
-                    self.next_char();
-                    if self.current_char() == '/' {
-                        while self.current_char() != '\n' && self.current_char() != '\0' {
-                            self.next_char();
-                        }
-                        if self.current_char() == '\r' {
-                            self.next_char();
-                        }
-                        continue 'main_loop;
-                    } else if self.current_char() == '=' {
+                '/' if self.source[self.position..].starts_with("//") => {
+                    while self.current_char() != '\n' && !self.is_at_end() {
+                        self.next_char();
+                    }
+                    if self.current_char() == '\r' {
                         self.next_char();
-                        Ok(Token {
-                            kind: TokenKind::SlashEqual,
-                            length: self.position - start_location.position,
-                            location: start_location,
-                        })
-                    } else {
-                        Ok(Token {
-                            kind: TokenKind::Slash,
-                            length: self.position - start_location.position,
-                            location: start_location,
-                        })
                     }
+                    continue 'main_loop;
                 }
 
-                '=' => Ok(self.double_char_token(TokenKind::Equal, '=', TokenKind::EqualEqual)),
-                '!' => Ok(self.double_char_token(
-                    TokenKind::ExclamationMark,
-                    '=',
-                    TokenKind::ExclamationMarkEqual,
-                )),
-                '<' => Ok(self.double_char_token_2_choice(
-                    TokenKind::LessThan,
-                    '=',
-                    TokenKind::LessThanEqual,
-                    '-',
-                    TokenKind::RightArrow,
-                )),
-                '>' => Ok(self.double_char_token(
-                    TokenKind::GreaterThan,
-                    '=',
-                    TokenKind::LessThanEqual,
-                )),
-
                 _ => {
+                    if let Some(token) = self.lex_symbol() {
+                        return Ok(token);
+                    }
                     let chr = self.next_char();
                     Err(CompileError {
                         location: start_location,
+                        end_location: None,
+                        kind: ErrorKind::Syntax,
+                        id: "unexpected-character",
+                        arguments: vec![chr.to_string()],
                         message: format!("Unexpected '{}'", chr),
                         notes: vec![],
+                        suggestions: vec![],
                     })
                 }
             };
         }
     }
 
-    pub fn peek_kind(&self) -> Result<TokenKind, CompileError> {
-        // maybe dont clone the entire lexer+source tokens every time?
-        Ok(self.clone().next_token()?.kind)
+    pub fn peek_token(&mut self) -> Result<Token, CompileError> {
+        self.peek_token_at(0)
+    }
+
+    pub fn peek_kind(&mut self) -> Result<TokenKind, CompileError> {
+        self.peek_kind_at(0)
+    }
+
+    /// Peeks the token `offset` tokens after the next one, so an offset of 0 is the same as `peek_token`
+    pub fn peek_token_at(&mut self, offset: usize) -> Result<Token, CompileError> {
+        self.fill_peeked(offset);
+        self.peeked[offset].clone()
+    }
+
+    pub fn peek_kind_at(&mut self, offset: usize) -> Result<TokenKind, CompileError> {
+        self.fill_peeked(offset);
+        // only the kind is cloned, not the whole token
+        match &self.peeked[offset] {
+            Ok(token) => Ok(token.kind.clone()),
+            Err(error) => Err(error.clone()),
+        }
+    }
+
+    fn fill_peeked(&mut self, offset: usize) {
+        assert!(
+            offset < MAX_LOOKAHEAD,
+            "the parser can only look {} tokens ahead",
+            MAX_LOOKAHEAD
+        );
+        if self.peeked.is_empty() {
+            self.peeked_from = self.current_checkpoint();
+        }
+        while self.peeked.len() <= offset {
+            let token = self.lex_token();
+            self.peeked.push_back(token);
+        }
+    }
+
+    /// Saves where the lexer is, peeked tokens count as not taken yet
+    pub fn checkpoint(&self) -> LexerCheckpoint {
+        if self.peeked.is_empty() {
+            self.current_checkpoint()
+        } else {
+            self.peeked_from
+        }
+    }
+
+    /// Rewinds (or fast forwards) the lexer to a checkpoint taken from this lexer
+    pub fn restore(&mut self, checkpoint: LexerCheckpoint) {
+        self.position = checkpoint.position;
+        self.line = checkpoint.line;
+        self.column = checkpoint.column;
+        self.peeked.clear();
+    }
+
+    fn current_checkpoint(&self) -> LexerCheckpoint {
+        LexerCheckpoint {
+            position: self.position,
+            line: self.line,
+            column: self.column,
+        }
     }
 }
@@ -0,0 +1,44 @@
+#![allow(dead_code)]
+
+pub mod ast;
+#[cfg(feature = "native")]
+pub mod bench;
+pub mod binding;
+pub mod bound_nodes;
+pub mod bytecode;
+pub mod bytecode_compilation;
+pub mod bytecode_text;
+pub mod cache;
+pub mod common;
+pub mod constant;
+pub mod coverage;
+pub mod diff;
+pub mod emit;
+pub mod execute;
+pub mod explain;
+pub mod fix;
+pub mod fuzz;
+pub mod grammar;
+pub mod ice;
+pub mod inspect;
+pub mod json;
+pub mod lexer;
+pub mod line_editor;
+pub mod lint;
+pub mod metrics;
+pub mod natives;
+pub mod notebook;
+pub mod parsing;
+pub mod peephole;
+pub mod pipeline;
+pub mod pragma;
+pub mod program;
+pub mod repl;
+pub mod serve;
+pub mod session;
+pub mod stats;
+pub mod testing;
+pub mod token;
+pub mod types;
+pub mod value;
+pub mod verify;
@@ -0,0 +1,385 @@
+use std::io::Read;
+#[cfg(feature = "native")]
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+const MAX_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    Tab,
+    Enter,
+    Interrupt,
+    EndOfFile,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditResult {
+    Continue,
+    /// The completion had more than one candidate, they should be shown to the user
+    Candidates(Vec<String>),
+    Submit(String),
+    Interrupt,
+    EndOfFile,
+}
+
+/// The editing state of a single line plus the history, independent of any terminal
+pub struct LineEditor {
+    buffer: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    history_index: usize,
+    // the line that was being edited before moving into the history
+    saved_line: Vec<char>,
+}
+
+impl LineEditor {
+    pub fn new(history: Vec<String>) -> LineEditor {
+        LineEditor {
+            buffer: vec![],
+            cursor: 0,
+            history_index: history.len(),
+            history,
+            saved_line: vec![],
+        }
+    }
+
+    pub fn get_line(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn get_history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(|last| last as &str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.cursor = 0;
+        self.history_index = self.history.len();
+        self.saved_line.clear();
+    }
+
+    fn set_buffer(&mut self, buffer: Vec<char>) {
+        self.buffer = buffer;
+        self.cursor = self.buffer.len();
+    }
+
+    fn current_word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0
+            && (self.buffer[start - 1].is_alphanumeric() || self.buffer[start - 1] == '_')
+        {
+            start -= 1;
+        }
+        start
+    }
+
+    pub fn handle_key(&mut self, key: Key, complete: &dyn Fn(&str) -> Vec<String>) -> EditResult {
+        match key {
+            Key::Char(chr) => {
+                self.buffer.insert(self.cursor, chr);
+                self.cursor += 1;
+            }
+
+            Key::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.buffer.remove(self.cursor);
+                }
+            }
+
+            Key::Delete => {
+                if self.cursor < self.buffer.len() {
+                    self.buffer.remove(self.cursor);
+                }
+            }
+
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.buffer.len(),
+
+            Key::Up => {
+                if self.history_index > 0 {
+                    if self.history_index == self.history.len() {
+                        self.saved_line = self.buffer.clone();
+                    }
+                    self.history_index -= 1;
+                    self.set_buffer(self.history[self.history_index].chars().collect());
+                }
+            }
+
+            Key::Down => {
+                if self.history_index < self.history.len() {
+                    self.history_index += 1;
+                    if self.history_index == self.history.len() {
+                        self.set_buffer(self.saved_line.clone());
+                    } else {
+                        self.set_buffer(self.history[self.history_index].chars().collect());
+                    }
+                }
+            }
+
+            Key::Tab => {
+                let start = self.current_word_start();
+                let prefix: String = self.buffer[start..self.cursor].iter().collect();
+                let mut candidates: Vec<String> = complete(&prefix)
+                    .into_iter()
+                    .filter(|candidate| candidate.starts_with(&prefix))
+                    .collect();
+                candidates.sort();
+                candidates.dedup();
+
+                if let Some(first) = candidates.first() {
+                    // complete as far as every candidate agrees
+                    let mut common: Vec<char> = first.chars().collect();
+                    for candidate in &candidates[1..] {
+                        let length = common
+                            .iter()
+                            .zip(candidate.chars())
+                            .take_while(|(a, b)| **a == *b)
+                            .count();
+                        common.truncate(length);
+                    }
+                    let completion = &common[prefix.chars().count()..];
+                    for (i, chr) in completion.iter().enumerate() {
+                        self.buffer.insert(self.cursor + i, *chr);
+                    }
+                    self.cursor += completion.len();
+                }
+                if candidates.len() > 1 {
+                    return EditResult::Candidates(candidates);
+                }
+            }
+
+            Key::Enter => {
+                let line = self.get_line();
+                self.add_history(&line);
+                self.reset();
+                return EditResult::Submit(line);
+            }
+
+            Key::Interrupt => {
+                self.reset();
+                return EditResult::Interrupt;
+            }
+
+            Key::EndOfFile => {
+                if self.buffer.is_empty() {
+                    return EditResult::EndOfFile;
+                }
+                // like most shells, ctrl-d on a non empty line deletes forward
+                if self.cursor < self.buffer.len() {
+                    self.buffer.remove(self.cursor);
+                }
+            }
+        }
+        EditResult::Continue
+    }
+}
+
+/// Decodes one key from a terminal in raw mode, returns `None` at the end of the input
+pub fn read_key(input: &mut dyn Read) -> Result<Option<Key>, std::io::Error> {
+    fn read_byte(input: &mut dyn Read) -> Result<Option<u8>, std::io::Error> {
+        let mut byte = [0];
+        Ok(if input.read(&mut byte)? == 0 {
+            None
+        } else {
+            Some(byte[0])
+        })
+    }
+
+    let first = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    Ok(Some(match first {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfFile,
+        0x1b => {
+            if read_byte(input)? != Some(b'[') {
+                return read_key(input);
+            }
+            match read_byte(input)? {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                Some(b'3') => {
+                    read_byte(input)?; // the trailing '~'
+                    Key::Delete
+                }
+                _ => return read_key(input),
+            }
+        }
+        _ => {
+            // decode the rest of a utf-8 sequence
+            let length = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![first];
+            for _ in 1..length {
+                bytes.push(read_byte(input)?.unwrap_or(0));
+            }
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(chr) if !chr.is_control() => Key::Char(chr),
+                _ => return read_key(input),
+            }
+        }
+    }))
+}
+
+/// Puts the terminal into raw mode until dropped
+#[cfg(feature = "native")]
+struct RawMode {
+    saved_settings: String,
+}
+
+#[cfg(feature = "native")]
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()?;
+        let saved_settings = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        let status = Command::new("stty")
+            .args(["raw", "-echo"])
+            .stdin(Stdio::inherit())
+            .status()
+            .ok()?;
+        status.success().then_some(RawMode { saved_settings })
+    }
+}
+
+#[cfg(feature = "native")]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty")
+            .arg(&self.saved_settings)
+            .stdin(Stdio::inherit())
+            .status();
+    }
+}
+
+#[cfg(feature = "native")]
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".lang_history"))
+}
+
+/// A line editor attached to the terminal, with the history saved between runs
+#[cfg(feature = "native")]
+pub struct Terminal {
+    editor: LineEditor,
+}
+
+#[cfg(feature = "native")]
+impl Terminal {
+    pub fn new() -> Terminal {
+        let history = history_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(|line| line.to_string()).collect())
+            .unwrap_or_default();
+        Terminal {
+            editor: LineEditor::new(history),
+        }
+    }
+
+    fn redraw(&self, prompt: &str, output: &mut dyn Write) -> Result<(), std::io::Error> {
+        write!(output, "\r\x1b[K{}{}", prompt, self.editor.get_line())?;
+        let back = self.editor.get_line().chars().count() - self.editor.get_cursor();
+        if back > 0 {
+            write!(output, "\x1b[{}D", back)?;
+        }
+        output.flush()
+    }
+
+    /// Reads a line, the result is never `Continue` or `Candidates`
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: &dyn Fn(&str) -> Vec<String>,
+    ) -> Result<EditResult, std::io::Error> {
+        let mut input = std::io::stdin().lock();
+        let mut output = std::io::stdout();
+        let _raw_mode = RawMode::enable();
+        self.redraw(prompt, &mut output)?;
+        loop {
+            let key = read_key(&mut input)?.unwrap_or(Key::EndOfFile);
+            match self.editor.handle_key(key, complete) {
+                EditResult::Continue => {}
+                EditResult::Candidates(candidates) => {
+                    write!(output, "\r\n{}\r\n", candidates.join("  "))?;
+                }
+                EditResult::Submit(line) => {
+                    self.redraw(prompt, &mut output)?;
+                    write!(output, "{}\r\n", line)?;
+                    self.save_history();
+                    return Ok(EditResult::Submit(line));
+                }
+                EditResult::Interrupt => {
+                    write!(output, "^C\r\n")?;
+                    return Ok(EditResult::Interrupt);
+                }
+                EditResult::EndOfFile => {
+                    write!(output, "\r\n")?;
+                    return Ok(EditResult::EndOfFile);
+                }
+            }
+            self.redraw(prompt, &mut output)?;
+        }
+    }
+
+    fn save_history(&self) {
+        if let Some(path) = history_path() {
+            let _ = std::fs::write(path, self.editor.get_history().join("\n") + "\n");
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal::new()
+    }
+}
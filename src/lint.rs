@@ -0,0 +1,514 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    ast::{Ast, AstFile, AstTrait},
+    bound_nodes::{BinaryOperatorKind, BoundNode, BoundNodeTrait},
+    common::{CompileError, CompileNote, Diagnostics, ErrorKind, Suggestion},
+    constant::evaluate_constant,
+    natives::NATIVES,
+    pragma::{parse_attributes, FileAttributes},
+    token::TokenKind,
+};
+
+/// Warns about operators whose precedence is easy to get wrong, the suggestions add
+/// parentheses that keep the current meaning:
+/// - chained comparisons, `a < b < c` compares the result of `a < b` with `c`
+/// - `!` in front of a comparison, `!a == b` is `(!a) == b` and not `!(a == b)`
+/// - `-` in front of a shift, `-a << 2` is `(-a) << 2` and not `-(a << 2)`
+/// - `&&` inside `||`, `a || b && c` is `a || (b && c)`
+/// - `+` and `-` inside a bitwise operator or a shift, `a & b + 1` is `a & (b + 1)`
+/// - any other operator inside `??`, `a ?? b + 1` is `a ?? (b + 1)`
+pub fn lint_precedence(file: &AstFile, source: &str) -> Vec<CompileError> {
+    let mut warnings = vec![];
+    for expression in &file.expressions {
+        lint_expression(expression, source, &mut warnings);
+    }
+    warnings
+}
+
+fn lint_expression(ast: &Ast, source: &str, warnings: &mut Vec<CompileError>) {
+    if let Ast::Binary(binary) = ast {
+        let kind = &binary.operator_token.kind;
+        let surprise = match &*binary.left {
+            Ast::Binary(left)
+                if is_comparison(kind) && is_comparison(&left.operator_token.kind) =>
+            {
+                let arguments = vec![
+                    get_text(&binary.left, source).to_string(),
+                    kind.to_string(),
+                    get_text(&binary.right, source).to_string(),
+                ];
+                Some((
+                    "chained-comparison",
+                    format!(
+                        "Comparisons do not chain, the result of {} is compared with {} {}",
+                        arguments[0], arguments[1], arguments[2],
+                    ),
+                    arguments,
+                ))
+            }
+            Ast::Unary(left) if is_comparison(kind) || is_shift(kind) => {
+                let whole = if is_comparison(kind) {
+                    "comparison"
+                } else {
+                    "shift"
+                };
+                let operator = &left.operator_token.kind;
+                let surprising = if is_comparison(kind) {
+                    *operator == TokenKind::ExclamationMark
+                } else {
+                    *operator == TokenKind::Minus
+                };
+                surprising.then(|| {
+                    let arguments = vec![
+                        operator.to_string(),
+                        get_text(&left.operand, source).to_string(),
+                        whole.to_string(),
+                    ];
+                    (
+                        "unary-applies-to-operand",
+                        format!(
+                            "{} only applies to {}, not the whole {}",
+                            arguments[0], arguments[1], arguments[2],
+                        ),
+                        arguments,
+                    )
+                })
+            }
+            _ => None,
+        };
+        if let Some((id, message, arguments)) = surprise {
+            warnings.push(CompileError {
+                location: binary.operator_token.location.clone(),
+                end_location: None,
+                kind: ErrorKind::Warning,
+                id,
+                arguments,
+                message,
+                notes: vec![],
+                suggestions: vec![parenthesize(&binary.left, source)],
+            });
+        }
+
+        // an operator that is done first even though it reads like it would be done later
+        for operand in [&binary.left, &binary.right] {
+            let Ast::Binary(inner) = &**operand else {
+                continue;
+            };
+            let inner_kind = &inner.operator_token.kind;
+            if is_done_first_surprisingly(inner_kind, kind) {
+                warnings.push(CompileError {
+                    location: binary.operator_token.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::Warning,
+                    id: "done-first",
+                    arguments: vec![
+                        inner_kind.to_string(),
+                        kind.to_string(),
+                        get_text(operand, source).to_string(),
+                        kind.to_string(),
+                    ],
+                    message: format!(
+                        "{} is done before {}, so {} is one side of the {}",
+                        inner_kind.to_string(),
+                        kind.to_string(),
+                        get_text(operand, source),
+                        kind.to_string(),
+                    ),
+                    notes: vec![],
+                    suggestions: vec![parenthesize(operand, source)],
+                });
+            }
+        }
+    }
+
+    for child in ast.children() {
+        lint_expression(child, source, warnings);
+    }
+}
+
+fn is_comparison(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::EqualEqual
+            | TokenKind::ExclamationMarkEqual
+            | TokenKind::LessThan
+            | TokenKind::GreaterThan
+            | TokenKind::LessThanEqual
+            | TokenKind::GreaterThanEqual
+    )
+}
+
+fn is_shift(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LessThanLessThan | TokenKind::GreaterThanGreaterThan
+    )
+}
+
+// whether `inner` as an operand of `outer` is likely to be read the wrong way around
+fn is_done_first_surprisingly(inner: &TokenKind, outer: &TokenKind) -> bool {
+    let is_additive = matches!(inner, TokenKind::Plus | TokenKind::Minus);
+    match outer {
+        TokenKind::PipePipe => *inner == TokenKind::AmpersandAmpersand,
+        TokenKind::Ampersand | TokenKind::Caret | TokenKind::Pipe => is_additive,
+        TokenKind::LessThanLessThan | TokenKind::GreaterThanGreaterThan => is_additive,
+        TokenKind::QuestionMarkQuestionMark => *inner != TokenKind::QuestionMarkQuestionMark,
+        _ => false,
+    }
+}
+
+fn parenthesize(ast: &Ast, source: &str) -> Suggestion {
+    let text = get_text(ast, source);
+    Suggestion {
+        location: ast.get_start(),
+        length: text.len(),
+        replacement: format!("({})", text),
+        message: format!("Add parentheses around {}", text),
+    }
+}
+
+fn get_text<'source>(ast: &Ast, source: &'source str) -> &'source str {
+    &source[ast.get_start().position..ast.get_end().position]
+}
+
+/// What happens when a lint finds something
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Allow,
+    Warn,
+    /// Reported as an error
+    Deny,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 3] = [Severity::Allow, Severity::Warn, Severity::Deny];
+
+    pub fn from_name(name: &str) -> Option<Severity> {
+        Severity::ALL
+            .into_iter()
+            .find(|severity| severity.get_name() == name)
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Severity::Allow => "allow",
+            Severity::Warn => "warn",
+            Severity::Deny => "deny",
+        }
+    }
+}
+
+/// A check over the bound file, `Linter` runs them, on several threads when there are several
+/// files
+pub trait Lint: Send + Sync {
+    /// The name that `allow(...)`, `warn(...)` and `deny(...)` use, like `unused_binding`
+    fn get_name(&self) -> &'static str;
+
+    fn get_default_severity(&self) -> Severity {
+        Severity::Warn
+    }
+
+    /// Adds a diagnostic for everything it finds in the file
+    fn check(&self, file: &Arc<BoundNode>, problems: &mut Vec<CompileError>);
+}
+
+/// The lints to run and how severe each one is
+///
+/// A file can change the severities for itself with pragma comments at its top, like
+/// `//! allow(unused_binding)` or `//! deny(constant_condition, shadowed_builtin)`, see
+/// `FileAttributes`
+pub struct Linter {
+    lints: Vec<Box<dyn Lint>>,
+    severities: HashMap<&'static str, Severity>,
+}
+
+/// What `Linter::check` found, the problems of denied lints are errors
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintReport {
+    pub warnings: Vec<CompileError>,
+    pub errors: Diagnostics,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        let mut linter = Linter {
+            lints: vec![],
+            severities: HashMap::new(),
+        };
+        linter.add_lint(Box::new(UnusedBinding));
+        linter.add_lint(Box::new(ConstantCondition));
+        linter.add_lint(Box::new(ShadowedBuiltin));
+        linter.add_lint(Box::new(ShadowedBinding));
+        linter
+    }
+}
+
+impl Linter {
+    /// A linter with every lint that comes with the language
+    pub fn new() -> Linter {
+        Linter::default()
+    }
+
+    pub fn add_lint(&mut self, lint: Box<dyn Lint>) {
+        self.severities
+            .insert(lint.get_name(), lint.get_default_severity());
+        self.lints.push(lint);
+    }
+
+    pub fn get_lints(&self) -> impl Iterator<Item = &dyn Lint> {
+        self.lints.iter().map(|lint| lint.as_ref())
+    }
+
+    pub fn get_severity(&self, name: &str) -> Option<Severity> {
+        self.severities.get(name).copied()
+    }
+
+    /// `false` if there is no lint with that name
+    pub fn set_severity(&mut self, name: &str, severity: Severity) -> bool {
+        match self.severities.get_mut(name) {
+            Some(current) => {
+                *current = severity;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs every lint that is not allowed, after applying the pragmas of the file
+    pub fn check(&self, file: &Arc<BoundNode>, source: &str) -> LintReport {
+        let mut report = LintReport::default();
+        let mut severities = self.severities.clone();
+        let attributes =
+            parse_attributes(&file.get_location().filepath, source).unwrap_or_else(|diagnostics| {
+                report.errors = diagnostics;
+                FileAttributes::default()
+            });
+        for (name, severity, location) in attributes.lint_severities {
+            match self.lints.iter().find(|lint| lint.get_name() == name) {
+                Some(lint) => {
+                    severities.insert(lint.get_name(), severity);
+                }
+                None => report.warnings.push(CompileError {
+                    location,
+                    end_location: None,
+                    kind: ErrorKind::Warning,
+                    id: "unknown-lint",
+                    arguments: vec![name.to_string()],
+                    message: format!("Unknown lint {}", name),
+                    notes: vec![],
+                    suggestions: vec![],
+                }),
+            }
+        }
+
+        for lint in &self.lints {
+            let severity = severities[lint.get_name()];
+            if severity == Severity::Allow {
+                continue;
+            }
+            let mut problems = vec![];
+            lint.check(file, &mut problems);
+            for mut problem in problems {
+                problem.notes.push(CompileNote {
+                    location: None,
+                    message: format!(
+                        "From the {} lint, which //! allow({}) at the top of the file turns off",
+                        lint.get_name(),
+                        lint.get_name(),
+                    ),
+                });
+                match severity {
+                    Severity::Allow => unreachable!("allowed lints are not run"),
+                    Severity::Warn => report.warnings.push(problem),
+                    Severity::Deny => report.errors.push(problem),
+                }
+            }
+        }
+        report
+    }
+}
+
+// calls `visit` on the node and everything under it
+fn visit_nodes(node: &Arc<BoundNode>, visit: &mut impl FnMut(&Arc<BoundNode>)) {
+    visit(node);
+    for child in node.children() {
+        visit_nodes(child, visit);
+    }
+}
+
+/// `let`s that nothing reads, names starting with `_` are left alone
+pub struct UnusedBinding;
+
+impl Lint for UnusedBinding {
+    fn get_name(&self) -> &'static str {
+        "unused_binding"
+    }
+
+    fn check(&self, file: &Arc<BoundNode>, problems: &mut Vec<CompileError>) {
+        let mut used = HashSet::new();
+        let mut destructured = HashSet::new();
+        visit_nodes(file, &mut |node| match node as &BoundNode {
+            BoundNode::Name(name) => {
+                used.insert(name.resolved_expression.as_ptr());
+            }
+            BoundNode::Destructure(destructure) => {
+                destructured.extend(destructure.bindings.iter().map(Arc::as_ptr));
+            }
+            _ => {}
+        });
+        visit_nodes(file, &mut |node| {
+            if let BoundNode::Let(lett) = node as &BoundNode {
+                if !lett.name.starts_with('_') && !used.contains(&Arc::as_ptr(node)) {
+                    // a destructured name is also the member it comes from, so it can't be renamed
+                    let suggestions = if destructured.contains(&Arc::as_ptr(node)) {
+                        vec![]
+                    } else {
+                        vec![Suggestion {
+                            location: lett.location.clone(),
+                            length: 0,
+                            replacement: "_".to_string(),
+                            message: format!("Rename it to _{}", lett.name),
+                        }]
+                    };
+                    problems.push(CompileError {
+                        location: lett.location.clone(),
+                        end_location: None,
+                        kind: ErrorKind::Warning,
+                        id: "unused-let",
+                        arguments: vec![lett.name.to_string()],
+                        message: format!("{} is never used", lett.name),
+                        notes: vec![],
+                        suggestions,
+                    });
+                }
+            }
+        });
+    }
+}
+
+/// `&&` and `||` with a constant left operand, which always or never run their right operand
+pub struct ConstantCondition;
+
+impl Lint for ConstantCondition {
+    fn get_name(&self) -> &'static str {
+        "constant_condition"
+    }
+
+    fn check(&self, file: &Arc<BoundNode>, problems: &mut Vec<CompileError>) {
+        visit_nodes(file, &mut |node| {
+            let BoundNode::Binary(binary) = node as &BoundNode else {
+                return;
+            };
+            let (operator, runs_right) = match binary.operator.kind {
+                BinaryOperatorKind::LogicalAnd => ("&&", true),
+                BinaryOperatorKind::LogicalOr => ("||", false),
+                _ => return,
+            };
+            let Ok(Some(condition)) = evaluate_constant(&binary.left) else {
+                return;
+            };
+            problems.push(CompileError {
+                location: binary.left.get_location(),
+                end_location: None,
+                kind: ErrorKind::Warning,
+                id: "constant-condition",
+                arguments: vec![
+                    operator.to_string(),
+                    condition.to_string(),
+                    if (condition != 0) == runs_right {
+                        "always"
+                    } else {
+                        "never"
+                    }
+                    .to_string(),
+                ],
+                message: format!(
+                    "The condition of {} is always {}, so the right side {} runs",
+                    operator,
+                    condition,
+                    if (condition != 0) == runs_right {
+                        "always"
+                    } else {
+                        "never"
+                    },
+                ),
+                notes: vec![],
+                suggestions: vec![],
+            });
+        });
+    }
+}
+
+/// Definitions named like a builtin, which `x.name()` then calls instead of the builtin
+pub struct ShadowedBuiltin;
+
+impl Lint for ShadowedBuiltin {
+    fn get_name(&self) -> &'static str {
+        "shadowed_builtin"
+    }
+
+    fn check(&self, file: &Arc<BoundNode>, problems: &mut Vec<CompileError>) {
+        visit_nodes(file, &mut |node| {
+            let (name, location) = match node as &BoundNode {
+                BoundNode::Let(lett) => (&lett.name, &lett.location),
+                BoundNode::Export(export) => (&export.name, &export.location),
+                _ => return,
+            };
+            if let Some(native) = NATIVES.iter().find(|native| native.name == name) {
+                problems.push(CompileError {
+                    location: location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::Warning,
+                    id: "shadows-builtin",
+                    arguments: vec![name.to_string(), native.full_name().to_string(), name.to_string()],
+                    message: format!(
+                        "{} has the same name as the builtin {}, so calls like x.{}() use it instead",
+                        name,
+                        native.full_name(),
+                        name,
+                    ),
+                    notes: vec![],
+                    suggestions: vec![],
+                });
+            }
+        });
+    }
+}
+
+/// Lets that reuse the name of a definition from an outer scope, in a file that allows it with
+/// `//! shadowing`, where the outer definition can not be reached until the scope ends
+pub struct ShadowedBinding;
+
+impl Lint for ShadowedBinding {
+    fn get_name(&self) -> &'static str {
+        "shadowed_binding"
+    }
+
+    fn check(&self, file: &Arc<BoundNode>, problems: &mut Vec<CompileError>) {
+        visit_nodes(file, &mut |node| {
+            let BoundNode::Let(lett) = node as &BoundNode else {
+                return;
+            };
+            let Some(shadowed) = &lett.shadowed else {
+                return;
+            };
+            problems.push(CompileError {
+                location: lett.location.clone(),
+                end_location: None,
+                kind: ErrorKind::Warning,
+                id: "shadows-outer",
+                arguments: vec![lett.name.to_string(), lett.name.to_string()],
+                message: format!("{} shadows the {} of an outer scope", lett.name, lett.name),
+                notes: vec![CompileNote {
+                    location: Some(shadowed.clone()),
+                    message: format!("{} was previously defined here", lett.name),
+                }],
+                suggestions: vec![],
+            });
+        });
+    }
+}
@@ -1,39 +1,41 @@
-#![allow(dead_code)]
-
 use std::{
-    collections::{HashMap, VecDeque},
-    io::Write,
+    collections::VecDeque,
+    io::{ErrorKind, IsTerminal, Write},
+    path::Path,
     process::exit,
-    rc::Rc,
+    time::{Duration, Instant},
 };
 
-use ast::Ast;
-use binding::bind_ast;
-use bytecode::Bytecode;
-use bytecode_compilation::compile_bytecode;
-use common::CompileError;
-use execute::execute_bytecode;
-
-use crate::{
-    ast::AstFile,
-    bound_nodes::{BoundNode, BoundPrintInteger},
-    common::SourceLocation,
+use lang::{
+    bench::{bench_program, vm_bench},
+    bytecode_compilation::OptimizationLevel,
+    bytecode_text::explain_bytecode,
+    common::{
+        write_diagnostics_with, write_warning, ColumnPolicy, ColumnUnit, CompileError,
+        DiagnosticStyle, DiagnosticVerbosity, Diagnostics, MessageCatalog, RuntimeError,
+    },
+    coverage::Coverage,
+    diff::{diff_lines, format_diff, outline},
+    emit::{emit, parse_stages, Stage},
+    execute::Vm,
+    explain::{explain_pipeline, DEFAULT_MAX_STEPS},
+    fix::{fix_source_with, FixOptions},
+    grammar::{dump_ebnf, dump_grammar},
+    ice,
+    inspect::{bind_file, dump_callgraph, dump_scopes, dump_types},
     lexer::Lexer,
+    lint::{lint_precedence, Linter, Severity},
+    metrics::collect_metrics,
     parsing::parse_file,
+    pipeline::Pipeline,
+    program::{Prelude, Program},
+    repl::{run_repl, run_terminal_repl},
+    serve::{serve, METHODS},
+    stats::Stats,
+    value::Value,
+    verify::{count_instructions, verify_bytecode},
 };
 
-mod ast;
-mod binding;
-mod bound_nodes;
-mod bytecode;
-mod bytecode_compilation;
-mod common;
-mod execute;
-mod lexer;
-mod parsing;
-mod token;
-mod types;
-
 fn print_usage(stream: &mut dyn Write) -> Result<(), std::io::Error> {
     let program_str = std::env::current_exe()
         .ok()
@@ -45,51 +47,303 @@ fn print_usage(stream: &mut dyn Write) -> Result<(), std::io::Error> {
     writeln!(stream, "    {} help: Prints this message", program_str)?;
     writeln!(
         stream,
-        "    {} dump_ast <file>: Dumps the ast of the program",
+        "    {} compile <file> [--emit=tokens|ast|ir|bytecode|all,...] [--out-dir DIR] [--opt-level 0|1|2]: Checks the program and writes out the requested stages",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} fix <file> [--lint-precedence]: Applies the suggested fixes for the errors in the file and lists what changed, optionally fixing the precedence warnings too",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} lint <file or directory>... [--metrics] [--allow|--warn|--deny LINT]...: Runs the lints and warns about operators whose precedence is easy to get wrong, optionally listing the size and complexity of each definition, directories are searched for .lang files which are linted in parallel",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} explain_bytecode <file> [--opt-level 0|1|2]: Prints the bytecode of the program with the source line each instruction came from",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} dump_ir --diff <old file> <new file> [--stage tokens|ast|ir|bytecode]: Shows which nodes of the bound tree, or of another stage, were added, removed or changed between two files",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} dump_types <file>: Lists every let and export with its type and location",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} dump_scopes <file>: Prints the scopes of the program and the names defined in them",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} dump_callgraph <file>: Lists which definitions call which and any recursion",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} dump_grammar [--ebnf]: Lists the keywords, symbols, operators with their precedences and builtins, or the grammar in EBNF",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} run <file> [--stats] [--coverage] [--lcov FILE] [--max-errors N] [--diagnostic-verbosity terse|normal|verbose] [--message-catalog FILE] [--tab-width N] [--column-unit chars|utf8|utf16] [--lint-precedence] [--opt-level 0|1|2] [--dry-run] [--explain-pipeline] [--timeout DURATION] [--max-output BYTES]: Runs the program, optionally reporting memory usage or which lines ran, --dry-run only compiles and verifies it, --message-catalog replaces the messages of errors with the ones for their ids in FILE, --tab-width and --column-unit change how the columns of errors are counted, --explain-pipeline prints every stage of it and a trace of its execution, --timeout (like 5s or 500ms) and --max-output stop it with exit status 124 once it runs too long or prints too much",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} bench <file> [--iters N]: Times the program or its exported bench_* procedures",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} vm_bench [--iters N]: Measures the speed of each family of vm instructions",
+        program_str,
+    )?;
+    writeln!(
+        stream,
+        "    {} repl: Starts an interactive session",
         program_str,
     )?;
     writeln!(
         stream,
-        "    {} dump_ir <file>: Dumps the ir of the program",
+        "    {} serve: Answers json-rpc requests from stdin, one per line, for editors and other tools, the methods are {}",
         program_str,
+        METHODS
+            .iter()
+            .map(|&(name, _)| name)
+            .collect::<Vec<_>>()
+            .join(", "),
     )?;
-    writeln!(stream, "    {} run <file>: Runs the program", program_str,)?;
     Ok(())
 }
 
-fn parse_ast_or_error(filepath: String) -> AstFile {
-    let source = std::fs::read_to_string(filepath.clone()).unwrap_or_else(|_| {
-        writeln!(std::io::stderr(), "Unable to open file: '{}'", filepath).unwrap();
+fn read_file_or_error(filepath: &str) -> String {
+    std::fs::read_to_string(filepath).unwrap_or_else(|_| {
+        eprintln!("Unable to open file: '{}'", filepath);
         exit(1)
-    });
-    let mut lexer = Lexer::new(filepath, &source);
-    parse_file(&mut lexer).unwrap_or_else(|error| report_compile_error(error))
+    })
 }
 
-fn report_compile_error(error: CompileError) -> ! {
-    let mut stderr = std::io::stderr();
-    writeln!(
-        stderr,
-        "{}:{}:{}: Compile Error: {}",
-        error.location.filepath, error.location.line, error.location.column, error.message,
-    )
-    .unwrap();
-    for note in error.notes {
-        if let Some(location) = &note.location {
+const DEFAULT_MAX_ERRORS: usize = 20;
+
+fn parse_optimization_level(args: &mut VecDeque<String>) -> OptimizationLevel {
+    args.pop_front()
+        .and_then(|level| level.parse().ok())
+        .and_then(OptimizationLevel::from_number)
+        .unwrap_or_else(|| {
+            let mut stderr = std::io::stderr();
             writeln!(
                 stderr,
-                "{}:{}:{}: ",
-                location.filepath, location.line, location.column,
+                "Please specify an optimization level from 0 to {}",
+                OptimizationLevel::ALL.len() - 1
             )
             .unwrap();
-        }
-        writeln!(stderr, "Note: {}", note.message).unwrap();
+            print_usage(&mut stderr).unwrap();
+            exit(1)
+        })
+}
+
+// a number of seconds, or a number followed by ms, s or m
+fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => text.split_at(index),
+        None => (text, "s"),
+    };
+    let seconds = match unit {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Duration::try_from_secs_f64(number * seconds).ok()
+}
+
+fn report_compile_error(error: CompileError) -> ! {
+    report_diagnostics(&error.into(), DEFAULT_MAX_ERRORS)
+}
+
+fn report_diagnostics(diagnostics: &Diagnostics, max_errors: usize) -> ! {
+    report_diagnostics_with(diagnostics, max_errors, &DiagnosticStyle::default())
+}
+
+fn report_diagnostics_with(
+    diagnostics: &Diagnostics,
+    max_errors: usize,
+    style: &DiagnosticStyle,
+) -> ! {
+    write_diagnostics_with(&mut std::io::stderr(), diagnostics, max_errors, style).unwrap();
+    exit(EXIT_COMPILE_ERROR)
+}
+
+// the exit statuses of `run`, 70 is EX_SOFTWARE from sysexits.h and 124 is what `timeout`
+// exits with when it stops a command
+const EXIT_COMPILE_ERROR: i32 = 1;
+const EXIT_RUNTIME_ERROR: i32 = 70;
+const EXIT_RESOURCE_LIMIT: i32 = 124;
+
+fn report_runtime_error(error: RuntimeError) -> ! {
+    if error.is_output_closed() {
+        // whoever was reading the output has stopped, so there is nobody left to tell
+        exit(0)
     }
+    eprintln!("Runtime Error: {}", error.message);
+    if error.is_resource_limit() {
+        // what was printed before the limit is still worth having
+        std::io::stdout().flush().ok();
+        exit(EXIT_RESOURCE_LIMIT)
+    }
+    exit(EXIT_RUNTIME_ERROR)
+}
+
+fn report_output_error(error: std::io::Error) -> ! {
+    if error.kind() == ErrorKind::BrokenPipe {
+        exit(0)
+    }
+    eprintln!("Unable to write output: {}", error);
     exit(1)
 }
 
+fn write_output(output: std::fmt::Arguments) {
+    std::io::stdout()
+        .write_fmt(output)
+        .unwrap_or_else(|error| report_output_error(error));
+}
+
+// every .lang file under the directory, sorted so the output is the same on every run
+fn collect_source_files(directory: &Path, filepaths: &mut Vec<String>) {
+    let entries = std::fs::read_dir(directory).unwrap_or_else(|_| {
+        eprintln!("Unable to open directory: '{}'", directory.display());
+        exit(1)
+    });
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_source_files(&path, filepaths);
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "lang")
+        {
+            filepaths.push(path.to_string_lossy().into_owned());
+        }
+    }
+}
+
+struct LintResult {
+    output: String,
+    diagnostics: Vec<u8>,
+    failed: bool,
+}
+
+// the files are split between threads, the results come back in the order of the files so the
+// output does not depend on which thread finished first
+fn lint_files(
+    filepaths: &[String],
+    linter: &Linter,
+    metrics: bool,
+    prelude: &Prelude,
+) -> Vec<LintResult> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(filepaths.len());
+    let mut results: Vec<(usize, LintResult)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|thread| {
+                scope.spawn(move || {
+                    (thread..filepaths.len())
+                        .step_by(threads)
+                        .map(|index| {
+                            let result = lint_file(&filepaths[index], linter, metrics, prelude);
+                            (index, result)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn lint_file(filepath: &str, linter: &Linter, metrics: bool, prelude: &Prelude) -> LintResult {
+    let mut result = LintResult {
+        output: String::new(),
+        diagnostics: vec![],
+        failed: true,
+    };
+    let style = DiagnosticStyle::default();
+    let Ok(source) = std::fs::read_to_string(filepath) else {
+        writeln!(result.diagnostics, "Unable to open file: '{}'", filepath).unwrap();
+        return result;
+    };
+    let file = match parse_file(&mut Lexer::new(filepath.to_string(), &source)) {
+        Ok(file) => file,
+        Err(error) => {
+            write_diagnostics_with(
+                &mut result.diagnostics,
+                &error.into(),
+                DEFAULT_MAX_ERRORS,
+                &style,
+            )
+            .unwrap();
+            return result;
+        }
+    };
+    for warning in lint_precedence(&file, &source) {
+        write_warning(&mut result.diagnostics, &warning, &style).unwrap();
+    }
+    if metrics {
+        for definition in collect_metrics(filepath, &file) {
+            result.output += &format!("{}\n", definition);
+        }
+    }
+    // the precedence warnings and metrics only need the ast, so they still come out
+    // when the file does not bind
+    let bound_file = match bind_file(filepath.to_string(), &source, prelude) {
+        Ok(bound_file) => bound_file,
+        Err(diagnostics) => {
+            write_diagnostics_with(
+                &mut result.diagnostics,
+                &diagnostics,
+                DEFAULT_MAX_ERRORS,
+                &style,
+            )
+            .unwrap();
+            return result;
+        }
+    };
+    let report = linter.check(&bound_file, &source);
+    for warning in &report.warnings {
+        write_warning(&mut result.diagnostics, warning, &style).unwrap();
+    }
+    write_diagnostics_with(
+        &mut result.diagnostics,
+        &report.errors,
+        DEFAULT_MAX_ERRORS,
+        &style,
+    )
+    .unwrap();
+    result.failed = !report.errors.is_empty();
+    result
+}
+
 fn main() {
-    let mut args: VecDeque<String> = std::env::args().into_iter().collect();
+    // panics of the compiler are reported as internal compiler errors, not with a backtrace
+    ice::install_panic_hook();
+    let mut args: VecDeque<String> = std::env::args().collect();
     args.pop_front().unwrap();
     let command = args.pop_front().unwrap_or_else(|| {
         let mut stderr = std::io::stderr();
@@ -99,44 +353,304 @@ fn main() {
     });
     match &command as &str {
         "help" => {
-            print_usage(&mut std::io::stdout()).unwrap();
+            print_usage(&mut std::io::stdout()).unwrap_or_else(|error| report_output_error(error));
+        }
+
+        "compile" => {
+            let filepath = args.pop_front().unwrap_or_else(|| {
+                let mut stderr = std::io::stderr();
+                writeln!(stderr, "Please specify a file").unwrap();
+                print_usage(&mut stderr).unwrap();
+                exit(1)
+            });
+            let mut stages = vec![];
+            let mut out_dir = None;
+            let mut pipeline = Pipeline::new();
+            while let Some(option) = args.pop_front() {
+                if let Some(list) = option.strip_prefix("--emit=") {
+                    stages.extend(parse_stages(list).unwrap_or_else(|message| {
+                        let mut stderr = std::io::stderr();
+                        writeln!(stderr, "{}", message).unwrap();
+                        print_usage(&mut stderr).unwrap();
+                        exit(1)
+                    }));
+                } else if option == "--opt-level" {
+                    pipeline = pipeline.with_optimizations(parse_optimization_level(&mut args));
+                } else if option == "--out-dir" {
+                    out_dir = Some(args.pop_front().unwrap_or_else(|| {
+                        let mut stderr = std::io::stderr();
+                        writeln!(stderr, "Please specify a directory").unwrap();
+                        print_usage(&mut stderr).unwrap();
+                        exit(1)
+                    }));
+                } else {
+                    let mut stderr = std::io::stderr();
+                    writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                    print_usage(&mut stderr).unwrap();
+                    exit(1)
+                }
+            }
+
+            let source = read_file_or_error(&filepath);
+            for &stage in &stages {
+                pipeline = pipeline.emit(stage);
+            }
+            // with nothing to emit this just checks that the file compiles
+            let compilation = pipeline.run(filepath.clone(), &source);
+            if let Err(diagnostics) = &compilation.result {
+                if stages.is_empty() {
+                    report_diagnostics(diagnostics, DEFAULT_MAX_ERRORS);
+                }
+            }
+            for &stage in &stages {
+                // the stages are written in the order they were asked for, up to the first one
+                // compilation didn't get to
+                let output = compilation.get_emitted(stage).unwrap_or_else(|| {
+                    let diagnostics = compilation.result.as_ref().err();
+                    report_diagnostics(
+                        diagnostics.expect("a stage is only missing if compilation failed"),
+                        DEFAULT_MAX_ERRORS,
+                    )
+                });
+                if let Some(out_dir) = &out_dir {
+                    let stem = Path::new(&filepath).file_stem().unwrap_or_default();
+                    let path = Path::new(out_dir)
+                        .join(stem)
+                        .with_extension(stage.get_name());
+                    std::fs::write(&path, output).unwrap_or_else(|_| {
+                        eprintln!("Unable to write file: '{}'", path.display());
+                        exit(1)
+                    });
+                } else if stages.len() > 1 {
+                    write_output(format_args!("== {} ==\n{}", stage.get_name(), output));
+                } else {
+                    write_output(format_args!("{}", output));
+                }
+            }
         }
 
-        "dump_ast" => {
+        // the old names for `compile --emit=ast` and `compile --emit=ir`, with `--diff` they
+        // compare two files instead
+        "dump_ast" | "dump_ir" => {
+            let mut stage = if command == "dump_ast" {
+                Stage::Ast
+            } else {
+                Stage::Ir
+            };
+            if args.front().is_some_and(|option| option == "--diff") {
+                args.pop_front();
+                let mut files = vec![];
+                while let Some(option) = args.pop_front() {
+                    if option == "--stage" {
+                        stage = args
+                            .pop_front()
+                            .and_then(|name| Stage::from_name(&name))
+                            .unwrap_or_else(|| {
+                                let mut stderr = std::io::stderr();
+                                writeln!(stderr, "Please specify a stage").unwrap();
+                                print_usage(&mut stderr).unwrap();
+                                exit(1)
+                            });
+                    } else if files.len() < 2 && !option.starts_with("--") {
+                        files.push(option);
+                    } else {
+                        let mut stderr = std::io::stderr();
+                        writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                        print_usage(&mut stderr).unwrap();
+                        exit(1)
+                    }
+                }
+                if files.len() != 2 {
+                    let mut stderr = std::io::stderr();
+                    writeln!(stderr, "Please specify the old and the new file").unwrap();
+                    print_usage(&mut stderr).unwrap();
+                    exit(1)
+                }
+                let outlines: Vec<Vec<String>> = files
+                    .into_iter()
+                    .map(|filepath| {
+                        let source = read_file_or_error(&filepath);
+                        outline(stage, filepath, &source).unwrap_or_else(|diagnostics| {
+                            report_diagnostics(&diagnostics, DEFAULT_MAX_ERRORS)
+                        })
+                    })
+                    .collect();
+                let diff = diff_lines(&outlines[0], &outlines[1]);
+                write_output(format_args!("{}", format_diff(&diff)));
+                return;
+            }
+
             let filepath = args.pop_front().unwrap_or_else(|| {
                 let mut stderr = std::io::stderr();
                 writeln!(stderr, "Please specify a file").unwrap();
                 print_usage(&mut stderr).unwrap();
                 exit(1)
             });
-            let file = parse_ast_or_error(filepath);
-            println!("{:#?}", file);
+            let source = read_file_or_error(&filepath);
+            let output = emit(stage, filepath, &source)
+                .unwrap_or_else(|diagnostics| report_diagnostics(&diagnostics, DEFAULT_MAX_ERRORS));
+            write_output(format_args!("{}", output));
         }
 
-        "dump_ir" => {
+        "fix" => {
             let filepath = args.pop_front().unwrap_or_else(|| {
                 let mut stderr = std::io::stderr();
                 writeln!(stderr, "Please specify a file").unwrap();
                 print_usage(&mut stderr).unwrap();
                 exit(1)
             });
-            let file = parse_ast_or_error(filepath);
+            let mut options = FixOptions::default();
+            while let Some(option) = args.pop_front() {
+                if option == "--lint-precedence" {
+                    options.lint_precedence = true;
+                } else {
+                    let mut stderr = std::io::stderr();
+                    writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                    print_usage(&mut stderr).unwrap();
+                    exit(1)
+                }
+            }
+            let source = read_file_or_error(&filepath);
+            let result = fix_source_with(filepath.clone(), &source, options);
+            for suggestion in &result.applied {
+                write_output(format_args!(
+                    "{}:{}:{}: {}\n",
+                    suggestion.location.filepath,
+                    suggestion.location.line,
+                    suggestion.location.column,
+                    suggestion.message,
+                ));
+            }
+            if result.source != source {
+                std::fs::write(&filepath, &result.source).unwrap_or_else(|_| {
+                    eprintln!("Unable to write file: '{}'", filepath);
+                    exit(1)
+                });
+            }
+            if !result.remaining.is_empty() {
+                report_diagnostics(&result.remaining, DEFAULT_MAX_ERRORS);
+            }
+        }
 
-            let mut names = HashMap::new();
+        "lint" => {
+            let mut paths = vec![];
+            let mut metrics = false;
+            let mut linter = Linter::new();
+            while let Some(option) = args.pop_front() {
+                if !option.starts_with("--") {
+                    paths.push(option);
+                } else if option == "--metrics" {
+                    metrics = true;
+                } else if let Some(severity) =
+                    option.strip_prefix("--").and_then(Severity::from_name)
+                {
+                    let name = args.pop_front().unwrap_or_default();
+                    if !linter.set_severity(&name, severity) {
+                        let mut stderr = std::io::stderr();
+                        writeln!(stderr, "Unknown lint: '{}'", name).unwrap();
+                        print_usage(&mut stderr).unwrap();
+                        exit(1)
+                    }
+                } else {
+                    let mut stderr = std::io::stderr();
+                    writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                    print_usage(&mut stderr).unwrap();
+                    exit(1)
+                }
+            }
+            if paths.is_empty() {
+                let mut stderr = std::io::stderr();
+                writeln!(stderr, "Please specify a file").unwrap();
+                print_usage(&mut stderr).unwrap();
+                exit(1)
+            }
+            let mut filepaths = vec![];
+            for path in &paths {
+                if Path::new(path).is_dir() {
+                    collect_source_files(Path::new(path), &mut filepaths);
+                } else {
+                    filepaths.push(path.clone());
+                }
+            }
 
-            let print_integer = Rc::new(BoundNode::PrintInteger(BoundPrintInteger {
-                location: SourceLocation {
-                    filepath: "builtin.lang".to_string(),
-                    position: 0,
-                    line: 1,
-                    column: 1,
-                },
-            }));
-            names.insert("print_integer".to_string(), Rc::downgrade(&print_integer));
+            let prelude = Prelude::new();
+            let results = lint_files(&filepaths, &linter, metrics, &prelude);
+            let mut failed = 0;
+            for result in &results {
+                write_output(format_args!("{}", result.output));
+                std::io::stderr().write_all(&result.diagnostics).unwrap();
+                if result.failed {
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                if filepaths.len() > 1 {
+                    eprintln!("{} of {} files failed", failed, filepaths.len());
+                }
+                exit(EXIT_COMPILE_ERROR)
+            }
+        }
+
+        "explain_bytecode" => {
+            let filepath = args.pop_front().unwrap_or_else(|| {
+                let mut stderr = std::io::stderr();
+                writeln!(stderr, "Please specify a file").unwrap();
+                print_usage(&mut stderr).unwrap();
+                exit(1)
+            });
+            let mut pipeline = Pipeline::new();
+            while let Some(option) = args.pop_front() {
+                if option == "--opt-level" {
+                    pipeline = pipeline.with_optimizations(parse_optimization_level(&mut args));
+                } else {
+                    let mut stderr = std::io::stderr();
+                    writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                    print_usage(&mut stderr).unwrap();
+                    exit(1)
+                }
+            }
+            let source = read_file_or_error(&filepath);
+            let program = pipeline
+                .compile(filepath, &source)
+                .unwrap_or_else(|diagnostics| report_diagnostics(&diagnostics, DEFAULT_MAX_ERRORS));
+            write_output(format_args!(
+                "{}",
+                explain_bytecode(program.get_bytecode(), program.get_lines(), &source)
+            ));
+        }
+
+        "dump_types" | "dump_scopes" | "dump_callgraph" => {
+            let filepath = args.pop_front().unwrap_or_else(|| {
+                let mut stderr = std::io::stderr();
+                writeln!(stderr, "Please specify a file").unwrap();
+                print_usage(&mut stderr).unwrap();
+                exit(1)
+            });
+            let source = read_file_or_error(&filepath);
+            let dump = match &command as &str {
+                "dump_types" => dump_types,
+                "dump_scopes" => dump_scopes,
+                _ => dump_callgraph,
+            };
+            let output = dump(filepath, &source)
+                .unwrap_or_else(|diagnostics| report_diagnostics(&diagnostics, DEFAULT_MAX_ERRORS));
+            write_output(format_args!("{}", output));
+        }
 
-            let bound_file = bind_ast(&Ast::File(file), &mut names)
-                .unwrap_or_else(|error| report_compile_error(error));
-            println!("{:#?}", bound_file);
+        "dump_grammar" => {
+            let mut ebnf = false;
+            while let Some(option) = args.pop_front() {
+                if option == "--ebnf" {
+                    ebnf = true;
+                } else {
+                    let mut stderr = std::io::stderr();
+                    writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                    print_usage(&mut stderr).unwrap();
+                    exit(1)
+                }
+            }
+            let output = if ebnf { dump_ebnf() } else { dump_grammar() };
+            write_output(format_args!("{}", output));
         }
 
         "run" => {
@@ -146,31 +660,332 @@ fn main() {
                 print_usage(&mut stderr).unwrap();
                 exit(1)
             });
-            let file = parse_ast_or_error(filepath);
+            let mut print_stats = false;
+            let mut dry_run = false;
+            let mut print_coverage = false;
+            let mut lcov_path = None;
+            let mut max_errors = DEFAULT_MAX_ERRORS;
+            let mut diagnostic_style = DiagnosticStyle::default();
+            let mut columns = ColumnPolicy::default();
+            let mut lint = false;
+            let mut explain = false;
+            let mut pipeline = Pipeline::new();
+            let mut timeout = None;
+            let mut max_output = None;
+            while let Some(option) = args.pop_front() {
+                match &option as &str {
+                    "--stats" => print_stats = true,
+                    "--timeout" => {
+                        timeout = Some(
+                            args.pop_front()
+                                .and_then(|duration| parse_duration(&duration))
+                                .unwrap_or_else(|| {
+                                    let mut stderr = std::io::stderr();
+                                    writeln!(stderr, "Please specify a duration like 5s or 500ms")
+                                        .unwrap();
+                                    print_usage(&mut stderr).unwrap();
+                                    exit(1)
+                                }),
+                        );
+                    }
+                    "--max-output" => {
+                        max_output = Some(
+                            args.pop_front()
+                                .and_then(|size| size.parse().ok())
+                                .unwrap_or_else(|| {
+                                    let mut stderr = std::io::stderr();
+                                    writeln!(stderr, "Please specify a number of bytes").unwrap();
+                                    print_usage(&mut stderr).unwrap();
+                                    exit(1)
+                                }),
+                        );
+                    }
+                    "--explain-pipeline" => explain = true,
+                    "--opt-level" => {
+                        pipeline = pipeline.with_optimizations(parse_optimization_level(&mut args))
+                    }
+                    "--dry-run" => dry_run = true,
+                    "--lint-precedence" => lint = true,
+                    "--coverage" => print_coverage = true,
+                    "--lcov" => {
+                        lcov_path = Some(args.pop_front().unwrap_or_else(|| {
+                            let mut stderr = std::io::stderr();
+                            writeln!(stderr, "Please specify a file").unwrap();
+                            print_usage(&mut stderr).unwrap();
+                            exit(1)
+                        }));
+                    }
+                    "--max-errors" => {
+                        max_errors = args
+                            .pop_front()
+                            .and_then(|count| count.parse().ok())
+                            .unwrap_or_else(|| {
+                                let mut stderr = std::io::stderr();
+                                writeln!(stderr, "Please specify a number of errors").unwrap();
+                                print_usage(&mut stderr).unwrap();
+                                exit(1)
+                            });
+                    }
+                    "--diagnostic-verbosity" => {
+                        diagnostic_style.verbosity = args
+                            .pop_front()
+                            .and_then(|name| DiagnosticVerbosity::from_name(&name))
+                            .unwrap_or_else(|| {
+                                let mut stderr = std::io::stderr();
+                                writeln!(stderr, "Please specify terse, normal or verbose")
+                                    .unwrap();
+                                print_usage(&mut stderr).unwrap();
+                                exit(1)
+                            });
+                    }
+                    "--message-catalog" => {
+                        let path = args.pop_front().unwrap_or_else(|| {
+                            let mut stderr = std::io::stderr();
+                            writeln!(stderr, "Please specify a file").unwrap();
+                            print_usage(&mut stderr).unwrap();
+                            exit(1)
+                        });
+                        diagnostic_style.catalog = MessageCatalog::parse(&read_file_or_error(
+                            &path,
+                        ))
+                        .unwrap_or_else(|message| {
+                            eprintln!("{}", message);
+                            exit(1)
+                        });
+                    }
+                    "--tab-width" => {
+                        columns.tab_width = args
+                            .pop_front()
+                            .and_then(|width| width.parse().ok())
+                            .filter(|&width| width > 0)
+                            .unwrap_or_else(|| {
+                                let mut stderr = std::io::stderr();
+                                writeln!(stderr, "Please specify a tab width of at least 1")
+                                    .unwrap();
+                                print_usage(&mut stderr).unwrap();
+                                exit(1)
+                            });
+                    }
+                    "--column-unit" => {
+                        columns.unit = args
+                            .pop_front()
+                            .and_then(|name| ColumnUnit::from_name(&name))
+                            .unwrap_or_else(|| {
+                                let mut stderr = std::io::stderr();
+                                writeln!(stderr, "Please specify chars, utf8 or utf16").unwrap();
+                                print_usage(&mut stderr).unwrap();
+                                exit(1)
+                            });
+                    }
+                    _ => {
+                        let mut stderr = std::io::stderr();
+                        writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                        print_usage(&mut stderr).unwrap();
+                        exit(1)
+                    }
+                }
+            }
 
-            let mut names = HashMap::new();
+            let source = read_file_or_error(&filepath);
+            if explain {
+                let explanation = explain_pipeline(filepath, &source, DEFAULT_MAX_STEPS)
+                    .unwrap_or_else(|mut diagnostics| {
+                        for error in &mut diagnostics.errors {
+                            columns.recount(error, &source);
+                        }
+                        report_diagnostics_with(&diagnostics, max_errors, &diagnostic_style)
+                    });
+                write_output(format_args!("{}", explanation));
+                return;
+            }
+            if lint {
+                // warnings come first so they are not lost when compilation fails, a file
+                // that does not parse gets its error from the compilation below
+                if let Ok(file) = parse_file(&mut Lexer::new(filepath.clone(), &source)) {
+                    for mut warning in lint_precedence(&file, &source) {
+                        columns.recount(&mut warning, &source);
+                        write_warning(&mut std::io::stderr(), &warning, &diagnostic_style).unwrap();
+                    }
+                }
+            }
+            let mut program =
+                pipeline
+                    .compile(filepath.clone(), &source)
+                    .unwrap_or_else(|mut diagnostics| {
+                        for error in &mut diagnostics.errors {
+                            columns.recount(error, &source);
+                        }
+                        report_diagnostics_with(&diagnostics, max_errors, &diagnostic_style)
+                    });
+            if dry_run {
+                let bytecode = program.get_bytecode();
+                write_output(format_args!(
+                    "{} instructions\n",
+                    count_instructions(bytecode)
+                ));
+                let errors = verify_bytecode(bytecode);
+                for error in &errors {
+                    let path: Vec<_> = error.path.iter().map(|index| index.to_string()).collect();
+                    eprintln!("{}: Verify Error: {}", path.join("."), error.message);
+                }
+                exit(if errors.is_empty() {
+                    0
+                } else {
+                    EXIT_COMPILE_ERROR
+                })
+            }
+            let mut vm = Vm::new();
+            if print_coverage || lcov_path.is_some() {
+                vm.coverage = Some(vec![]);
+            }
+            // the timeout is for running the program, compiling it always finishes
+            vm.deadline = timeout.map(|timeout| Instant::now() + timeout);
+            vm.max_output = max_output;
+            let result = program.run_with(&mut vm);
+            // reported even when the program fails, that is when it is most useful
+            if let Some(counts) = &vm.coverage {
+                let coverage = Coverage::collect(filepath.clone(), &program, counts);
+                if print_coverage {
+                    eprint!("{}", coverage);
+                }
+                if let Some(path) = &lcov_path {
+                    std::fs::write(path, coverage.to_lcov()).unwrap_or_else(|_| {
+                        eprintln!("Unable to write file: '{}'", path);
+                        exit(1)
+                    });
+                }
+            }
+            result.unwrap_or_else(|error| report_runtime_error(error));
 
-            let print_integer = Rc::new(BoundNode::PrintInteger(BoundPrintInteger {
-                location: SourceLocation {
-                    filepath: "builtin.lang".to_string(),
-                    position: 0,
-                    line: 1,
-                    column: 1,
-                },
-            }));
-            names.insert("print_integer".to_string(), Rc::downgrade(&print_integer));
+            // an exported `main` procedure taking no arguments is called after the top level,
+            // if it returns an integer that is the exit status, which has to fit in the 0 to
+            // 255 that every platform keeps
+            let mut status = 0;
+            if let Some(main) = program.get_proc("main") {
+                if main.get_type().parameter_types.is_empty() {
+                    let result = main
+                        .call_with(&mut vm, &[])
+                        .unwrap_or_else(|error| report_runtime_error(error));
+                    if let Value::Integer(code) = result {
+                        status = u8::try_from(code).map(i32::from).unwrap_or_else(|_| {
+                            report_runtime_error(RuntimeError {
+                                message: format!(
+                                    "main returned {}, but exit statuses have to be from 0 to 255",
+                                    code,
+                                ),
+                            })
+                        });
+                    }
+                }
+            }
+
+            if print_stats {
+                let mut stats = Stats::collect(filepath, &source, &program)
+                    .unwrap_or_else(|error| report_compile_error(error));
+                stats.add_execution(&vm);
+                eprint!("{}", stats);
+            }
+            std::io::stdout()
+                .flush()
+                .unwrap_or_else(|error| report_output_error(error));
+            exit(status)
+        }
+
+        "bench" => {
+            let filepath = args.pop_front().unwrap_or_else(|| {
+                let mut stderr = std::io::stderr();
+                writeln!(stderr, "Please specify a file").unwrap();
+                print_usage(&mut stderr).unwrap();
+                exit(1)
+            });
+            let mut iterations = 10;
+            while let Some(option) = args.pop_front() {
+                match &option as &str {
+                    "--iters" => {
+                        iterations = args
+                            .pop_front()
+                            .and_then(|count| count.parse().ok())
+                            .unwrap_or_else(|| {
+                                let mut stderr = std::io::stderr();
+                                writeln!(stderr, "Please specify a number of iterations").unwrap();
+                                print_usage(&mut stderr).unwrap();
+                                exit(1)
+                            });
+                    }
+                    _ => {
+                        let mut stderr = std::io::stderr();
+                        writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                        print_usage(&mut stderr).unwrap();
+                        exit(1)
+                    }
+                }
+            }
 
-            let bound_file = bind_ast(&Ast::File(file), &mut names)
-                .unwrap_or_else(|error| report_compile_error(error));
+            let source = read_file_or_error(&filepath);
+            let mut program = Program::compile(filepath, &source)
+                .unwrap_or_else(|diagnostics| report_diagnostics(&diagnostics, DEFAULT_MAX_ERRORS));
+            let results = bench_program(&mut program, iterations)
+                .unwrap_or_else(|error| report_runtime_error(error));
+            for result in results {
+                write_output(format_args!(
+                    "{}: {} iterations, min {:?}, mean {:?}, max {:?}, {} instructions\n",
+                    result.name,
+                    result.iterations,
+                    result.min,
+                    result.mean,
+                    result.max,
+                    result.instruction_count,
+                ));
+            }
+        }
+
+        "vm_bench" => {
+            let mut iterations = 100;
+            while let Some(option) = args.pop_front() {
+                match &option as &str {
+                    "--iters" => {
+                        iterations = args
+                            .pop_front()
+                            .and_then(|count| count.parse().ok())
+                            .unwrap_or_else(|| {
+                                let mut stderr = std::io::stderr();
+                                writeln!(stderr, "Please specify a number of iterations").unwrap();
+                                print_usage(&mut stderr).unwrap();
+                                exit(1)
+                            });
+                    }
+                    _ => {
+                        let mut stderr = std::io::stderr();
+                        writeln!(stderr, "Unknown option: '{}'", option).unwrap();
+                        print_usage(&mut stderr).unwrap();
+                        exit(1)
+                    }
+                }
+            }
+
+            let results =
+                vm_bench(10_000, iterations).unwrap_or_else(|error| report_runtime_error(error));
+            for result in results {
+                write_output(format_args!(
+                    "{}: {} instructions in {:?}, {:.0} instructions/sec\n",
+                    result.family,
+                    result.instruction_count,
+                    result.elapsed,
+                    result.instructions_per_second(),
+                ));
+            }
+        }
 
-            let mut bytecode = vec![];
-            compile_bytecode(&print_integer, &mut bytecode);
-            bytecode.push(Bytecode::Store("print_integer".to_string()));
-            compile_bytecode(&bound_file, &mut bytecode);
-            bytecode.push(Bytecode::Exit);
-            execute_bytecode(&bytecode, Vec::new());
+        "repl" => {
+            if std::io::stdin().is_terminal() {
+                run_terminal_repl().unwrap();
+            } else {
+                run_repl(&mut std::io::stdin().lock(), &mut std::io::stdout()).unwrap();
+            }
         }
 
+        "serve" => serve(&mut std::io::stdin().lock(), &mut std::io::stdout()).unwrap(),
+
         _ => {
             let mut stderr = std::io::stderr();
             writeln!(stderr, "Unknown command: '{}'", command).unwrap();
@@ -178,12 +993,184 @@ fn main() {
             exit(1)
         }
     }
-    return;
 }
 
 #[cfg(test)]
 mod lexer_tests {
-    use crate::{lexer::Lexer, token::TokenKind};
+    use lang::{
+        grammar::SYMBOLS,
+        lexer::{quote_string, Lexer},
+        token::TokenKind,
+    };
+
+    #[test]
+    fn symbols() {
+        for (text, kind) in SYMBOLS {
+            assert_eq!(&kind.to_string(), text);
+            let mut lexer = Lexer::new("Symbols.fpl".to_string(), text);
+            let token = lexer.next_token().unwrap();
+            assert_eq!(
+                (&token.kind, token.length()),
+                (kind, text.len()),
+                "{}",
+                text
+            );
+            assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EndOfFile);
+        }
+
+        // every pair, both apart and touching, where touching takes the longest symbol first
+        for (first_text, first_kind) in SYMBOLS {
+            for (second_text, second_kind) in SYMBOLS {
+                let source = format!("{} {}", first_text, second_text);
+                let mut lexer = Lexer::new("Symbols.fpl".to_string(), &source);
+                assert_eq!(&lexer.next_token().unwrap().kind, first_kind);
+                assert_eq!(&lexer.next_token().unwrap().kind, second_kind);
+
+                let source = format!("{}{}", first_text, second_text);
+                if source.starts_with("//") {
+                    // a comment, not two slashes
+                    continue;
+                }
+                let longest = SYMBOLS
+                    .iter()
+                    .filter(|(text, _)| source.starts_with(text))
+                    .map(|(text, _)| text.len())
+                    .max()
+                    .unwrap();
+                let mut lexer = Lexer::new("Symbols.fpl".to_string(), &source);
+                assert_eq!(lexer.next_token().unwrap().length(), longest, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn comparison_and_arrow_tokens() {
+        let source = "a >= b <- c -> d <= e";
+        let mut lexer = Lexer::new("Symbols.fpl".to_string(), source);
+        let kinds: Vec<_> = std::iter::from_fn(|| {
+            let kind = lexer.next_token().unwrap().kind;
+            (kind != TokenKind::EndOfFile).then_some(kind)
+        })
+        .filter(|kind| !matches!(kind, TokenKind::Name(_)))
+        .collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::GreaterThanEqual,
+                TokenKind::LeftArrow,
+                TokenKind::RightArrow,
+                TokenKind::LessThanEqual,
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_tokens() {
+        let source = "a ?? ?b ??? none nones";
+        let mut lexer = Lexer::new("Optionals.fpl".to_string(), source);
+        let kinds: Vec<_> = std::iter::from_fn(|| {
+            let kind = lexer.next_token().unwrap().kind;
+            (kind != TokenKind::EndOfFile).then_some(kind)
+        })
+        .collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Name("a".to_string()),
+                TokenKind::QuestionMarkQuestionMark,
+                TokenKind::QuestionMark,
+                TokenKind::Name("b".to_string()),
+                TokenKind::QuestionMarkQuestionMark,
+                TokenKind::QuestionMark,
+                TokenKind::None,
+                TokenKind::Name("nones".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let source = "1.5 1e9 2.5E-3 1_000.000_1 0.1e+2 7";
+        let mut lexer = Lexer::new("Floats.fpl".to_string(), source);
+        let mut floats = vec![];
+        while let TokenKind::Float(float) = lexer.next_token().unwrap().kind {
+            floats.push(float);
+        }
+        assert_eq!(floats, [1.5, 1e9, 2.5e-3, 1000.0001, 10.0]);
+
+        // only a digit after the dot or the e makes it a float
+        for (source, kind) in [
+            ("1.a", TokenKind::Integer(1)),
+            ("1.", TokenKind::Integer(1)),
+            ("0x1e5", TokenKind::Integer(0x1e5)),
+            ("1.5.a", TokenKind::Float(1.5)),
+        ] {
+            let mut lexer = Lexer::new("Floats.fpl".to_string(), source);
+            assert_eq!(lexer.next_token().unwrap().kind, kind, "{}", source);
+        }
+        let mut lexer = Lexer::new("Floats.fpl".to_string(), "1e");
+        assert_eq!(
+            lexer.next_token().unwrap_err().message,
+            "Digit 'e' is invalid for base 10"
+        );
+
+        let mut lexer = Lexer::new("Floats.fpl".to_string(), "1e400 a");
+        assert_eq!(
+            lexer.next_token().unwrap_err().message,
+            "Float literal is too big for a 64 bit float"
+        );
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("a".to_string())
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let source = r#""a # b" "\n\r\t\0\\\"" """#;
+        let mut lexer = Lexer::new("Strings.fpl".to_string(), source);
+        let token = lexer.next_token().unwrap();
+        assert_eq!(token.kind, TokenKind::String("a # b".to_string()));
+        assert_eq!(token.length(), 7);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::String("\n\r\t\0\\\"".to_string())
+        );
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::String(String::new())
+        );
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EndOfFile);
+
+        let value = "\"\\é\n";
+        let quoted = quote_string(value);
+        let mut lexer = Lexer::new("Strings.fpl".to_string(), &quoted);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::String(value.to_string())
+        );
+
+        let mut lexer = Lexer::new("Strings.fpl".to_string(), r#""a\qb" c"#);
+        let error = lexer.next_token().unwrap_err();
+        assert_eq!(error.message, r"Unknown escape sequence '\q'");
+        assert_eq!(error.location.column, 3);
+        // the rest of the string is skipped
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("c".to_string())
+        );
+
+        for source in ["\"abc", "\"abc\ndef\"", "\"abc\\", "\"abc\\\ndef\""] {
+            let mut lexer = Lexer::new("Strings.fpl".to_string(), source);
+            let error = lexer.next_token().unwrap_err();
+            assert_eq!(
+                error.message, "Unterminated string, strings have to end on the line they start on",
+                "{:?}",
+                source
+            );
+            assert_eq!(error.location.column, 1);
+        }
+    }
 
     #[test]
     fn empty_file() {
@@ -193,6 +1180,50 @@ mod lexer_tests {
         assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EndOfFile);
     }
 
+    #[test]
+    fn end_of_file_span() {
+        for source in ["", "a", "a  ", "a // comment", "a\n", "// é"] {
+            let mut lexer = Lexer::new("EndOfFile.fpl".to_string(), source);
+            let token = loop {
+                let token = lexer.next_token().unwrap();
+                if token.kind == TokenKind::EndOfFile {
+                    break token;
+                }
+            };
+            assert_eq!(token.location.position, source.len(), "{:?}", source);
+            assert_eq!(token.length(), 0, "{:?}", source);
+            // the end of file keeps being returned once it is reached
+            assert_eq!(lexer.next_token().unwrap(), token);
+        }
+
+        // a literal nul inside the file is not the end of it
+        let mut lexer = Lexer::new("EndOfFile.fpl".to_string(), "a\0b");
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("a".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap_err().location.column, 2);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("b".to_string())
+        );
+    }
+
+    #[test]
+    fn token_spans() {
+        let source = "let abc = 0x1F";
+        let mut lexer = Lexer::new("Spans.fpl".to_string(), source);
+        let spans: Vec<_> = std::iter::from_fn(|| {
+            let token = lexer.next_token().unwrap();
+            (token.kind != TokenKind::EndOfFile).then(|| {
+                let text = &source[token.location.position..token.end];
+                (text, token.length())
+            })
+        })
+        .collect();
+        assert_eq!(spans, [("let", 3), ("abc", 3), ("=", 1), ("0x1F", 4)]);
+    }
+
     #[test]
     fn integer() {
         let filepath = "Integer.fpl".to_string();
@@ -206,8 +1237,46 @@ mod lexer_tests {
             TokenKind::Integer(0b0100101)
         );
         assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Integer(0o5674));
-        lexer.next_token().unwrap_err();
-        // TODO: allow the lexer to keep going after an error
+        let error = lexer.next_token().unwrap_err();
+        assert_eq!(error.message, "Digit '3' is invalid for base 2");
+        assert_eq!(error.location.column, 37);
+        // the rest of the literal is skipped instead of becoming its own token
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EndOfFile);
+
+        let source = "99999999999999999999999999999999999999999 a";
+        let mut lexer = Lexer::new("Integer.fpl".to_string(), source);
+        let error = lexer.next_token().unwrap_err();
+        assert_eq!(error.location.column, 1);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("a".to_string())
+        );
+
+        // only the minimum needs one past i64::MAX, anything bigger is an error of the lexer
+        let source = "9223372036854775808 9223372036854775809 0x8000000000000001";
+        let mut lexer = Lexer::new("Integer.fpl".to_string(), source);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Integer(9223372036854775808)
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                lexer.next_token().unwrap_err().message,
+                "Integer literal is too big for a 64 bit signed integer"
+            );
+        }
+
+        let source = "0x 0b_ 0";
+        let mut lexer = Lexer::new("Integer.fpl".to_string(), source);
+        assert_eq!(
+            lexer.next_token().unwrap_err().message,
+            "Expected a digit after 0x"
+        );
+        assert_eq!(
+            lexer.next_token().unwrap_err().message,
+            "Expected a digit after 0b_"
+        );
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Integer(0));
     }
 
     #[test]
@@ -225,27 +1294,109 @@ mod lexer_tests {
         );
         assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EndOfFile);
     }
-}
-
-#[cfg(test)]
-mod parser_tests {
-    use crate::{lexer::Lexer, parsing::parse_file, token::TokenKind};
 
     #[test]
-    fn empty_file() {
-        let filepath = "Empty.fpl".to_string();
-        let source = "";
-        let mut lexer = Lexer::new(filepath.clone(), source);
-        let file = parse_file(&mut lexer).unwrap();
-        assert_eq!(file.expressions.len(), 0);
-        assert_eq!(file.end_of_file_token.kind, TokenKind::EndOfFile);
+    fn peek() {
+        let filepath = "Peek.fpl".to_string();
+        let source = "a é b";
+        let mut lexer = Lexer::new(filepath, source);
+        assert_eq!(lexer.peek_kind().unwrap(), TokenKind::Name("a".to_string()));
+        assert_eq!(lexer.peek_kind().unwrap(), TokenKind::Name("a".to_string()));
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("a".to_string())
+        );
+        let error = lexer.peek_kind().unwrap_err();
+        assert_eq!(lexer.next_token().unwrap_err(), error);
+        assert_eq!(error.location.column, 3);
+        let b = lexer.next_token().unwrap();
+        assert_eq!(b.kind, TokenKind::Name("b".to_string()));
+        // positions are byte offsets, columns are characters
+        assert_eq!(b.location.position, 5);
+        assert_eq!(b.location.column, 5);
     }
 
     #[test]
-    fn expression_test() {
-        let filepath = "Expression.fpl".to_string();
-        let source = "1 + 2 * 3";
-        let mut lexer = Lexer::new(filepath.clone(), source);
+    fn lookahead() {
+        let filepath = "Lookahead.fpl".to_string();
+        let source = "a, b é c";
+        let mut lexer = Lexer::new(filepath, source);
+        assert_eq!(
+            lexer.peek_kind_at(2).unwrap(),
+            TokenKind::Name("b".to_string())
+        );
+        assert_eq!(lexer.peek_kind_at(1).unwrap(), TokenKind::Comma);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("a".to_string())
+        );
+        // errors are kept in order with the tokens around them
+        let error = lexer.peek_token_at(2).unwrap_err();
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Comma);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("b".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap_err(), error);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("c".to_string())
+        );
+        assert_eq!(lexer.peek_kind_at(2).unwrap(), TokenKind::EndOfFile);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EndOfFile);
+    }
+
+    #[test]
+    fn checkpoint() {
+        let filepath = "Checkpoint.fpl".to_string();
+        let source = "a\n  b c";
+        let mut lexer = Lexer::new(filepath, source);
+        lexer.next_token().unwrap();
+        assert_eq!(
+            lexer.peek_kind_at(1).unwrap(),
+            TokenKind::Name("b".to_string())
+        );
+        // peeked tokens are lexed again after restoring
+        let checkpoint = lexer.checkpoint();
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Newline);
+        let b = lexer.next_token().unwrap();
+        assert_eq!(b.location.line, 2);
+        assert_eq!(b.location.column, 3);
+        lexer.restore(checkpoint);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Newline);
+        assert_eq!(lexer.next_token().unwrap(), b);
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("c".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EndOfFile);
+    }
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use lang::{
+        ast::{Ast, AstBinary, AstInteger, AstPattern, AstTrait},
+        lexer::Lexer,
+        parsing::{parse_file, parse_file_with_max_depth},
+        token::TokenKind,
+    };
+
+    #[test]
+    fn empty_file() {
+        let filepath = "Empty.fpl".to_string();
+        let source = "";
+        let mut lexer = Lexer::new(filepath.clone(), source);
+        let file = parse_file(&mut lexer).unwrap();
+        assert_eq!(file.expressions.len(), 0);
+        assert_eq!(file.end_of_file_token.kind, TokenKind::EndOfFile);
+    }
+
+    #[test]
+    fn expression_test() {
+        let filepath = "Expression.fpl".to_string();
+        let source = "1 + 2 * 3";
+        let mut lexer = Lexer::new(filepath.clone(), source);
         let file = parse_file(&mut lexer).unwrap();
         assert_eq!(file.expressions.len(), 1);
         assert_eq!(file.end_of_file_token.kind, TokenKind::EndOfFile);
@@ -254,16 +1405,31 @@ mod parser_tests {
         assert_eq!(binary_plus.operator_token.kind, TokenKind::Plus);
 
         let integer_1 = binary_plus.left.unwrap_integer();
-        assert_eq!(integer_1.integer_token.kind, TokenKind::Integer(1));
+        assert_eq!(integer_1.integer_token.value, 1);
 
         let binary_asterisk = binary_plus.right.unwrap_binary();
         assert_eq!(binary_asterisk.operator_token.kind, TokenKind::Asterisk);
 
         let integer_2 = binary_asterisk.left.unwrap_integer();
-        assert_eq!(integer_2.integer_token.kind, TokenKind::Integer(2));
+        assert_eq!(integer_2.integer_token.value, 2);
 
         let integer_3 = binary_asterisk.right.unwrap_integer();
-        assert_eq!(integer_3.integer_token.kind, TokenKind::Integer(3));
+        assert_eq!(integer_3.integer_token.value, 3);
+    }
+
+    #[test]
+    fn accessors() {
+        let mut lexer = Lexer::new("Accessors.fpl".to_string(), "1 + 2");
+        let file = parse_file(&mut lexer).unwrap();
+        let binary = &file.expressions[0];
+        assert!(binary.as_binary().is_some());
+        assert!(binary.as_integer().is_none());
+        assert!(<&AstInteger>::try_from(binary).is_err());
+
+        let binary = AstBinary::try_from(binary.clone()).unwrap();
+        // the node is given back when it is the wrong kind
+        let left = AstBinary::try_from(binary.left.as_ref().clone()).unwrap_err();
+        assert_eq!(AstInteger::try_from(left).unwrap().integer_token.value, 1);
     }
 
     #[test]
@@ -279,14 +1445,14 @@ mod parser_tests {
         assert_eq!(file.end_of_file_token.kind, TokenKind::EndOfFile);
 
         let a = file.expressions[0].unwrap_let();
-        assert_eq!(a.name_token.kind, TokenKind::Name("a".to_string()));
+        assert_eq!(a.name_token.name, "a");
         assert_eq!(a.value, None);
 
         let b = file.expressions[1].unwrap_let();
-        assert_eq!(b.name_token.kind, TokenKind::Name("b".to_string()));
+        assert_eq!(b.name_token.name, "b");
         let b_value = b.value.clone().unwrap();
         let integer_5 = b_value.unwrap_integer();
-        assert_eq!(integer_5.integer_token.kind, TokenKind::Integer(5));
+        assert_eq!(integer_5.integer_token.value, 5);
     }
 
     #[test]
@@ -304,18 +1470,18 @@ mod parser_tests {
         assert_eq!(file.end_of_file_token.kind, TokenKind::EndOfFile);
 
         let foo = file.expressions[0].unwrap_let();
-        assert_eq!(foo.name_token.kind, TokenKind::Name("foo".to_string()));
+        assert_eq!(foo.name_token.name, "foo");
         let foo_value = foo.value.clone().unwrap();
 
         let block = foo_value.unwrap_block();
         assert_eq!(block.expressions.len(), 2);
 
         let a = block.expressions[0].unwrap_let();
-        assert_eq!(a.name_token.kind, TokenKind::Name("a".to_string()));
+        assert_eq!(a.name_token.name, "a");
         assert_eq!(a.value, None);
 
         let integer_5 = block.expressions[1].unwrap_integer();
-        assert_eq!(integer_5.integer_token.kind, TokenKind::Integer(5));
+        assert_eq!(integer_5.integer_token.value, 5);
     }
 
     #[test]
@@ -333,21 +1499,3641 @@ mod parser_tests {
         assert_eq!(file.end_of_file_token.kind, TokenKind::EndOfFile);
 
         let foo_export = file.expressions[0].unwrap_export();
-        assert_eq!(
-            foo_export.name_token.kind,
-            TokenKind::Name("foo".to_string())
-        );
+        assert_eq!(foo_export.name_token.name, "foo");
 
         let block = foo_export.value.unwrap_block();
         assert_eq!(block.expressions.len(), 2);
 
         let a = block.expressions[0].unwrap_let();
-        assert_eq!(a.name_token.kind, TokenKind::Name("a".to_string()));
+        assert_eq!(a.name_token.name, "a");
         assert_eq!(a.value, None);
 
         let export_b = block.expressions[1].unwrap_export();
-        assert_eq!(export_b.name_token.kind, TokenKind::Name("b".to_string()));
+        assert_eq!(export_b.name_token.name, "b");
         let integer_5 = export_b.value.unwrap_integer();
-        assert_eq!(integer_5.integer_token.kind, TokenKind::Integer(5));
+        assert_eq!(integer_5.integer_token.value, 5);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let filepath = "Nesting.fpl".to_string();
+
+        let source = format!("{}1{}", "(".repeat(60), ")".repeat(60));
+        let mut lexer = Lexer::new(filepath.clone(), &source);
+        parse_file(&mut lexer).unwrap();
+
+        let source = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let mut lexer = Lexer::new(filepath.clone(), &source);
+        let error = parse_file(&mut lexer).unwrap_err();
+        assert!(error.message.contains("nested too deeply"));
+
+        let source = format!("{}1", "-".repeat(100_000));
+        let mut lexer = Lexer::new(filepath.clone(), &source);
+        parse_file(&mut lexer).unwrap_err();
+
+        let source = format!("{}1", "-".repeat(20));
+        let mut lexer = Lexer::new(filepath.clone(), &source);
+        parse_file_with_max_depth(&mut lexer, 10).unwrap_err();
+    }
+
+    #[test]
+    fn node_extents() {
+        let source = "let a = (1 + b) * c(2)\n  { x }\n";
+        let mut lexer = Lexer::new("Extents.fpl".to_string(), source);
+        let file = parse_file(&mut lexer).unwrap();
+        let extent = |ast: &Ast| {
+            let (start, end) = (ast.get_start(), ast.get_end());
+            (
+                &source[start.position..end.position],
+                (start.line, start.column),
+                (end.line, end.column),
+            )
+        };
+
+        let lett = &file.expressions[0];
+        assert_eq!(extent(lett), ("let a = (1 + b) * c(2)", (1, 1), (1, 23)));
+        let binary = lett.unwrap_let().value.as_ref().unwrap();
+        assert_eq!(extent(binary), ("(1 + b) * c(2)", (1, 9), (1, 23)));
+        // a binary operator is located at its start instead of at the operator
+        assert_eq!(binary.get_location(), binary.get_start());
+        let left = &binary.unwrap_binary().left;
+        assert_eq!(extent(left), ("(1 + b)", (1, 9), (1, 16)));
+        let right = &binary.unwrap_binary().right;
+        assert_eq!(extent(right), ("c(2)", (1, 19), (1, 23)));
+        assert_eq!(extent(&file.expressions[1]), ("{ x }", (2, 3), (2, 8)));
+
+        let file = Ast::File(file);
+        assert_eq!(extent(&file), (source, (1, 1), (3, 1)));
+    }
+
+    #[test]
+    fn large_file() {
+        let filepath = "Large.fpl".to_string();
+        let source = "let a = 1 + 2 * 3\n".repeat(100_000);
+        let mut lexer = Lexer::new(filepath.clone(), &source);
+        let file = parse_file(&mut lexer).unwrap();
+        assert_eq!(file.expressions.len(), 100_000);
+    }
+
+    #[test]
+    fn match_test() {
+        let source = "match a {\n    1 -> 2\n\n    -3 ->\n        4\n    _ -> 5\n}";
+        let mut lexer = Lexer::new("Match.fpl".to_string(), source);
+        let file = parse_file(&mut lexer).unwrap();
+        let matchh = file.expressions[0].unwrap_match();
+        assert_eq!(matchh.value.unwrap_name().name_token.name, "a");
+        assert_eq!(matchh.arms.len(), 3);
+        assert!(matches!(
+            &matchh.arms[1].pattern,
+            AstPattern::Integer { minus_token: Some(_), integer_token } if integer_token.value == 3
+        ));
+        assert!(matches!(matchh.arms[2].pattern, AstPattern::Default(_)));
+        assert_eq!(matchh.arms[2].value.unwrap_integer().integer_token.value, 5);
+        assert_eq!(matchh.get_end().line, 7);
+
+        for source in [
+            "match a { b -> 1 }",
+            "match a { 1 2 }",
+            "match a { 1 -> 2 3 -> 4 }",
+        ] {
+            let mut lexer = Lexer::new("Match.fpl".to_string(), source);
+            assert!(parse_file(&mut lexer).is_err(), "in {:?}", source);
+        }
+    }
+
+    #[test]
+    fn enum_test() {
+        let source = "enum {\n    Red,\n    Green, Blue,\n}\nmatch a {\n    B.c -> 1\n}";
+        let mut lexer = Lexer::new("Enum.fpl".to_string(), source);
+        let file = parse_file(&mut lexer).unwrap();
+        let enumm = file.expressions[0].unwrap_enum();
+        let names: Vec<_> = enumm.variants.iter().map(|name| &name.name).collect();
+        assert_eq!(names, ["Red", "Green", "Blue"]);
+        assert_eq!(
+            file.expressions[0].pretty_print(0),
+            "enum { Red, Green, Blue }"
+        );
+        let matchh = file.expressions[1].unwrap_match();
+        assert!(matches!(
+            &matchh.arms[0].pattern,
+            AstPattern::Variant(variant) if variant.name_token.name == "c"
+        ));
+
+        for source in [
+            "enum { a b }",
+            "enum { 1 }",
+            "enum { a,, }",
+            "match a { B. -> 1 }",
+        ] {
+            let mut lexer = Lexer::new("Enum.fpl".to_string(), source);
+            assert!(parse_file(&mut lexer).is_err(), "in {:?}", source);
+        }
+    }
+
+    #[test]
+    fn destructure_test() {
+        let source = "let { a, b } = c
+let {
+    d,
+} =
+    e";
+        let mut lexer = Lexer::new("Destructure.fpl".to_string(), source);
+        let file = parse_file(&mut lexer).unwrap();
+        let destructure = file.expressions[0].unwrap_destructure();
+        let names: Vec<_> = destructure.names.iter().map(|name| &name.name).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(file.expressions[0].pretty_print(0), "let { a, b } = c");
+        assert_eq!(file.expressions[1].pretty_print(0), "let { d } = e");
+
+        for source in [
+            "let {} = a",
+            "let { a b } = c",
+            "let { a }",
+            "let { a } + b",
+        ] {
+            let mut lexer = Lexer::new("Destructure.fpl".to_string(), source);
+            assert!(parse_file(&mut lexer).is_err(), "in {:?}", source);
+        }
+    }
+}
+
+#[cfg(test)]
+mod binder_tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use lang::{
+        ast::Ast,
+        binding::{
+            bind_ast, bind_ast_with_diagnostics, bind_ast_with_max_depth, bind_ast_with_options,
+            BindOptions,
+        },
+        bound_nodes::{
+            BoundInteger, BoundLet, BoundNode, BoundNodeTrait, BoundUnary, UnaryOperator,
+            UnaryOperatorKind,
+        },
+        bytecode_compilation::{compile_optimized_bytecode_with_cancellation, OptimizationLevel},
+        common::{
+            write_diagnostics, CancellationToken, CompileError, Diagnostics, ErrorKind,
+            SourceLocation,
+        },
+        lexer::Lexer,
+        parsing::{parse_file, parse_file_with_max_depth},
+        program::Prelude,
+        testing::{assert_bind_errors, assert_binds, assert_type_of_last, bind_str_with_prelude},
+        types::{BlockType, Type},
+    };
+
+    #[test]
+    fn cancellation() {
+        // the missing name at the end would be an error if binding got that far
+        let source = "{ 1 + 2 }\n".repeat(10_000) + "missing\n";
+        let mut lexer = Lexer::new("Cancel.fpl".to_string(), &source);
+        let file = Ast::File(parse_file(&mut lexer).unwrap());
+        let cancellation = CancellationToken::new();
+        let options = BindOptions {
+            cancellation: Some(cancellation.clone()),
+            ..BindOptions::default()
+        };
+
+        cancellation.cancel();
+        let mut diagnostics = Diagnostics::new();
+        bind_ast_with_options(
+            &file,
+            &mut Prelude::new().names(),
+            &mut diagnostics,
+            &options,
+        );
+        let messages: Vec<_> = diagnostics
+            .errors
+            .iter()
+            .map(|error| error.message.as_str())
+            .collect();
+        assert_eq!(messages, ["Compilation was cancelled"]);
+
+        // compiling and fusing give up the same way
+        let mut lexer = Lexer::new("Cancel.fpl".to_string(), &source[..source.len() - 8]);
+        let file = Ast::File(parse_file(&mut lexer).unwrap());
+        let bound_file = bind_ast(&file, &mut Prelude::new().names()).unwrap();
+        let error = compile_optimized_bytecode_with_cancellation(
+            &bound_file,
+            &mut vec![],
+            &mut vec![],
+            OptimizationLevel::Superinstructions,
+            &cancellation,
+        )
+        .unwrap_err();
+        assert_eq!(error.message, "Compilation was cancelled");
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let source = format!("{}1", "-".repeat(100));
+        let mut lexer = Lexer::new("Nesting.fpl".to_string(), &source);
+        let file = Ast::File(parse_file_with_max_depth(&mut lexer, 1000).unwrap());
+
+        let error = bind_ast(&file, &mut Prelude::new().names()).unwrap_err();
+        assert!(error.message.contains("nested too deeply"));
+        bind_ast_with_max_depth(&file, &mut Prelude::new().names(), 1000).unwrap();
+
+        // the parser does not nest a chain of operators, so neither does the limit
+        let source = format!("{}1", "1 + ".repeat(70));
+        let mut lexer = Lexer::new("Nesting.fpl".to_string(), &source);
+        let file = Ast::File(parse_file(&mut lexer).unwrap());
+        bind_ast(&file, &mut Prelude::new().names()).unwrap();
+
+        // but the tree of a very long one is still too deep to bind, or to drop by recursion
+        let source = format!("{}1", "1 + ".repeat(100_000));
+        let mut lexer = Lexer::new("Nesting.fpl".to_string(), &source);
+        let file = Ast::File(parse_file(&mut lexer).unwrap());
+        let error = bind_ast(&file, &mut Prelude::new().names()).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Expression is too long, the maximum depth of its tree is 256"
+        );
+    }
+
+    #[test]
+    fn deep_trees_drop() {
+        let location = SourceLocation::synthetic("test", None);
+        let mut node = Arc::new(BoundNode::Integer(BoundInteger {
+            location: location.clone(),
+            value: 1,
+        }));
+        for _ in 0..100_000 {
+            node = Arc::new(BoundNode::Unary(BoundUnary {
+                location: location.clone(),
+                operator: UnaryOperator {
+                    kind: UnaryOperatorKind::Negation,
+                    operand: Type::Integer,
+                    result: Type::Integer,
+                },
+                operand: node,
+            }));
+        }
+        drop(node);
+    }
+
+    #[test]
+    fn accessors() {
+        let bound_file = assert_binds("let a = 1\na");
+        let block = bound_file.as_block().unwrap();
+        assert!(bound_file.as_let().is_none());
+        let lett = <&BoundLet>::try_from(&*block.expressions[0]).unwrap();
+        assert_eq!(lett.name, "a");
+        assert!(<&BoundLet>::try_from(&*block.expressions[1]).is_err());
+    }
+
+    #[test]
+    fn binding_rules() {
+        assert_type_of_last("1 + 2 * 3", Type::Integer);
+        assert_type_of_last("let a = -1\na", Type::Integer);
+        assert_type_of_last("let a", Type::Void);
+        assert_type_of_last("io.print_integer(1)", Type::Void);
+        assert_bind_errors(
+            "let a = io.print_integer(1)\na(2)",
+            &[ErrorKind::NotAProcedure],
+        );
+        assert_type_of_last("tasks.channel()", Type::Channel);
+        assert_type_of_last(
+            "{ export a = 1 }",
+            Type::Block(BlockType {
+                exported_types: HashMap::from([("a".to_string(), Type::Integer)]),
+            }),
+        );
+        assert_binds("let a = 1\n{ let b = a }\nlet b = 2");
+        assert_type_of_last("join spawn 1", Type::Integer);
+        assert_type_of_last("spawn 1 + 2", Type::Task(Box::new(Type::Integer)));
+
+        assert_bind_errors("let a = 1\nlet a = 2", &[ErrorKind::AlreadyDefined]);
+        assert_bind_errors("export a = 1\nlet a = 2", &[ErrorKind::AlreadyDefined]);
+        assert_bind_errors("{ let b = 1 }\nb", &[ErrorKind::UnresolvedName]);
+        assert_bind_errors("let a = {}\n-a", &[ErrorKind::UnknownOperator]);
+        assert_bind_errors("1 + io.print_integer", &[ErrorKind::UnknownOperator]);
+        assert_bind_errors("1(2)", &[ErrorKind::NotAProcedure]);
+        assert_bind_errors("io.print_integer(1, 2)", &[ErrorKind::WrongArgumentCount]);
+        assert_bind_errors("io.print_integer({})", &[ErrorKind::WrongArgumentType]);
+        assert_bind_errors("9223372036854775808", &[ErrorKind::IntegerTooBig]);
+        assert_bind_errors("join 1", &[ErrorKind::InvalidJoin]);
+        assert_bind_errors(
+            "let a = 1\nspawn { a = 2 }",
+            &[ErrorKind::InvalidAssignment],
+        );
+        assert_bind_errors("let", &[ErrorKind::Syntax]);
+        assert_bind_errors("1 / 0", &[ErrorKind::InvalidConstant]);
+        assert_bind_errors("9223372036854775807 + 1", &[ErrorKind::InvalidConstant]);
+
+        assert_type_of_last(
+            "let a = { export b = { export c = 1 } }\na.b.c",
+            Type::Integer,
+        );
+        assert_type_of_last("{ export f = io.print_integer }.f(1)", Type::Void);
+        // a block that exports nothing is the value of its last expression
+        assert_type_of_last("{ let a = 1\n2.5 }", Type::Float);
+        assert_type_of_last("let a = {\nlet b = 2\nb * b\n}\na + 1", Type::Integer);
+        assert_bind_errors("{ let b = 1 }.b", &[ErrorKind::NotABlock]);
+        assert_bind_errors("let a = 1\na.b", &[ErrorKind::NotABlock]);
+        assert_bind_errors("{ export a = 1 }.b", &[ErrorKind::UnknownMember]);
+        assert_bind_errors("{ export b = 1 }.b.c", &[ErrorKind::NotABlock]);
+        assert_bind_errors("a.b", &[ErrorKind::UnresolvedName]);
+
+        assert_type_of_last("let a = 1\nlet b = 2\na = b = 3", Type::Integer);
+        assert_binds("let a = 1\n{ a = 2 }");
+        assert_bind_errors("a = 1", &[ErrorKind::UnresolvedName]);
+        assert_bind_errors("let a = 1\na = {}", &[ErrorKind::InvalidAssignment]);
+        assert_bind_errors("let a\na = 1", &[ErrorKind::InvalidAssignment]);
+        assert_bind_errors("io = 1", &[ErrorKind::InvalidAssignment]);
+        assert_bind_errors("let a = 1\n(a) = 2", &[ErrorKind::Syntax]);
+        assert_bind_errors("let a = 1\na + 1 = 2", &[ErrorKind::Syntax]);
+    }
+
+    #[test]
+    fn exports_are_declared_up_front() {
+        // exports are in scope for their whole block, but cannot be used before they run
+        assert_bind_errors("export a = a", &[ErrorKind::UsedBeforeDefinition]);
+        assert_bind_errors(
+            "let a = b\n{ c = 1 }\nexport b = 2\nexport c = 3",
+            &[
+                ErrorKind::UsedBeforeDefinition,
+                ErrorKind::UsedBeforeDefinition,
+            ],
+        );
+        assert_bind_errors(
+            "{ export a = b\nexport b = 1 }\nb",
+            &[ErrorKind::UsedBeforeDefinition, ErrorKind::UnresolvedName],
+        );
+        assert_type_of_last("export a = 1\nexport b = a\nb", Type::Integer);
+        assert_bind_errors("let a = 1\nexport a = 2", &[ErrorKind::AlreadyDefined]);
+        // a nested export or let clashes with an outer export before or after it alike
+        for source in [
+            "export a = 1\nexport b = { export a = 2 }",
+            "export b = { export a = 2 }\nexport a = 1",
+            "export a = { export a = 2 }",
+            "export b = { let a = 2 }\nexport a = 1",
+        ] {
+            assert_bind_errors(source, &[ErrorKind::AlreadyDefined]);
+        }
+        assert_binds("//! shadowing\nexport b = { let a = 2 }\nexport a = 1");
+
+        let diagnostics = bind_str_with_prelude("let a = 1 + b\nexport b = 2").unwrap_err();
+        let error = &diagnostics.errors[0];
+        assert_eq!(error.message, "b cannot be used before it is defined");
+        assert_eq!(
+            (
+                error.location.column,
+                error.notes[0].location.as_ref().unwrap().line
+            ),
+            (13, 2)
+        );
+    }
+
+    #[test]
+    fn error_recovery() {
+        let source = "
+		let a = b + 1
+		let c = -a * 2
+		io.print_integer(c)
+		io.print_integer(d)
+		io.print_integer(io.print_integer)
+		let a = 5
+		";
+        let mut lexer = Lexer::new("Errors.fpl".to_string(), source);
+        let file = Ast::File(parse_file(&mut lexer).unwrap());
+        let mut diagnostics = Diagnostics::new();
+        bind_ast_with_diagnostics(&file, &mut Prelude::new().names(), &mut diagnostics);
+
+        let messages: Vec<_> = diagnostics
+            .errors
+            .iter()
+            .map(|error| error.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Unable to find b",
+                "Unable to find d",
+                "Wrong argument type for procedure, expected type Integer but got type Proc(ProcType { parameter_types: [Integer], return_type: Void })",
+                "a is already defined",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_cover_the_expression() {
+        let cases = [
+            ("let a = {}\n-a", "Ranges.fpl:2:1-2:3"),
+            ("1 + {\nlet b\n}", "Ranges.fpl:1:1-3:2"),
+            ("let a = 1\n  abc", "Ranges.fpl:2:3-2:6"),
+            ("9223372036854775807 + 1", "Ranges.fpl:1:1-1:24"),
+        ];
+        for (source, location) in cases {
+            let mut lexer = Lexer::new("Ranges.fpl".to_string(), source);
+            let file = Ast::File(parse_file(&mut lexer).unwrap());
+            let mut diagnostics = Diagnostics::new();
+            bind_ast_with_diagnostics(&file, &mut Prelude::new().names(), &mut diagnostics);
+            let mut output = vec![];
+            write_diagnostics(&mut output, &diagnostics, 1).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            assert!(
+                output.starts_with(&format!("{}: Compile Error: ", location)),
+                "{:?}: {}",
+                source,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn bind_on_worker_thread() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Ast>();
+        assert_send_sync::<BoundNode>();
+        assert_send_sync::<Type>();
+        assert_send_sync::<CompileError>();
+
+        let bound_file = std::thread::spawn(|| {
+            let mut lexer = Lexer::new("Thread.fpl".to_string(), "export a = 1 + 2\n");
+            let file = Ast::File(parse_file(&mut lexer).unwrap());
+            bind_ast(&file, &mut HashMap::new()).unwrap()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            bound_file.get_type(),
+            Type::Block(BlockType {
+                exported_types: HashMap::from([("a".to_string(), Type::Integer)]),
+            })
+        );
+    }
+
+    #[test]
+    fn match_rules() {
+        assert_type_of_last("match 1 {\n1 -> 2\n_ -> 3\n}", Type::Integer);
+        assert_type_of_last(
+            "match 1 {\n1 -> none\n2 -> ?1\n_ -> 3\n}",
+            Type::Optional(Box::new(Type::Integer)),
+        );
+        // what an arm defines is gone after the match
+        assert_bind_errors(
+            "match 1 {\n_ -> let a = 1\n}\na",
+            &[ErrorKind::UnresolvedName],
+        );
+        assert_bind_errors("match 1.5 {\n_ -> 1\n}", &[ErrorKind::InvalidMatch]);
+        assert_bind_errors("match 1 {\n1 -> 2\n}", &[ErrorKind::InvalidMatch]);
+        assert_bind_errors(
+            "match 1 {\n1 -> 2\n2 -> \"a\"\n_ -> 3\n}",
+            &[ErrorKind::MismatchedArmTypes],
+        );
+        assert_bind_errors(
+            "match 1 {\n1 -> 2\n_ -> 3\n2 -> 4\n}",
+            &[ErrorKind::UnreachableArm],
+        );
+        assert_bind_errors(
+            "match 1 {\n-1 -> 2\n-1 -> 3\n_ -> 4\n}",
+            &[ErrorKind::UnreachableArm],
+        );
+        assert_bind_errors(
+            "match 1 {\n-9223372036854775809 -> 2\n_ -> 3\n}",
+            &[ErrorKind::IntegerTooBig],
+        );
+        assert_binds("match 1 {\n-9223372036854775808 -> 2\n_ -> 3\n}");
+    }
+
+    #[test]
+    fn destructure_rules() {
+        let point = "let point = {\nexport x = 1\nexport y = 2.5\n}\n";
+        assert_type_of_last(
+            &format!("{}let {{ x, y }} = point\nx", point),
+            Type::Integer,
+        );
+        assert_type_of_last(&format!("{}let {{ x, y }} = point\ny", point), Type::Float);
+        assert_binds(&format!("{}let {{ y }} = point\nio.print_float(y)", point));
+        assert_bind_errors(
+            &format!("{}let {{ x, z }} = point\nz", point),
+            &[ErrorKind::UnknownMember],
+        );
+        assert_bind_errors("let { a } = 1\na + 1", &[ErrorKind::NotABlock]);
+        assert_bind_errors(
+            &format!("{}let x = 1\nlet {{ x }} = point", point),
+            &[ErrorKind::AlreadyDefined],
+        );
+        assert_bind_errors(
+            &format!("{}let {{ x, x }} = point", point),
+            &[ErrorKind::AlreadyDefined],
+        );
+    }
+
+    #[test]
+    fn shadowing_rules() {
+        let nested = "let x = 1\nlet y = {\nlet x = 2.5\nx\n}\n";
+        assert_bind_errors(nested, &[ErrorKind::AlreadyDefined]);
+        assert_binds(&format!("//! shadowing\n{}", nested));
+        assert_type_of_last(&format!("//! shadowing\n{}x", nested), Type::Integer);
+        // a name still can only be defined once in the same scope
+        assert_bind_errors(
+            "//! shadowing\nlet x = 1\nlet x = 2",
+            &[ErrorKind::AlreadyDefined],
+        );
+        assert_bind_errors(
+            "//! shadowing\nlet x = 1\n{\nlet x = 2\nlet x = 3\n}",
+            &[ErrorKind::AlreadyDefined],
+        );
+        // exports are members of the block, they never shadow
+        assert_bind_errors(
+            "//! shadowing\nlet x = 1\n{\nexport x = 2\n}",
+            &[ErrorKind::AlreadyDefined],
+        );
+    }
+
+    #[test]
+    fn enum_rules() {
+        let color = "let Color = enum { Red, Green }\n";
+        assert_type_of_last(&format!("{}Color.Red == Color.Green", color), Type::Integer);
+        // an arm for every variant is enough without a `_` arm
+        assert_type_of_last(
+            &format!(
+                "{}match Color.Red {{\nColor.Red -> 1\nColor.Green -> 2\n}}",
+                color
+            ),
+            Type::Integer,
+        );
+        assert_bind_errors("enum {}", &[ErrorKind::InvalidEnum]);
+        assert_bind_errors("freeze Color.Red", &[ErrorKind::UnresolvedName]);
+        assert_bind_errors(
+            &format!("{}freeze Color.Red", color),
+            &[ErrorKind::InvalidFreeze],
+        );
+        assert_bind_errors("enum { A, B, A }", &[ErrorKind::InvalidEnum]);
+        // two enums are different types even with the same variants
+        assert_bind_errors(
+            &format!(
+                "{}let Other = enum {{ Red, Green }}\nColor.Red == Other.Red",
+                color
+            ),
+            &[ErrorKind::UnknownOperator],
+        );
+        assert_bind_errors(
+            &format!("{}Color.Red + Color.Green", color),
+            &[ErrorKind::UnknownOperator],
+        );
+        assert_bind_errors(
+            &format!("{}match Color.Red {{\nColor.Red -> 1\n}}", color),
+            &[ErrorKind::InvalidMatch],
+        );
+        assert_bind_errors(
+            &format!("{}match Color.Red {{\n1 -> 1\n_ -> 2\n}}", color),
+            &[ErrorKind::InvalidMatch],
+        );
+        assert_bind_errors(
+            &format!("{}match 1 {{\nColor.Red -> 1\n_ -> 2\n}}", color),
+            &[ErrorKind::InvalidMatch],
+        );
+        assert_bind_errors(
+            &format!("{}match Color.Red {{\nColor.Blue -> 1\n_ -> 2\n}}", color),
+            &[ErrorKind::UnknownMember],
+        );
+        assert_bind_errors(
+            &format!(
+                "{}match Color.Red {{\nColor.Red -> 1\nColor.Red -> 2\n_ -> 3\n}}",
+                color
+            ),
+            &[ErrorKind::UnreachableArm],
+        );
+    }
+}
+
+#[cfg(test)]
+mod value_tests {
+    use std::collections::HashMap;
+
+    use lang::{
+        bytecode::{Bytecode, BytecodeValue, NativeId, VmValue},
+        value::{Block, ConversionError, Value},
+    };
+
+    #[test]
+    fn scalar_conversions() {
+        assert_eq!(Value::from(5), Value::Integer(5));
+        assert_eq!(Value::from(true), Value::Integer(1));
+        assert_eq!(Value::from(()), Value::Void);
+        assert_eq!(i64::try_from(Value::Integer(-3)), Ok(-3));
+        assert_eq!(bool::try_from(Value::Integer(0)), Ok(false));
+        assert_eq!(
+            i64::try_from(Value::Void),
+            Err(ConversionError {
+                expected: "integer",
+                found: "void",
+            })
+        );
+    }
+
+    #[test]
+    fn block_getters() {
+        let mut inner = HashMap::new();
+        inner.insert("b".to_string(), VmValue::Integer(2));
+        let mut members = HashMap::new();
+        members.insert("a".to_string(), VmValue::Integer(1));
+        members.insert(
+            "inner".to_string(),
+            VmValue::from(BytecodeValue::Block(inner)),
+        );
+
+        let block = Block::try_from(Value::from(BytecodeValue::Block(members))).unwrap();
+        assert_eq!(block.get_integer("a"), Some(Ok(1)));
+        assert_eq!(block.get_integer("missing"), None);
+        assert!(block.get_integer("inner").unwrap().is_err());
+
+        let inner = block.get_block("inner").unwrap().unwrap();
+        assert_eq!(inner.get_integer("b"), Some(Ok(2)));
+
+        let round_trip = Value::from(BytecodeValue::from(Value::Block(block.clone())));
+        assert_eq!(round_trip, Value::Block(block));
+    }
+
+    #[test]
+    fn procedures_convert_back_unchanged() {
+        let procedure = BytecodeValue::Procedure([Bytecode::Return].into());
+        let value = Value::from(&procedure);
+        assert_eq!(value.type_name(), "procedure");
+        assert_eq!(BytecodeValue::from(value), procedure);
+
+        let native = BytecodeValue::Native(NativeId(0));
+        assert_eq!(BytecodeValue::from(Value::from(&native)), native);
+    }
+}
+
+#[cfg(test)]
+mod program_tests {
+    use lang::{
+        bytecode::Bytecode,
+        bytecode_compilation::OptimizationLevel,
+        common::CancellationToken,
+        execute::Vm,
+        natives::namespaces,
+        pipeline::Pipeline,
+        program::{Program, ReloadError},
+        value::Value,
+        verify::verify_bytecode,
+    };
+
+    #[test]
+    fn call_exported_proc() {
+        let source = "
+		export a = 5
+		export print = io.print_integer
+		";
+        let mut program = Program::compile("Program.fpl".to_string(), source).unwrap();
+        program.run().unwrap();
+        assert_eq!(program.get_export("a"), Some(Value::Integer(5)));
+        assert!(program.get_proc("a").is_none());
+        assert!(program.get_proc("missing").is_none());
+
+        let print = program.get_proc("print").unwrap();
+        for i in 0..3 {
+            assert_eq!(print.call(&[Value::Integer(i)]), Ok(Value::Void));
+        }
+        print.call(&[]).unwrap_err();
+        print.call(&[Value::Void]).unwrap_err();
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let source = "let zero = 0\nlet a = 1 / zero\n";
+        let mut program = Program::compile("Division.fpl".to_string(), source).unwrap();
+        let error = program.run().unwrap_err();
+        assert_eq!(error.message, "Unable to divide 1 by 0");
+    }
+
+    #[test]
+    fn spawn_and_join() {
+        let source = "
+let a = 1
+let first = spawn {
+    let b = a + 1
+    io.print_integer(b)
+    b
+}
+a = 10
+let second = spawn {
+    io.print_integer(a)
+    join first
+}
+io.print_integer(join second + join first)
+";
+        let mut program = Program::compile("Tasks.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        // the tasks start with the values from when they were spawned
+        assert_eq!(vm.captured_output.as_deref(), Some("2\n10\n4\n"));
+        // only the names from outside the task are captured, each of them once
+        let closures: Vec<_> = program
+            .get_bytecode()
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Bytecode::MakeClosure(names) => {
+                    Some(names.iter().map(String::as_str).collect::<Vec<_>>())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(closures, [vec!["a", "io"], vec!["io", "a", "first"]]);
+
+        // a shadowing let keeps its value in a variable of its own, which is what gets captured
+        let source =
+            "//! shadowing\nlet a = 1\n{\nlet a = 2\nio.print_integer(join spawn a * 10)\n}\n";
+        let mut program = Program::compile("Tasks.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("20\n"));
+
+        // a task that is never joined finishes once the program has
+        let source = "
+let t = spawn io.print_integer(1)
+io.print_integer(0)
+";
+        let mut program = Program::compile("Tasks.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("0\n1\n"));
+
+        // and its errors stop the program like any other
+        let source = "let zero = 0\nspawn { 1 / zero }\n";
+        let mut program = Program::compile("Tasks.fpl".to_string(), source).unwrap();
+        let error = program.run().unwrap_err();
+        assert_eq!(error.message, "Unable to divide 1 by 0");
+    }
+
+    #[test]
+    fn channels() {
+        // the consumer waits for each value until the producer gets a turn to send it
+        let source = "
+let numbers = tasks.channel()
+let results = tasks.channel()
+let consumer = spawn {
+    let total = tasks.recv(numbers) + tasks.recv(numbers)
+    tasks.send(results, total)
+    io.print_integer(total)
+}
+let producer = spawn {
+    tasks.send(numbers, 1)
+    io.print_integer(0)
+    tasks.send(numbers, 2)
+}
+io.print_integer(tasks.recv(results) * 10)
+";
+        let mut program = Program::compile("Channels.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("0\n3\n30\n"));
+
+        // nothing is ever going to be sent
+        let source = "
+let numbers = tasks.channel()
+let waiting = spawn tasks.recv(numbers)
+join waiting
+";
+        let mut program = Program::compile("Channels.fpl".to_string(), source).unwrap();
+        let error = program.run().unwrap_err();
+        assert_eq!(
+            error.message,
+            "Deadlock, every task is waiting for another one"
+        );
+    }
+
+    #[test]
+    fn cancellation() {
+        let source = "1 + 2\n".repeat(1000);
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let error =
+            Program::compile_with_cancellation("Cancel.fpl".to_string(), &source, &cancellation)
+                .err()
+                .unwrap();
+        assert_eq!(error.errors[0].message, "Compilation was cancelled");
+
+        let mut program = Program::compile("Cancel.fpl".to_string(), &source).unwrap();
+        let mut vm = Vm::new();
+        vm.cancellation = Some(cancellation);
+        let error = program.run_with(&mut vm).unwrap_err();
+        assert_eq!(error.message, "Execution was cancelled");
+        program.run().unwrap();
+    }
+
+    #[test]
+    fn reload() {
+        let mut program = Program::compile(
+            "Reload.fpl".to_string(),
+            "export count = 1\nexport step = 1\nexport print = io.print_integer\n",
+        )
+        .unwrap();
+        program.run().unwrap();
+
+        // `count` keeps its value, `step` changed type and `added` is new
+        let kept = program
+            .reload(
+                "export count = 5\nlet step\nexport added = 2\nexport print = io.print_integer\n",
+            )
+            .unwrap();
+        assert_eq!(kept, vec!["count".to_string()]);
+        assert_eq!(program.get_export("count"), Some(Value::Integer(1)));
+        assert_eq!(program.get_export("added"), Some(Value::Integer(2)));
+        assert_eq!(program.get_export("step"), None);
+        assert!(program.get_proc("print").is_some());
+
+        // a broken reload leaves everything as it was
+        assert!(matches!(
+            program.reload("export count = a\n"),
+            Err(ReloadError::Compile(_))
+        ));
+        assert!(matches!(
+            program.reload("let zero = 0\nexport count = 1 / zero\n"),
+            Err(ReloadError::Runtime(_))
+        ));
+        assert_eq!(program.get_export("added"), Some(Value::Integer(2)));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let cases = [
+            (
+                "let a = 9223372036854775807\na + 1",
+                "Unable to add 9223372036854775807 and 1 without overflowing",
+            ),
+            (
+                "let a = -9223372036854775807\na - 2",
+                "Unable to subtract 2 from -9223372036854775807 without overflowing",
+            ),
+            (
+                "let a = 4611686018427387904\na * 2",
+                "Unable to multiply 4611686018427387904 by 2 without overflowing",
+            ),
+            (
+                "let a = -9223372036854775807 - 1\n-a",
+                "Unable to negate -9223372036854775808 without overflowing",
+            ),
+        ];
+        for (source, message) in cases {
+            let mut program = Program::compile("Overflow.fpl".to_string(), source).unwrap();
+            assert_eq!(program.run().unwrap_err().message, message);
+        }
+    }
+
+    #[test]
+    fn constant_overflow_is_a_compile_error() {
+        let cases = [
+            (
+                "9223372036854775807 + 1",
+                1,
+                "Unable to add 9223372036854775807 and 1 without overflowing",
+            ),
+            (
+                "1 + (-9223372036854775807 - 2) * 2",
+                6,
+                "Unable to subtract 2 from -9223372036854775807 without overflowing",
+            ),
+            (
+                "io.print_integer(4611686018427387904 * 2)",
+                18,
+                "Unable to multiply 4611686018427387904 by 2 without overflowing",
+            ),
+            (
+                "-(-9223372036854775807 - 1)",
+                1,
+                "Unable to negate -9223372036854775808 without overflowing",
+            ),
+            (
+                "(-9223372036854775807 - 1) / -1",
+                1,
+                "Unable to divide -9223372036854775808 by -1",
+            ),
+        ];
+        for (source, column, message) in cases {
+            let diagnostics = Program::compile("Overflow.fpl".to_string(), source)
+                .err()
+                .unwrap();
+            // only the innermost operator that overflows is reported
+            assert_eq!(diagnostics.errors.len(), 1, "{}", source);
+            assert_eq!(diagnostics.errors[0].message, message);
+            assert_eq!(diagnostics.errors[0].location.column, column, "{}", source);
+        }
+    }
+
+    #[test]
+    fn division_by_constant_zero() {
+        // the error covers the whole divisor
+        for (source, columns) in [
+            ("1 / 0", (5, 6)),
+            ("5 / (1 - 1)", (5, 12)),
+            ("1 + 2 / -0", (9, 11)),
+        ] {
+            let diagnostics = Program::compile("Zero.fpl".to_string(), source)
+                .err()
+                .unwrap();
+            assert_eq!(diagnostics.errors.len(), 1);
+            let error = &diagnostics.errors[0];
+            assert_eq!(error.message, "Division by zero");
+            let end = error.end_location.as_ref().unwrap();
+            assert_eq!((error.location.column, end.column), columns, "{}", source);
+        }
+        // only what is written in the expression counts, not the values of names
+        assert!(Program::compile("Zero.fpl".to_string(), "let zero = 0\n1 / zero").is_ok());
+    }
+
+    #[test]
+    fn evaluation_order() {
+        // whichever division runs first is the one that is reported
+        let cases = [
+            (
+                "let zero = 0\n(1 / zero) + (3 / zero)",
+                "",
+                "Unable to divide 1 by 0",
+            ),
+            (
+                "let zero = 0\n-(4 / zero) * -(5 / zero)",
+                "",
+                "Unable to divide 4 by 0",
+            ),
+            (
+                "let zero = 0\n1 - (6 / zero) - (7 / zero)",
+                "",
+                "Unable to divide 6 by 0",
+            ),
+            (
+                "let zero = 0\nio.print_integer(1)\nio.print_integer(2)\nio.print_integer(8 / zero)",
+                "1\n2\n",
+                "Unable to divide 8 by 0",
+            ),
+        ];
+        for (source, output, message) in cases {
+            let mut vm = Vm::new();
+            vm.captured_output = Some(String::new());
+            let error = match Program::compile("Order.fpl".to_string(), source) {
+                Ok(mut program) => program.run_with(&mut vm).unwrap_err().message,
+                Err(diagnostics) => diagnostics.errors[0].message.clone(),
+            };
+            assert!(error.starts_with(message), "{:?}: {}", source, error);
+            assert_eq!(vm.captured_output.as_deref(), Some(output), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn reassignment() {
+        let source = "
+let a = 1
+let b = a
+{
+    a = a + 10
+}
+io.print_integer(a = a * 2)
+io.print_integer(b)
+";
+        let mut program = Program::compile("Assign.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("22\n1\n"));
+        // still the one variable, not a new one for each assignment
+        assert_eq!(vm.peak_variable_count, namespaces().len() + 2);
+    }
+
+    #[test]
+    fn freeze() {
+        let source = "
+export a = freeze {
+    export b = 1
+}
+let c = a
+io.print_integer(c.b)
+c = { export b = 2 }
+io.print_integer(3)
+";
+        let mut program = Program::compile("Freeze.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        let error = program.run_with(&mut vm).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Unable to assign to c, its value was frozen at Freeze.fpl:2:12"
+        );
+        assert_eq!(vm.captured_output.as_deref(), Some("1\n"));
+
+        // freezing the value of a name freezes it for that name too
+        let source = "
+let a = { export b = 1 }
+let frozen = freeze a
+a = { export b = 2 }
+io.print_integer(a.b + frozen.b)
+";
+        let mut program = Program::compile("Freeze.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        let error = program.run_with(&mut vm).unwrap_err();
+        assert!(error.message.starts_with("Unable to assign to a"));
+
+        // blocks share their members, so it is still frozen after being nested in another
+        let source = "
+let outer = { export inner = freeze { export b = 1 } }
+let inner = outer.inner
+inner = { export b = 2 }
+";
+        let mut program = Program::compile("Freeze.fpl".to_string(), source).unwrap();
+        let error = program.run().unwrap_err();
+        assert_eq!(
+            error.message,
+            "Unable to assign to inner, its value was frozen at Freeze.fpl:2:30"
+        );
+    }
+
+    #[test]
+    fn member_access() {
+        let source = "
+let a = {
+    let hidden = 1
+    export b = hidden + 1
+    export c = { export d = 3 }
+}
+io.print_integer(a.b * a.c.d)
+export e = { export f = io.print_integer }.f
+e(a.c.d)
+";
+        let mut program = Program::compile("Members.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("6\n3\n"));
+
+        let diagnostics = Program::compile(
+            "Members.fpl".to_string(),
+            "let a = { export value = 1 }\na.valeu",
+        )
+        .err()
+        .unwrap();
+        let error = &diagnostics.errors[0];
+        assert_eq!(error.message, "The block does not export valeu");
+        assert_eq!(error.location.column, 3);
+        assert_eq!(error.suggestions[0].replacement, "value");
+    }
+
+    #[test]
+    fn method_calls() {
+        let source = "
+let a = {
+    export print = io.print_integer
+    export inner = { export show = io.print_integer }
+}
+a.print(1)
+a.inner.show(2)
+";
+        let mut program = Program::compile("Methods.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("1\n2\n"));
+
+        let diagnostics = Program::compile(
+            "Methods.fpl".to_string(),
+            "let a = { export value = 1 }\na.value(2)",
+        )
+        .err()
+        .unwrap();
+        let error = &diagnostics.errors[0];
+        assert_eq!(
+            error.message,
+            "Cannot call value because it is not a procedure, it has type Integer"
+        );
+        assert_eq!(error.location.column, 3);
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        let source = "
+let zero = 0
+let runs = 0
+io.print_integer(zero && (runs = runs + 1))
+io.print_integer(zero || (runs = runs + 1))
+io.print_integer(-3 && (runs = runs + 1))
+io.print_integer(-3 || (runs = runs + 1))
+io.print_integer(runs)
+io.print_integer(1 || 0 && 0)
+";
+        let mut program = Program::compile("Logic.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("0\n1\n1\n1\n2\n1\n"));
+        assert_eq!(verify_bytecode(program.get_bytecode()), vec![]);
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let source = "
+io.print_integer(6 & 3 | 8 ^ 1)
+io.print_integer(1 + 2 & 3)
+io.print_integer(~0)
+io.print_integer(~-9223372036854775807 - 1)
+io.print_integer(0 | 2 && 1)
+";
+        let mut program = Program::compile("Bitwise.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(
+            vm.captured_output.as_deref(),
+            Some("11\n3\n-1\n9223372036854775805\n1\n")
+        );
+    }
+
+    #[test]
+    fn shift_operators() {
+        let source = "
+let amount = 64
+io.print_integer(1 << 3 + 1)
+io.print_integer(1 << 62 << 1)
+io.print_integer(-1 << amount)
+io.print_integer(-16 >> 2)
+io.print_integer(-16 >> amount)
+io.print_integer(16 >> amount * 2)
+io.print_integer(1 << 2 & 4)
+";
+        let mut program = Program::compile("Shift.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(
+            vm.captured_output.as_deref(),
+            Some("16\n-9223372036854775808\n0\n-4\n-1\n0\n4\n")
+        );
+
+        let mut program =
+            Program::compile("Shift.fpl".to_string(), "let a = -1\n1 >> a\n").unwrap();
+        let error = program.run().unwrap_err();
+        assert_eq!(error.message, "Unable to shift 1 by a negative amount -1");
+    }
+
+    #[test]
+    fn floats() {
+        let source = "
+let half = 0.5
+io.print_float(half + 2.0 * 3.0)
+io.print_float(-half / 0.0)
+io.print_float(1e9)
+io.print_float(+1.0 - 0.25)
+export f = half * half
+";
+        let mut program = Program::compile("Floats.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(
+            vm.captured_output.as_deref(),
+            Some("6.5\n-inf\n1000000000.0\n0.75\n")
+        );
+        assert_eq!(program.get_export("f"), Some(Value::Float(0.25)));
+        assert_eq!(Value::Float(1.0).to_string(), "1.0");
+
+        let diagnostics = match Program::compile("Floats.fpl".to_string(), "1 + 1.0") {
+            Ok(_) => panic!("integers and floats do not mix"),
+            Err(diagnostics) => diagnostics,
+        };
+        assert_eq!(
+            diagnostics.errors[0].message,
+            "Unable to find binary operator + for types Integer and Float"
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let source = r#"
+let greeting = "hello \"world\""
+io.print_string(greeting)
+io.print_string("")
+export s = { export t = "a\tb" }.t
+export print = io.print_string
+"#;
+        let mut program = Program::compile("Strings.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("hello \"world\"\n\n"));
+        assert_eq!(program.get_export("s"), Some(Value::from("a\tb")));
+        assert_eq!(Value::from("a\tb").to_string(), r#""a\tb""#);
+
+        let diagnostics =
+            match Program::compile("Strings.fpl".to_string(), "io.print_integer(\"1\")") {
+                Ok(_) => panic!("a string is not an integer"),
+                Err(diagnostics) => diagnostics,
+            };
+        assert_eq!(
+            diagnostics.errors[0].message,
+            "Wrong argument type for procedure, expected type Integer but got type String"
+        );
+
+        let proc = program.get_proc("print").unwrap();
+        assert_eq!(proc.call(&[Value::from("hi")]), Ok(Value::Void));
+        proc.call(&[Value::Integer(1)]).unwrap_err();
+    }
+
+    #[test]
+    fn uniform_call_syntax() {
+        let source = "
+let a = -5
+a.abs().print_integer()
+6.print_integer()
+let print = io.print_integer
+(3 - 10).abs().print()
+\"hi\".print_string()
+let b = { export show = io.print_string }
+b.show(\"member\")
+";
+        let mut program = Program::compile("Ufcs.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("5\n6\n7\nhi\nmember\n"));
+
+        let cases = [
+            ("5.missing()", "Cannot access missing of a non block"),
+            ("\"a\".abs()", "Cannot access abs of a non block"),
+            ("5.abs", "Cannot access abs of a non block"),
+        ];
+        for (source, message) in cases {
+            let diagnostics = Program::compile("Ufcs.fpl".to_string(), source)
+                .err()
+                .unwrap();
+            assert_eq!(diagnostics.errors[0].message, message, "{}", source);
+        }
+
+        let diagnostics = Program::compile(
+            "Ufcs.fpl".to_string(),
+            "let double = 3\nlet x = 2\nx.double()",
+        )
+        .err()
+        .unwrap();
+        let error = &diagnostics.errors[0];
+        assert_eq!(
+            error.message,
+            "Cannot call double because it is not a procedure, it has type Integer"
+        );
+        assert_eq!(error.location.line, 3);
+        assert_eq!(error.notes[0].message, "double was defined here");
+        assert_eq!(error.notes[0].location.as_ref().unwrap().line, 1);
+    }
+
+    #[test]
+    fn optionals() {
+        let source = "
+let a = ?5
+io.print_integer(a ?? 0)
+a = none
+io.print_integer(a ?? 1)
+a = 2
+io.print_integer(a ?? 3)
+io.print_integer(none ?? 4)
+let zero = 0
+io.print_integer(?6 ?? 1 / zero)
+export b = a ?? none
+export c = none
+";
+        for optimizations in OptimizationLevel::ALL {
+            let mut program = Pipeline::new()
+                .with_optimizations(optimizations)
+                .compile("Optionals.fpl".to_string(), source)
+                .unwrap();
+            let mut vm = Vm::new();
+            vm.captured_output = Some(String::new());
+            program.run_with(&mut vm).unwrap();
+            assert_eq!(vm.captured_output.as_deref(), Some("5\n1\n2\n4\n6\n"));
+            assert_eq!(program.get_export("b"), Some(Value::Integer(2)));
+            assert_eq!(program.get_export("c"), Some(Value::None));
+        }
+
+        let cases = [
+            (
+                "let a = ?1\na + 1",
+                "Unable to find binary operator + for types Optional(Integer) and Integer",
+            ),
+            (
+                "1 ?? 2",
+                "Unable to find binary operator ?? for types Integer and Integer",
+            ),
+            (
+                "?1 ?? 2.0",
+                "Unable to find binary operator ?? for types Optional(Integer) and Float",
+            ),
+            (
+                "let a = 1\na = none",
+                "Cannot assign a value of type None to a, which has type Integer",
+            ),
+            (
+                "io.print_integer(?1)",
+                "Wrong argument type for procedure, expected type Integer but got type Optional(Integer)",
+            ),
+        ];
+        for (source, message) in cases {
+            let diagnostics = Program::compile("Optionals.fpl".to_string(), source)
+                .err()
+                .unwrap();
+            assert_eq!(diagnostics.errors[0].message, message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn namespaced_builtins() {
+        let source = "
+io.print_integer(math.abs(-5))
+let output = io
+output.print_integer(math.abs(6))
+export print = output.print_integer
+";
+        let mut program = Program::compile("Namespaces.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        program.run_with(&mut vm).unwrap();
+        assert_eq!(
+            vm.captured_output.as_deref(),
+            Some(
+                "5
+6
+"
+            )
+        );
+        assert_eq!(
+            program
+                .get_proc("print")
+                .unwrap()
+                .call(&[Value::Integer(7)]),
+            Ok(Value::Void)
+        );
+
+        let mut program = Program::compile(
+            "Namespaces.fpl".to_string(),
+            "math.abs(-9223372036854775807 - 1)",
+        )
+        .unwrap();
+        assert_eq!(
+            program.run().unwrap_err().message,
+            "Unable to take the absolute value of -9223372036854775808 without overflowing"
+        );
+
+        let diagnostics = Program::compile("Namespaces.fpl".to_string(), "io.print_integr(1)")
+            .err()
+            .unwrap();
+        assert_eq!(
+            diagnostics.errors[0].message,
+            "The block does not export print_integr"
+        );
+        let diagnostics = Program::compile("Namespaces.fpl".to_string(), "io = io")
+            .err()
+            .unwrap();
+        assert_eq!(
+            diagnostics.errors[0].message,
+            "Cannot assign to the builtin io"
+        );
+    }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use lang::{
+        session::{EvalError, Session, Snapshot},
+        value::Value,
+    };
+
+    #[test]
+    fn state_persists() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.eval("Session.fpl".to_string(), "let a = 5"),
+            Ok(Value::Integer(5))
+        );
+        assert_eq!(
+            session.eval("Session.fpl".to_string(), "a * 2"),
+            Ok(Value::Integer(10))
+        );
+        assert!(matches!(
+            session.eval("Session.fpl".to_string(), "b"),
+            Err(EvalError::Compile(_))
+        ));
+        assert!(matches!(
+            session.eval("Session.fpl".to_string(), "let zero = 0\na / zero"),
+            Err(EvalError::Runtime(_))
+        ));
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut session = Session::new();
+        session
+            .eval(
+                "Session.fpl".to_string(),
+                "let a = -5\nlet b\nlet p = io.print_integer",
+            )
+            .unwrap();
+
+        let text = session.snapshot().serialize();
+        assert_eq!(text, "a: -5\nb: void\n");
+        let snapshot = Snapshot::deserialize(&text).unwrap();
+        assert_eq!(snapshot, session.snapshot());
+
+        let mut restored = Session::new();
+        restored.restore(&snapshot);
+        assert_eq!(
+            restored.eval("Session.fpl".to_string(), "a + 1"),
+            Ok(Value::Integer(-4))
+        );
+        assert!(restored.eval("Session.fpl".to_string(), "p").is_err());
+
+        let mut session = Session::new();
+        session
+            .eval("Session.fpl".to_string(), r#"let s = "a\"b\n""#)
+            .unwrap();
+        let text = session.snapshot().serialize();
+        assert_eq!(text, "s: \"a\\\"b\\n\"\n");
+        let mut restored = Session::new();
+        restored.restore(&Snapshot::deserialize(&text).unwrap());
+        assert_eq!(
+            restored.eval("Session.fpl".to_string(), "s"),
+            Ok(Value::from("a\"b\n"))
+        );
+        Snapshot::deserialize("s: \"a").unwrap_err();
+
+        // one value per line
+        let error = Snapshot::deserialize("a: 1.0, b: -2.5e-8").unwrap_err();
+        assert_eq!(error.line, 1);
+        let snapshot = Snapshot::deserialize("a: 1.0\nb: -2.5e-8\nc: -inf\nd: { e: NaN }").unwrap();
+        assert_eq!(
+            snapshot.serialize(),
+            "a: 1.0\nb: -2.5e-8\nc: -inf\nd: { e: NaN }\n"
+        );
+        assert_eq!(snapshot.vars["b"], Value::Float(-2.5e-8));
+        Snapshot::deserialize("a: 1.0.0").unwrap_err();
+        Snapshot::deserialize(r#"s: "\q""#).unwrap_err();
+
+        let block = Snapshot::deserialize("c: { x: 1, y: { } }").unwrap();
+        assert_eq!(block.serialize(), "c: { x: 1, y: { } }\n");
+        Snapshot::deserialize("c: {").unwrap_err();
+    }
+
+    #[test]
+    fn snapshot_enums() {
+        let mut session = Session::new();
+        session
+            .eval(
+                "Session.fpl".to_string(),
+                "let Color = enum { Red, Green }
+let c = Color.Green
+let b = { export d = Color.Red }",
+            )
+            .unwrap();
+
+        let text = session.snapshot().serialize();
+        assert!(text.contains("\nc: Green of enum { Red, Green } at \"Session.fpl\":1:13:12\n"));
+        let snapshot = Snapshot::deserialize(&text).unwrap();
+        assert_eq!(snapshot, session.snapshot());
+        assert_eq!(snapshot.vars["c"], Value::Integer(1));
+
+        // the variants keep their enum, so they can still be compared and matched
+        let mut restored = Session::new();
+        restored.restore(&snapshot);
+        assert_eq!(
+            restored.eval("Session.fpl".to_string(), "c == Color.Green"),
+            Ok(Value::Integer(1))
+        );
+        assert_eq!(
+            restored.eval(
+                "Session.fpl".to_string(),
+                "match b.d { Color.Red -> 1\nColor.Green -> 2 }"
+            ),
+            Ok(Value::Integer(1))
+        );
+        assert!(restored.eval("Session.fpl".to_string(), "c == 1").is_err());
+
+        Snapshot::deserialize("c: Blue of enum { Red, Green } at \"a\":1:1:0").unwrap_err();
+        Snapshot::deserialize("c: Red of enum { Red } at 1:1:0").unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use lang::{
+        parsing::is_input_complete,
+        repl::{run_repl, run_repl_with_files, Files, NoFiles},
+    };
+
+    #[test]
+    fn input_completeness() {
+        assert!(is_input_complete("1 + 2"));
+        assert!(is_input_complete(""));
+        assert!(!is_input_complete("let a = {"));
+        assert!(!is_input_complete("io.print_integer(1,"));
+        assert!(!is_input_complete("1 +"));
+        assert!(is_input_complete("let a = {\n5\n}"));
+    }
+
+    #[test]
+    fn session() {
+        let input = "let a = 5\n:type a\na *\n2\n{\nlet b = 3\n}\n:reset\na\n:quit\n1\n";
+        let mut output = vec![];
+        run_repl(&mut input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "> 5\n> Integer\n> ... 10\n> ... ... 3\n> > <repl>:1:1-1:2: Compile Error: Unable to find a\n> "
+        );
+    }
+
+    // files that only live in memory, like a browser would give the repl
+    #[derive(Default)]
+    struct MemoryFiles {
+        files: RefCell<HashMap<String, String>>,
+    }
+
+    impl Files for MemoryFiles {
+        fn read(&self, path: &str) -> Result<String, std::io::Error> {
+            self.files
+                .borrow()
+                .get(path)
+                .cloned()
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }
+
+        fn write(&self, path: &str, contents: &str) -> Result<(), std::io::Error> {
+            self.files
+                .borrow_mut()
+                .insert(path.to_string(), contents.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn injected_files() {
+        let files = MemoryFiles::default();
+        files.write("a.lang", "let b = 2\n").unwrap();
+        let input = ":load a.lang\nlet c = b + 1\n:save vars\n:reset\n:restore vars\nc\n:load x\n";
+        let mut output = vec![];
+        run_repl_with_files(&mut input.as_bytes(), &mut output, &files).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> 2\n> 3\n> > > > 3\n> Unable to open file: 'x'\n> \n"
+        );
+        assert_eq!(files.read("vars").unwrap(), "b: 2\nc: 3\n");
+
+        let mut output = vec![];
+        run_repl_with_files(&mut ":load a.lang\n".as_bytes(), &mut output, &NoFiles).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> Unable to open file: 'a.lang'\n> \n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod line_editor_tests {
+    use lang::line_editor::{read_key, EditResult, Key, LineEditor};
+
+    fn no_completions(_: &str) -> Vec<String> {
+        vec![]
+    }
+
+    fn type_text(editor: &mut LineEditor, text: &str) {
+        for chr in text.chars() {
+            editor.handle_key(Key::Char(chr), &no_completions);
+        }
+    }
+
+    #[test]
+    fn editing() {
+        let mut editor = LineEditor::new(vec![]);
+        type_text(&mut editor, "1 + 3");
+        editor.handle_key(Key::Left, &no_completions);
+        editor.handle_key(Key::Backspace, &no_completions);
+        type_text(&mut editor, "* ");
+        editor.handle_key(Key::Home, &no_completions);
+        editor.handle_key(Key::Delete, &no_completions);
+        type_text(&mut editor, "2");
+        assert_eq!(
+            editor.handle_key(Key::Enter, &no_completions),
+            EditResult::Submit("2 +* 3".to_string())
+        );
+        assert_eq!(editor.get_line(), "");
+    }
+
+    #[test]
+    fn history() {
+        let mut editor = LineEditor::new(vec!["first".to_string()]);
+        type_text(&mut editor, "second");
+        editor.handle_key(Key::Enter, &no_completions);
+        type_text(&mut editor, "draft");
+        editor.handle_key(Key::Up, &no_completions);
+        assert_eq!(editor.get_line(), "second");
+        editor.handle_key(Key::Up, &no_completions);
+        editor.handle_key(Key::Up, &no_completions);
+        assert_eq!(editor.get_line(), "first");
+        editor.handle_key(Key::Down, &no_completions);
+        editor.handle_key(Key::Down, &no_completions);
+        assert_eq!(editor.get_line(), "draft");
+    }
+
+    #[test]
+    fn completion() {
+        let names = |_: &str| {
+            vec![
+                "print_integer".to_string(),
+                "print_item".to_string(),
+                "other".to_string(),
+            ]
+        };
+        let mut editor = LineEditor::new(vec![]);
+        type_text(&mut editor, "1 + pr");
+        assert_eq!(
+            editor.handle_key(Key::Tab, &names),
+            EditResult::Candidates(vec!["print_integer".to_string(), "print_item".to_string()])
+        );
+        assert_eq!(editor.get_line(), "1 + print_i");
+        type_text(&mut editor, "n");
+        assert_eq!(editor.handle_key(Key::Tab, &names), EditResult::Continue);
+        assert_eq!(editor.get_line(), "1 + print_integer");
+    }
+
+    #[test]
+    fn key_decoding() {
+        let mut input: &[u8] = b"a\x1b[A\x1b[3~\x7f\t\r\xc3\xa9";
+        let mut keys = vec![];
+        while let Some(key) = read_key(&mut input).unwrap() {
+            keys.push(key);
+        }
+        assert_eq!(
+            keys,
+            vec![
+                Key::Char('a'),
+                Key::Up,
+                Key::Delete,
+                Key::Backspace,
+                Key::Tab,
+                Key::Enter,
+                Key::Char('é'),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use lang::{
+        bench::{bench_program, vm_bench},
+        natives::{namespaces, NATIVES},
+        program::Program,
+    };
+
+    #[test]
+    fn counts_instructions() {
+        let source = "let a = 1 + 2";
+        let mut program = Program::compile("Bench.fpl".to_string(), source).unwrap();
+        let results = bench_program(&mut program, 3).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "<program>");
+        assert_eq!(results[0].iterations, 3);
+        assert!(results[0].min <= results[0].mean && results[0].mean <= results[0].max);
+        // the prelude push for each builtin and make_block and store for each namespace, then
+        // push, push, add, dup, store, pop, the file's make_block and pop, exit
+        assert_eq!(
+            results[0].instruction_count,
+            (NATIVES.len() + 2 * namespaces().len()) as u64 + 9
+        );
+    }
+
+    #[test]
+    fn vm_bench_programs_run() {
+        let results = vm_bench(10, 2).unwrap();
+        let families: Vec<_> = results.iter().map(|result| result.family).collect();
+        assert_eq!(families, vec!["arithmetic", "stack", "load/store", "call"]);
+        // setup and the counter, 10 times the check, 2 instructions of the body and the
+        // counting, the last check, the teardown and exit, twice
+        assert_eq!(
+            results[1].instruction_count,
+            2 * (3 + 10 * (2 + 2 + 4) + 2 + 1 + 1)
+        );
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use lang::{
+        execute::Vm,
+        natives::{namespaces, NATIVES},
+        program::Program,
+        stats::Stats,
+    };
+
+    #[test]
+    fn counts() {
+        let source = "1 + 2";
+        let mut program = Program::compile("Stats.fpl".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        program.run_with(&mut vm).unwrap();
+        let mut stats = Stats::collect("Stats.fpl".to_string(), source, &program).unwrap();
+        stats.add_execution(&vm);
+
+        assert_eq!(stats.tokens.count, 4);
+        assert_eq!(stats.ast_nodes.count, 3);
+        // the file block plus the three expression nodes
+        assert_eq!(stats.bound_nodes.count, 4);
+        // pushing each builtin and making and storing each namespace, then push, push, add,
+        // pop, make_block, pop, exit
+        assert_eq!(
+            stats.bytecode_instructions.count,
+            NATIVES.len() + 2 * namespaces().len() + 7
+        );
+        // the members of io are all on the stack before they are made into its block
+        assert_eq!(stats.peak_stack_depth, 4);
+        assert_eq!(stats.peak_variable_count, namespaces().len());
+    }
+}
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use lang::common::{
+        write_compile_error_with, write_diagnostics, write_diagnostics_with, ColumnPolicy,
+        ColumnUnit, CompileError, CompileNote, DiagnosticStyle, DiagnosticVerbosity, Diagnostics,
+        ErrorKind, MessageCatalog, SourceLocation,
+    };
+    use lang::program::Program;
+
+    fn error(line: usize, message: &str) -> CompileError {
+        CompileError {
+            location: SourceLocation {
+                filepath: "Diagnostics.fpl".to_string(),
+                position: 0,
+                line,
+                column: 1,
+                synthetic: None,
+            },
+            end_location: None,
+            kind: ErrorKind::UnresolvedName,
+            id: "test-error",
+            arguments: vec![],
+            message: message.to_string(),
+            notes: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    #[test]
+    fn dedup_and_limit() {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(error(1, "Unable to find a"));
+        diagnostics.push(error(2, "Unable to find a"));
+        diagnostics.push(error(3, "Unable to find b"));
+        diagnostics.push(error(4, "Unable to find c"));
+        assert_eq!(diagnostics.errors.len(), 3);
+
+        let mut output = vec![];
+        write_diagnostics(&mut output, &diagnostics, 1).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Diagnostics.fpl:1:1: Compile Error: Unable to find a\n... and 2 more errors\n",
+        );
+
+        let mut output = vec![];
+        write_diagnostics(&mut output, &diagnostics, 10).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn verbosity() {
+        let mut diagnostics = Diagnostics::new();
+        let mut with_note = error(1, "Unable to find a");
+        with_note.notes.push(CompileNote {
+            location: None,
+            message: "Did you mean b?".to_string(),
+        });
+        diagnostics.push(with_note);
+        diagnostics.push(error(2, "Unable to find c"));
+
+        let write = |verbosity| {
+            let style = DiagnosticStyle {
+                verbosity,
+                catalog: MessageCatalog::new(),
+            };
+            let mut output = vec![];
+            write_diagnostics_with(&mut output, &diagnostics, 1, &style).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(
+            write(DiagnosticVerbosity::Terse),
+            "Diagnostics.fpl:1:1: Unable to find a\n... and 1 more error\n",
+        );
+        assert_eq!(
+            write(DiagnosticVerbosity::Normal),
+            "Diagnostics.fpl:1:1: Compile Error: Unable to find a\nNote: Did you mean b?\n... and 1 more error\n",
+        );
+        assert!(write(DiagnosticVerbosity::Verbose).ends_with("\n2 errors in total\n"));
+    }
+
+    #[test]
+    fn message_catalog() {
+        let mut catalog = MessageCatalog::new();
+        catalog.add("Unable to find {}", "Impossible de trouver {}");
+        catalog.add(
+            "Unable to add {} and {} without overflowing",
+            "{} + {} déborde",
+        );
+        catalog.add("Compilation was cancelled", "Compilation annulée");
+        assert_eq!(
+            catalog.translate("Unable to find a"),
+            "Impossible de trouver a"
+        );
+        assert_eq!(
+            catalog.translate("Unable to add 1 and 2 without overflowing"),
+            "1 + 2 déborde"
+        );
+        assert_eq!(
+            catalog.translate("Compilation was cancelled"),
+            "Compilation annulée"
+        );
+        // a pattern without `{}` has to match the whole message
+        assert_eq!(
+            catalog.translate("Compilation was cancelled early"),
+            "Compilation was cancelled early"
+        );
+        assert_eq!(catalog.translate("Unknown message"), "Unknown message");
+
+        let style = DiagnosticStyle {
+            verbosity: DiagnosticVerbosity::Terse,
+            catalog,
+        };
+        let mut output = vec![];
+        write_compile_error_with(&mut output, &error(3, "Unable to find x"), &style).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Diagnostics.fpl:3:1: Impossible de trouver x\n"
+        );
+    }
+
+    #[test]
+    fn message_ids() {
+        let catalog = MessageCatalog::parse(
+            "# errors\nunresolved-name = Impossible de trouver {}\n\nwrong-argument-count = {} attendus, {} reçus\n",
+        )
+        .unwrap();
+        let errors = Program::compile("Ids.fpl".to_string(), "a\nio.print_integer()\n")
+            .err()
+            .unwrap()
+            .errors;
+        assert_eq!(errors[0].id, "unresolved-name");
+        assert_eq!(errors[0].arguments, ["a"]);
+        assert_eq!(
+            catalog.translate_error(&errors[0]),
+            "Impossible de trouver a"
+        );
+        assert_eq!(catalog.translate_error(&errors[1]), "1 attendus, 0 reçus");
+        // errors without a message in the catalog keep the english one
+        let catalog = MessageCatalog::parse("already-defined = {} est déjà défini\n").unwrap();
+        assert_eq!(catalog.translate_error(&errors[0]), "Unable to find a");
+
+        assert_eq!(
+            MessageCatalog::parse("unresolved-name = {}\nno equals sign\n"),
+            Err("Expected an id, = and a message on line 2 of the catalog".to_string())
+        );
+    }
+
+    #[test]
+    fn synthetic_locations() {
+        let mut builtin = error(1, "Unable to find x");
+        builtin.location = SourceLocation::synthetic("builtin", None);
+        builtin.end_location = Some(SourceLocation {
+            position: 5,
+            ..builtin.location.clone()
+        });
+        let mut expanded = error(1, "Unable to find y");
+        expanded.location = SourceLocation::synthetic("method call", Some(&error(4, "").location));
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(builtin);
+        diagnostics.push(expanded.clone());
+        let mut output = vec![];
+        write_diagnostics(&mut output, &diagnostics, 10).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<builtin>: Compile Error: Unable to find x\n\
+<method call> expanded from Diagnostics.fpl:4:1: Compile Error: Unable to find y\n"
+        );
+        // the place is the one it was made from, for anything that only reads the line
+        assert_eq!(expanded.location.line, 4);
+    }
+
+    #[test]
+    fn column_policy() {
+        let source = "let a = 1\n\t\"é😀\" + b";
+        let program = Program::compile("Columns.fpl".to_string(), source);
+        let error = &program.err().unwrap().errors[0];
+        assert_eq!(error.location.column, 9);
+
+        for (tab_width, unit, column) in [
+            (1, ColumnUnit::Chars, 9),
+            (4, ColumnUnit::Chars, 12),
+            (8, ColumnUnit::Chars, 16),
+            (1, ColumnUnit::Utf8Bytes, 13),
+            (4, ColumnUnit::Utf16CodeUnits, 13),
+        ] {
+            let mut error = error.clone();
+            ColumnPolicy { tab_width, unit }.recount(&mut error, source);
+            assert_eq!(error.location.column, column, "{} {:?}", tab_width, unit);
+            assert_eq!(error.location.line, 2);
+        }
+        // a tab only moves to the next stop
+        assert_eq!(
+            ColumnPolicy {
+                tab_width: 4,
+                unit: ColumnUnit::Chars
+            }
+            .get_column("ab\tc", 3),
+            5
+        );
+    }
+}
+
+#[cfg(test)]
+mod emit_tests {
+    use lang::emit::{emit, parse_stages, Stage};
+
+    #[test]
+    fn stages() {
+        assert_eq!(parse_stages("ast,ir"), Ok(vec![Stage::Ast, Stage::Ir]));
+        assert_eq!(parse_stages("all"), Ok(Stage::ALL.to_vec()));
+        parse_stages("ast,missing").unwrap_err();
+    }
+
+    #[test]
+    fn emit_each_stage() {
+        let source = "let a = 1\n";
+        let tokens = emit(Stage::Tokens, "Emit.fpl".to_string(), source).unwrap();
+        assert_eq!(
+            tokens,
+            "1:1: Let\n1:5: Name(\"a\")\n1:7: Equal\n1:9: Integer(1)\n1:10: Newline\n2:1: EndOfFile\n",
+        );
+        for stage in Stage::ALL {
+            emit(stage, "Emit.fpl".to_string(), source).unwrap();
+        }
+
+        let errors = emit(Stage::Bytecode, "Emit.fpl".to_string(), "a\nb\n").unwrap_err();
+        assert_eq!(errors.errors.len(), 2);
+        emit(Stage::Ast, "Emit.fpl".to_string(), "a\nb\n").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod pipeline_tests {
+    use std::rc::Rc;
+
+    use lang::{
+        bytecode::{Bytecode, BytecodeValue},
+        bytecode_compilation::OptimizationLevel,
+        bytecode_text::{format_bytecode, parse_bytecode},
+        emit::Stage,
+        execute::Vm,
+        pipeline::{Limits, Pipeline},
+        program::{Prelude, Program},
+        value::Value,
+        verify::verify_bytecode,
+    };
+
+    #[test]
+    fn fold_constants() {
+        let source = "export a = 2 * 3 + 4\nexport b = -(1 - 2) * 5 && 0 || 3\n";
+        let mut plain = Program::compile("Fold.fpl".to_string(), source).unwrap();
+        let mut folded = Pipeline::new()
+            .with_optimizations(OptimizationLevel::FoldConstants)
+            .compile("Fold.fpl".to_string(), source)
+            .unwrap();
+        assert!(folded.get_bytecode().len() < plain.get_bytecode().len());
+
+        plain.run().unwrap();
+        folded.run().unwrap();
+        for name in ["a", "b"] {
+            assert_eq!(folded.get_export(name), plain.get_export(name));
+        }
+        assert_eq!(folded.get_export("a"), Some(Value::Integer(10)));
+        assert_eq!(folded.get_export("b"), Some(Value::Integer(1)));
+
+        assert_eq!(
+            OptimizationLevel::from_number(0),
+            Some(OptimizationLevel::None)
+        );
+        assert_eq!(
+            OptimizationLevel::from_number(OptimizationLevel::ALL.len()),
+            None
+        );
+    }
+
+    #[test]
+    fn identical_strings_are_shared() {
+        let source = "io.print_string(\"hi\")\njoin spawn io.print_string(\"hi\")\nio.print_string(\"bye\")\n";
+        let program = Program::compile("Strings.fpl".to_string(), source).unwrap();
+        let mut strings = vec![];
+        let mut bytecodes = vec![program.get_bytecode()];
+        while let Some(bytecode) = bytecodes.pop() {
+            for instruction in bytecode {
+                match instruction {
+                    Bytecode::Push(BytecodeValue::String(string)) => strings.push(string.clone()),
+                    Bytecode::Push(BytecodeValue::Procedure(procedure)) => {
+                        bytecodes.push(procedure)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        strings.sort();
+        assert_eq!(strings.len(), 3);
+        assert!(Rc::ptr_eq(&strings[1], &strings[2]));
+    }
+
+    #[test]
+    fn superinstructions() {
+        // the jumps of `&&` and `||` land between instructions that would otherwise be fused
+        let source = "
+let a = 1
+let b = a + 2
+b = a + b
+io.print_integer(a + b + 1)
+io.print_integer(0 || a + b)
+io.print_integer(b && 1 + 2)
+export c = { let d = 5
+export e = d + a }.e + 1
+";
+        let outputs = OptimizationLevel::ALL.map(|optimizations| {
+            let mut program = Pipeline::new()
+                .with_optimizations(optimizations)
+                .compile("Fused.fpl".to_string(), source)
+                .unwrap();
+            assert_eq!(verify_bytecode(program.get_bytecode()), vec![]);
+            assert_eq!(program.get_bytecode().len(), program.get_lines().len());
+            let mut vm = Vm::new();
+            vm.captured_output = Some(String::new());
+            program.run_with(&mut vm).unwrap();
+            (vm.captured_output, program.get_export("c"), program)
+        });
+        let (output, c, fused) = &outputs[2];
+        assert_eq!(output.as_deref(), Some("6\n1\n1\n"));
+        assert_eq!(*c, Some(Value::Integer(7)));
+        for (other_output, other_c, _) in &outputs[..2] {
+            assert_eq!((other_output, other_c), (output, c));
+        }
+
+        let text = format_bytecode(fused.get_bytecode());
+        for instruction in ["add_loads a b", "add_const 1", "dup_store b"] {
+            assert!(text.contains(instruction), "{}\n{}", instruction, text);
+        }
+        assert!(fused.get_bytecode().len() < outputs[1].2.get_bytecode().len());
+        assert_eq!(format_bytecode(&parse_bytecode(&text).unwrap()), text);
+    }
+
+    #[test]
+    fn limits() {
+        let source = format!("{}1{}\n", "(".repeat(10), ")".repeat(10));
+        Pipeline::new()
+            .compile("Limits.fpl".to_string(), &source)
+            .unwrap();
+        let errors = Pipeline::new()
+            .with_limits(Limits {
+                max_nesting_depth: 5,
+            })
+            .compile("Limits.fpl".to_string(), &source)
+            .err()
+            .unwrap();
+        assert_eq!(
+            errors.errors[0].message,
+            "Expression is nested too deeply, the maximum depth is 5"
+        );
+    }
+
+    #[test]
+    fn prelude() {
+        let mut prelude = Prelude::new();
+        prelude.builtins.retain(|(name, _)| name != "io");
+        let pipeline = Pipeline::new().with_prelude(prelude);
+        pipeline
+            .compile("Prelude.fpl".to_string(), "io.print_integer(1)\n")
+            .err()
+            .unwrap();
+        pipeline
+            .compile("Prelude.fpl".to_string(), "let io = 1\n")
+            .unwrap();
+    }
+
+    #[test]
+    fn stops_after_the_last_stage() {
+        // the name is only an error once binding runs
+        let pipeline = Pipeline::new().emit(Stage::Tokens).emit(Stage::Ast);
+        let compilation = pipeline.run("Stop.fpl".to_string(), "missing\n");
+        assert!(matches!(compilation.result, Ok(None)));
+        assert_eq!(
+            compilation
+                .emitted
+                .iter()
+                .map(|&(stage, _)| stage)
+                .collect::<Vec<_>>(),
+            [Stage::Tokens, Stage::Ast],
+        );
+        assert!(compilation.get_emitted(Stage::Ir).is_none());
+
+        let compilation = pipeline
+            .emit(Stage::Ir)
+            .run("Stop.fpl".to_string(), "missing\n");
+        assert_eq!(compilation.result.as_ref().err().unwrap().errors.len(), 1);
+        assert!(compilation.get_emitted(Stage::Ast).is_some());
+        assert!(compilation.get_emitted(Stage::Ir).is_none());
+
+        let compilation = Pipeline::new().run("Stop.fpl".to_string(), "1\n");
+        assert!(compilation.emitted.is_empty());
+        assert!(compilation.result.unwrap().is_some());
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use std::sync::Arc;
+
+    use lang::{cache::FrontEndCache, common::CancellationToken, emit::Stage, pipeline::Pipeline};
+
+    #[test]
+    fn reuses_unchanged_files() {
+        let cache = FrontEndCache::new();
+        let cancellation = CancellationToken::new();
+        let first = cache
+            .parse("Cache.fpl", "1 + 2\n", 64, &cancellation)
+            .unwrap();
+        let second = cache
+            .parse("Cache.fpl", "1 + 2\n", 64, &cancellation)
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        // a different depth limit could give a different result
+        let deeper = cache
+            .parse("Cache.fpl", "1 + 2\n", 128, &cancellation)
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &deeper));
+
+        let tokens = cache.lex("Cache.fpl", "1 + 2\n", &cancellation).unwrap();
+        assert_eq!(tokens.len(), 5);
+        assert!(Arc::ptr_eq(
+            &tokens,
+            &cache.lex("Cache.fpl", "1 + 2\n", &cancellation).unwrap()
+        ));
+
+        let changed = cache
+            .parse("Cache.fpl", "1 + 3\n", 64, &cancellation)
+            .unwrap();
+        assert_ne!(first, changed);
+        let other_file = cache
+            .parse("Other.fpl", "1 + 2\n", 64, &cancellation)
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &other_file));
+
+        cache.clear();
+        let cleared = cache
+            .parse("Cache.fpl", "1 + 2\n", 64, &cancellation)
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &cleared));
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache = FrontEndCache::new();
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        cache
+            .parse("Cancel.fpl", "1 + 2\n", 64, &cancellation)
+            .unwrap_err();
+        cache
+            .parse("Cancel.fpl", "1 + 2\n", 64, &CancellationToken::new())
+            .unwrap();
+    }
+
+    #[test]
+    fn shared_with_pipelines() {
+        let cache = FrontEndCache::new();
+        let pipeline = Pipeline::new()
+            .with_cache(cache.clone())
+            .emit(Stage::Tokens)
+            .emit(Stage::Ast);
+        let source = "export a = 1\n";
+        let first = pipeline.run("Shared.fpl".to_string(), source);
+        let ast = cache
+            .parse("Shared.fpl", source, 64, &CancellationToken::new())
+            .unwrap();
+        let second = pipeline.run("Shared.fpl".to_string(), source);
+        assert_eq!(first.emitted, second.emitted);
+        assert!(Arc::ptr_eq(
+            &ast,
+            &cache
+                .parse("Shared.fpl", source, 64, &CancellationToken::new())
+                .unwrap()
+        ));
+
+        let mut program = Pipeline::new()
+            .with_cache(cache)
+            .compile("Shared.fpl".to_string(), source)
+            .unwrap();
+        program.run().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod bytecode_text_tests {
+    use lang::{
+        bytecode_text::{
+            explain_bytecode, format_bytecode, parse_bytecode, parse_bytecode_module,
+            BytecodeParseError,
+        },
+        natives::NATIVES,
+        program::Program,
+    };
+
+    #[test]
+    fn explain() {
+        let source = "let a = 1\n\n    io.print_integer(a)\n";
+        let program = Program::compile("Explain.fpl".to_string(), source).unwrap();
+        let text = explain_bytecode(program.get_bytecode(), program.get_lines(), source);
+        let start = text.find("# 1: let a = 1\n").unwrap();
+        assert_eq!(
+            &text[start..],
+            "# 1: let a = 1\n  13  push 1\n  14  dup\n  15  store a\n  16  pop\n# 3: io.print_integer(a)\n  17  load a\n  18  call_native io.print_integer\n  19  pop\n# 1: let a = 1\n  20  make_block\n# no source line\n  21  pop\n  22  exit\n"
+        );
+        assert!(text.starts_with("# no source line\n   0  push native "));
+
+        let bytecode = parse_bytecode("push proc\n    return\nend\nreturn\n").unwrap();
+        assert_eq!(
+            explain_bytecode(&bytecode, &[], ""),
+            "# no source line\n   0  push proc\n          return\n      end\n   1  return\n"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = Program::compile(
+            "Text.fpl".to_string(),
+            "let a = -(1 + 2)\nio.print_integer(a)\nlet b = { export c = a }.c\na || b && a\n~a & b | a ^ b\n-1.5 * 2e-9 / 3.0 + 0.1\nio.print_string(\"\\\"#\\n\")\nlet d = freeze { export e = 1 }\nd = d\njoin spawn a\n",
+        )
+        .unwrap();
+        let text = format_bytecode(program.get_bytecode());
+        let parsed = parse_bytecode(&text).unwrap();
+        assert_eq!(format_bytecode(&parsed), text);
+
+        assert_eq!(
+            parse_bytecode("push 1 # comment\n\nreturn\n")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(parse_bytecode("push\n").unwrap_err().line, 1);
+        assert_eq!(parse_bytecode("pop\nfoo\n").unwrap_err().line, 2);
+        parse_bytecode("push proc\nreturn\n").unwrap_err();
+        parse_bytecode("end\n").unwrap_err();
+        parse_bytecode("call_native print_nothing\n").unwrap_err();
+        parse_bytecode("push native\n").unwrap_err();
+        parse_bytecode("load_member\n").unwrap_err();
+        parse_bytecode("freeze site\n").unwrap_err();
+        assert_eq!(parse_bytecode("make_block\n").unwrap().len(), 1);
+        assert_eq!(parse_bytecode("make_closure a b\n").unwrap().len(), 1);
+        parse_bytecode("jump -1\n").unwrap_err();
+        parse_bytecode("jump_if_zero\n").unwrap_err();
+    }
+
+    #[test]
+    fn builtin_declarations() {
+        let program = Program::compile(
+            "Text.fpl".to_string(),
+            "let p = io.print_string\nio.print_integer(1)\n",
+        )
+        .unwrap();
+        let text = format_bytecode(program.get_bytecode());
+        // every builtin the prelude stores is referenced, each one is declared once
+        let declarations: Vec<_> = text
+            .lines()
+            .take_while(|line| line.starts_with("builtin "))
+            .collect();
+        assert_eq!(declarations.len(), NATIVES.len(), "{}", text);
+        assert!(declarations.contains(&"builtin io.print_integer: proc(integer) -> void"));
+        parse_bytecode(&text).unwrap();
+
+        let error = parse_bytecode_module(
+            "old.bc",
+            "builtin io.print_integer: proc(string) -> void\ncall_native io.print_integer\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            BytecodeParseError {
+                line: 1,
+                message: "Builtin io.print_integer has type proc(integer) -> void but module old.bc requires proc(string) -> void".to_string(),
+            }
+        );
+
+        // declarations are all checked before the instructions
+        let error =
+            parse_bytecode_module("new.bc", "foo\nbuiltin print_everything: proc() -> void\n")
+                .unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(
+            error.message,
+            "Missing builtin print_everything required by module new.bc"
+        );
+        assert_eq!(
+            parse_bytecode("call_native print_nothing\n")
+                .unwrap_err()
+                .message,
+            "Missing builtin print_nothing required by module <bytecode>"
+        );
+        parse_bytecode("builtin io.print_integer\n").unwrap_err();
+        parse_bytecode("push proc\nbuiltin io.print_integer: proc(integer) -> void\nend\n")
+            .unwrap_err();
+        parse_bytecode("builtin io.print_integer:proc( integer )->void\n").unwrap();
+    }
+
+    #[test]
+    fn direct_builtin_calls() {
+        let program = Program::compile(
+            "Text.fpl".to_string(),
+            "io.print_integer(1)
+",
+        )
+        .unwrap();
+        let text = format_bytecode(program.get_bytecode());
+        assert!(text.contains("call_native io.print_integer"), "{}", text);
+        assert!(!text.contains("call 1"), "{}", text);
+    }
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use std::rc::Rc;
+
+    use lang::{
+        bytecode::{BytecodeValue, NativeId, VmValue},
+        bytecode_text::parse_bytecode,
+        execute::{execute_bytecode, Vars, Vm},
+        natives::find_native,
+    };
+
+    fn run(source: &str) -> (VmValue, Vars) {
+        let bytecode = parse_bytecode(source).unwrap();
+        let mut vars = Vars::new();
+        let result = execute_bytecode(&bytecode, vec![], &mut vars)
+            .unwrap()
+            .unwrap();
+        (result, vars)
+    }
+
+    #[test]
+    fn scalars_are_inline() {
+        // only procedures and blocks are behind a pointer
+        assert_eq!(std::mem::size_of::<VmValue>(), 16);
+        assert_eq!(
+            VmValue::from(BytecodeValue::Integer(1)),
+            VmValue::Integer(1)
+        );
+        assert_eq!(VmValue::from(BytecodeValue::Void), VmValue::Void);
+
+        let (result, vars) = run("push 1\nstore a\nload a\nreturn\n");
+        assert_eq!(result, VmValue::Integer(1));
+        assert_eq!(vars["a"], VmValue::Integer(1));
+
+        let (result, vars) = run("push 1\ndup\nstore a\nreturn\n");
+        assert_eq!(result, VmValue::Integer(1));
+        assert_eq!(vars["a"], VmValue::Integer(1));
+
+        let (result, _) = run("push 1\nmake_block a\nreturn\n");
+        assert_eq!(
+            result.to_bytecode_value(),
+            BytecodeValue::Block([("a".to_string(), VmValue::Integer(1))].into())
+        );
+
+        // a block nested in another one is shared, not copied
+        let (result, vars) = run("push 1\nmake_block a\ndup\nstore a\nmake_block b\nreturn\n");
+        let nested = &result.as_object().unwrap().unwrap_block()["b"];
+        let (VmValue::Object(nested), VmValue::Object(a)) = (nested, &vars["a"]) else {
+            panic!("expected blocks");
+        };
+        assert!(Rc::ptr_eq(nested, a));
+    }
+
+    #[test]
+    fn accessors() {
+        let value = BytecodeValue::Integer(5);
+        assert_eq!(value.as_integer(), Some(&5));
+        assert!(value.as_procedure().is_none());
+        assert!(value.as_block().is_none());
+        assert_eq!(i64::try_from(value.clone()), Ok(5));
+        assert_eq!(NativeId::try_from(value.clone()), Err(value));
+    }
+
+    #[test]
+    fn calling_other_values_is_an_error() {
+        let mut vm = Vm::new();
+        let error = vm.call(&VmValue::Integer(1), vec![]).unwrap_err();
+        assert_eq!(error.message, "Unable to call integer 1");
+        let block = VmValue::from(BytecodeValue::Block(Default::default()));
+        let error = vm.call(&block, vec![]).unwrap_err();
+        assert_eq!(error.message, "Unable to call block {  }");
+
+        let print_integer = VmValue::Native(find_native("io.print_integer").unwrap());
+        let error = vm.call(&print_integer, vec![]).unwrap_err();
+        assert_eq!(error.message, "print_integer needs an integer");
+    }
+
+    #[test]
+    fn procedures_share_their_bytecode() {
+        let (result, vars) = run("push proc\n    return\nend\nstore p\nload p\nreturn\n");
+        assert!(Rc::ptr_eq(
+            result.as_object().unwrap().unwrap_procedure(),
+            vars["p"].as_object().unwrap().unwrap_procedure(),
+        ));
+    }
+
+    #[test]
+    fn tasks_take_turns() {
+        use lang::execute::Vm;
+
+        // the program makes enough calls that the task gets a turn before it prints
+        let mut text = "push proc\n    push 1\n    call_native io.print_integer\n    return\nend\nspawn\nstore t\npush proc\n    return\nend\nstore f\n".to_string();
+        text += &"load f\ncall 0\npop\n".repeat(100);
+        text += "push 0\ncall_native io.print_integer\npop\nload t\njoin\nreturn\n";
+        let bytecode = parse_bytecode(&text).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        vm.execute(&bytecode, vec![], &mut Vars::new()).unwrap();
+        assert_eq!(vm.captured_output.as_deref(), Some("1\n0\n"));
+
+        let bytecode = parse_bytecode(
+            "push proc\n    push 0\n    join\n    return\nend\nspawn\njoin\nreturn\n",
+        )
+        .unwrap();
+        let error = execute_bytecode(&bytecode, vec![], &mut Vars::new()).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Deadlock, every task is waiting for another one"
+        );
+
+        let bytecode = parse_bytecode("push 0\njoin\nreturn\n").unwrap();
+        let error = execute_bytecode(&bytecode, vec![], &mut Vars::new()).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Unable to join task 0, it is from another run"
+        );
+    }
+
+    #[test]
+    fn hooks() {
+        use std::cell::RefCell;
+
+        use lang::{common::RuntimeError, program::Program};
+
+        let printed = Rc::new(RefCell::new(String::new()));
+        let calls = Rc::new(RefCell::new(vec![]));
+        let errors = Rc::new(RefCell::new(vec![]));
+
+        let mut vm = Vm::new();
+        vm.hooks.on_print = Some({
+            let printed = printed.clone();
+            Rc::new(move |text: &str| *printed.borrow_mut() += text)
+        });
+        vm.hooks.on_call = Some({
+            let calls = calls.clone();
+            Rc::new(move |procedure: &VmValue, argument_count| {
+                calls
+                    .borrow_mut()
+                    .push((matches!(procedure, VmValue::Native(_)), argument_count))
+            })
+        });
+        vm.hooks.on_error = Some({
+            let errors = errors.clone();
+            Rc::new(move |error: &RuntimeError| errors.borrow_mut().push(error.message.clone()))
+        });
+
+        let source =
+            "io.print_integer(1)\nlet p = io.print_integer\np(2)\nlet zero = 0\np(3 / zero)\n";
+        let mut program = Program::compile("Hooks.fpl".to_string(), source).unwrap();
+        let error = program.run_with(&mut vm).unwrap_err();
+
+        assert_eq!(*printed.borrow(), "1\n2\n");
+        // the last call never happens because its argument fails
+        assert_eq!(*calls.borrow(), vec![(true, 1), (true, 1)]);
+        assert_eq!(*errors.borrow(), vec![error.message]);
+    }
+
+    #[test]
+    fn internal_errors_dump_state() {
+        let bytecode = parse_bytecode("push 1\nstore a\npush void\npush 2\nadd\nreturn\n").unwrap();
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            execute_bytecode(&bytecode, vec![], &mut Vars::new())
+        }))
+        .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert_eq!(
+            message,
+            "\
+Internal VM error: Expected an integer but got void
+Instructions:
+       1: store a
+       2: push void
+       3: push 2
+  >    4: add
+       5: return
+Stack, top last:
+       0: void
+Variables:
+    a: integer 1
+",
+        );
+    }
+}
+
+#[cfg(test)]
+mod value_equality_tests {
+    use std::collections::{HashMap, HashSet};
+
+    use lang::{
+        bytecode::{Bytecode, BytecodeValue, VmValue},
+        natives::find_native,
+    };
+
+    #[test]
+    fn equality() {
+        assert_eq!(BytecodeValue::Void, BytecodeValue::Void);
+        assert_eq!(BytecodeValue::Integer(1), BytecodeValue::Integer(1));
+        assert_ne!(BytecodeValue::Integer(1), BytecodeValue::Integer(2));
+        assert_ne!(BytecodeValue::Integer(0), BytecodeValue::Void);
+
+        let print_integer = BytecodeValue::Native(find_native("io.print_integer").unwrap());
+        assert_eq!(print_integer, print_integer.clone());
+
+        // procedures are only equal to themselves
+        let procedure = BytecodeValue::Procedure([Bytecode::Return].into());
+        assert_eq!(procedure, procedure.clone());
+        assert_ne!(
+            procedure,
+            BytecodeValue::Procedure([Bytecode::Return].into())
+        );
+
+        let a = BytecodeValue::Block(HashMap::from([
+            ("x".to_string(), VmValue::Integer(1)),
+            ("y".to_string(), VmValue::Float(f64::NAN)),
+        ]));
+        let b = BytecodeValue::Block(HashMap::from([
+            ("y".to_string(), VmValue::Float(f64::NAN)),
+            ("x".to_string(), VmValue::Integer(1)),
+        ]));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn hashing() {
+        let procedure = BytecodeValue::Procedure([Bytecode::Return].into());
+        let values = [
+            BytecodeValue::Void,
+            BytecodeValue::Integer(1),
+            BytecodeValue::Integer(1),
+            BytecodeValue::Integer(2),
+            procedure.clone(),
+            procedure,
+            BytecodeValue::Block(HashMap::new()),
+            BytecodeValue::Block(HashMap::new()),
+        ];
+        let distinct: HashSet<_> = values.into_iter().collect();
+        assert_eq!(distinct.len(), 5);
+    }
+}
+
+#[cfg(test)]
+mod inspect_tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use lang::inspect::{dump_callgraph, dump_scopes, dump_types, CallGraph};
+
+    #[test]
+    fn types() {
+        let source = "\
+let a = 1
+export p = io.print_integer
+let b = {
+    export c = a + 1
+    let d
+}
+{ let e = 2 }
+";
+        assert_eq!(
+            dump_types("test.lang".to_string(), source).unwrap(),
+            "\
+let a: integer at test.lang:1:5
+export p: proc(integer) -> void at test.lang:2:8
+let b: { c: integer } at test.lang:3:5
+    export c: integer at test.lang:4:12
+    let d: void at test.lang:5:9
+block at test.lang:7:1
+    let e: integer at test.lang:7:7
+",
+        );
+    }
+
+    #[test]
+    fn scopes() {
+        let source = "\
+let a = 1
+let b = {
+    let a = 2
+    let c = d
+    let c
+}
+let io
+";
+        assert_eq!(
+            dump_scopes("test.lang".to_string(), source).unwrap(),
+            "\
+builtins
+    io from <builtin>
+    math from <builtin>
+    tasks from <builtin>
+file test.lang
+    let a at test.lang:1:5
+    block at test.lang:2:9
+        let a at test.lang:3:9, shadows the outer a at test.lang:1:5
+        d at test.lang:4:13 cannot be found
+        let c at test.lang:4:9
+        let c at test.lang:5:9, already defined at test.lang:4:9
+    let b at test.lang:2:5
+    let io at test.lang:7:5, already defined by the builtin at <builtin>
+",
+        );
+    }
+
+    #[test]
+    fn callgraph() {
+        let source = "\
+let print = io.print_integer
+print(1)
+export run = {
+    io.print_integer(2)
+    print(3)
+}
+";
+        assert_eq!(
+            dump_callgraph("test.lang".to_string(), source).unwrap(),
+            "\
+run at test.lang:3:8
+    calls io.print_integer (builtin)
+    calls print at test.lang:1:5
+test.lang
+    calls print at test.lang:1:5
+",
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let graph = CallGraph {
+            calls: BTreeMap::from([
+                ("a".to_string(), BTreeSet::from(["b".to_string()])),
+                (
+                    "b".to_string(),
+                    BTreeSet::from(["a".to_string(), "c".to_string()]),
+                ),
+                ("c".to_string(), BTreeSet::from(["c".to_string()])),
+            ]),
+        };
+        assert_eq!(
+            graph.recursive_cycles(),
+            vec![
+                vec!["a".to_string(), "b".to_string()],
+                vec!["c".to_string()],
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use lang::{
+        bytecode_text::parse_bytecode,
+        program::Program,
+        verify::{count_instructions, verify_bytecode},
+    };
+
+    fn verify_text(text: &str) -> Vec<(Vec<usize>, String)> {
+        verify_bytecode(&parse_bytecode(text).unwrap())
+            .into_iter()
+            .map(|error| (error.path, error.message))
+            .collect()
+    }
+
+    #[test]
+    fn compiled_programs_verify() {
+        let source = "let a = -(1 + 2)\nio.print_integer(a)\nexport p = io.print_integer\n";
+        let program = Program::compile("Verify.fpl".to_string(), source).unwrap();
+        assert_eq!(verify_bytecode(program.get_bytecode()), vec![]);
+    }
+
+    #[test]
+    fn findings() {
+        assert_eq!(
+            verify_text("pop\npop\nexit\n"),
+            vec![(
+                vec![1],
+                "Needs 1 values but the stack only has 0".to_string()
+            )],
+        );
+        assert_eq!(
+            verify_text("push 1\n"),
+            vec![(
+                vec![1],
+                "Reaches the end without an exit or return".to_string()
+            )],
+        );
+        assert_eq!(
+            verify_text("return\npush 1\n"),
+            vec![(vec![1], "Unreachable instruction".to_string())],
+        );
+        assert_eq!(
+            verify_text("push proc\n    load a\n    return\nend\nreturn\n"),
+            vec![(vec![0, 0], "a is loaded before it is stored".to_string())],
+        );
+        // a closure starts with its captured variables, which have to be stored first
+        assert_eq!(
+            verify_text(
+                "push 1\nstore a\npush proc\n    load a\n    return\nend\nmake_closure a\nreturn\n"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            verify_text("push proc\n    load a\n    return\nend\nmake_closure a\nreturn\n"),
+            vec![(vec![1], "a is loaded before it is stored".to_string())],
+        );
+        // the arguments of a procedure are not known
+        assert_eq!(
+            verify_text("push proc\n    add\n    return\nend\nreturn\n"),
+            vec![]
+        );
+        assert_eq!(
+            verify_text("push 0\njump_if_zero 3\npush 1\nreturn\n"),
+            vec![(
+                vec![3],
+                "Reached with 2 values on one path and 1 on another".to_string()
+            )],
+        );
+        assert_eq!(
+            verify_text("jump 2\npush 1\nreturn\njump 9\n"),
+            vec![
+                (vec![3], "Jumps to 9 which is past the end".to_string()),
+                (vec![1], "Unreachable instruction".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn counts_nested_instructions() {
+        let bytecode = parse_bytecode("push proc\n    return\nend\nreturn\n").unwrap();
+        assert_eq!(count_instructions(&bytecode), 3);
+    }
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use std::collections::BTreeMap;
+
+    use lang::{coverage::Coverage, execute::Vm, program::Program};
+
+    #[test]
+    fn lines_that_ran() {
+        let source = "\
+let a = 1
+
+io.print_integer(a)
+io.print_integer(a / (a - 1))
+io.print_integer(
+    a)
+";
+        let mut program = Program::compile("test.lang".to_string(), source).unwrap();
+        let mut vm = Vm::new();
+        vm.captured_output = Some(String::new());
+        vm.coverage = Some(vec![]);
+        program.run_with(&mut vm).unwrap_err();
+
+        let coverage = Coverage::collect("test.lang".to_string(), &program, &vm.coverage.unwrap());
+        assert_eq!(
+            coverage.lines,
+            BTreeMap::from([(1, 1), (3, 1), (4, 1), (5, 0), (6, 0)]),
+        );
+        assert_eq!(
+            coverage.to_string(),
+            "test.lang: 3 of 5 lines ran (60%)\nLines that never ran: 5, 6\n",
+        );
+        assert_eq!(
+            coverage.to_lcov(),
+            "TN:\nSF:test.lang\nDA:1,1\nDA:3,1\nDA:4,1\nDA:5,0\nDA:6,0\nLF:5\nLH:3\nend_of_record\n",
+        );
+    }
+}
+
+#[cfg(test)]
+mod grammar_tests {
+    use lang::{
+        grammar::{dump_ebnf, dump_grammar, get_binary_precedence, productions, KEYWORDS},
+        lexer::Lexer,
+        natives::NATIVES,
+        parsing::parse_file,
+        token::TokenKind,
+    };
+
+    #[test]
+    fn keywords_match_the_lexer() {
+        for (keyword, kind) in KEYWORDS {
+            let mut lexer = Lexer::new("keyword.lang".to_string(), keyword);
+            assert_eq!(&lexer.next_token().unwrap().kind, kind);
+        }
+        // keywords are only whole words
+        let mut lexer = Lexer::new("keyword.lang".to_string(), "letter");
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::Name("letter".to_string())
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(
+            get_binary_precedence(&TokenKind::Asterisk) > get_binary_precedence(&TokenKind::Plus)
+        );
+        assert!(
+            get_binary_precedence(&TokenKind::AmpersandAmpersand)
+                > get_binary_precedence(&TokenKind::PipePipe)
+        );
+        // the shifts and bitwise operators are between the comparisons and the arithmetic
+        assert!(
+            get_binary_precedence(&TokenKind::Plus)
+                > get_binary_precedence(&TokenKind::LessThanLessThan)
+        );
+        assert!(
+            get_binary_precedence(&TokenKind::GreaterThanGreaterThan)
+                > get_binary_precedence(&TokenKind::Ampersand)
+        );
+        assert!(
+            get_binary_precedence(&TokenKind::Ampersand) > get_binary_precedence(&TokenKind::Caret)
+        );
+        assert!(get_binary_precedence(&TokenKind::Caret) > get_binary_precedence(&TokenKind::Pipe));
+        assert!(
+            get_binary_precedence(&TokenKind::Pipe) > get_binary_precedence(&TokenKind::EqualEqual)
+        );
+        assert_eq!(get_binary_precedence(&TokenKind::Comma), 0);
+    }
+
+    #[test]
+    fn dump() {
+        let grammar = dump_grammar();
+        assert!(grammar.starts_with(
+            "keywords\n    enum\n    export\n    freeze\n    join\n    let\n    match\n    none\n    spawn\nsymbols\n"
+        ));
+        assert!(grammar.contains("\n    * precedence 10\n"));
+        for native in NATIVES {
+            assert!(grammar.contains(&format!("\n    {}: ", native.full_name())));
+        }
+    }
+
+    #[test]
+    fn every_production_is_exercised() {
+        // an example for each production that only parses if the production is right
+        let examples = [
+            ("file", "\nlet a = 1\n\n\nio.print_integer(a)\n"),
+            ("expression", "a = b =\n1 + 2"),
+            ("binary_1", "a ??\nb || c ?? d"),
+            ("binary_2", "a ||\nb && c || d"),
+            ("binary_3", "a == b &&\nc"),
+            ("binary_4", "1 | 2 ==\n3"),
+            ("binary_5", "1 ^ 2 |\n3"),
+            ("binary_6", "1 & 2 ^\n3"),
+            ("binary_7", "1 << 2 &\n3"),
+            ("binary_8", "1 + 2 >>\n3 << 4"),
+            ("binary_9", "1 * 2 -\n3 + 4"),
+            ("binary_10", "-1 *\n2 / 3"),
+            ("unary", "-\n~+?a(1)"),
+            ("postfix", "f(\n1,\n2,\n)(3)().a(4).b"),
+            ("primary", "(none)"),
+            ("block", "{\n\na\n\nb\n}"),
+            ("export", "export a =\n1"),
+            ("let", "{ let a\nlet b =\n2\nlet {\nc,\nd, e,\n} =\nf }"),
+            ("match", "match a {\n\n1 -> b\n\n_ -> c\n}"),
+            ("arm", "match a { -1 ->\nb\nC.d -> e }"),
+            ("enum", "enum {\na,\nb, c,\n}"),
+            ("freeze", "freeze\n{ a }"),
+            ("spawn", "spawn\n{ a }"),
+            ("join", "join a.b(c)"),
+            ("name", "_a1"),
+            ("integer", "0x1F"),
+            ("float", "1_000.5e-3"),
+            ("string", "\"a\\n\\\"b\""),
+            ("newline", "{ a\r\nb\rc\n\rd }"),
+        ];
+        let names: Vec<String> = productions()
+            .into_iter()
+            .map(|production| production.name)
+            .collect();
+        assert_eq!(
+            names,
+            examples.map(|(name, _)| name.to_string()).to_vec(),
+            "every production needs an example"
+        );
+        for (name, source) in examples {
+            let mut lexer = Lexer::new(format!("{}.lang", name), source);
+            if let Err(error) = parse_file(&mut lexer) {
+                panic!("the example for {} does not parse: {}", name, error.message);
+            }
+        }
+    }
+
+    #[test]
+    fn ebnf() {
+        let ebnf = dump_ebnf();
+        assert!(ebnf.starts_with("file = "));
+        assert!(
+            ebnf.contains("\nbinary_9 = binary_10 { ( \"+\" | \"-\" ) [ newline ] binary_10 } ;\n")
+        );
+        assert!(ebnf.contains(
+            "\nunary = ( \"+\" | \"-\" | \"!\" | \"~\" | \"?\" ) [ newline ] unary | postfix ;\n"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod fix_tests {
+    use lang::{fix::fix_source, program::Program};
+
+    #[test]
+    fn suggestions() {
+        let diagnostics = Program::compile("test.lang".to_string(), "let value = 1\nvalu\nb\n")
+            .err()
+            .unwrap();
+        assert_eq!(diagnostics.errors.len(), 2);
+        let suggestion = &diagnostics.errors[0].suggestions[0];
+        assert_eq!(suggestion.replacement, "value");
+        assert_eq!(suggestion.message, "Did you mean value?");
+        assert_eq!((suggestion.location.position, suggestion.length), (14, 4));
+        // a single letter is too short to guess what it was meant to be
+        assert!(diagnostics.errors[1].suggestions.is_empty());
+    }
+
+    #[test]
+    fn fix() {
+        let result = fix_source(
+            "test.lang".to_string(),
+            "let value = 1 let b = 2\nio.print_integr(valeu)\n",
+        );
+        assert_eq!(
+            result.source,
+            "let value = 1\nlet b = 2\nio.print_integer(value)\n"
+        );
+        let messages: Vec<_> = result
+            .applied
+            .iter()
+            .map(|suggestion| suggestion.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Add a newline before let",
+                "Did you mean print_integer?",
+                "Did you mean value?",
+            ]
+        );
+        assert!(result.remaining.is_empty());
+    }
+
+    #[test]
+    fn unfixable_errors_remain() {
+        let source = "let a = 1 )\n";
+        let result = fix_source("test.lang".to_string(), source);
+        assert_eq!(result.source, source);
+        assert!(result.applied.is_empty());
+        assert_eq!(result.remaining.errors.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod lint_tests {
+    use lang::{
+        fix::{fix_source_with, FixOptions},
+        inspect::bind_file,
+        lexer::Lexer,
+        lint::{lint_precedence, Linter, Severity},
+        metrics::collect_metrics,
+        parsing::parse_file,
+        program::Prelude,
+    };
+
+    fn lint(source: &str) -> Vec<String> {
+        let file = parse_file(&mut Lexer::new("test.lang".to_string(), source)).unwrap();
+        lint_precedence(&file, source)
+            .into_iter()
+            .map(|warning| {
+                format!(
+                    "{}: {}: {}",
+                    warning.location.column, warning.message, warning.suggestions[0].replacement
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            lint("a < b == f(c)\n"),
+            ["7: Comparisons do not chain, the result of a < b is compared with == f(c): (a < b)"]
+        );
+        assert_eq!(
+            lint("{ !a != b }\n"),
+            ["6: ! only applies to a, not the whole comparison: (!a)"]
+        );
+        assert_eq!(
+            lint("-a << 2\n"),
+            ["4: - only applies to a, not the whole shift: (-a)"]
+        );
+        assert_eq!(
+            lint("a || b && c\n"),
+            ["3: && is done before ||, so b && c is one side of the ||: (b && c)"]
+        );
+        assert_eq!(
+            lint("a & b + 1\n"),
+            ["3: + is done before &, so b + 1 is one side of the &: (b + 1)"]
+        );
+        assert_eq!(
+            lint("1 << a - 1\n"),
+            ["3: - is done before <<, so a - 1 is one side of the <<: (a - 1)"]
+        );
+        assert_eq!(
+            lint("a ?? b + 1\n"),
+            ["3: + is done before ??, so b + 1 is one side of the ??: (b + 1)"]
+        );
+        // parentheses and operators that are not surprising are left alone
+        assert!(lint("(a < b) == c\n!(a == b)\na + b * c < d - e\n-a * b\n").is_empty());
+        assert!(
+            lint("(a || b) && c\na & (b + 1)\n(-a) << 2\na ?? b ?? c\na << 2 & b\n").is_empty()
+        );
+    }
+
+    fn check(linter: &Linter, source: &str) -> (Vec<String>, Vec<String>) {
+        let prelude = Prelude::new();
+        let file = bind_file("test.lang".to_string(), source, &prelude).unwrap();
+        let report = linter.check(&file, source);
+        let describe = |problem: &lang::common::CompileError| {
+            format!(
+                "{}:{}: {}",
+                problem.location.line, problem.location.column, problem.message
+            )
+        };
+        (
+            report.warnings.iter().map(describe).collect(),
+            report.errors.errors.iter().map(describe).collect(),
+        )
+    }
+
+    #[test]
+    fn lints() {
+        let linter = Linter::new();
+        let (warnings, errors) = check(
+            &linter,
+            "let unused = 1\nlet _ignored = 2\nlet used = 0 && 3\nexport abs = io.print_integer\nabs(used || 1)\n",
+        );
+        assert_eq!(
+            warnings,
+            [
+                "1:5: unused is never used",
+                "3:12: The condition of && is always 0, so the right side never runs",
+                "4:8: abs has the same name as the builtin math.abs, so calls like x.abs() use it instead",
+            ]
+        );
+        assert!(errors.is_empty());
+
+        // pragmas at the top of the file change the severity for just that file
+        let (warnings, errors) = check(
+            &linter,
+            "// settings\n//! allow(unused_binding)\n//! deny(constant_condition, unknown)\nlet a = 1 || 2\n",
+        );
+        assert_eq!(warnings, ["3:30: Unknown lint unknown"]);
+        assert_eq!(
+            errors,
+            ["4:9: The condition of || is always 1, so the right side never runs"]
+        );
+
+        let (warnings, _) = check(
+            &linter,
+            "//! shadowing\nlet x = 1\nlet b = {\nlet x = 2\nexport y = x\n}\nio.print_integer(b.y + x)\n",
+        );
+        assert_eq!(warnings, ["4:5: x shadows the x of an outer scope"]);
+
+        let mut linter = Linter::new();
+        assert!(linter.set_severity("unused_binding", Severity::Allow));
+        assert!(!linter.set_severity("unknown", Severity::Allow));
+        assert_eq!(check(&linter, "let unused = 1\n"), (vec![], vec![]));
+        // pragmas after the first line of code are just comments
+        assert_eq!(
+            check(&linter, "let a = 1\n//! deny(unused_binding)\na\n"),
+            (vec![], vec![])
+        );
+    }
+
+    #[test]
+    fn metrics() {
+        let source = "let limit = 10\nexport run = {\n    let show = io.print_integer\n    show(limit && a || -b ?? 1)\n}\n";
+        let file = parse_file(&mut Lexer::new("test.lang".to_string(), source)).unwrap();
+        let metrics: Vec<String> = collect_metrics("test.lang", &file)
+            .iter()
+            .map(|definition| definition.to_string())
+            .collect();
+        assert_eq!(
+            metrics,
+            [
+                "test.lang: 0 nodes, nesting 0, complexity 1",
+                "limit at test.lang:1:5: 2 nodes, nesting 1, complexity 1",
+                "run at test.lang:2:8: 12 nodes, nesting 6, complexity 4",
+                "show at test.lang:3:9: 3 nodes, nesting 2, complexity 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn fix() {
+        let options = FixOptions {
+            lint_precedence: true,
+        };
+        let result = fix_source_with("test.lang".to_string(), "1 < 2 < 3 < 4\n", options);
+        assert_eq!(result.source, "((1 < 2) < 3) < 4\n");
+        assert_eq!(result.applied.len(), 2);
+    }
+}
+
+#[cfg(test)]
+mod ice_tests {
+    use lang::{
+        common::SourceLocation,
+        ice::{catch_internal_errors, track, Phase},
+    };
+
+    // panics while "binding" a line that contains `crash`
+    fn compile(source: &str) {
+        for (index, line) in source.lines().enumerate() {
+            let _context = track(
+                Phase::Binding,
+                SourceLocation {
+                    filepath: "test.lang".to_string(),
+                    position: 0,
+                    line: index + 1,
+                    column: 1,
+                    synthetic: None,
+                },
+            );
+            if line.contains("crash") {
+                panic!("found {}", line.trim());
+            }
+        }
+    }
+
+    #[test]
+    fn panics_become_internal_errors() {
+        assert_eq!(catch_internal_errors("let a = 1\n", || 5, |_| {}), Ok(5));
+
+        let source = "let a = 1\nlet b = 2\nlet c = crash\nlet d = 4\n";
+        let error = catch_internal_errors(source, || compile(source), compile).unwrap_err();
+        assert_eq!(error.message, "found let c = crash");
+        let context = error.context.as_ref().unwrap();
+        assert_eq!(context.phase, Phase::Binding);
+        assert_eq!(context.location.line, 3);
+        assert_eq!(error.minimized_input, "let c = crash\n");
+
+        let compile_error = error.to_compile_error("test.lang");
+        assert_eq!(
+            compile_error.message,
+            "internal compiler error: found let c = crash"
+        );
+        assert_eq!(compile_error.location.line, 3);
+        assert_eq!(
+            compile_error.notes[0].message,
+            "The compiler panicked while binding"
+        );
+        assert!(compile_error
+            .notes
+            .last()
+            .unwrap()
+            .message
+            .ends_with("which still crashes it:\nlet c = crash"));
+    }
+}
+
+#[cfg(test)]
+mod pragma_tests {
+    use lang::{
+        lint::Severity,
+        pragma::{parse_attributes, Edition},
+        program::Program,
+    };
+
+    fn errors(source: &str) -> Vec<String> {
+        parse_attributes("test.lang", source)
+            .unwrap_err()
+            .errors
+            .into_iter()
+            .map(|error| format!("{}: {}", error.location.column, error.message))
+            .collect()
+    }
+
+    #[test]
+    fn attributes() {
+        let attributes = parse_attributes(
+            "test.lang",
+            "// about the file\n\n//! strict\n  //!edition( 2026 )\n//! deny(a,  b)\nlet c = 1\n//! allow(c)\n",
+        )
+        .unwrap();
+        assert!(attributes.strict);
+        assert_eq!(attributes.edition, Edition::Edition2026);
+        let severities: Vec<_> = attributes
+            .lint_severities
+            .iter()
+            .map(|(name, severity, location)| {
+                (name as &str, *severity, location.line, location.column)
+            })
+            .collect();
+        assert_eq!(
+            severities,
+            [("a", Severity::Deny, 5, 10), ("b", Severity::Deny, 5, 14)]
+        );
+
+        assert_eq!(errors("//! stirct\n"), ["5: Unknown pragma stirct"]);
+        assert_eq!(
+            errors("//! edition(1999)\n"),
+            ["13: Unknown edition 1999, the editions are 2026"]
+        );
+        assert_eq!(
+            errors("//! allow(a\n"),
+            ["12: Expected ) at the end of the pragma"]
+        );
+    }
+
+    #[test]
+    fn strict() {
+        let source = "let a = 1\nio.print_integer(1)\n";
+        Program::compile("test.lang".to_string(), source).unwrap();
+        let diagnostics =
+            Program::compile("test.lang".to_string(), &format!("//! strict\n{}", source))
+                .err()
+                .unwrap();
+        assert_eq!(diagnostics.errors[0].message, "a is never used");
+        Program::compile(
+            "test.lang".to_string(),
+            &format!("//! strict\n//! allow(unused_binding)\n{}", source),
+        )
+        .unwrap();
+        assert!(Program::compile("test.lang".to_string(), "//! what\n").is_err());
+    }
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use lang::{
+        diff::{diff_lines, format_diff, outline, DiffLine},
+        emit::Stage,
+    };
+
+    #[test]
+    fn outlines() {
+        let source = "let a = 1 + 2\n";
+        assert_eq!(
+            outline(Stage::Ast, "test.lang".to_string(), source).unwrap(),
+            [
+                "let a",
+                "    binary +",
+                "        integer 1",
+                "        integer 2"
+            ]
+        );
+        assert_eq!(
+            outline(Stage::Ir, "test.lang".to_string(), source).unwrap(),
+            [
+                "let a: integer",
+                "    binary Addition: integer",
+                "        integer 1: integer",
+                "        integer 2: integer"
+            ]
+        );
+        assert!(outline(Stage::Ir, "test.lang".to_string(), "b\n").is_err());
+    }
+
+    #[test]
+    fn diffs() {
+        let lines = |text: &str| -> Vec<String> { text.lines().map(str::to_string).collect() };
+        let old = lines("let a\n    integer 1\nlet b\n    name a\nlet c");
+        let new = lines("let a\n    integer 2\nlet c\n    float 1.5");
+        let diff = diff_lines(&old, &new);
+        assert_eq!(
+            diff,
+            [
+                DiffLine::Same("let a".to_string()),
+                DiffLine::Changed("    integer 1".to_string(), "    integer 2".to_string()),
+                DiffLine::Removed("let b".to_string()),
+                DiffLine::Removed("    name a".to_string()),
+                DiffLine::Same("let c".to_string()),
+                DiffLine::Added("    float 1.5".to_string()),
+            ]
+        );
+        assert_eq!(
+            format_diff(&diff),
+            "  let a\n~     integer 1 -> integer 2\n- let b\n-     name a\n  let c\n+     float 1.5\n"
+        );
+        assert!(diff_lines(&old, &old)
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_))));
+    }
+}
+
+#[cfg(test)]
+mod serve_tests {
+    use lang::{
+        json::{parse_json, Json},
+        serve::{format_source, handle_request, serve},
+    };
+
+    #[test]
+    fn json() {
+        let text = r#"{"a":[1,2.5,-3e2],"b":"x\"\né","c":{"d":null,"e":true}}"#;
+        let value = parse_json(text).unwrap();
+        assert_eq!(value.get("b").and_then(Json::as_str), Some("x\"\né"));
+        assert_eq!(
+            value.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(2.5),
+                Json::Number(-300.0)
+            ]))
+        );
+        assert_eq!(
+            value.to_string(),
+            r#"{"a":[1,2.5,-300],"b":"x\"\né","c":{"d":null,"e":true}}"#
+        );
+        assert_eq!(parse_json("[1,").unwrap_err().position, 3);
+        assert!(parse_json("{} {}").is_err());
+        assert!(parse_json(r#""\q""#).is_err());
+    }
+
+    #[test]
+    fn requests() {
+        let response = |text: &str| handle_request(text).map(|response| response.to_string());
+        assert_eq!(
+            response(
+                r#"{"jsonrpc":"2.0","id":1,"method":"run","params":{"source":"io.print_integer(1 + 2)\n"}}"#
+            )
+            .unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"diagnostics":[],"output":"3\n","error":null}}"#
+        );
+        assert_eq!(
+            response(
+                r#"{"jsonrpc":"2.0","id":"a","method":"type","params":{"source":"let a = 1.5\na"}}"#
+            )
+            .unwrap(),
+            r#"{"jsonrpc":"2.0","id":"a","result":{"diagnostics":[],"type":"float"}}"#
+        );
+        assert!(response(
+            r#"{"jsonrpc":"2.0","id":2,"method":"compile","params":{"source":"b","filepath":"b.lang"}}"#
+        )
+        .unwrap()
+        .contains(r#""location":{"filepath":"b.lang","line":1,"column":1}"#));
+        assert!(response(
+            r#"{"jsonrpc":"2.0","id":6,"method":"compile","params":{"source":"\"😀\" + b","column_unit":"utf16"}}"#
+        )
+        .unwrap()
+        .contains(r#""line":1,"column":8}"#));
+        assert!(response(
+            r#"{"jsonrpc":"2.0","id":7,"method":"compile","params":{"source":"b","column_unit":"bytes"}}"#
+        )
+        .unwrap()
+        .contains(r#""code":-32602"#));
+        assert!(response(
+            r#"{"jsonrpc":"2.0","id":3,"method":"run","params":{"source":"let a = 0\na = a + 1\n","fuel":1}}"#
+        )
+        .unwrap()
+        .contains(r#""error":"Execution ran out of fuel""#));
+
+        // notifications get no answer, but broken requests still do
+        assert_eq!(
+            response(r#"{"jsonrpc":"2.0","method":"compile","params":{"source":""}}"#),
+            None
+        );
+        assert!(response("{").unwrap().contains(r#""code":-32700"#));
+        let nested = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(response(&nested)
+            .unwrap()
+            .contains(r#""code":-32700,"message":"Json is nested too deeply"#));
+        assert!(response(r#"{"id":4,"method":"what"}"#)
+            .unwrap()
+            .contains(r#""code":-32601"#));
+        assert!(response(r#"{"id":5,"method":"run","params":{}}"#)
+            .unwrap()
+            .contains(r#""code":-32602"#));
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(
+            format_source("test.lang".to_string(), "let   a={export b=1\n}\na.b+2").unwrap(),
+            "let a = {\n    export b = 1\n}\na.b + 2\n"
+        );
+        let diagnostics = format_source("test.lang".to_string(), "let a = 1\n// b\n").unwrap_err();
+        assert_eq!(diagnostics.errors[0].location.line, 2);
+        assert_eq!(diagnostics.errors[0].location.column, 1);
+    }
+
+    #[test]
+    fn lines() {
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"compile","params":{"source":""}}"#,
+            "\n\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"format","params":{"source":"1+1"}}"#,
+            "\n",
+        );
+        let mut output = vec![];
+        serve(&mut input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"{"jsonrpc":"2.0","id":1,"result":{"diagnostics":[]}}"#,
+                "\n",
+                r#"{"jsonrpc":"2.0","id":2,"result":{"diagnostics":[],"source":"1 + 1\n"}}"#,
+                "\n",
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod notebook_tests {
+    use lang::{notebook::Notebook, session::EvalError, value::Value};
+
+    #[test]
+    fn cells() {
+        let mut notebook = Notebook::new();
+        let cell = notebook.push_cell("let a = 2\nio.print_integer(a)".to_string());
+        assert_eq!(cell.output, "2\n");
+        assert_eq!(cell.result, Ok(Value::Void));
+        assert_eq!(
+            notebook.push_cell("let b = a * 3\nb".to_string()).result,
+            Ok(Value::Integer(6))
+        );
+        notebook.push_cell("io.print_integer(b + 1)".to_string());
+        assert_eq!(notebook.get_cells()[2].output, "7\n");
+
+        // the later cells see the new a
+        notebook.edit_cell(0, "let a = 10".to_string());
+        let results: Vec<_> = notebook
+            .get_cells()
+            .iter()
+            .map(|cell| (cell.output.as_str(), cell.result.clone()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("", Ok(Value::Integer(10))),
+                ("", Ok(Value::Integer(30))),
+                ("31\n", Ok(Value::Void))
+            ]
+        );
+
+        // without the definition of b the last cell fails, and says which cell it is
+        notebook.remove_cell(1);
+        let Err(EvalError::Compile(error)) = &notebook.get_cells()[1].result else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(error.location.filepath, "<cell 2>");
+        assert_eq!(error.message, "Unable to find b");
+        assert!(notebook.get_session().get_names().contains_key("a"));
+
+        // running again from a cell does not see what the cells after it defined before
+        notebook.push_cell("let c = a".to_string());
+        notebook.rerun_from(0);
+        assert_eq!(notebook.get_cells()[2].result, Ok(Value::Integer(10)));
+    }
+}
+
+#[cfg(test)]
+mod explain_tests {
+    use lang::explain::explain_pipeline;
+
+    #[test]
+    fn sections() {
+        let explanation =
+            explain_pipeline("test.lang".to_string(), "io.print_integer(1 + 2)\n", 1000).unwrap();
+        let sections: Vec<_> = explanation
+            .lines()
+            .filter(|line| line.starts_with("=="))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                "== tokens ==",
+                "== parse tree ==",
+                "== bound tree ==",
+                "== bytecode ==",
+                "== execution ==",
+            ]
+        );
+        assert!(explanation.contains("1:20: Plus\n"));
+        assert!(explanation.contains("    binary Addition: integer\n"));
+        assert!(explanation.contains("# 1: io.print_integer(1 + 2)\n"));
+        assert!(explanation.contains("add                      [1, 2]\n"));
+        assert!(explanation.contains("printed \"3\\n\"\n"));
+        assert!(explanation.ends_with(" instructions\n"));
+    }
+
+    #[test]
+    fn steps_run_out() {
+        let explanation = explain_pipeline(
+            "test.lang".to_string(),
+            "let a = 0\na = a + 1\na = a + 1\n",
+            3,
+        )
+        .unwrap();
+        assert!(explanation.ends_with("error: Execution ran out of fuel\n"));
+        assert!(explain_pipeline("test.lang".to_string(), "1 +", 3).is_err());
+    }
+}
+
+#[cfg(test)]
+mod ast_builder_tests {
+    use lang::{
+        ast::{self, AstTrait, GENERATED_NAME},
+        binding::bind_ast,
+        lexer::Lexer,
+        parsing::parse_file,
+        program::Prelude,
+        token::TokenKind,
+    };
+
+    #[test]
+    fn round_trips() {
+        let sum = ast::binary(ast::integer(1), TokenKind::Plus, ast::integer(2));
+        let file = ast::file(vec![
+            ast::lett(
+                "a",
+                Some(ast::binary(
+                    sum.clone(),
+                    TokenKind::Asterisk,
+                    ast::integer(3),
+                )),
+            ),
+            ast::binary(ast::integer(10), TokenKind::Minus, sum),
+            ast::unary(
+                TokenKind::Minus,
+                ast::binary(ast::name("a"), TokenKind::Plus, ast::integer(1)),
+            ),
+            ast::call(
+                ast::member_access(ast::name("io"), "print_integer"),
+                vec![ast::name("a")],
+            ),
+            ast::matchh(
+                ast::name("a"),
+                vec![(Some(-1), ast::string("no")), (None, ast::none())],
+            ),
+            ast::destructure(&["print_float"], ast::name("io")),
+            ast::binary(
+                ast::join(ast::spawn(ast::name("a"))),
+                TokenKind::Plus,
+                ast::integer(1),
+            ),
+        ]);
+        let printed = file.pretty_print(0);
+        assert_eq!(
+            printed,
+            "\nlet a = (1 + 2) * 3\n10 - (1 + 2)\n-(a + 1)\nio.print_integer(a)\nmatch a {\n    -1 -> \"no\"\n    _ -> none\n}\nlet { print_float } = io\njoin(spawn a) + 1\n"
+        );
+        let parsed = parse_file(&mut Lexer::new("test.lang".to_string(), &printed)).unwrap();
+        assert_eq!(parsed.pretty_print(0), printed);
+    }
+
+    #[test]
+    fn binds() {
+        let prelude = Prelude::new();
+        let file = ast::file(vec![ast::call(
+            ast::member_access(ast::name("io"), "print_integer"),
+            vec![ast::integer(1)],
+        )]);
+        assert!(bind_ast(&file, &mut prelude.names()).is_ok());
+
+        let error =
+            bind_ast(&ast::file(vec![ast::name("missing")]), &mut prelude.names()).unwrap_err();
+        assert_eq!(error.location.to_string(), "<generated>");
+        assert_eq!(error.location.synthetic.unwrap().name, GENERATED_NAME);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a binary operator")]
+    fn wrong_operator() {
+        ast::binary(ast::integer(1), TokenKind::Tilde, ast::integer(2));
     }
 }
@@ -0,0 +1,91 @@
+use std::fmt::Display;
+
+use crate::{
+    ast::{Ast, AstFile, AstTrait},
+    token::{NameToken, TokenKind},
+};
+
+/// How big and how tangled one definition is, every `let` and `export` gets its own and the
+/// top level of the file gets one too
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// Like `run at test.lang:3:8`, or just the file path for the top level
+    pub definition: String,
+    /// The ast nodes that belong to it, the ones inside a nested definition belong to that
+    pub node_count: usize,
+    /// How many nodes deep its value goes, a definition of a literal is 1
+    pub max_nesting: usize,
+    /// 1 plus one for each `&&`, `||` and `??` and for each arm of a `match` but the `_` one,
+    /// which are the only places that branch
+    pub cyclomatic_complexity: usize,
+}
+
+impl Metrics {
+    fn new(definition: String) -> Metrics {
+        Metrics {
+            definition,
+            node_count: 0,
+            max_nesting: 0,
+            cyclomatic_complexity: 1,
+        }
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} nodes, nesting {}, complexity {}",
+            self.definition, self.node_count, self.max_nesting, self.cyclomatic_complexity
+        )
+    }
+}
+
+/// The metrics of the top level of the file followed by each definition in source order
+pub fn collect_metrics(filepath: &str, file: &AstFile) -> Vec<Metrics> {
+    let mut metrics = vec![Metrics::new(filepath.to_string())];
+    for expression in &file.expressions {
+        add_metrics(expression, 0, 1, &mut metrics);
+    }
+    metrics
+}
+
+// `current` is the index of the definition the node belongs to
+fn add_metrics(ast: &Ast, current: usize, depth: usize, metrics: &mut Vec<Metrics>) {
+    let (current, depth) = match ast {
+        Ast::Let(lett) => {
+            metrics.push(Metrics::new(describe_definition(&lett.name_token)));
+            (metrics.len() - 1, 0)
+        }
+        Ast::Export(export) => {
+            metrics.push(Metrics::new(describe_definition(&export.name_token)));
+            (metrics.len() - 1, 0)
+        }
+        _ => (current, depth),
+    };
+
+    let definition = &mut metrics[current];
+    definition.node_count += 1;
+    definition.max_nesting = definition.max_nesting.max(depth);
+    if let Ast::Binary(binary) = ast {
+        if matches!(
+            binary.operator_token.kind,
+            TokenKind::AmpersandAmpersand
+                | TokenKind::PipePipe
+                | TokenKind::QuestionMarkQuestionMark
+        ) {
+            definition.cyclomatic_complexity += 1;
+        }
+    }
+    if let Ast::Match(matchh) = ast {
+        definition.cyclomatic_complexity += matchh.arms.len().saturating_sub(1);
+    }
+
+    for child in ast.children() {
+        add_metrics(child, current, depth + 1, metrics);
+    }
+}
+
+fn describe_definition(name_token: &NameToken) -> String {
+    format!("{} at {}", name_token.name, name_token.location)
+}
@@ -0,0 +1,189 @@
+use crate::{
+    bytecode::{BytecodeValue, NativeId, VmValue},
+    common::RuntimeError,
+    execute::Vm,
+    types::{ProcType, Type},
+};
+
+pub type NativeFunction = fn(&mut Vm, &[VmValue]) -> Result<VmValue, RuntimeError>;
+
+/// A builtin procedure implemented in Rust
+pub struct Native {
+    /// The builtin block the native is a member of, like `io` in `io.print_integer`
+    pub namespace: &'static str,
+    pub name: &'static str,
+    pub parameter_types: &'static [Type],
+    pub return_type: Type,
+    pub function: NativeFunction,
+}
+
+impl Native {
+    /// The name with its namespace, which is how bytecode refers to the native
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.namespace, self.name)
+    }
+
+    pub fn get_type(&self) -> ProcType {
+        ProcType {
+            parameter_types: self.parameter_types.to_vec(),
+            return_type: Box::new(self.return_type.clone()),
+        }
+    }
+}
+
+/// Every builtin, `NativeId` is an index into this table
+pub static NATIVES: &[Native] = &[
+    Native {
+        namespace: "io",
+        name: "print_integer",
+        parameter_types: &[Type::Integer],
+        return_type: Type::Void,
+        function: print_integer,
+    },
+    Native {
+        namespace: "io",
+        name: "print_float",
+        parameter_types: &[Type::Float],
+        return_type: Type::Void,
+        function: print_float,
+    },
+    Native {
+        namespace: "io",
+        name: "print_string",
+        parameter_types: &[Type::String],
+        return_type: Type::Void,
+        function: print_string,
+    },
+    Native {
+        namespace: "math",
+        name: "abs",
+        parameter_types: &[Type::Integer],
+        return_type: Type::Integer,
+        function: abs,
+    },
+    Native {
+        namespace: "tasks",
+        name: "channel",
+        parameter_types: &[],
+        return_type: Type::Channel,
+        function: channel,
+    },
+    Native {
+        namespace: "tasks",
+        name: "send",
+        parameter_types: &[Type::Channel, Type::Integer],
+        return_type: Type::Void,
+        function: send,
+    },
+    Native {
+        namespace: "tasks",
+        name: "recv",
+        parameter_types: &[Type::Channel],
+        return_type: Type::Integer,
+        function: recv,
+    },
+];
+
+pub fn get_native(id: NativeId) -> &'static Native {
+    &NATIVES[id.0]
+}
+
+/// Finds a native by its full name, like `io.print_integer`
+pub fn find_native(full_name: &str) -> Option<NativeId> {
+    NATIVES
+        .iter()
+        .position(|native| native.full_name() == full_name)
+        .map(NativeId)
+}
+
+/// The namespaces of the natives in the order they first appear in `NATIVES`
+pub fn namespaces() -> Vec<&'static str> {
+    let mut namespaces = vec![];
+    for native in NATIVES {
+        if !namespaces.contains(&native.namespace) {
+            namespaces.push(native.namespace);
+        }
+    }
+    namespaces
+}
+
+fn print_integer(vm: &mut Vm, arguments: &[VmValue]) -> Result<VmValue, RuntimeError> {
+    // the vm can be called with anything from outside, so the argument is checked
+    let integer = arguments
+        .first()
+        .and_then(VmValue::as_integer)
+        .ok_or_else(|| RuntimeError {
+            message: "print_integer needs an integer".to_string(),
+        })?;
+    vm.write_output(&format!("{}\n", integer))?;
+    Ok(VmValue::Void)
+}
+
+// written the same as a float literal, so `1.0` does not print like an integer
+fn print_float(vm: &mut Vm, arguments: &[VmValue]) -> Result<VmValue, RuntimeError> {
+    let float = arguments
+        .first()
+        .and_then(VmValue::as_float)
+        .ok_or_else(|| RuntimeError {
+            message: "print_float needs a float".to_string(),
+        })?;
+    vm.write_output(&format!("{:?}\n", float))?;
+    Ok(VmValue::Void)
+}
+
+fn print_string(vm: &mut Vm, arguments: &[VmValue]) -> Result<VmValue, RuntimeError> {
+    let string = arguments
+        .first()
+        .and_then(VmValue::as_object)
+        .and_then(BytecodeValue::as_string)
+        .ok_or_else(|| RuntimeError {
+            message: "print_string needs a string".to_string(),
+        })?;
+    vm.write_output(&format!("{}\n", string))?;
+    Ok(VmValue::Void)
+}
+
+fn abs(_vm: &mut Vm, arguments: &[VmValue]) -> Result<VmValue, RuntimeError> {
+    let integer = arguments
+        .first()
+        .and_then(VmValue::as_integer)
+        .ok_or_else(|| RuntimeError {
+            message: "abs needs an integer".to_string(),
+        })?;
+    let result = integer.checked_abs().ok_or_else(|| RuntimeError {
+        message: format!(
+            "Unable to take the absolute value of {} without overflowing",
+            integer
+        ),
+    })?;
+    Ok(VmValue::Integer(result))
+}
+
+fn channel(vm: &mut Vm, _arguments: &[VmValue]) -> Result<VmValue, RuntimeError> {
+    Ok(vm.make_channel())
+}
+
+fn send(vm: &mut Vm, arguments: &[VmValue]) -> Result<VmValue, RuntimeError> {
+    let (Some(channel), Some(value)) = (
+        arguments.first().and_then(VmValue::as_integer),
+        arguments
+            .get(1)
+            .filter(|value| value.as_integer().is_some()),
+    ) else {
+        return Err(RuntimeError {
+            message: "send needs a channel and an integer".to_string(),
+        });
+    };
+    vm.send(channel, value.clone())?;
+    Ok(VmValue::Void)
+}
+
+fn recv(vm: &mut Vm, arguments: &[VmValue]) -> Result<VmValue, RuntimeError> {
+    let channel = arguments
+        .first()
+        .and_then(VmValue::as_integer)
+        .ok_or_else(|| RuntimeError {
+            message: "recv needs a channel".to_string(),
+        })?;
+    vm.receive(channel)
+}
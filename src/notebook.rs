@@ -0,0 +1,93 @@
+use crate::{
+    execute::Vm,
+    session::{EvalError, Session},
+    value::Value,
+};
+
+/// A cell of a `Notebook` and what happened the last time it ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub source: String,
+    /// Everything the cell printed
+    pub output: String,
+    /// The value of the last expression of the cell, or the error that stopped it
+    pub result: Result<Value, EvalError>,
+}
+
+/// Cells of source that run one after another, each one sees the definitions and variables
+/// the cells before it left behind, like lines of the repl
+///
+/// Changing a cell throws away what it and every cell after it did, then runs them again from
+/// the state the cells before it left. A cell that fails does not stop the ones after it, they
+/// still see whatever it defined before the error, the same as in the repl
+pub struct Notebook {
+    cells: Vec<Cell>,
+    // the session as it was before each cell ran, with one more for after the last cell
+    sessions: Vec<Session>,
+}
+
+impl Notebook {
+    pub fn new() -> Notebook {
+        Notebook {
+            cells: vec![],
+            sessions: vec![Session::new()],
+        }
+    }
+
+    pub fn get_cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// Adds a cell after the others and runs it
+    pub fn push_cell(&mut self, source: String) -> &Cell {
+        self.cells.push(Cell {
+            source,
+            output: String::new(),
+            result: Ok(Value::Void),
+        });
+        self.rerun_from(self.cells.len() - 1);
+        self.cells.last().unwrap()
+    }
+
+    /// Replaces the source of the cell, then runs it and every cell after it again
+    pub fn edit_cell(&mut self, index: usize, source: String) {
+        self.cells[index].source = source;
+        self.rerun_from(index);
+    }
+
+    /// Removes the cell and runs every cell after it again without it
+    pub fn remove_cell(&mut self, index: usize) {
+        self.cells.remove(index);
+        self.rerun_from(index);
+    }
+
+    /// Runs the cell and every cell after it again, starting from the state the cells before
+    /// it left
+    pub fn rerun_from(&mut self, index: usize) {
+        self.sessions.truncate(index + 1);
+        for (index, cell) in self.cells.iter_mut().enumerate().skip(index) {
+            let mut session = self.sessions[index].clone();
+            let mut vm = Vm::new();
+            vm.captured_output = Some(String::new());
+            cell.result = session.eval_with(&mut vm, cell_filepath(index), &cell.source);
+            cell.output = vm.captured_output.unwrap_or_default();
+            self.sessions.push(session);
+        }
+    }
+
+    /// The state after the last cell, for running code that is not part of the notebook
+    pub fn get_session(&self) -> &Session {
+        self.sessions.last().unwrap()
+    }
+}
+
+impl Default for Notebook {
+    fn default() -> Self {
+        Notebook::new()
+    }
+}
+
+// errors say which cell they are in, counting from 1 like a user would
+fn cell_filepath(index: usize) -> String {
+    format!("<cell {}>", index + 1)
+}
@@ -1,13 +1,60 @@
 use crate::{
     ast::{
-        Ast, AstBinary, AstBlock, AstCall, AstExport, AstFile, AstInteger, AstLet, AstName,
-        AstUnary,
+        Ast, AstAssignment, AstBinary, AstBlock, AstCall, AstDestructure, AstEnum, AstExport,
+        AstFile, AstFloat, AstFreeze, AstInteger, AstJoin, AstLet, AstMatch, AstMatchArm,
+        AstMemberAccess, AstName, AstNone, AstParenthesized, AstPattern, AstSpawn, AstString,
+        AstTrait, AstUnary,
     },
-    common::CompileError,
+    common::{CompileError, ErrorKind, Suggestion},
+    grammar::{get_binary_precedence, get_unary_precedence},
+    ice::{track, Phase},
     lexer::Lexer,
-    token::TokenKind,
+    token::{FloatToken, IntegerToken, NameToken, StringToken, Token, TokenKind},
 };
 
+// each level of nesting takes around 20KB of stack in debug builds
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 64;
+
+/// How deeply the expression being parsed is nested, so adversarial input
+/// gets an error instead of overflowing the stack
+#[derive(Debug, Clone, Copy)]
+struct Nesting {
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Nesting {
+    fn new(max_depth: usize) -> Nesting {
+        Nesting {
+            depth: 0,
+            max_depth,
+        }
+    }
+
+    fn enter(self, lexer: &mut Lexer) -> Result<Nesting, CompileError> {
+        if self.depth >= self.max_depth {
+            let token = lexer.peek_token()?;
+            return Err(CompileError {
+                location: token.location,
+                end_location: None,
+                kind: ErrorKind::NestedTooDeeply,
+                id: "nested-too-deeply",
+                arguments: vec![self.max_depth.to_string()],
+                message: format!(
+                    "Expression is nested too deeply, the maximum depth is {}",
+                    self.max_depth,
+                ),
+                notes: vec![],
+                suggestions: vec![],
+            });
+        }
+        Ok(Nesting {
+            depth: self.depth + 1,
+            max_depth: self.max_depth,
+        })
+    }
+}
+
 fn allow_newline(lexer: &mut Lexer) -> Result<(), CompileError> {
     if lexer.peek_kind()? == TokenKind::Newline {
         lexer.next_token()?;
@@ -16,23 +63,40 @@ fn allow_newline(lexer: &mut Lexer) -> Result<(), CompileError> {
 }
 
 pub fn parse_file(lexer: &mut Lexer) -> Result<AstFile, CompileError> {
+    parse_file_with_max_depth(lexer, DEFAULT_MAX_NESTING_DEPTH)
+}
+
+pub fn parse_file_with_max_depth(
+    lexer: &mut Lexer,
+    max_depth: usize,
+) -> Result<AstFile, CompileError> {
+    let nesting = Nesting::new(max_depth);
     let mut expressions = vec![];
     while lexer.peek_kind()? != TokenKind::EndOfFile {
+        lexer.check_cancelled()?;
         while lexer.peek_kind()? == TokenKind::Newline {
             lexer.next_token()?;
         }
-        expressions.push(parse_expression(lexer)?);
+        // tracked per expression of the file rather than for every nested one, which would
+        // take more stack than the nesting limit allows for
+        let _context = track(Phase::Parsing, lexer.peek_token()?.location);
+        expressions.push(parse_nested_expression(lexer, nesting)?);
         if lexer.peek_kind()? != TokenKind::EndOfFile {
             let newline = lexer.next_token()?;
             if newline.kind != TokenKind::Newline {
                 return Err(CompileError {
                     location: newline.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::Syntax,
+                    id: "expected-newline",
+                    arguments: vec![TokenKind::Newline.to_string(), newline.kind.to_string()],
                     message: format!(
                         "Expected {} at the end of the expression, but got {}",
                         TokenKind::Newline.to_string(),
                         newline.kind.to_string(),
                     ),
                     notes: vec![],
+                    suggestions: missing_newline_suggestions(&newline),
                 });
             }
         }
@@ -46,286 +110,827 @@ pub fn parse_file(lexer: &mut Lexer) -> Result<AstFile, CompileError> {
 }
 
 pub fn parse_expression(lexer: &mut Lexer) -> Result<Ast, CompileError> {
-    parse_binary_expression(lexer, 0)
+    parse_nested_expression(lexer, Nesting::new(DEFAULT_MAX_NESTING_DEPTH))
+}
+
+fn parse_nested_expression(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let target = parse_binary_expression(lexer, 0, nesting)?;
+    if lexer.peek_kind()? != TokenKind::Equal {
+        return Ok(target);
+    }
+    parse_assignment(lexer, target, nesting)
+}
+
+// assignment binds looser than every operator and is right associative, so `a = b = 1`
+// assigns 1 to b and then to a
+fn parse_assignment(lexer: &mut Lexer, target: Ast, nesting: Nesting) -> Result<Ast, CompileError> {
+    let name_token = match target {
+        Ast::Name(name) => name.name_token,
+        target => {
+            return Err(CompileError {
+                location: target.get_start(),
+                end_location: Some(target.get_end()),
+                kind: ErrorKind::Syntax,
+                id: "only-names-assigned",
+                arguments: vec![],
+                message: "Only names can be assigned to".to_string(),
+                notes: vec![],
+                suggestions: vec![],
+            })
+        }
+    };
+    let equal_token = lexer.next_token()?;
+    allow_newline(lexer)?;
+    let nesting = nesting.enter(lexer)?;
+    let value = parse_nested_expression(lexer, nesting)?;
+    Ok(Ast::Assignment(AstAssignment {
+        name_token,
+        equal_token,
+        value: Box::new(value),
+    }))
 }
 
 fn parse_binary_expression(
     lexer: &mut Lexer,
     parent_precedence: usize,
+    nesting: Nesting,
 ) -> Result<Ast, CompileError> {
-    fn get_unary_precedence(kind: TokenKind) -> usize {
-        match kind {
-            TokenKind::Plus | TokenKind::Minus | TokenKind::ExclamationMark => 4,
-            _ => 0,
+    let nesting = nesting.enter(lexer)?;
+    let mut left = if get_unary_precedence(&lexer.peek_kind()?) > 0 {
+        parse_unary_expression(lexer, nesting)?
+    } else {
+        parse_primary_expression(lexer, nesting)?
+    };
+
+    loop {
+        left = parse_postfix_expression(lexer, left, nesting)?;
+
+        let binary_precedence = get_binary_precedence(&lexer.peek_kind()?);
+        if binary_precedence <= parent_precedence {
+            break;
         }
+        left = parse_binary_operator(lexer, left, binary_precedence, nesting)?;
     }
 
-    fn get_binary_precedence(kind: TokenKind) -> usize {
-        match kind {
-            TokenKind::Asterisk | TokenKind::Slash => 3,
-            TokenKind::Plus | TokenKind::Minus => 2,
-            TokenKind::EqualEqual
-            | TokenKind::ExclamationMarkEqual
-            | TokenKind::LessThan
-            | TokenKind::GreaterThan
-            | TokenKind::LessThanEqual
-            | TokenKind::GreaterThanEqual => 1,
-            _ => 0,
-        }
+    Ok(left)
+}
+
+fn parse_unary_expression(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let operator_token = lexer.next_token()?;
+    let precedence = get_unary_precedence(&operator_token.kind);
+    allow_newline(lexer)?;
+    let operand = parse_binary_expression(lexer, precedence, nesting)?;
+    Ok(Ast::Unary(AstUnary {
+        operator_token,
+        operand: Box::new(operand),
+    }))
+}
+
+// the operator and its right operand, `left` is everything before the operator
+fn parse_binary_operator(
+    lexer: &mut Lexer,
+    left: Ast,
+    precedence: usize,
+    nesting: Nesting,
+) -> Result<Ast, CompileError> {
+    let operator_token = lexer.next_token()?;
+    allow_newline(lexer)?;
+    let right = parse_binary_expression(lexer, precedence, nesting)?;
+    Ok(Ast::Binary(AstBinary {
+        left: Box::new(left),
+        operator_token,
+        right: Box::new(right),
+    }))
+}
+
+// the calls and member accesses that follow an operand
+fn parse_postfix_expression(
+    lexer: &mut Lexer,
+    mut operand: Ast,
+    nesting: Nesting,
+) -> Result<Ast, CompileError> {
+    loop {
+        operand = match lexer.peek_kind()? {
+            TokenKind::OpenParenthesis => parse_call(lexer, operand, nesting)?,
+            TokenKind::Dot => Ast::MemberAccess(AstMemberAccess {
+                operand: Box::new(operand),
+                dot_token: lexer.next_token()?,
+                name_token: expect_name(lexer, "for member access")?,
+            }),
+            _ => return Ok(operand),
+        };
     }
+}
 
-    let mut left;
+// each kind of expression is parsed in its own function to keep the stack frames
+// of the recursive functions small, otherwise deep nesting overflows the stack
+fn parse_primary_expression(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    match lexer.peek_kind()? {
+        TokenKind::Name(_)
+        | TokenKind::Integer(_)
+        | TokenKind::Float(_)
+        | TokenKind::String(_)
+        | TokenKind::None => parse_leaf_expression(lexer),
 
-    let unary_precedence = get_unary_precedence(lexer.peek_kind()?);
-    if unary_precedence > 0 {
-        let operator_token = lexer.next_token()?;
-        allow_newline(lexer)?;
-        let operand = parse_binary_expression(lexer, unary_precedence)?;
-        left = Ast::Unary(AstUnary {
-            operator_token,
-            operand: Box::new(operand),
-        });
-    } else {
-        left = parse_primary_expression(lexer)?;
+        TokenKind::OpenBrace => parse_block(lexer, nesting).map(Ast::Block),
+
+        TokenKind::OpenParenthesis => parse_parenthesized_expression(lexer, nesting),
+
+        TokenKind::Export => parse_export(lexer, nesting),
+
+        TokenKind::Let => parse_let(lexer, nesting),
+
+        TokenKind::Match => parse_match(lexer, nesting),
+
+        TokenKind::Enum => parse_enum(lexer),
+
+        TokenKind::Freeze => parse_freeze(lexer, nesting),
+
+        TokenKind::Spawn => parse_spawn(lexer, nesting),
+
+        TokenKind::Join => parse_join(lexer, nesting),
+
+        _ => expected_expression(lexer),
     }
+}
 
-    'main_loop: loop {
-        while lexer.peek_kind()? == TokenKind::OpenParenthesis {
-            let open_parenthesis_token = lexer.next_token()?;
-            allow_newline(lexer)?;
-            let mut first = true;
-            let mut arguments = vec![];
-            while lexer.peek_kind()? != TokenKind::CloseParenthesis
-                && lexer.peek_kind()? != TokenKind::EndOfFile
-            {
-                if first {
-                    first = false;
-                } else {
-                    let comma = lexer.next_token()?;
-                    if comma.kind != TokenKind::Comma {
-                        return Err(CompileError {
-                            location: comma.location.clone(),
-                            message: format!(
-                                "Expected {} to seperate arguments in the call, but got {}",
-                                TokenKind::Comma.to_string(),
-                                comma.kind.to_string(),
-                            ),
-                            notes: vec![],
-                        });
-                    }
-                    allow_newline(lexer)?;
-                    if lexer.peek_kind()? == TokenKind::CloseParenthesis {
-                        break;
-                    }
-                }
-                arguments.push(parse_expression(lexer)?);
-            }
-            let close_parenthesis_token = lexer.next_token()?;
-            if close_parenthesis_token.kind != TokenKind::CloseParenthesis {
+// the expressions that have nothing nested in them
+fn parse_leaf_expression(lexer: &mut Lexer) -> Result<Ast, CompileError> {
+    Ok(match lexer.peek_kind()? {
+        TokenKind::Name(_) => Ast::Name(AstName {
+            name_token: expect_name(lexer, "for name")?,
+        }),
+        TokenKind::Integer(_) => Ast::Integer(AstInteger {
+            integer_token: expect_integer(lexer)?,
+        }),
+        TokenKind::Float(_) => Ast::Float(AstFloat {
+            float_token: expect_float(lexer)?,
+        }),
+        TokenKind::String(_) => Ast::String(AstString {
+            string_token: expect_string(lexer)?,
+        }),
+        _ => Ast::None(AstNone {
+            none_token: lexer.next_token()?,
+        }),
+    })
+}
+
+fn expected_expression(lexer: &mut Lexer) -> Result<Ast, CompileError> {
+    let token = lexer.next_token()?;
+    Err(CompileError {
+        location: token.location.clone(),
+        end_location: None,
+        kind: ErrorKind::Syntax,
+        id: "expected-expression",
+        arguments: vec![token.kind.to_string()],
+        message: format!("Expected an expression but got {}", token.kind.to_string()),
+        notes: vec![],
+        suggestions: vec![],
+    })
+}
+
+fn parse_call(lexer: &mut Lexer, operand: Ast, nesting: Nesting) -> Result<Ast, CompileError> {
+    let open_parenthesis_token = lexer.next_token()?;
+    allow_newline(lexer)?;
+    let mut first = true;
+    let mut arguments = vec![];
+    while lexer.peek_kind()? != TokenKind::CloseParenthesis
+        && lexer.peek_kind()? != TokenKind::EndOfFile
+    {
+        if first {
+            first = false;
+        } else {
+            let comma = lexer.next_token()?;
+            if comma.kind != TokenKind::Comma {
                 return Err(CompileError {
-                    location: close_parenthesis_token.location.clone(),
+                    location: comma.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::Syntax,
+                    id: "expected-argument-separator",
+                    arguments: vec![TokenKind::Comma.to_string(), comma.kind.to_string()],
                     message: format!(
-                        "Expected {} at the end of the call, but got {}",
-                        TokenKind::CloseParenthesis.to_string(),
-                        close_parenthesis_token.kind.to_string(),
+                        "Expected {} to seperate arguments in the call, but got {}",
+                        TokenKind::Comma.to_string(),
+                        comma.kind.to_string(),
                     ),
                     notes: vec![],
+                    suggestions: vec![],
                 });
             }
-            left = Ast::Call(AstCall {
-                operand: Box::new(left),
-                open_parenthesis_token,
-                arguments,
-                close_parenthesis_token,
-            })
+            allow_newline(lexer)?;
+            if lexer.peek_kind()? == TokenKind::CloseParenthesis {
+                break;
+            }
         }
+        arguments.push(parse_nested_expression(lexer, nesting)?);
+    }
+    let close_parenthesis_token = lexer.next_token()?;
+    if close_parenthesis_token.kind != TokenKind::CloseParenthesis {
+        return Err(CompileError {
+            location: close_parenthesis_token.location.clone(),
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-end-of-call",
+            arguments: vec![
+                TokenKind::CloseParenthesis.to_string(),
+                close_parenthesis_token.kind.to_string(),
+            ],
+            message: format!(
+                "Expected {} at the end of the call, but got {}",
+                TokenKind::CloseParenthesis.to_string(),
+                close_parenthesis_token.kind.to_string(),
+            ),
+            notes: vec![],
+            suggestions: vec![],
+        });
+    }
+    Ok(Ast::Call(AstCall {
+        operand: Box::new(operand),
+        open_parenthesis_token,
+        arguments,
+        close_parenthesis_token,
+    }))
+}
 
-        let binary_precedence = get_binary_precedence(lexer.peek_kind()?);
-        if binary_precedence <= parent_precedence {
-            break 'main_loop;
-        }
+fn parse_parenthesized_expression(
+    lexer: &mut Lexer,
+    nesting: Nesting,
+) -> Result<Ast, CompileError> {
+    let open_parenthesis_token = lexer.next_token()?;
+    let expression = parse_nested_expression(lexer, nesting)?;
+    let close_parenthesis_token = lexer.next_token()?;
+    if close_parenthesis_token.kind != TokenKind::CloseParenthesis {
+        return Err(CompileError {
+            location: close_parenthesis_token.location.clone(),
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-close-parenthesis",
+            arguments: vec![
+                TokenKind::CloseParenthesis.to_string(),
+                close_parenthesis_token.kind.to_string(),
+            ],
+            message: format!(
+                "Expected {} to close the opening (, but got {}",
+                TokenKind::CloseParenthesis.to_string(),
+                close_parenthesis_token.kind.to_string(),
+            ),
+            notes: vec![],
+            suggestions: vec![],
+        });
+    }
+    Ok(Ast::Parenthesized(AstParenthesized {
+        open_parenthesis_token,
+        expression: Box::new(expression),
+        close_parenthesis_token,
+    }))
+}
 
-        let operator_token = lexer.next_token()?;
-        allow_newline(lexer)?;
-        let right = parse_binary_expression(lexer, binary_precedence)?;
-        left = Ast::Binary(AstBinary {
-            left: Box::new(left),
-            operator_token,
-            right: Box::new(right),
+fn parse_export(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let export_token = lexer.next_token()?;
+    let name_token = expect_name(lexer, "for export")?;
+    let equals_token = lexer.next_token()?;
+    if equals_token.kind != TokenKind::Equal {
+        return Err(CompileError {
+            location: equals_token.location.clone(),
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-export-name",
+            arguments: vec![
+                TokenKind::Name(String::new()).to_string(),
+                equals_token.kind.to_string(),
+            ],
+            message: format!(
+                "Expected {} for export value, but got {}",
+                TokenKind::Name(String::new()).to_string(),
+                equals_token.kind.to_string(),
+            ),
+            notes: vec![],
+            suggestions: vec![],
         });
     }
+    allow_newline(lexer)?;
+    let value = parse_nested_expression(lexer, nesting)?;
+    Ok(Ast::Export(AstExport {
+        export_token,
+        name_token,
+        equals_token,
+        value: Box::new(value),
+    }))
+}
 
-    Ok(left)
+fn parse_freeze(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let freeze_token = lexer.next_token()?;
+    allow_newline(lexer)?;
+    let nesting = nesting.enter(lexer)?;
+    let value = parse_nested_expression(lexer, nesting)?;
+    Ok(Ast::Freeze(AstFreeze {
+        freeze_token,
+        value: Box::new(value),
+    }))
 }
 
-fn parse_primary_expression(lexer: &mut Lexer) -> Result<Ast, CompileError> {
-    match lexer.peek_kind()? {
-        TokenKind::Name(_) => {
-            let name_token = lexer.next_token()?;
-            Ok(Ast::Name(AstName { name_token }))
-        }
+fn parse_spawn(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let spawn_token = lexer.next_token()?;
+    allow_newline(lexer)?;
+    let nesting = nesting.enter(lexer)?;
+    let value = parse_nested_expression(lexer, nesting)?;
+    Ok(Ast::Spawn(AstSpawn {
+        spawn_token,
+        value: Box::new(value),
+    }))
+}
 
-        TokenKind::Integer(_) => {
-            let integer_token = lexer.next_token()?;
-            Ok(Ast::Integer(AstInteger { integer_token }))
-        }
+// the operand is only a primary expression with its calls and member accesses, so
+// `join a + 1` adds to the value of the task
+fn parse_join(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let join_token = lexer.next_token()?;
+    let nesting = nesting.enter(lexer)?;
+    let operand = parse_primary_expression(lexer, nesting)?;
+    let operand = parse_postfix_expression(lexer, operand, nesting)?;
+    Ok(Ast::Join(AstJoin {
+        join_token,
+        operand: Box::new(operand),
+    }))
+}
 
-        TokenKind::OpenBrace => Ok(Ast::Block(parse_block(lexer)?)),
+fn parse_let(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let let_token = lexer.next_token()?;
+    if lexer.peek_kind()? == TokenKind::OpenBrace {
+        return parse_destructure(lexer, let_token, nesting);
+    }
+    let name_token = expect_name(lexer, "for let")?;
+    let equal_token;
+    let value;
+    if lexer.peek_kind()? == TokenKind::Equal {
+        equal_token = Some(lexer.next_token()?);
+        allow_newline(lexer)?;
+        value = Some(Box::new(parse_nested_expression(lexer, nesting)?));
+    } else {
+        equal_token = None;
+        value = None;
+    }
+    Ok(Ast::Let(AstLet {
+        let_token,
+        name_token,
+        equal_token,
+        value,
+    }))
+}
 
-        TokenKind::OpenParenthesis => {
-            lexer.next_token()?;
-            let expression = parse_expression(lexer)?;
-            let close_parenthesis_token = lexer.next_token()?;
-            if close_parenthesis_token.kind != TokenKind::CloseParenthesis {
-                return Err(CompileError {
-                    location: close_parenthesis_token.location.clone(),
-                    message: format!(
-                        "Expected {} to close the opening (, but got {}",
-                        TokenKind::CloseParenthesis.to_string(),
-                        close_parenthesis_token.kind.to_string(),
-                    ),
-                    notes: vec![],
-                });
-            }
-            Ok(expression)
-        }
+fn parse_destructure(
+    lexer: &mut Lexer,
+    let_token: Token,
+    nesting: Nesting,
+) -> Result<Ast, CompileError> {
+    let (open_brace_token, names, close_brace_token) =
+        parse_names_in_braces(lexer, "names of the let", "for let")?;
+    if names.is_empty() {
+        return Err(CompileError {
+            location: close_brace_token.location,
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-let-name",
+            arguments: vec![
+                TokenKind::Name(String::new()).to_string(),
+                TokenKind::CloseBrace.to_string(),
+            ],
+            message: format!(
+                "Expected {} for let, but got {}",
+                TokenKind::Name(String::new()).to_string(),
+                TokenKind::CloseBrace.to_string(),
+            ),
+            notes: vec![],
+            suggestions: vec![],
+        });
+    }
+    let equal_token = lexer.next_token()?;
+    if equal_token.kind != TokenKind::Equal {
+        return Err(CompileError {
+            location: equal_token.location,
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-destructure-value",
+            arguments: vec![TokenKind::Equal.to_string(), equal_token.kind.to_string()],
+            message: format!(
+                "Expected {} and the block to take the names from, but got {}",
+                TokenKind::Equal.to_string(),
+                equal_token.kind.to_string(),
+            ),
+            notes: vec![],
+            suggestions: vec![],
+        });
+    }
+    allow_newline(lexer)?;
+    let value = parse_nested_expression(lexer, nesting)?;
+    Ok(Ast::Destructure(AstDestructure {
+        let_token,
+        open_brace_token,
+        names,
+        close_brace_token,
+        equal_token,
+        value: Box::new(value),
+    }))
+}
 
-        TokenKind::Export => {
-            let export_token = lexer.next_token()?;
-            let name_token = lexer.next_token()?;
-            if let TokenKind::Name(_) = name_token.kind {
-            } else {
-                return Err(CompileError {
-                    location: name_token.location.clone(),
-                    message: format!(
-                        "Expected {} for export, but got {}",
-                        TokenKind::Name(String::new()).to_string(),
-                        name_token.kind.to_string(),
-                    ),
-                    notes: vec![],
-                });
-            }
-            let equals_token = lexer.next_token()?;
-            if equals_token.kind != TokenKind::Equal {
-                return Err(CompileError {
-                    location: equals_token.location.clone(),
-                    message: format!(
-                        "Expected {} for export value, but got {}",
-                        TokenKind::Name(String::new()).to_string(),
-                        equals_token.kind.to_string(),
-                    ),
-                    notes: vec![],
-                });
-            }
-            allow_newline(lexer)?;
-            let value = parse_expression(lexer)?;
-            Ok(Ast::Export(AstExport {
-                export_token,
-                name_token,
-                equals_token,
-                value: Box::new(value),
-            }))
+fn parse_match(lexer: &mut Lexer, nesting: Nesting) -> Result<Ast, CompileError> {
+    let match_token = lexer.next_token()?;
+    let value = parse_nested_expression(lexer, nesting)?;
+    let open_brace_token = expect_brace(lexer, TokenKind::OpenBrace)?;
+
+    let mut arms = vec![];
+    while !is_at_end_of_block(lexer)? {
+        skip_newlines(lexer)?;
+        if is_at_end_of_block(lexer)? {
+            break;
         }
+        arms.push(parse_match_arm(lexer, nesting)?);
+        expect_end_of_block_expression(lexer)?;
+    }
+
+    let close_brace_token = expect_brace(lexer, TokenKind::CloseBrace)?;
+    Ok(Ast::Match(AstMatch {
+        match_token,
+        value: Box::new(value),
+        open_brace_token,
+        arms,
+        close_brace_token,
+    }))
+}
 
-        TokenKind::Let => {
-            let let_token = lexer.next_token()?;
-            let name_token = lexer.next_token()?;
-            if let TokenKind::Name(_) = name_token.kind {
-            } else {
+fn parse_match_arm(lexer: &mut Lexer, nesting: Nesting) -> Result<AstMatchArm, CompileError> {
+    let pattern = match lexer.peek_kind()? {
+        TokenKind::Minus => AstPattern::Integer {
+            minus_token: Some(lexer.next_token()?),
+            integer_token: expect_integer(lexer)?,
+        },
+        TokenKind::Integer(_) => AstPattern::Integer {
+            minus_token: None,
+            integer_token: expect_integer(lexer)?,
+        },
+        TokenKind::Name(name) if name == "_" => {
+            AstPattern::Default(expect_name(lexer, "for the arm")?)
+        }
+        TokenKind::Name(_) => {
+            let operand = Ast::Name(AstName {
+                name_token: expect_name(lexer, "for the arm")?,
+            });
+            let dot_token = lexer.next_token()?;
+            if dot_token.kind != TokenKind::Dot {
                 return Err(CompileError {
-                    location: name_token.location.clone(),
+                    location: dot_token.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::Syntax,
+                    id: "expected-enum-variant",
+                    arguments: vec![TokenKind::Dot.to_string(), dot_token.kind.to_string()],
                     message: format!(
-                        "Expected {} for let, but got {}",
-                        TokenKind::Name(String::new()).to_string(),
-                        name_token.kind.to_string(),
+                        "Expected {} and a variant after the name of the enum, but got {}",
+                        TokenKind::Dot.to_string(),
+                        dot_token.kind.to_string(),
                     ),
                     notes: vec![],
+                    suggestions: vec![],
                 });
             }
-            let equal_token;
-            let value;
-            if lexer.peek_kind()? == TokenKind::Equal {
-                equal_token = Some(lexer.next_token()?);
-                allow_newline(lexer)?;
-                value = Some(Box::new(parse_expression(lexer)?));
-            } else {
-                equal_token = None;
-                value = None;
-            }
-            Ok(Ast::Let(AstLet {
-                let_token,
-                name_token,
-                equal_token,
-                value,
-            }))
+            AstPattern::Variant(AstMemberAccess {
+                operand: Box::new(operand),
+                dot_token,
+                name_token: expect_name(lexer, "for the variant")?,
+            })
         }
-
         _ => {
             let token = lexer.next_token()?;
-            Err(CompileError {
+            return Err(CompileError {
                 location: token.location.clone(),
-                message: format!("Expected an expression but got {}", token.kind.to_string()),
+                end_location: None,
+                kind: ErrorKind::Syntax,
+                id: "expected-pattern",
+                arguments: vec![TokenKind::Integer(0).to_string(), token.kind.to_string()],
+                message: format!(
+                    "Expected {}, a variant or _ for the arm of the match, but got {}",
+                    TokenKind::Integer(0).to_string(),
+                    token.kind.to_string(),
+                ),
                 notes: vec![],
-            })
+                suggestions: vec![],
+            });
         }
-    }
-}
+    };
 
-fn parse_block(lexer: &mut Lexer) -> Result<AstBlock, CompileError> {
-    let open_brace_token = lexer.next_token()?;
-    if open_brace_token.kind != TokenKind::OpenBrace {
+    let arrow_token = lexer.next_token()?;
+    if arrow_token.kind != TokenKind::RightArrow {
         return Err(CompileError {
-            location: open_brace_token.location.clone(),
+            location: arrow_token.location.clone(),
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-arm-arrow",
+            arguments: vec![
+                TokenKind::RightArrow.to_string(),
+                arrow_token.kind.to_string(),
+            ],
             message: format!(
-                "Expected {}, but got a {}",
-                TokenKind::OpenBrace.to_string(),
-                open_brace_token.kind.to_string(),
+                "Expected {} after the pattern of the arm, but got {}",
+                TokenKind::RightArrow.to_string(),
+                arrow_token.kind.to_string(),
             ),
             notes: vec![],
+            suggestions: vec![],
         });
     }
+    allow_newline(lexer)?;
+    let value = parse_nested_expression(lexer, nesting)?;
+    Ok(AstMatchArm {
+        pattern,
+        arrow_token,
+        value,
+    })
+}
 
-    let mut expressions = vec![];
+fn parse_enum(lexer: &mut Lexer) -> Result<Ast, CompileError> {
+    let enum_token = lexer.next_token()?;
+    let (open_brace_token, variants, close_brace_token) =
+        parse_names_in_braces(lexer, "variants of the enum", "for the variant")?;
+    Ok(Ast::Enum(AstEnum {
+        enum_token,
+        open_brace_token,
+        variants,
+        close_brace_token,
+    }))
+}
+
+// `{ a, b, c }` with an optional trailing comma and newlines after the commas, `what` and
+// `context` say what the names are in the errors
+fn parse_names_in_braces(
+    lexer: &mut Lexer,
+    what: &str,
+    context: &str,
+) -> Result<(Token, Vec<NameToken>, Token), CompileError> {
+    let open_brace_token = expect_brace(lexer, TokenKind::OpenBrace)?;
+    allow_newline(lexer)?;
+    let mut names = vec![];
     while lexer.peek_kind()? != TokenKind::CloseBrace && lexer.peek_kind()? != TokenKind::EndOfFile
     {
-        while lexer.peek_kind()? == TokenKind::Newline {
-            lexer.next_token()?;
-        }
-        expressions.push(parse_expression(lexer)?);
-        if lexer.peek_kind()? != TokenKind::CloseBrace && lexer.peek_kind()? != TokenKind::EndOfFile
-        {
-            let newline = lexer.next_token()?;
-            if newline.kind != TokenKind::Newline {
+        if !names.is_empty() {
+            let comma = lexer.next_token()?;
+            if comma.kind != TokenKind::Comma {
                 return Err(CompileError {
-                    location: newline.location.clone(),
+                    location: comma.location.clone(),
+                    end_location: None,
+                    kind: ErrorKind::Syntax,
+                    id: "expected-separator",
+                    arguments: vec![
+                        TokenKind::Comma.to_string(),
+                        what.to_string(),
+                        comma.kind.to_string(),
+                    ],
                     message: format!(
-                        "Expected {} or {} at the end of the expression, but got {}",
-                        TokenKind::Newline.to_string(),
-                        TokenKind::CloseBrace.to_string(),
-                        newline.kind.to_string(),
+                        "Expected {} to seperate the {}, but got {}",
+                        TokenKind::Comma.to_string(),
+                        what,
+                        comma.kind.to_string(),
                     ),
                     notes: vec![],
+                    suggestions: vec![],
                 });
             }
+            allow_newline(lexer)?;
+            if lexer.peek_kind()? == TokenKind::CloseBrace {
+                break;
+            }
         }
+        names.push(expect_name(lexer, context)?);
+    }
+    let close_brace_token = expect_brace(lexer, TokenKind::CloseBrace)?;
+    Ok((open_brace_token, names, close_brace_token))
+}
+
+fn parse_block(lexer: &mut Lexer, nesting: Nesting) -> Result<AstBlock, CompileError> {
+    let open_brace_token = expect_brace(lexer, TokenKind::OpenBrace)?;
+
+    let mut expressions = vec![];
+    while !is_at_end_of_block(lexer)? {
+        lexer.check_cancelled()?;
+        skip_newlines(lexer)?;
+        expressions.push(parse_nested_expression(lexer, nesting)?);
+        expect_end_of_block_expression(lexer)?;
     }
 
-    let close_brace_token = lexer.next_token()?;
-    if close_brace_token.kind != TokenKind::CloseBrace {
+    let close_brace_token = expect_brace(lexer, TokenKind::CloseBrace)?;
+    Ok(AstBlock {
+        open_brace_token,
+        expressions,
+        close_brace_token,
+    })
+}
+
+// the checks of `parse_block` are their own functions so its stack frame stays small,
+// blocks can be nested as deeply as any other expression
+fn expect_brace(lexer: &mut Lexer, kind: TokenKind) -> Result<Token, CompileError> {
+    let token = lexer.next_token()?;
+    if token.kind != kind {
         return Err(CompileError {
-            location: close_brace_token.location.clone(),
+            location: token.location.clone(),
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-brace",
+            arguments: vec![kind.to_string(), token.kind.to_string()],
             message: format!(
                 "Expected {}, but got a {}",
+                kind.to_string(),
+                token.kind.to_string(),
+            ),
+            notes: vec![],
+            suggestions: vec![],
+        });
+    }
+    Ok(token)
+}
+
+fn is_at_end_of_block(lexer: &mut Lexer) -> Result<bool, CompileError> {
+    Ok(lexer.peek_kind()? == TokenKind::CloseBrace || lexer.peek_kind()? == TokenKind::EndOfFile)
+}
+
+fn skip_newlines(lexer: &mut Lexer) -> Result<(), CompileError> {
+    while lexer.peek_kind()? == TokenKind::Newline {
+        lexer.next_token()?;
+    }
+    Ok(())
+}
+
+fn expect_end_of_block_expression(lexer: &mut Lexer) -> Result<(), CompileError> {
+    if is_at_end_of_block(lexer)? {
+        return Ok(());
+    }
+    let newline = lexer.next_token()?;
+    if newline.kind != TokenKind::Newline {
+        return Err(CompileError {
+            location: newline.location.clone(),
+            end_location: None,
+            kind: ErrorKind::Syntax,
+            id: "expected-end-of-block-expression",
+            arguments: vec![
+                TokenKind::Newline.to_string(),
+                TokenKind::CloseBrace.to_string(),
+                newline.kind.to_string(),
+            ],
+            message: format!(
+                "Expected {} or {} at the end of the expression, but got {}",
+                TokenKind::Newline.to_string(),
                 TokenKind::CloseBrace.to_string(),
-                close_brace_token.kind.to_string(),
+                newline.kind.to_string(),
             ),
             notes: vec![],
+            suggestions: missing_newline_suggestions(&newline),
         });
     }
+    Ok(())
+}
 
-    Ok(AstBlock {
-        open_brace_token,
-        expressions,
-        close_brace_token,
+/// Checks if the source ends in the middle of an expression, so the repl knows to ask for more lines
+pub fn is_input_complete(source: &str) -> bool {
+    let mut lexer = Lexer::new("input".to_string(), source);
+    let mut depth = 0isize;
+    let mut last_kind = TokenKind::Newline;
+    loop {
+        let token = match lexer.next_token() {
+            Ok(token) => token,
+            // let the parser report the error
+            Err(_) => return true,
+        };
+        match token.kind {
+            TokenKind::EndOfFile => break,
+            TokenKind::Newline => continue,
+            TokenKind::OpenParenthesis | TokenKind::OpenBrace => depth += 1,
+            TokenKind::CloseParenthesis | TokenKind::CloseBrace => depth -= 1,
+            _ => {}
+        }
+        last_kind = token.kind;
+    }
+    let ends_with_operator = matches!(
+        last_kind,
+        TokenKind::Plus
+            | TokenKind::Minus
+            | TokenKind::Asterisk
+            | TokenKind::Slash
+            | TokenKind::ExclamationMark
+            | TokenKind::Ampersand
+            | TokenKind::Pipe
+            | TokenKind::Caret
+            | TokenKind::Tilde
+            | TokenKind::LessThanLessThan
+            | TokenKind::GreaterThanGreaterThan
+            | TokenKind::EqualEqual
+            | TokenKind::ExclamationMarkEqual
+            | TokenKind::LessThan
+            | TokenKind::GreaterThan
+            | TokenKind::LessThanEqual
+            | TokenKind::GreaterThanEqual
+            | TokenKind::AmpersandAmpersand
+            | TokenKind::PipePipe
+            | TokenKind::Equal
+            | TokenKind::Comma
+    );
+    depth <= 0 && !ends_with_operator
+}
+
+/// Takes the next token if it is a name, the context says what the name was for in the error
+fn expect_name(lexer: &mut Lexer, context: &str) -> Result<NameToken, CompileError> {
+    NameToken::try_from(lexer.next_token()?).map_err(|token| CompileError {
+        location: token.location.clone(),
+        end_location: None,
+        kind: ErrorKind::Syntax,
+        id: "expected-name",
+        arguments: vec![
+            TokenKind::Name(String::new()).to_string(),
+            context.to_string(),
+            token.kind.to_string(),
+        ],
+        message: format!(
+            "Expected {} {}, but got {}",
+            TokenKind::Name(String::new()).to_string(),
+            context,
+            token.kind.to_string(),
+        ),
+        notes: vec![],
+        suggestions: vec![],
+    })
+}
+
+fn expect_integer(lexer: &mut Lexer) -> Result<IntegerToken, CompileError> {
+    IntegerToken::try_from(lexer.next_token()?).map_err(|token| CompileError {
+        location: token.location.clone(),
+        end_location: None,
+        kind: ErrorKind::Syntax,
+        id: "expected-integer",
+        arguments: vec![TokenKind::Integer(0).to_string(), token.kind.to_string()],
+        message: format!(
+            "Expected {}, but got {}",
+            TokenKind::Integer(0).to_string(),
+            token.kind.to_string(),
+        ),
+        notes: vec![],
+        suggestions: vec![],
+    })
+}
+
+fn expect_float(lexer: &mut Lexer) -> Result<FloatToken, CompileError> {
+    FloatToken::try_from(lexer.next_token()?).map_err(|token| CompileError {
+        location: token.location.clone(),
+        end_location: None,
+        kind: ErrorKind::Syntax,
+        id: "expected-float",
+        arguments: vec![TokenKind::Float(0.0).to_string(), token.kind.to_string()],
+        message: format!(
+            "Expected {}, but got {}",
+            TokenKind::Float(0.0).to_string(),
+            token.kind.to_string(),
+        ),
+        notes: vec![],
+        suggestions: vec![],
+    })
+}
+
+fn expect_string(lexer: &mut Lexer) -> Result<StringToken, CompileError> {
+    StringToken::try_from(lexer.next_token()?).map_err(|token| CompileError {
+        location: token.location.clone(),
+        end_location: None,
+        kind: ErrorKind::Syntax,
+        id: "expected-string",
+        arguments: vec![
+            TokenKind::String(String::new()).to_string(),
+            token.kind.to_string(),
+        ],
+        message: format!(
+            "Expected {}, but got {}",
+            TokenKind::String(String::new()).to_string(),
+            token.kind.to_string(),
+        ),
+        notes: vec![],
+        suggestions: vec![],
     })
 }
+
+// two expressions on one line only get split up when the second one clearly starts
+// a new expression, anything else is more likely a typo inside the first one
+fn missing_newline_suggestions(token: &Token) -> Vec<Suggestion> {
+    match token.kind {
+        TokenKind::Name(_)
+        | TokenKind::Integer(_)
+        | TokenKind::Float(_)
+        | TokenKind::String(_)
+        | TokenKind::None
+        | TokenKind::OpenBrace
+        | TokenKind::Export
+        | TokenKind::Let
+        | TokenKind::Match
+        | TokenKind::Enum
+        | TokenKind::Freeze
+        | TokenKind::Spawn
+        | TokenKind::Join => vec![Suggestion {
+            location: token.location.clone(),
+            length: 0,
+            replacement: "\n".to_string(),
+            message: format!("Add a newline before {}", token.kind.to_string()),
+        }],
+        _ => vec![],
+    }
+}
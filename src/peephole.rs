@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+
+use crate::{
+    bytecode::{Bytecode, BytecodeValue},
+    common::CancellationToken,
+};
+
+/// Replaces the instruction sequences that show up most in hot code with one fused
+/// instruction each, so the vm dispatches fewer of them
+///
+/// `lines` has the source line of each instruction and is kept in step, a fused instruction
+/// takes the line of the first one it replaces. Sequences that a jump lands in the middle of
+/// are left alone, and every jump target is moved to where its instruction ended up
+///
+/// It stops part way through if `cancellation` is cancelled, the bytecode is only fit to be
+/// thrown away then
+pub fn fuse_superinstructions(
+    bytecode: &mut Vec<Bytecode>,
+    lines: &mut Vec<usize>,
+    cancellation: &CancellationToken,
+) {
+    let targets: HashSet<usize> = bytecode
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Bytecode::Jump(target) | Bytecode::JumpIfZero(target) => Some(*target),
+            _ => None,
+        })
+        .collect();
+
+    let old_bytecode = std::mem::take(bytecode);
+    let old_lines = std::mem::take(lines);
+    // the new index of every old instruction, plus one past the end for jumps to the end
+    let mut new_indices = Vec::with_capacity(old_bytecode.len() + 1);
+    let mut index = 0;
+    while index < old_bytecode.len() {
+        if cancellation.is_cancelled() {
+            return;
+        }
+        let (instruction, length) = match fuse(&old_bytecode[index..]) {
+            Some((instruction, length))
+                if (index + 1..index + length).all(|inside| !targets.contains(&inside)) =>
+            {
+                (instruction, length)
+            }
+            _ => (fuse_procedure(&old_bytecode[index], cancellation), 1),
+        };
+        for _ in 0..length {
+            new_indices.push(bytecode.len());
+        }
+        bytecode.push(instruction);
+        if let Some(&line) = old_lines.get(index) {
+            lines.push(line);
+        }
+        index += length;
+    }
+    new_indices.push(bytecode.len());
+
+    for instruction in bytecode.iter_mut() {
+        if let Bytecode::Jump(target) | Bytecode::JumpIfZero(target) = instruction {
+            *target = new_indices[*target];
+        }
+    }
+}
+
+// the fused instruction that the start of `bytecode` can be replaced with, and how many
+// instructions it replaces
+fn fuse(bytecode: &[Bytecode]) -> Option<(Bytecode, usize)> {
+    Some(match bytecode {
+        [Bytecode::Load(a), Bytecode::Load(b), Bytecode::AddInteger, ..] => {
+            (Bytecode::AddLoadsInteger(a.clone(), b.clone()), 3)
+        }
+        [Bytecode::Push(BytecodeValue::Integer(constant)), Bytecode::AddInteger, ..] => {
+            (Bytecode::AddConstInteger(*constant), 2)
+        }
+        [Bytecode::Dup, Bytecode::Store(name), ..] => (Bytecode::DupStore(name.clone()), 2),
+        _ => return None,
+    })
+}
+
+// procedures have their own jump targets, so they are fused on their own
+fn fuse_procedure(instruction: &Bytecode, cancellation: &CancellationToken) -> Bytecode {
+    match instruction {
+        Bytecode::Push(BytecodeValue::Procedure(procedure)) => {
+            let mut procedure = procedure.to_vec();
+            let mut lines = vec![];
+            fuse_superinstructions(&mut procedure, &mut lines, cancellation);
+            Bytecode::Push(BytecodeValue::Procedure(procedure.into()))
+        }
+        instruction => instruction.clone(),
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt::Write;
+
+use crate::{
+    ast::AstTrait,
+    binding::{bind_ast_with_options, BindOptions},
+    bytecode::Bytecode,
+    bytecode_compilation::{compile_optimized_bytecode_with_cancellation, OptimizationLevel},
+    bytecode_text::format_bytecode,
+    cache::FrontEndCache,
+    common::{CancellationToken, Diagnostics},
+    emit::Stage,
+    ice::{catch_internal_errors, track, Phase},
+    lint::Linter,
+    parsing::DEFAULT_MAX_NESTING_DEPTH,
+    pragma::parse_attributes,
+    program::{Prelude, Program},
+    token::Token,
+};
+
+/// Bounds on what the compiler accepts, so adversarial input gets an error instead of
+/// using up the stack
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// How deeply expressions can be nested, for both the parser and the binder. A chain like
+    /// `1 + 2 + 3` is not nested, but its tree can only be four times this deep
+    pub max_nesting_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
+/// Every option of compilation in one place, for the command line and for embedders,
+/// `Program::compile` is the same as `Pipeline::new().compile`
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    prelude: Prelude,
+    optimizations: OptimizationLevel,
+    limits: Limits,
+    cancellation: CancellationToken,
+    cache: Option<FrontEndCache>,
+    stages: Vec<Stage>,
+}
+
+/// What `Pipeline::run` got done
+pub struct Compilation {
+    /// The text of each stage given to `Pipeline::emit` that compilation got to, in the
+    /// order the stages run
+    pub emitted: Vec<(Stage, String)>,
+    /// The errors that stopped compilation, otherwise the program if compilation went as far
+    /// as the bytecode
+    pub result: Result<Option<Program>, Diagnostics>,
+}
+
+impl Compilation {
+    pub fn get_emitted(&self, stage: Stage) -> Option<&str> {
+        self.emitted
+            .iter()
+            .find(|(emitted_stage, _)| *emitted_stage == stage)
+            .map(|(_, text)| text as &str)
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    pub fn with_optimizations(mut self, optimizations: OptimizationLevel) -> Pipeline {
+        self.optimizations = optimizations;
+        self
+    }
+
+    /// The builtins the program can see
+    pub fn with_prelude(mut self, prelude: Prelude) -> Pipeline {
+        self.prelude = prelude;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Pipeline {
+        self.limits = limits;
+        self
+    }
+
+    /// Compilation gives up with an error once `cancellation` is cancelled
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Pipeline {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Reuses the tokens and ast from `cache` when the same file was compiled before with the
+    /// same source
+    pub fn with_cache(mut self, cache: FrontEndCache) -> Pipeline {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Also writes out `stage` when the pipeline runs
+    pub fn emit(mut self, stage: Stage) -> Pipeline {
+        if !self.stages.contains(&stage) {
+            self.stages.push(stage);
+        }
+        self
+    }
+
+    /// Compiles the file all the way to bytecode, reporting every binding error rather than
+    /// just the first
+    pub fn compile(&self, filepath: String, source: &str) -> Result<Program, Diagnostics> {
+        let mut emitted = vec![];
+        let program = self.run_until(Stage::Bytecode, filepath, source, &mut emitted)?;
+        Ok(program.expect("compilation should go as far as the bytecode"))
+    }
+
+    /// Compiles the file as far as the last stage given to `emit` needs, or all the way if
+    /// there are none
+    pub fn run(&self, filepath: String, source: &str) -> Compilation {
+        let last_stage = self.stages.iter().max().copied().unwrap_or(Stage::Bytecode);
+        let mut emitted = vec![];
+        let result = self.run_until(last_stage, filepath, source, &mut emitted);
+        Compilation { emitted, result }
+    }
+
+    // a panic of the compiler comes back as an internal compiler error instead of unwinding
+    // out of the pipeline
+    fn run_until(
+        &self,
+        last_stage: Stage,
+        filepath: String,
+        source: &str,
+        emitted: &mut Vec<(Stage, String)>,
+    ) -> Result<Option<Program>, Diagnostics> {
+        // the minimized inputs are not worth caching or emitting
+        let rerun = Pipeline {
+            cache: None,
+            stages: vec![],
+            ..self.clone()
+        };
+        catch_internal_errors(
+            source,
+            || self.run_stages(last_stage, filepath.clone(), source, emitted),
+            |source| {
+                let _ = rerun.run_stages(last_stage, filepath.clone(), source, &mut vec![]);
+            },
+        )
+        .unwrap_or_else(|error| Err(Diagnostics::from(error.to_compile_error(&filepath))))
+    }
+
+    fn run_stages(
+        &self,
+        last_stage: Stage,
+        filepath: String,
+        source: &str,
+        emitted: &mut Vec<(Stage, String)>,
+    ) -> Result<Option<Program>, Diagnostics> {
+        // without a cache to share this one is just thrown away
+        let cache = self.cache.clone().unwrap_or_default();
+        if self.stages.contains(&Stage::Tokens) {
+            let tokens = cache.lex(&filepath, source, &self.cancellation)?;
+            emitted.push((Stage::Tokens, format_tokens(&tokens)));
+        }
+        if last_stage == Stage::Tokens {
+            return Ok(None);
+        }
+
+        let file = cache.parse(
+            &filepath,
+            source,
+            self.limits.max_nesting_depth,
+            &self.cancellation,
+        )?;
+        if self.stages.contains(&Stage::Ast) {
+            emitted.push((Stage::Ast, format!("{:#?}\n", file.unwrap_file())));
+        }
+        if last_stage == Stage::Ast {
+            return Ok(None);
+        }
+
+        self.cancellation.check_compile(&file.get_location())?;
+        let attributes = parse_attributes(&filepath, source)?;
+        let mut diagnostics = Diagnostics::new();
+        let bound_file = bind_ast_with_options(
+            &file,
+            &mut self.prelude.names(),
+            &mut diagnostics,
+            &BindOptions {
+                max_depth: self.limits.max_nesting_depth,
+                allow_shadowing: attributes.shadowing,
+                cancellation: Some(self.cancellation.clone()),
+            },
+        );
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        if attributes.strict {
+            // a strict file has no warnings, everything a lint finds is an error
+            let _context = track(Phase::Linting, file.get_location());
+            let report = Linter::new().check(&bound_file, source);
+            let mut errors = report.errors;
+            for warning in report.warnings {
+                errors.push(warning);
+            }
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+        }
+        if self.stages.contains(&Stage::Ir) {
+            emitted.push((Stage::Ir, format!("{:#?}\n", bound_file)));
+        }
+        if last_stage == Stage::Ir {
+            return Ok(None);
+        }
+
+        self.cancellation.check_compile(&file.get_location())?;
+        let mut bytecode = vec![];
+        self.prelude.compile(&mut bytecode);
+        let mut lines = vec![0; bytecode.len()];
+        compile_optimized_bytecode_with_cancellation(
+            &bound_file,
+            &mut bytecode,
+            &mut lines,
+            self.optimizations,
+            &self.cancellation,
+        )?;
+        // the exports of the file stay in the variables, so its block value is not needed
+        bytecode.push(Bytecode::Pop);
+        bytecode.push(Bytecode::Exit);
+        lines.extend([0, 0]);
+        if self.stages.contains(&Stage::Bytecode) {
+            emitted.push((Stage::Bytecode, format_bytecode(&bytecode)));
+        }
+
+        Ok(Some(Program::new(
+            self.prelude.clone(),
+            bound_file,
+            bytecode,
+            lines,
+        )))
+    }
+}
+
+fn format_tokens(tokens: &[Token]) -> String {
+    let mut result = String::new();
+    for token in tokens {
+        writeln!(
+            result,
+            "{}:{}: {:?}",
+            token.location.line, token.location.column, token.kind,
+        )
+        .unwrap();
+    }
+    result
+}
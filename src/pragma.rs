@@ -0,0 +1,156 @@
+use crate::{
+    common::{CompileError, Diagnostics, ErrorKind, SourceLocation},
+    lint::Severity,
+};
+
+/// Which version of the language a file is written in, so later ones can change things
+/// without breaking older files
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Edition {
+    #[default]
+    Edition2026,
+}
+
+impl Edition {
+    pub const ALL: [Edition; 1] = [Edition::Edition2026];
+
+    pub fn from_name(name: &str) -> Option<Edition> {
+        Edition::ALL
+            .into_iter()
+            .find(|edition| edition.get_name() == name)
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Edition::Edition2026 => "2026",
+        }
+    }
+}
+
+/// The settings a file gives itself with `//!` pragma comments at its top, before the first
+/// line of code:
+/// - `allow(name, ...)`, `warn(name, ...)` and `deny(name, ...)` set the severity of lints
+/// - `strict` makes every lint that is not allowed stop compilation
+/// - `edition(2026)` picks the edition
+/// - `shadowing` lets a `let` reuse a name from an outer scope, only for the rest of its scope
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileAttributes {
+    /// Every lint named by a pragma with its severity, a later pragma wins over an earlier one
+    pub lint_severities: Vec<(String, Severity, SourceLocation)>,
+    pub strict: bool,
+    pub edition: Edition,
+    pub shadowing: bool,
+}
+
+/// Reads the pragmas at the top of the file, with an error for each one that is not understood
+pub fn parse_attributes(filepath: &str, source: &str) -> Result<FileAttributes, Diagnostics> {
+    let mut attributes = FileAttributes::default();
+    let mut diagnostics = Diagnostics::new();
+    let mut position = 0;
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let line_start = position;
+        position += line.len();
+        let code = line.trim_start();
+        let Some(pragma) = code.strip_prefix("//!") else {
+            if code.trim_end().is_empty() || code.starts_with("//") {
+                continue;
+            }
+            break;
+        };
+
+        let start =
+            line_start + (line.len() - code.len()) + "//!".len() + leading_whitespace(pragma);
+        let pragma = pragma.trim();
+        let location = |offset: usize| SourceLocation {
+            filepath: filepath.to_string(),
+            position: start + offset,
+            line: index + 1,
+            column: source[..start + offset]
+                .rsplit(['\n', '\r'])
+                .next()
+                .unwrap()
+                .chars()
+                .count()
+                + 1,
+            synthetic: None,
+        };
+        let mut error =
+            |offset: usize, id: &'static str, arguments: Vec<String>, message: String| {
+                diagnostics.push(CompileError {
+                    location: location(offset),
+                    end_location: None,
+                    kind: ErrorKind::InvalidPragma,
+                    id,
+                    arguments,
+                    message,
+                    notes: vec![],
+                    suggestions: vec![],
+                })
+            };
+
+        // the directive and, for the ones written like a call, the offset and text of what is
+        // inside the parentheses
+        let (directive, arguments) = match pragma.split_once('(') {
+            Some((directive, rest)) => {
+                let Some(arguments) = rest.strip_suffix(')') else {
+                    error(
+                        pragma.len(),
+                        "unclosed-pragma",
+                        vec![],
+                        "Expected ) at the end of the pragma".to_string(),
+                    );
+                    continue;
+                };
+                (directive.trim_end(), Some((directive.len() + 1, arguments)))
+            }
+            None => (pragma, None),
+        };
+        match (directive, arguments) {
+            ("strict", None) => attributes.strict = true,
+            ("shadowing", None) => attributes.shadowing = true,
+            ("edition", Some((offset, edition))) => match Edition::from_name(edition.trim()) {
+                Some(edition) => attributes.edition = edition,
+                None => {
+                    let editions = Edition::ALL.map(Edition::get_name).join(", ");
+                    error(
+                        offset + leading_whitespace(edition),
+                        "unknown-edition",
+                        vec![edition.trim().to_string(), editions.clone()],
+                        format!(
+                            "Unknown edition {}, the editions are {}",
+                            edition.trim(),
+                            editions
+                        ),
+                    )
+                }
+            },
+            (directive, Some((mut offset, names))) if Severity::from_name(directive).is_some() => {
+                let severity = Severity::from_name(directive).unwrap();
+                for name in names.split(',') {
+                    attributes.lint_severities.push((
+                        name.trim().to_string(),
+                        severity,
+                        location(offset + leading_whitespace(name)),
+                    ));
+                    offset += name.len() + 1;
+                }
+            }
+            _ => error(
+                0,
+                "unknown-pragma",
+                vec![pragma.to_string()],
+                format!("Unknown pragma {}", pragma),
+            ),
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(attributes)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn leading_whitespace(text: &str) -> usize {
+    text.len() - text.trim_start().len()
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+use crate::{
+    bound_nodes::{BoundBlock, BoundNative, BoundNode, BoundNodeTrait},
+    bytecode::{Bytecode, NativeId, VmValue},
+    bytecode_compilation::compile_bytecode,
+    common::{CancellationToken, Diagnostics, RuntimeError, SourceLocation},
+    execute::{Vars, Vm},
+    natives::{namespaces, NATIVES},
+    pipeline::Pipeline,
+    types::{BlockType, ProcType, Type},
+    value::Value,
+};
+
+/// The builtins every program can see, each namespace of natives is a block like `io`
+#[derive(Clone)]
+pub struct Prelude {
+    pub builtins: Vec<(String, Arc<BoundNode>)>,
+}
+
+impl Prelude {
+    pub fn new() -> Prelude {
+        let location = SourceLocation::synthetic("builtin", None);
+        let mut builtins = vec![];
+        for namespace in namespaces() {
+            // the members are the expressions of the block, which keeps them alive
+            let mut expressions = vec![];
+            let mut exported_expressions = HashMap::new();
+            let mut exported_types = HashMap::new();
+            for (i, native) in NATIVES.iter().enumerate() {
+                if native.namespace != namespace {
+                    continue;
+                }
+                let node = Arc::new(BoundNode::Native(BoundNative {
+                    location: location.clone(),
+                    id: NativeId(i),
+                }));
+                exported_expressions.insert(native.name.to_string(), Arc::downgrade(&node));
+                exported_types.insert(native.name.to_string(), Type::Proc(native.get_type()));
+                expressions.push(node);
+            }
+            let block = BoundNode::Block(BoundBlock {
+                location: location.clone(),
+                expressions,
+                exported_expressions,
+                block_type: Type::Block(BlockType { exported_types }),
+                evaluates_to_last: false,
+            });
+            builtins.push((namespace.to_string(), Arc::new(block)));
+        }
+        Prelude { builtins }
+    }
+
+    pub fn names(&self) -> HashMap<String, Weak<BoundNode>> {
+        self.builtins
+            .iter()
+            .map(|(name, node)| (name.clone(), Arc::downgrade(node)))
+            .collect()
+    }
+
+    pub fn compile(&self, bytecode: &mut Vec<Bytecode>) {
+        for (name, node) in &self.builtins {
+            match node as &BoundNode {
+                // a namespace has nothing to run, it is just its members put into a block
+                BoundNode::Block(block) => {
+                    let mut names: Vec<_> = block.exported_expressions.keys().cloned().collect();
+                    names.sort();
+                    for member in &names {
+                        let member = block.exported_expressions[member].upgrade().unwrap();
+                        compile_bytecode(&member, bytecode);
+                    }
+                    bytecode.push(Bytecode::MakeBlock(names));
+                }
+                _ => compile_bytecode(node, bytecode),
+            }
+            bytecode.push(Bytecode::Store(name.clone()));
+        }
+    }
+}
+
+impl Default for Prelude {
+    fn default() -> Self {
+        Prelude::new()
+    }
+}
+
+/// A compiled file together with the VM state from running it
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReloadError {
+    Compile(Diagnostics),
+    Runtime(RuntimeError),
+}
+
+impl From<Diagnostics> for ReloadError {
+    fn from(diagnostics: Diagnostics) -> Self {
+        ReloadError::Compile(diagnostics)
+    }
+}
+
+impl From<RuntimeError> for ReloadError {
+    fn from(error: RuntimeError) -> Self {
+        ReloadError::Runtime(error)
+    }
+}
+
+pub struct Program {
+    prelude: Prelude,
+    bound_file: Arc<BoundNode>,
+    bytecode: Vec<Bytecode>,
+    lines: Vec<usize>,
+    vars: Vars,
+}
+
+impl Program {
+    /// Compiles the file, reporting every binding error rather than just the first
+    pub fn compile(filepath: String, source: &str) -> Result<Program, Diagnostics> {
+        Pipeline::new().compile(filepath, source)
+    }
+
+    /// Compiles the file, giving up with an error if `cancellation` is cancelled part way through
+    pub fn compile_with_cancellation(
+        filepath: String,
+        source: &str,
+        cancellation: &CancellationToken,
+    ) -> Result<Program, Diagnostics> {
+        Pipeline::new()
+            .with_cancellation(cancellation.clone())
+            .compile(filepath, source)
+    }
+
+    /// A program that has not been run yet, `Pipeline` is what makes these
+    pub(crate) fn new(
+        prelude: Prelude,
+        bound_file: Arc<BoundNode>,
+        bytecode: Vec<Bytecode>,
+        lines: Vec<usize>,
+    ) -> Program {
+        Program {
+            prelude,
+            bound_file,
+            bytecode,
+            lines,
+            vars: HashMap::new(),
+        }
+    }
+
+    pub fn get_bound_file(&self) -> &Arc<BoundNode> {
+        &self.bound_file
+    }
+
+    pub fn get_bytecode(&self) -> &[Bytecode] {
+        &self.bytecode
+    }
+
+    /// The source line of each instruction of `get_bytecode`, 0 for the ones the source didn't make
+    pub fn get_lines(&self) -> &[usize] {
+        &self.lines
+    }
+
+    /// Runs the top level of the file, the exported values stay alive for `get_proc`
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        self.run_with(&mut Vm::new())
+    }
+
+    pub fn run_with(&mut self, vm: &mut Vm) -> Result<(), RuntimeError> {
+        self.vars.clear();
+        vm.execute(&self.bytecode, Vec::new(), &mut self.vars)?;
+        Ok(())
+    }
+
+    /// Replaces the program with a new version of its source, keeping the values of the
+    /// top level bindings that still exist with the same type
+    ///
+    /// The new top level is run so procedures get their new values, then every other kept
+    /// binding gets its old value back. Nothing changes if the new source fails, otherwise
+    /// the names that kept their values are returned
+    pub fn reload(&mut self, new_source: &str) -> Result<Vec<String>, ReloadError> {
+        self.reload_with(&mut Vm::new(), new_source)
+    }
+
+    pub fn reload_with(
+        &mut self,
+        vm: &mut Vm,
+        new_source: &str,
+    ) -> Result<Vec<String>, ReloadError> {
+        let filepath = self.bound_file.get_location().filepath;
+        let mut new_program = Program::compile(filepath, new_source)?;
+        new_program.run_with(vm)?;
+
+        let old_types = self.top_level_types();
+        let mut kept = vec![];
+        for (name, typ) in new_program.top_level_types() {
+            if matches!(typ, Type::Proc(_))
+                || typ.holds_handle()
+                || old_types.get(&name) != Some(&typ)
+            {
+                continue;
+            }
+            if let Some(value) = self.vars.get(&name) {
+                new_program.vars.insert(name.clone(), value.clone());
+                kept.push(name);
+            }
+        }
+        kept.sort();
+        *self = new_program;
+        Ok(kept)
+    }
+
+    fn top_level_types(&self) -> HashMap<String, Type> {
+        let mut types = HashMap::new();
+        for expression in &self.bound_file.unwrap_block().expressions {
+            match expression as &BoundNode {
+                BoundNode::Let(lett) => types.insert(lett.name.clone(), lett.get_type()),
+                BoundNode::Export(export) => types.insert(export.name.clone(), export.get_type()),
+                BoundNode::Destructure(destructure) => {
+                    for binding in &destructure.bindings {
+                        let lett = binding.unwrap_let();
+                        types.insert(lett.name.clone(), lett.get_type());
+                    }
+                    None
+                }
+                _ => None,
+            };
+        }
+        types
+    }
+
+    /// The names of every exported procedure
+    pub fn get_proc_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        if let Type::Block(block_type) = self.bound_file.get_type() {
+            for (name, typ) in &block_type.exported_types {
+                if let Type::Proc(_) = typ {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names.sort();
+        names
+    }
+
+    pub fn get_export(&self, name: &str) -> Option<Value> {
+        self.exported_type(name)?;
+        self.vars.get(name).map(Value::from)
+    }
+
+    /// Gets a handle to an exported procedure, `run` must have been called first
+    pub fn get_proc(&self, name: &str) -> Option<ProcHandle> {
+        let proc_type = if let Type::Proc(proc_type) = self.exported_type(name)? {
+            proc_type
+        } else {
+            return None;
+        };
+        Some(ProcHandle {
+            name: name.to_string(),
+            proc_type,
+            procedure: self.vars.get(name)?.clone(),
+        })
+    }
+
+    fn exported_type(&self, name: &str) -> Option<Type> {
+        if let Type::Block(block_type) = self.bound_file.get_type() {
+            block_type.exported_types.get(name).cloned()
+        } else {
+            None
+        }
+    }
+}
+
+/// An exported procedure that can be called from Rust many times
+#[derive(Debug, Clone)]
+pub struct ProcHandle {
+    name: String,
+    proc_type: ProcType,
+    procedure: VmValue,
+}
+
+impl ProcHandle {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_type(&self) -> &ProcType {
+        &self.proc_type
+    }
+
+    pub fn call(&self, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        self.call_with(&mut Vm::new(), arguments)
+    }
+
+    pub fn call_with(&self, vm: &mut Vm, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        if arguments.len() != self.proc_type.parameter_types.len() {
+            return Err(RuntimeError {
+                message: format!(
+                    "Invalid number of arguments for {}, expected {} arguments but got {}",
+                    self.name,
+                    self.proc_type.parameter_types.len(),
+                    arguments.len(),
+                ),
+            });
+        }
+
+        let mut stack = vec![];
+        for (i, argument) in arguments.iter().enumerate() {
+            if !has_type(argument, &self.proc_type.parameter_types[i]) {
+                return Err(RuntimeError {
+                    message: format!(
+                        "Wrong argument type for {}, expected type {:?} but got {}",
+                        self.name,
+                        self.proc_type.parameter_types[i],
+                        argument.type_name(),
+                    ),
+                });
+            }
+            stack.push(argument.clone().into());
+        }
+
+        let result = vm.call(&self.procedure, stack)?;
+        Ok(result.map_or(Value::Void, |value| Value::from(&value)))
+    }
+}
+
+// only the kind of value is checked, the vm checks the rest as it is used
+fn has_type(value: &Value, value_type: &Type) -> bool {
+    match (value_type, value) {
+        (Type::Optional(_), Value::None) => true,
+        (Type::Optional(inner), value) => has_type(value, inner),
+        // a variant is its index, so it has to be the index of one of the variants
+        (Type::Enum(enum_type), Value::Integer(index)) => {
+            usize::try_from(*index).is_ok_and(|index| index < enum_type.variants.len())
+        }
+        _ => matches!(
+            (value_type, value),
+            (Type::Integer, Value::Integer(_))
+                | (Type::Float, Value::Float(_))
+                | (Type::String, Value::String(_))
+                | (Type::Void, Value::Void)
+                | (Type::Proc(_), Value::Procedure(_))
+                | (Type::Block(_), Value::Block(_))
+        ),
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::{BufRead, Write};
+
+#[cfg(feature = "native")]
+use crate::line_editor::Terminal;
+use crate::{
+    common::write_compile_error,
+    line_editor::EditResult,
+    parsing::is_input_complete,
+    session::{EvalError, Session, Snapshot},
+    value::Value,
+};
+
+const REPL_FILEPATH: &str = "<repl>";
+
+/// Where `:load`, `:save` and `:restore` read and write files, so the repl can also run where
+/// there is no file system, like in a browser
+pub trait Files {
+    fn read(&self, path: &str) -> Result<String, std::io::Error>;
+    fn write(&self, path: &str, contents: &str) -> Result<(), std::io::Error>;
+}
+
+/// The files of the operating system
+#[cfg(feature = "native")]
+pub struct NativeFiles;
+
+#[cfg(feature = "native")]
+impl Files for NativeFiles {
+    fn read(&self, path: &str) -> Result<String, std::io::Error> {
+        std::fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &str, contents: &str) -> Result<(), std::io::Error> {
+        std::fs::write(path, contents)
+    }
+}
+
+/// No files at all, every read and write fails
+pub struct NoFiles;
+
+impl Files for NoFiles {
+    fn read(&self, _path: &str) -> Result<String, std::io::Error> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    fn write(&self, _path: &str, _contents: &str) -> Result<(), std::io::Error> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+fn print_help(output: &mut dyn Write) -> Result<(), std::io::Error> {
+    writeln!(output, "Commands:")?;
+    writeln!(output, "    :help: Prints this message")?;
+    writeln!(
+        output,
+        "    :type <expr>: Prints the type of the expression"
+    )?;
+    writeln!(
+        output,
+        "    :dump_ir <expr>: Dumps the ir of the expression"
+    )?;
+    writeln!(output, "    :reset: Forgets every definition")?;
+    writeln!(
+        output,
+        "    :load <file>: Runs a file in the current session"
+    )?;
+    writeln!(output, "    :save <file>: Saves the variables to a file")?;
+    writeln!(
+        output,
+        "    :restore <file>: Restores the variables from a file"
+    )?;
+    writeln!(output, "    :quit: Exits the repl")?;
+    Ok(())
+}
+
+fn write_eval_error(output: &mut dyn Write, error: &EvalError) -> Result<(), std::io::Error> {
+    match error {
+        EvalError::Compile(error) => write_compile_error(output, error),
+        EvalError::Runtime(error) => writeln!(output, "Runtime Error: {}", error.message),
+    }
+}
+
+fn eval_and_print(
+    session: &mut Session,
+    filepath: String,
+    source: &str,
+    output: &mut dyn Write,
+) -> Result<(), std::io::Error> {
+    match session.eval(filepath, source) {
+        Ok(Value::Void) => Ok(()),
+        Ok(value) => writeln!(output, "{}", value),
+        Err(error) => write_eval_error(output, &error),
+    }
+}
+
+/// Returns false when the repl should exit
+fn run_command(
+    session: &mut Session,
+    command: &str,
+    files: &dyn Files,
+    output: &mut dyn Write,
+) -> Result<bool, std::io::Error> {
+    let (name, argument) = command
+        .split_once(char::is_whitespace)
+        .map_or((command, ""), |(name, argument)| (name, argument.trim()));
+    match name {
+        ":help" => print_help(output)?,
+
+        ":quit" | ":exit" => return Ok(false),
+
+        ":type" => match session.type_of(REPL_FILEPATH.to_string(), argument) {
+            Ok(typ) => writeln!(output, "{:?}", typ)?,
+            Err(error) => write_compile_error(output, &error)?,
+        },
+
+        ":dump_ir" => match session.bind(REPL_FILEPATH.to_string(), argument) {
+            Ok(expressions) => {
+                for expression in expressions {
+                    writeln!(output, "{:#?}", expression)?;
+                }
+            }
+            Err(error) => write_compile_error(output, &error)?,
+        },
+
+        ":reset" => *session = Session::new(),
+
+        ":load" => match files.read(argument) {
+            Ok(source) => eval_and_print(session, argument.to_string(), &source, output)?,
+            Err(_) => writeln!(output, "Unable to open file: '{}'", argument)?,
+        },
+
+        ":save" => {
+            if files
+                .write(argument, &session.snapshot().serialize())
+                .is_err()
+            {
+                writeln!(output, "Unable to write file: '{}'", argument)?;
+            }
+        }
+
+        ":restore" => match files.read(argument) {
+            Ok(text) => match Snapshot::deserialize(&text) {
+                Ok(snapshot) => session.restore(&snapshot),
+                Err(error) => writeln!(
+                    output,
+                    "{}:{}: Snapshot Error: {}",
+                    argument, error.line, error.message
+                )?,
+            },
+            Err(_) => writeln!(output, "Unable to open file: '{}'", argument)?,
+        },
+
+        _ => {
+            writeln!(output, "Unknown command: '{}'", name)?;
+            print_help(output)?;
+        }
+    }
+    Ok(true)
+}
+
+type ReadLine<'a> =
+    dyn FnMut(&str, &Session, &mut dyn Write) -> Result<EditResult, std::io::Error> + 'a;
+
+fn repl_loop(
+    read_line: &mut ReadLine,
+    files: &dyn Files,
+    output: &mut dyn Write,
+) -> Result<(), std::io::Error> {
+    let mut session = Session::new();
+    let mut source = String::new();
+    loop {
+        let prompt = if source.is_empty() { "> " } else { "... " };
+        let line = match read_line(prompt, &session, output)? {
+            EditResult::Submit(line) => line,
+            EditResult::Interrupt => {
+                source.clear();
+                continue;
+            }
+            _ => return Ok(()),
+        };
+
+        if source.is_empty() && line.trim_start().starts_with(':') {
+            if !run_command(&mut session, line.trim(), files, output)? {
+                return Ok(());
+            }
+            continue;
+        }
+
+        source += &line;
+        source.push('\n');
+        if !is_input_complete(&source) {
+            continue;
+        }
+        eval_and_print(&mut session, REPL_FILEPATH.to_string(), &source, output)?;
+        source.clear();
+    }
+}
+
+/// Runs the repl on plain streams, used when the input is not a terminal
+#[cfg(feature = "native")]
+pub fn run_repl(input: &mut dyn BufRead, output: &mut dyn Write) -> Result<(), std::io::Error> {
+    run_repl_with_files(input, output, &NativeFiles)
+}
+
+/// `run_repl` with the files of `files` instead of the ones of the operating system
+pub fn run_repl_with_files(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    files: &dyn Files,
+) -> Result<(), std::io::Error> {
+    repl_loop(
+        &mut |prompt, _, output| {
+            write!(output, "{}", prompt)?;
+            output.flush()?;
+            let mut line = String::new();
+            Ok(if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                EditResult::EndOfFile
+            } else {
+                EditResult::Submit(line.trim_end_matches(['\n', '\r']).to_string())
+            })
+        },
+        files,
+        output,
+    )
+}
+
+/// Runs the repl with line editing, history, and tab completion of the names in scope
+#[cfg(feature = "native")]
+pub fn run_terminal_repl() -> Result<(), std::io::Error> {
+    let mut terminal = Terminal::new();
+    repl_loop(
+        &mut |prompt, session, _| {
+            terminal.read_line(prompt, &|prefix| {
+                session
+                    .get_names()
+                    .keys()
+                    .filter(|name| name.starts_with(prefix))
+                    .cloned()
+                    .collect()
+            })
+        },
+        &NativeFiles,
+        &mut std::io::stdout(),
+    )
+}
@@ -0,0 +1,285 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    ast::AstTrait,
+    common::{ColumnPolicy, ColumnUnit, CompileError, Diagnostics, ErrorKind, SourceLocation},
+    execute::Vm,
+    json::{parse_json, Json},
+    lexer::Lexer,
+    parsing::parse_file,
+    program::Program,
+    session::Session,
+    token::TokenKind,
+};
+
+const DEFAULT_FILEPATH: &str = "<serve>";
+
+// the error codes json-rpc gives to problems with the request itself
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+/// The methods `serve` answers, each takes `source` and an optional `filepath` in its params,
+/// and an optional `column_unit` of `chars`, `utf8` or `utf16` for the columns of diagnostics
+pub const METHODS: &[(&str, &str)] = &[
+    ("compile", "Checks the source and gives back its errors"),
+    (
+        "run",
+        "Runs the source and gives back what it printed and any error, an optional fuel stops it after that many instructions",
+    ),
+    ("type", "Gives the type of the last expression in the source"),
+    ("format", "Gives the source back pretty printed"),
+];
+
+/// Answers json-rpc 2.0 requests, one per line of `input`, with one line of `output` each
+/// until `input` ends, requests without an id are notifications and get no answer
+///
+/// Errors in the source are not json-rpc errors, they come back in the result as a
+/// `diagnostics` array so a request that compiled nothing still succeeded
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> Result<(), std::io::Error> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_request(&line) {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+    }
+}
+
+/// The response to one request, `None` for a notification
+///
+/// It only needs the text of the request, so it works without the `native` feature and a
+/// browser playground can call it directly
+pub fn handle_request(text: &str) -> Option<Json> {
+    let request = match parse_json(text) {
+        Ok(request) => request,
+        Err(error) => {
+            return Some(error_response(
+                Json::Null,
+                PARSE_ERROR,
+                format!("{} at byte {}", error.message, error.position),
+            ))
+        }
+    };
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(Json::as_str) else {
+        return Some(error_response(
+            id.unwrap_or(Json::Null),
+            INVALID_REQUEST,
+            "Expected a method".to_string(),
+        ));
+    };
+    let result = call_method(method, request.get("params").unwrap_or(&Json::Null));
+    let id = id?;
+    Some(match result {
+        Ok(result) => Json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)]),
+        Err((code, message)) => error_response(id, code, message),
+    })
+}
+
+fn error_response(id: Json, code: i32, message: String) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([
+                ("code", Json::Number(code as f64)),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+fn call_method(method: &str, params: &Json) -> Result<Json, (i32, String)> {
+    if !METHODS.iter().any(|&(name, _)| name == method) {
+        return Err((METHOD_NOT_FOUND, format!("Unknown method {}", method)));
+    }
+    let source = params
+        .get("source")
+        .and_then(Json::as_str)
+        .ok_or_else(|| (INVALID_PARAMS, "Expected a string source".to_string()))?;
+    let filepath = match params.get("filepath") {
+        None => DEFAULT_FILEPATH.to_string(),
+        Some(Json::String(filepath)) => filepath.clone(),
+        Some(_) => return Err((INVALID_PARAMS, "Expected a string filepath".to_string())),
+    };
+    let mut columns = ColumnPolicy::default();
+    if let Some(unit) = params.get("column_unit") {
+        columns.unit = unit
+            .as_str()
+            .and_then(ColumnUnit::from_name)
+            .ok_or_else(|| {
+                (
+                    INVALID_PARAMS,
+                    "Expected a column_unit of chars, utf8 or utf16".to_string(),
+                )
+            })?;
+    }
+
+    Ok(match method {
+        "compile" => {
+            let diagnostics = Program::compile(filepath, source).err().unwrap_or_default();
+            Json::object([(
+                "diagnostics",
+                diagnostics_to_json(&diagnostics.errors, &columns, source),
+            )])
+        }
+
+        "run" => {
+            let fuel = match params.get("fuel") {
+                None => None,
+                Some(fuel) => Some(
+                    fuel.as_f64()
+                        .filter(|fuel| *fuel >= 0.0 && fuel.fract() == 0.0)
+                        .ok_or_else(|| {
+                            (
+                                INVALID_PARAMS,
+                                "Expected a whole number of fuel".to_string(),
+                            )
+                        })? as u64,
+                ),
+            };
+            match Program::compile(filepath, source) {
+                Ok(mut program) => {
+                    let mut vm = Vm::new();
+                    vm.captured_output = Some(String::new());
+                    vm.fuel = fuel;
+                    let error = program.run_with(&mut vm).err().map(|error| error.message);
+                    Json::object([
+                        ("diagnostics", Json::Array(vec![])),
+                        ("output", vm.captured_output.unwrap_or_default().into()),
+                        ("error", error.into()),
+                    ])
+                }
+                Err(diagnostics) => Json::object([
+                    (
+                        "diagnostics",
+                        diagnostics_to_json(&diagnostics.errors, &columns, source),
+                    ),
+                    ("output", "".into()),
+                    ("error", Json::Null),
+                ]),
+            }
+        }
+
+        "type" => match Session::new().type_of(filepath, source) {
+            Ok(typ) => Json::object([
+                ("diagnostics", Json::Array(vec![])),
+                ("type", typ.to_string().into()),
+            ]),
+            Err(error) => Json::object([
+                (
+                    "diagnostics",
+                    diagnostics_to_json(&[error], &columns, source),
+                ),
+                ("type", Json::Null),
+            ]),
+        },
+
+        "format" => match format_source(filepath, source) {
+            Ok(formatted) => Json::object([
+                ("diagnostics", Json::Array(vec![])),
+                ("source", formatted.into()),
+            ]),
+            Err(diagnostics) => Json::object([
+                (
+                    "diagnostics",
+                    diagnostics_to_json(&diagnostics.errors, &columns, source),
+                ),
+                ("source", Json::Null),
+            ]),
+        },
+
+        _ => unreachable!("every method in METHODS is handled"),
+    })
+}
+
+/// Pretty prints the file, files with comments are an error because the ast has nowhere to
+/// keep them
+pub fn format_source(filepath: String, source: &str) -> Result<String, Diagnostics> {
+    let mut lexer = Lexer::new(filepath.clone(), source);
+    let mut end = 0;
+    loop {
+        let token = lexer.next_token()?;
+        // the lexer skips whitespace and comments, so anything else between two tokens is a
+        // comment
+        if let Some(offset) = source[end..token.location.position].find("//") {
+            let position = end + offset;
+            let line_start = source[..position].rfind('\n').map_or(0, |index| index + 1);
+            return Err(Diagnostics::from(CompileError {
+                location: SourceLocation {
+                    filepath: filepath.clone(),
+                    position,
+                    line: source[..position].matches('\n').count() + 1,
+                    column: source[line_start..position].chars().count() + 1,
+                    synthetic: None,
+                },
+                end_location: None,
+                kind: ErrorKind::Unsupported,
+                id: "format-with-comments",
+                arguments: vec![],
+                message: "Unable to format a file with comments, they would be lost".to_string(),
+                notes: vec![],
+                suggestions: vec![],
+            }));
+        }
+        if token.kind == TokenKind::EndOfFile {
+            break;
+        }
+        end = token.end;
+    }
+
+    let file = parse_file(&mut Lexer::new(filepath, source))?;
+    Ok(file.pretty_print(0).trim_start().to_string())
+}
+
+fn diagnostics_to_json(errors: &[CompileError], columns: &ColumnPolicy, source: &str) -> Json {
+    Json::Array(
+        errors
+            .iter()
+            .map(|error| {
+                let mut error = error.clone();
+                columns.recount(&mut error, source);
+                let notes = error
+                    .notes
+                    .iter()
+                    .map(|note| {
+                        Json::object([
+                            (
+                                "location",
+                                note.location.as_ref().map(location_to_json).into(),
+                            ),
+                            ("message", note.message.as_str().into()),
+                        ])
+                    })
+                    .collect();
+                Json::object([
+                    ("location", location_to_json(&error.location)),
+                    (
+                        "end_location",
+                        error.end_location.as_ref().map(location_to_json).into(),
+                    ),
+                    ("message", error.message.as_str().into()),
+                    ("notes", Json::Array(notes)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn location_to_json(location: &SourceLocation) -> Json {
+    Json::object([
+        ("filepath", location.filepath.as_str().into()),
+        ("line", location.line.into()),
+        ("column", location.column.into()),
+    ])
+}
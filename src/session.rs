@@ -0,0 +1,609 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Weak},
+};
+
+use crate::{
+    ast::Ast,
+    binding::bind_ast,
+    bound_nodes::{
+        BoundBlock, BoundEnum, BoundFloat, BoundInteger, BoundLet, BoundMemberAccess, BoundNode,
+        BoundNodeTrait, BoundString,
+    },
+    bytecode::Bytecode,
+    bytecode_compilation::compile_bytecode,
+    cache::FrontEndCache,
+    common::{CancellationToken, CompileError, RuntimeError, SourceLocation},
+    execute::{execute_bytecode, Vars, Vm},
+    lexer::{quote_string, ESCAPES},
+    parsing::DEFAULT_MAX_NESTING_DEPTH,
+    program::Prelude,
+    types::{BlockType, EnumType, Type},
+    value::{Block, Value},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    Compile(Box<CompileError>),
+    Runtime(RuntimeError),
+}
+
+impl From<CompileError> for EvalError {
+    fn from(error: CompileError) -> Self {
+        EvalError::Compile(Box::new(error))
+    }
+}
+
+impl From<RuntimeError> for EvalError {
+    fn from(error: RuntimeError) -> Self {
+        EvalError::Runtime(error)
+    }
+}
+
+/// Binder scope and VM variables that persist between evaluations
+#[derive(Clone)]
+pub struct Session {
+    prelude: Prelude,
+    bound_nodes: Vec<Arc<BoundNode>>,
+    names: HashMap<String, Weak<BoundNode>>,
+    vars: Vars,
+    // so checking the type of something and then evaluating it only parses it once
+    cache: FrontEndCache,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        let prelude = Prelude::new();
+        let mut bytecode = vec![];
+        prelude.compile(&mut bytecode);
+        bytecode.push(Bytecode::Exit);
+
+        let mut vars = HashMap::new();
+        execute_bytecode(&bytecode, Vec::new(), &mut vars)
+            .expect("the prelude should not fail to run");
+
+        Session {
+            names: prelude.names(),
+            prelude,
+            bound_nodes: vec![],
+            vars,
+            cache: FrontEndCache::new(),
+        }
+    }
+
+    pub fn get_names(&self) -> &HashMap<String, Weak<BoundNode>> {
+        &self.names
+    }
+
+    /// Binds the source against the current scope without running it or keeping its definitions
+    pub fn bind(
+        &self,
+        filepath: String,
+        source: &str,
+    ) -> Result<Vec<Arc<BoundNode>>, CompileError> {
+        let file = self.parse(&filepath, source)?;
+
+        let mut names = self.names.clone();
+        let mut bound_expressions = vec![];
+        for expression in &file.unwrap_file().expressions {
+            bound_expressions.push(bind_ast(expression, &mut names)?);
+        }
+        Ok(bound_expressions)
+    }
+
+    /// Gets the type of the last expression in the source
+    pub fn type_of(&self, filepath: String, source: &str) -> Result<Type, CompileError> {
+        Ok(self
+            .bind(filepath, source)?
+            .last()
+            .map_or(Type::Void, |expression| expression.get_type()))
+    }
+
+    /// Evaluates every expression in `source` and returns the value of the last one
+    pub fn eval(&mut self, filepath: String, source: &str) -> Result<Value, EvalError> {
+        self.eval_with(&mut Vm::new(), filepath, source)
+    }
+
+    /// `eval` on `vm`, so its hooks and captured output are used
+    pub fn eval_with(
+        &mut self,
+        vm: &mut Vm,
+        filepath: String,
+        source: &str,
+    ) -> Result<Value, EvalError> {
+        let file = self.parse(&filepath, source)?;
+
+        let mut result = Value::Void;
+        for expression in &file.unwrap_file().expressions {
+            let bound_expression = bind_ast(expression, &mut self.names)?;
+            self.bound_nodes.push(bound_expression.clone());
+
+            let mut bytecode = vec![];
+            compile_bytecode(&bound_expression, &mut bytecode);
+            bytecode.push(Bytecode::Return);
+            let value = vm.execute(&bytecode, Vec::new(), &mut self.vars)?;
+            result = value.map_or(Value::Void, |value| Value::from(&value));
+        }
+        Ok(result)
+    }
+
+    fn parse(&self, filepath: &str, source: &str) -> Result<Arc<Ast>, CompileError> {
+        self.cache.parse(
+            filepath,
+            source,
+            DEFAULT_MAX_NESTING_DEPTH,
+            &CancellationToken::new(),
+        )
+    }
+
+    /// Captures every user defined variable that can be serialized
+    pub fn snapshot(&self) -> Snapshot {
+        let mut vars = BTreeMap::new();
+        let mut enums = BTreeMap::new();
+        for name in self.names.keys() {
+            if self
+                .prelude
+                .builtins
+                .iter()
+                .any(|(builtin, _)| builtin == name)
+            {
+                continue;
+            }
+            if let Some(value) = self.vars.get(name) {
+                let value = Value::from(value);
+                let typ = self.names[name]
+                    .upgrade()
+                    .map(|definition| definition.get_type());
+                // a task or channel is just its index in the vm, which would restore as an integer
+                if is_serializable(&value) && !typ.as_ref().is_some_and(Type::holds_handle) {
+                    if let Some(typ) = &typ {
+                        collect_enums(name, typ, &mut enums);
+                    }
+                    vars.insert(name.clone(), value);
+                }
+            }
+        }
+        Snapshot { vars, enums }
+    }
+
+    /// Replaces all user defined variables with the ones in the snapshot
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let prelude_names = self.prelude.names();
+        self.vars.retain(|name, _| prelude_names.contains_key(name));
+        self.names = prelude_names;
+        self.bound_nodes.clear();
+
+        let location = SourceLocation::synthetic("snapshot", None);
+        for (name, value) in &snapshot.vars {
+            let lett = Arc::new(BoundNode::Let(BoundLet {
+                location: location.clone(),
+                name: name.clone(),
+                value: node_for_value(value, name, &snapshot.enums, &location),
+                shadowed: None,
+            }));
+            self.names.insert(name.clone(), Arc::downgrade(&lett));
+            self.bound_nodes.push(lett);
+            self.vars.insert(name.clone(), value.clone().into());
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+fn is_serializable(value: &Value) -> bool {
+    match value {
+        Value::Void | Value::Integer(_) | Value::Float(_) | Value::String(_) => true,
+        // none doesn't say which optional it came from, so its type couldn't be restored. an
+        // optional with a value is restored as just the value
+        Value::None | Value::Procedure(_) => false,
+        Value::Block(block) => block.members.values().all(is_serializable),
+    }
+}
+
+// the values of enums are integers, so where they are is kept next to the values. a value
+// in a block is at `name.member`
+fn collect_enums(path: &str, typ: &Type, enums: &mut BTreeMap<String, EnumType>) {
+    match typ {
+        Type::Enum(enum_type) => {
+            enums.insert(path.to_string(), enum_type.clone());
+        }
+        // an optional in a snapshot always has a value
+        Type::Optional(inner) => collect_enums(path, inner, enums),
+        Type::Block(block_type) => {
+            for (name, member_type) in &block_type.exported_types {
+                collect_enums(&format!("{}.{}", path, name), member_type, enums);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn type_for_value(value: &Value, path: &str, enums: &BTreeMap<String, EnumType>) -> Type {
+    if let Some(enum_type) = enums.get(path) {
+        return Type::Enum(enum_type.clone());
+    }
+    match value {
+        Value::Void => Type::Void,
+        Value::Integer(_) => Type::Integer,
+        Value::Float(_) => Type::Float,
+        Value::String(_) => Type::String,
+        Value::None | Value::Procedure(_) => {
+            unreachable!("none and procedures are never part of a snapshot")
+        }
+        Value::Block(block) => Type::Block(BlockType {
+            exported_types: block
+                .members
+                .iter()
+                .map(|(name, value)| {
+                    let member_type = type_for_value(value, &format!("{}.{}", path, name), enums);
+                    (name.clone(), member_type)
+                })
+                .collect(),
+        }),
+    }
+}
+
+// creates a node with the same type as the value so the binder can resolve the restored name,
+// the actual value only lives in the vm variables
+fn node_for_value(
+    value: &Value,
+    path: &str,
+    enums: &BTreeMap<String, EnumType>,
+    location: &SourceLocation,
+) -> Option<Arc<BoundNode>> {
+    if let (Some(enum_type), Value::Integer(index)) = (enums.get(path), value) {
+        let enum_node = Arc::new(BoundNode::Enum(BoundEnum {
+            location: location.clone(),
+            enum_type: enum_type.clone(),
+        }));
+        return Some(Arc::new(BoundNode::MemberAccess(BoundMemberAccess {
+            location: location.clone(),
+            operand: enum_node,
+            name: enum_type.variants[*index as usize].clone(),
+            member_type: Type::Enum(enum_type.clone()),
+        })));
+    }
+    match value {
+        Value::Void => None,
+        Value::Integer(_) => Some(Arc::new(BoundNode::Integer(BoundInteger {
+            location: location.clone(),
+            value: 0,
+        }))),
+        Value::Float(_) => Some(Arc::new(BoundNode::Float(BoundFloat {
+            location: location.clone(),
+            value: 0.0,
+        }))),
+        Value::String(_) => Some(Arc::new(BoundNode::String(BoundString {
+            location: location.clone(),
+            value: String::new(),
+        }))),
+        Value::None | Value::Procedure(_) => {
+            unreachable!("none and procedures are never part of a snapshot")
+        }
+        Value::Block(_) => Some(Arc::new(BoundNode::Block(BoundBlock {
+            location: location.clone(),
+            expressions: vec![],
+            exported_expressions: HashMap::new(),
+            block_type: type_for_value(value, path, enums),
+            evaluates_to_last: false,
+        }))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// The serializable variables of a session
+///
+/// The text format is one `name: value` per line, where a value is `void`, an integer,
+/// a float, a string written the same as in the source, or a block written as `{ name: value, ... }`.
+/// A variant of an enum is written as `Variant of enum { A, B } at "file":line:column:position`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub vars: BTreeMap<String, Value>,
+    /// The enum of every value that is a variant, by its name or `name.member` for one in
+    /// a block, the value itself is the index of the variant
+    pub enums: BTreeMap<String, EnumType>,
+}
+
+impl Snapshot {
+    pub fn serialize(&self) -> String {
+        let mut result = String::new();
+        for (name, value) in &self.vars {
+            result += name;
+            result += ": ";
+            serialize_value(value, name, &self.enums, &mut result);
+            result.push('\n');
+        }
+        result
+    }
+
+    pub fn deserialize(text: &str) -> Result<Snapshot, SnapshotError> {
+        let mut vars = BTreeMap::new();
+        let mut enums = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut parser = SnapshotParser {
+                chars: line.chars().collect(),
+                position: 0,
+                line: i + 1,
+                enums: &mut enums,
+            };
+            let (name, value) = parser.parse_member("")?;
+            parser.skip_whitespace();
+            if parser.position < parser.chars.len() {
+                return Err(parser.error("Expected the end of the line"));
+            }
+            vars.insert(name, value);
+        }
+        Ok(Snapshot { vars, enums })
+    }
+}
+
+fn serialize_value(
+    value: &Value,
+    path: &str,
+    enums: &BTreeMap<String, EnumType>,
+    result: &mut String,
+) {
+    if let (Some(enum_type), Value::Integer(index)) = (enums.get(path), value) {
+        let location = &enum_type.location;
+        *result += &format!(
+            "{} of enum {{ {} }} at {}:{}:{}:{}",
+            enum_type.variants[*index as usize],
+            enum_type.variants.join(", "),
+            quote_string(&location.filepath),
+            location.line,
+            location.column,
+            location.position,
+        );
+        return;
+    }
+    match value {
+        Value::Void => *result += "void",
+        Value::Integer(integer) => *result += &integer.to_string(),
+        // debug formatting keeps the `.` so it reads back as a float
+        Value::Float(float) => *result += &format!("{:?}", float),
+        Value::String(string) => *result += &quote_string(string),
+        Value::None | Value::Procedure(_) => {
+            unreachable!("none and procedures are never part of a snapshot")
+        }
+        Value::Block(block) => {
+            let members: BTreeMap<_, _> = block.members.iter().collect();
+            result.push('{');
+            for (i, (name, value)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    result.push(',');
+                }
+                result.push(' ');
+                *result += name;
+                *result += ": ";
+                serialize_value(value, &format!("{}.{}", path, name), enums, result);
+            }
+            *result += " }";
+        }
+    }
+}
+
+struct SnapshotParser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    enums: &'a mut BTreeMap<String, EnumType>,
+}
+
+impl SnapshotParser<'_> {
+    fn error(&self, message: &str) -> SnapshotError {
+        SnapshotError {
+            line: self.line,
+            message: format!("{} at column {}", message, self.position + 1),
+        }
+    }
+
+    fn current_char(&self) -> char {
+        self.chars.get(self.position).copied().unwrap_or('\0')
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.current_char() == ' ' || self.current_char() == '\t' {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, chr: char) -> Result<(), SnapshotError> {
+        self.skip_whitespace();
+        if self.current_char() != chr {
+            return Err(self.error(&format!("Expected '{}'", chr)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn parse_name(&mut self) -> Result<String, SnapshotError> {
+        self.skip_whitespace();
+        let mut name = String::new();
+        while matches!(self.current_char(), 'A'..='Z' | 'a'..='z' | '0'..='9' | '_') {
+            name.push(self.current_char());
+            self.position += 1;
+        }
+        if name.is_empty() {
+            return Err(self.error("Expected a name"));
+        }
+        Ok(name)
+    }
+
+    fn parse_unsigned(&mut self) -> Result<usize, SnapshotError> {
+        let start = self.position;
+        while self.current_char().is_ascii_digit() {
+            self.position += 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse().map_err(|_| self.error("Expected a number"))
+    }
+
+    // `path` is where the member is, see `Snapshot::enums`
+    fn parse_member(&mut self, path: &str) -> Result<(String, Value), SnapshotError> {
+        let name = self.parse_name()?;
+        self.expect(':')?;
+        let path = if path.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", path, name)
+        };
+        let value = self.parse_value(&path)?;
+        Ok((name, value))
+    }
+
+    // everything after the variant of `Variant of enum { A, B } at "file":line:column:position`
+    fn parse_enum(&mut self, variant: &str, path: &str) -> Result<Value, SnapshotError> {
+        for word in ["of", "enum"] {
+            if self.parse_name()? != word {
+                return Err(self.error(&format!("Expected '{}'", word)));
+            }
+        }
+        self.expect('{')?;
+        let mut variants = vec![self.parse_name()?];
+        loop {
+            self.skip_whitespace();
+            match self.current_char() {
+                ',' => {
+                    self.position += 1;
+                    variants.push(self.parse_name()?);
+                }
+                '}' => {
+                    self.position += 1;
+                    break;
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+        if self.parse_name()? != "at" {
+            return Err(self.error("Expected 'at'"));
+        }
+        let Value::String(filepath) = self.parse_value(path)? else {
+            return Err(self.error("Expected the filepath of the enum"));
+        };
+        self.expect(':')?;
+        let line = self.parse_unsigned()?;
+        self.expect(':')?;
+        let column = self.parse_unsigned()?;
+        self.expect(':')?;
+        let position = self.parse_unsigned()?;
+
+        let Some(index) = variants.iter().position(|other| other == variant) else {
+            return Err(self.error(&format!("{} is not a variant of the enum", variant)));
+        };
+        self.enums.insert(
+            path.to_string(),
+            EnumType {
+                variants,
+                location: SourceLocation {
+                    filepath,
+                    position,
+                    line,
+                    column,
+                    synthetic: None,
+                },
+            },
+        );
+        Ok(Value::Integer(index as i64))
+    }
+
+    fn parse_value(&mut self, path: &str) -> Result<Value, SnapshotError> {
+        self.skip_whitespace();
+        match self.current_char() {
+            '{' => {
+                self.position += 1;
+                let mut block = Block::new();
+                self.skip_whitespace();
+                if self.current_char() == '}' {
+                    self.position += 1;
+                    return Ok(Value::Block(block));
+                }
+                loop {
+                    let (name, value) = self.parse_member(path)?;
+                    block.insert(name, value);
+                    self.skip_whitespace();
+                    match self.current_char() {
+                        ',' => self.position += 1,
+                        '}' => {
+                            self.position += 1;
+                            return Ok(Value::Block(block));
+                        }
+                        _ => return Err(self.error("Expected ',' or '}'")),
+                    }
+                }
+            }
+
+            // floats can also be `-inf`, `inf` and `NaN` are read as names below
+            '-' | '0'..='9' => {
+                let start = self.position;
+                self.position += 1;
+                loop {
+                    match self.current_char() {
+                        'A'..='Z' | 'a'..='z' | '0'..='9' | '.' => self.position += 1,
+                        '+' | '-' if matches!(self.chars[self.position - 1], 'e' | 'E') => {
+                            self.position += 1
+                        }
+                        _ => break,
+                    }
+                }
+                let text: String = self.chars[start..self.position].iter().collect();
+                if let Ok(integer) = text.parse() {
+                    Ok(Value::Integer(integer))
+                } else {
+                    text.parse()
+                        .map(Value::Float)
+                        .map_err(|_| self.error("Invalid number"))
+                }
+            }
+
+            '"' => {
+                self.position += 1;
+                let mut string = String::new();
+                loop {
+                    match self.current_char() {
+                        '"' => break,
+                        '\\' => {
+                            self.position += 1;
+                            let escaped = self.current_char();
+                            match ESCAPES.iter().find(|&&(_, name)| name == escaped) {
+                                Some(&(chr, _)) => string.push(chr),
+                                None => return Err(self.error("Unknown escape sequence")),
+                            }
+                        }
+                        // `current_char` gives a nul past the end of the line
+                        '\0' if self.position >= self.chars.len() => {
+                            return Err(self.error("Unterminated string"))
+                        }
+                        chr => string.push(chr),
+                    }
+                    self.position += 1;
+                }
+                self.position += 1;
+                Ok(Value::String(string))
+            }
+
+            'A'..='Z' | 'a'..='z' | '_' => {
+                let name = self.parse_name()?;
+                match name.as_str() {
+                    "void" => Ok(Value::Void),
+                    "inf" => Ok(Value::Float(f64::INFINITY)),
+                    "NaN" => Ok(Value::Float(f64::NAN)),
+                    variant => self.parse_enum(variant, path),
+                }
+            }
+
+            _ => Err(self.error("Expected a value")),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{fmt::Display, mem::size_of};
+
+use crate::{
+    ast::{Ast, AstTrait},
+    bound_nodes::{BoundNode, BoundNodeTrait},
+    bytecode::{Bytecode, BytecodeValue},
+    common::CompileError,
+    execute::Vm,
+    lexer::Lexer,
+    parsing::parse_file,
+    program::Program,
+    token::{Token, TokenKind},
+};
+
+/// How many of something there were, and roughly how much memory they used
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl Display for Usage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} bytes)", self.count, self.bytes)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub tokens: Usage,
+    pub ast_nodes: Usage,
+    pub bound_nodes: Usage,
+    pub bytecode_instructions: Usage,
+    pub peak_stack_depth: usize,
+    pub peak_variable_count: usize,
+}
+
+impl Stats {
+    /// Collects the compile time stats, the source is lexed and parsed again to count
+    /// tokens and ast nodes since the program does not keep them
+    pub fn collect(
+        filepath: String,
+        source: &str,
+        program: &Program,
+    ) -> Result<Stats, CompileError> {
+        let mut stats = Stats::default();
+
+        let mut lexer = Lexer::new(filepath.clone(), source);
+        loop {
+            let token = lexer.next_token()?;
+            stats.tokens.count += 1;
+            stats.tokens.bytes += token_bytes(&token);
+            if token.kind == TokenKind::EndOfFile {
+                break;
+            }
+        }
+
+        let mut lexer = Lexer::new(filepath, source);
+        let file = parse_file(&mut lexer)?;
+        for expression in &file.expressions {
+            count_ast(expression, &mut stats.ast_nodes);
+        }
+
+        count_bound_node(program.get_bound_file(), &mut stats.bound_nodes);
+        count_bytecode(program.get_bytecode(), &mut stats.bytecode_instructions);
+
+        Ok(stats)
+    }
+
+    pub fn add_execution(&mut self, vm: &Vm) {
+        self.peak_stack_depth = self.peak_stack_depth.max(vm.peak_stack_depth);
+        self.peak_variable_count = self.peak_variable_count.max(vm.peak_variable_count);
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Tokens: {}", self.tokens)?;
+        writeln!(f, "Ast nodes: {}", self.ast_nodes)?;
+        writeln!(f, "Bound nodes: {}", self.bound_nodes)?;
+        writeln!(f, "Bytecode instructions: {}", self.bytecode_instructions)?;
+        writeln!(f, "Peak stack depth: {}", self.peak_stack_depth)?;
+        writeln!(f, "Peak variable count: {}", self.peak_variable_count)
+    }
+}
+
+fn token_bytes(token: &Token) -> usize {
+    let mut bytes = size_of::<Token>() + token.location.filepath.capacity();
+    if let TokenKind::Name(name) = &token.kind {
+        bytes += name.capacity();
+    }
+    bytes
+}
+
+fn count_ast(ast: &Ast, usage: &mut Usage) {
+    usage.count += 1;
+    usage.bytes += size_of::<Ast>();
+    for child in ast.children() {
+        count_ast(child, usage);
+    }
+}
+
+fn count_bound_node(node: &BoundNode, usage: &mut Usage) {
+    usage.count += 1;
+    usage.bytes += size_of::<BoundNode>();
+    for child in node.children() {
+        count_bound_node(child, usage);
+    }
+}
+
+fn count_bytecode(bytecode: &[Bytecode], usage: &mut Usage) {
+    for instruction in bytecode {
+        usage.count += 1;
+        usage.bytes += size_of::<Bytecode>();
+        if let Bytecode::Push(BytecodeValue::Procedure(procedure)) = instruction {
+            count_bytecode(procedure, usage);
+        }
+    }
+}
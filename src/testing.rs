@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use crate::{
+    ast::Ast,
+    binding::{bind_ast_with_options, BindOptions},
+    bound_nodes::{BoundNode, BoundNodeTrait},
+    common::{write_diagnostics, Diagnostics, ErrorKind},
+    lexer::Lexer,
+    parsing::parse_file,
+    pragma::parse_attributes,
+    program::Prelude,
+    types::Type,
+};
+
+/// Parses and binds the source as a file that can see the builtins
+pub fn bind_str_with_prelude(source: &str) -> Result<Arc<BoundNode>, Diagnostics> {
+    let mut lexer = Lexer::new("test.lang".to_string(), source);
+    let file = parse_file(&mut lexer)?;
+    let mut diagnostics = Diagnostics::new();
+    let attributes = parse_attributes("test.lang", source)?;
+    let bound_file = bind_ast_with_options(
+        &Ast::File(file),
+        &mut Prelude::new().names(),
+        &mut diagnostics,
+        &BindOptions {
+            allow_shadowing: attributes.shadowing,
+            ..BindOptions::default()
+        },
+    );
+    if diagnostics.is_empty() {
+        Ok(bound_file)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Binds the source and panics with the formatted errors if it fails
+pub fn assert_binds(source: &str) -> Arc<BoundNode> {
+    bind_str_with_prelude(source).unwrap_or_else(|diagnostics| {
+        let mut output = vec![];
+        write_diagnostics(&mut output, &diagnostics, usize::MAX).unwrap();
+        panic!(
+            "expected the source to bind, but got:\n{}",
+            String::from_utf8_lossy(&output),
+        )
+    })
+}
+
+/// The type of the last top level expression
+pub fn assert_type_of_last(source: &str, expected: Type) {
+    let bound_file = assert_binds(source);
+    let last = bound_file
+        .unwrap_block()
+        .expressions
+        .last()
+        .expect("the source should have at least one expression");
+    assert_eq!(last.get_type(), expected, "in {:?}", source);
+}
+
+/// Binds the source and checks it fails with exactly these kinds of errors, in order
+pub fn assert_bind_errors(source: &str, expected: &[ErrorKind]) {
+    let diagnostics = match bind_str_with_prelude(source) {
+        Ok(_) => panic!("expected {:?} to fail to bind", source),
+        Err(diagnostics) => diagnostics,
+    };
+    let kinds: Vec<_> = diagnostics.errors.iter().map(|error| error.kind).collect();
+    assert_eq!(
+        kinds, expected,
+        "in {:?}: {:#?}",
+        source, diagnostics.errors
+    );
+}
@@ -7,10 +7,19 @@ pub enum TokenKind {
     Newline,
     Name(String),
     Integer(u128),
+    Float(f64),
+    /// With the escape sequences already replaced
+    String(String),
 
     // Keywords
+    Enum,
     Export,
+    Freeze,
+    Join,
     Let,
+    Match,
+    Spawn,
+    None,
 
     // Brackets
     OpenParenthesis,
@@ -22,6 +31,7 @@ pub enum TokenKind {
     LeftArrow,
     RightArrow,
     Comma,
+    Dot,
 
     // Operators
     Plus,
@@ -29,6 +39,16 @@ pub enum TokenKind {
     Asterisk,
     Slash,
     ExclamationMark,
+    QuestionMark,
+    QuestionMarkQuestionMark,
+
+    // Bitwise Operators
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    LessThanLessThan,
+    GreaterThanGreaterThan,
 
     // Comparison Operators
     EqualEqual,
@@ -38,6 +58,10 @@ pub enum TokenKind {
     LessThanEqual,
     GreaterThanEqual,
 
+    // Logical Operators
+    AmpersandAmpersand,
+    PipePipe,
+
     // Assignment Operators
     Equal,
     PlusEqual,
@@ -46,6 +70,111 @@ pub enum TokenKind {
     SlashEqual,
 }
 
+/// A token that is known to be a name
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameToken {
+    pub location: SourceLocation,
+    pub end: usize,
+    pub name: String,
+}
+
+impl NameToken {
+    pub fn length(&self) -> usize {
+        self.end - self.location.position
+    }
+}
+
+impl TryFrom<Token> for NameToken {
+    type Error = Token;
+
+    fn try_from(token: Token) -> Result<NameToken, Token> {
+        match token.kind {
+            TokenKind::Name(name) => Ok(NameToken {
+                location: token.location,
+                end: token.end,
+                name,
+            }),
+            kind => Err(Token { kind, ..token }),
+        }
+    }
+}
+
+/// A token that is known to be an integer
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegerToken {
+    pub location: SourceLocation,
+    pub end: usize,
+    pub value: u128,
+}
+
+impl IntegerToken {
+    pub fn length(&self) -> usize {
+        self.end - self.location.position
+    }
+}
+
+impl TryFrom<Token> for IntegerToken {
+    type Error = Token;
+
+    fn try_from(token: Token) -> Result<IntegerToken, Token> {
+        match token.kind {
+            TokenKind::Integer(value) => Ok(IntegerToken {
+                location: token.location,
+                end: token.end,
+                value,
+            }),
+            kind => Err(Token { kind, ..token }),
+        }
+    }
+}
+
+/// A token that is known to be a float
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatToken {
+    pub location: SourceLocation,
+    pub end: usize,
+    pub value: f64,
+}
+
+impl TryFrom<Token> for FloatToken {
+    type Error = Token;
+
+    fn try_from(token: Token) -> Result<FloatToken, Token> {
+        match token.kind {
+            TokenKind::Float(value) => Ok(FloatToken {
+                location: token.location,
+                end: token.end,
+                value,
+            }),
+            kind => Err(Token { kind, ..token }),
+        }
+    }
+}
+
+/// A token that is known to be a string
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringToken {
+    pub location: SourceLocation,
+    pub end: usize,
+    pub value: String,
+}
+
+impl TryFrom<Token> for StringToken {
+    type Error = Token;
+
+    fn try_from(token: Token) -> Result<StringToken, Token> {
+        match token.kind {
+            TokenKind::String(value) => Ok(StringToken {
+                location: token.location,
+                end: token.end,
+                value,
+            }),
+            kind => Err(Token { kind, ..token }),
+        }
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for TokenKind {
     fn to_string(&self) -> String {
         match self {
@@ -54,10 +183,18 @@ impl ToString for TokenKind {
             TokenKind::Newline => "a newline".to_string(),
             TokenKind::Name(_) => "a name".to_string(),
             TokenKind::Integer(_) => "an integer".to_string(),
+            TokenKind::Float(_) => "a float".to_string(),
+            TokenKind::String(_) => "a string".to_string(),
 
             // Keywords
+            TokenKind::Enum => "enum".to_string(),
             TokenKind::Export => "export".to_string(),
+            TokenKind::Freeze => "freeze".to_string(),
+            TokenKind::Join => "join".to_string(),
             TokenKind::Let => "let".to_string(),
+            TokenKind::Match => "match".to_string(),
+            TokenKind::Spawn => "spawn".to_string(),
+            TokenKind::None => "none".to_string(),
 
             // Brackets
             TokenKind::OpenParenthesis => "(".to_string(),
@@ -69,6 +206,7 @@ impl ToString for TokenKind {
             TokenKind::LeftArrow => "<-".to_string(),
             TokenKind::RightArrow => "->".to_string(),
             TokenKind::Comma => ",".to_string(),
+            TokenKind::Dot => ".".to_string(),
 
             // Operators
             TokenKind::Plus => "+".to_string(),
@@ -76,6 +214,16 @@ impl ToString for TokenKind {
             TokenKind::Asterisk => "*".to_string(),
             TokenKind::Slash => "/".to_string(),
             TokenKind::ExclamationMark => "!".to_string(),
+            TokenKind::QuestionMark => "?".to_string(),
+            TokenKind::QuestionMarkQuestionMark => "??".to_string(),
+
+            // Bitwise Operators
+            TokenKind::Ampersand => "&".to_string(),
+            TokenKind::Pipe => "|".to_string(),
+            TokenKind::Caret => "^".to_string(),
+            TokenKind::Tilde => "~".to_string(),
+            TokenKind::LessThanLessThan => "<<".to_string(),
+            TokenKind::GreaterThanGreaterThan => ">>".to_string(),
 
             // Comparison Operators
             TokenKind::EqualEqual => "==".to_string(),
@@ -85,6 +233,10 @@ impl ToString for TokenKind {
             TokenKind::LessThanEqual => "<=".to_string(),
             TokenKind::GreaterThanEqual => ">=".to_string(),
 
+            // Logical Operators
+            TokenKind::AmpersandAmpersand => "&&".to_string(),
+            TokenKind::PipePipe => "||".to_string(),
+
             // Assignment Operators
             TokenKind::Equal => "=".to_string(),
             TokenKind::PlusEqual => "+=".to_string(),
@@ -98,6 +250,15 @@ impl ToString for TokenKind {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
+    /// Where the token starts, `location.position` is its start offset
     pub location: SourceLocation,
-    pub length: usize,
+    /// The byte offset just past the token, the end of file token is empty so this is the
+    /// length of the source for it
+    pub end: usize,
+}
+
+impl Token {
+    pub fn length(&self) -> usize {
+        self.end - self.location.position
+    }
 }
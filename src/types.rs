@@ -1,12 +1,33 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+};
+
+use crate::common::SourceLocation;
 
 #[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Type {
     Void,
     Type,
     Integer,
+    Float,
+    String,
     Block(BlockType),
     Proc(ProcType),
+    /// A variant of an enum, `Color.Red` where `Color` is the enum
+    Enum(EnumType),
+    /// Either a value of the inner type or none, the inner type is never optional itself
+    Optional(Box<Type>),
+    /// The handle of a task made by `spawn`, joining it gives a value of the inner type
+    Task(Box<Type>),
+    /// The handle of a channel from `tasks.channel`, integers are sent over it
+    Channel,
+    /// The type of `none` on its own, it can be used as any optional
+    None,
+    /// The type of an expression that failed to bind, it is accepted everywhere so the
+    /// original error is the only one reported
+    Error,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -19,3 +40,114 @@ pub struct ProcType {
     pub parameter_types: Vec<Type>,
     pub return_type: Box<Type>,
 }
+
+/// Two enums with the same variants are still different types, so each one is told apart by
+/// where it is defined
+#[derive(Clone, PartialEq)]
+pub struct EnumType {
+    pub variants: Vec<String>,
+    pub location: SourceLocation,
+}
+
+// errors still show types with debug formatting, so this keeps them short and says which
+// enum it is
+impl Debug for EnumType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "enum {{ {} }} at {}",
+            self.variants.join(", "),
+            self.location
+        )
+    }
+}
+
+impl EnumType {
+    /// The type of the enum itself, a block with a member for each variant
+    pub fn get_block_type(&self) -> BlockType {
+        BlockType {
+            exported_types: self
+                .variants
+                .iter()
+                .map(|variant| (variant.clone(), Type::Enum(self.clone())))
+                .collect(),
+        }
+    }
+
+    /// The value a variant has at runtime, which is its index
+    pub fn get_index(&self, variant: &str) -> Option<usize> {
+        self.variants.iter().position(|other| other == variant)
+    }
+}
+
+impl Type {
+    /// An optional of `inner`, which is `inner` itself if it is already optional
+    pub fn optional(inner: Type) -> Type {
+        match inner {
+            Type::Optional(_) | Type::None | Type::Error => inner,
+            inner => Type::Optional(Box::new(inner)),
+        }
+    }
+
+    /// Whether a value of type `value_type` can be used where this type is needed, a value
+    /// can also be used as an optional of its type and none can be used as any optional
+    pub fn accepts(&self, value_type: &Type) -> bool {
+        match (self, value_type) {
+            (Type::Optional(_), Type::None) => true,
+            (Type::Optional(inner), value_type) if **inner == *value_type => true,
+            _ => self == value_type,
+        }
+    }
+
+    /// Whether a value of this type holds a task or a channel, they only mean something to
+    /// the vm that made them so these values are not kept between runs
+    pub fn holds_handle(&self) -> bool {
+        match self {
+            Type::Task(_) | Type::Channel => true,
+            Type::Optional(inner) => inner.holds_handle(),
+            Type::Block(block_type) => block_type.exported_types.values().any(Type::holds_handle),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Type => write!(f, "type"),
+            Type::Integer => write!(f, "integer"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Block(block_type) => {
+                // sorted so the output doesn't change between runs
+                let mut names: Vec<_> = block_type.exported_types.keys().collect();
+                names.sort();
+                write!(f, "{{")?;
+                for (i, name) in names.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {}: {}", name, block_type.exported_types[name])?;
+                }
+                write!(f, " }}")
+            }
+            Type::Proc(proc_type) => {
+                write!(f, "proc(")?;
+                for (i, parameter_type) in proc_type.parameter_types.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", parameter_type)?;
+                }
+                write!(f, ") -> {}", proc_type.return_type)
+            }
+            Type::Enum(enum_type) => write!(f, "enum {{ {} }}", enum_type.variants.join(", ")),
+            Type::Optional(inner) => write!(f, "?{}", inner),
+            Type::Task(inner) => write!(f, "task({})", inner),
+            Type::Channel => write!(f, "channel"),
+            Type::None => write!(f, "none"),
+            Type::Error => write!(f, "<error>"),
+        }
+    }
+}
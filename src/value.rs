@@ -0,0 +1,352 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    bytecode::{BytecodeValue, VmValue},
+    lexer::quote_string,
+};
+
+/// A runtime value as seen from Rust, so embedders don't have to match on `BytecodeValue`
+#[derive(Debug, Clone)]
+pub enum Value {
+    Void,
+    /// An optional without a value, one with a value is just that value
+    None,
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Procedure(Procedure),
+    Block(Block),
+}
+
+/// A procedure that can be handed back to the vm, but not looked into,
+/// so that embedders don't depend on how procedures are compiled
+#[derive(Debug, Clone)]
+pub struct Procedure(BytecodeValue);
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Void => "void",
+            Value::None => "none",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Procedure(_) => "procedure",
+            Value::Block(_) => "block",
+        }
+    }
+
+    pub fn is_void(&self) -> bool {
+        matches!(self, Value::Void)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Void => write!(f, "void"),
+            Value::None => write!(f, "none"),
+            Value::Integer(integer) => write!(f, "{}", integer),
+            // always has a `.` or an exponent, so `1.0` is not mistaken for an integer
+            Value::Float(float) => write!(f, "{:?}", float),
+            // written the same as in the source so it can be told apart from the other values
+            Value::String(string) => write!(f, "{}", quote_string(string)),
+            Value::Procedure(_) => write!(f, "<procedure>"),
+            Value::Block(block) => {
+                let mut names: Vec<_> = block.members.keys().collect();
+                names.sort();
+                write!(f, "{{")?;
+                for (i, name) in names.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {}: {}", name, block.members[name])?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Void, Value::Void) => true,
+            (Value::None, Value::None) => true,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Block(a), Value::Block(b)) => a == b,
+            // procedures have no meaningful equality
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Expected a value of type {}, but got {}",
+            self.expected, self.found,
+        )
+    }
+}
+
+/// The exports of a block value, with typed getters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Block {
+    pub members: HashMap<String, Value>,
+}
+
+impl Block {
+    pub fn new() -> Block {
+        Block::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.members.get(name)
+    }
+
+    pub fn get_as<T>(&self, name: &str) -> Option<Result<T, ConversionError>>
+    where
+        T: TryFrom<Value, Error = ConversionError>,
+    {
+        self.members
+            .get(name)
+            .map(|value| T::try_from(value.clone()))
+    }
+
+    pub fn get_integer(&self, name: &str) -> Option<Result<i64, ConversionError>> {
+        self.get_as(name)
+    }
+
+    pub fn get_float(&self, name: &str) -> Option<Result<f64, ConversionError>> {
+        self.get_as(name)
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<Result<String, ConversionError>> {
+        self.get_as(name)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<Result<bool, ConversionError>> {
+        self.get_as(name)
+    }
+
+    pub fn get_block(&self, name: &str) -> Option<Result<Block, ConversionError>> {
+        self.get_as(name)
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        self.members.insert(name.into(), value.into());
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Void
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::None, Into::into)
+    }
+}
+
+// there are no booleans in the language yet, so they are represented as integers
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Integer(value as i64)
+    }
+}
+
+// there are no lists in the language, so there is no `Vec<T>` to convert to or from
+
+impl From<Block> for Value {
+    fn from(block: Block) -> Self {
+        Value::Block(block)
+    }
+}
+
+impl From<HashMap<String, Value>> for Value {
+    fn from(members: HashMap<String, Value>) -> Self {
+        Value::Block(Block { members })
+    }
+}
+
+impl TryFrom<Value> for () {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Void => Ok(()),
+            _ => Err(ConversionError {
+                expected: "void",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Integer(integer) => Ok(integer),
+            _ => Err(ConversionError {
+                expected: "integer",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Float(float) => Ok(float),
+            _ => Err(ConversionError {
+                expected: "float",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(string) => Ok(string),
+            _ => Err(ConversionError {
+                expected: "string",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Integer(integer) => Ok(integer != 0),
+            _ => Err(ConversionError {
+                expected: "integer",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for Block {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Block(block) => Ok(block),
+            _ => Err(ConversionError {
+                expected: "block",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl From<&BytecodeValue> for Value {
+    fn from(value: &BytecodeValue) -> Self {
+        match value {
+            BytecodeValue::Void => Value::Void,
+            BytecodeValue::None => Value::None,
+            BytecodeValue::Integer(integer) => Value::Integer(*integer),
+            BytecodeValue::Float(float) => Value::Float(*float),
+            BytecodeValue::String(string) => Value::String(string.to_string()),
+            BytecodeValue::Procedure(_) | BytecodeValue::Native(_) => {
+                Value::Procedure(Procedure(value.clone()))
+            }
+            BytecodeValue::Block(members) => Value::Block(Block {
+                members: members
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Value::from(value)))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl From<BytecodeValue> for Value {
+    fn from(value: BytecodeValue) -> Self {
+        Value::from(&value)
+    }
+}
+
+impl From<&VmValue> for Value {
+    fn from(value: &VmValue) -> Self {
+        match value {
+            VmValue::Object(object) => Value::from(&**object),
+            other => Value::from(other.to_bytecode_value()),
+        }
+    }
+}
+
+impl From<Value> for VmValue {
+    fn from(value: Value) -> Self {
+        VmValue::from(BytecodeValue::from(value))
+    }
+}
+
+impl From<Value> for BytecodeValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Void => BytecodeValue::Void,
+            Value::None => BytecodeValue::None,
+            Value::Integer(integer) => BytecodeValue::Integer(integer),
+            Value::Float(float) => BytecodeValue::Float(float),
+            Value::String(string) => BytecodeValue::String(string.into()),
+            Value::Procedure(Procedure(procedure)) => procedure,
+            Value::Block(block) => BytecodeValue::Block(
+                block
+                    .members
+                    .into_iter()
+                    .map(|(name, value)| (name, VmValue::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+
+use crate::{
+    bytecode::{Bytecode, BytecodeValue},
+    natives::get_native,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    /// The index of the instruction, and of each `push proc` it is nested in, outermost first
+    pub path: Vec<usize>,
+    pub message: String,
+}
+
+/// Checks bytecode for mistakes the vm would only find by crashing
+///
+/// The top level starts with only the void value of `Frame` on its stack, so it must never
+/// pop more than it pushed. Procedures don't know how many arguments they get, so only
+/// their own instructions are checked against each other
+pub fn verify_bytecode(bytecode: &[Bytecode]) -> Vec<VerifyError> {
+    let mut errors = vec![];
+    verify_instructions(bytecode, &mut vec![], Some(1), &[], &mut errors);
+    errors
+}
+
+fn verify_instructions(
+    bytecode: &[Bytecode],
+    path: &mut Vec<usize>,
+    // `None` when the starting stack depth is not known
+    start_depth: Option<usize>,
+    // the variables a closure starts with
+    captured: &[String],
+    errors: &mut Vec<VerifyError>,
+) {
+    // how many values each instruction pops and pushes, along with the checks that don't
+    // depend on the stack
+    let mut stored: HashSet<String> = captured.iter().cloned().collect();
+    let mut effects = Vec::with_capacity(bytecode.len());
+    for (index, instruction) in bytecode.iter().enumerate() {
+        effects.push(match instruction {
+            Bytecode::Exit => (0, 0),
+            Bytecode::Push(BytecodeValue::Procedure(procedure)) => {
+                let captured = match bytecode.get(index + 1) {
+                    Some(Bytecode::MakeClosure(names)) => names.as_slice(),
+                    _ => &[],
+                };
+                path.push(index);
+                verify_instructions(procedure, path, None, captured, errors);
+                path.pop();
+                (0, 1)
+            }
+            Bytecode::Push(_) => (0, 1),
+            Bytecode::Pop => (1, 0),
+            Bytecode::Dup => (1, 2),
+            Bytecode::Call { argument_count } => (argument_count + 1, 1),
+            Bytecode::CallNative(id) => (get_native(*id).parameter_types.len(), 1),
+            Bytecode::Return => (1, 0),
+            Bytecode::Load(name) => {
+                check_stored(&stored, name, errors, path, index);
+                (0, 1)
+            }
+            Bytecode::Store(name) => {
+                stored.insert(name.clone());
+                (1, 0)
+            }
+            Bytecode::AddLoadsInteger(a, b) => {
+                check_stored(&stored, a, errors, path, index);
+                check_stored(&stored, b, errors, path, index);
+                (0, 1)
+            }
+            Bytecode::DupStore(name) => {
+                stored.insert(name.clone());
+                (1, 1)
+            }
+            Bytecode::MakeBlock(names) => (names.len(), 1),
+            Bytecode::MakeClosure(names) => {
+                for name in names {
+                    check_stored(&stored, name, errors, path, index);
+                }
+                (1, 1)
+            }
+            Bytecode::LoadMember(_) | Bytecode::Freeze(_) | Bytecode::Spawn | Bytecode::Join => {
+                (1, 1)
+            }
+            Bytecode::CheckNotFrozen(name) => {
+                check_stored(&stored, name, errors, path, index);
+                (0, 0)
+            }
+            Bytecode::Jump(target) => {
+                check_target(bytecode, path, index, *target, errors);
+                (0, 0)
+            }
+            Bytecode::JumpIfZero(target) => {
+                check_target(bytecode, path, index, *target, errors);
+                (1, 0)
+            }
+            Bytecode::AddInteger
+            | Bytecode::SubInteger
+            | Bytecode::MulInteger
+            | Bytecode::DivInteger
+            | Bytecode::AndInteger
+            | Bytecode::OrInteger
+            | Bytecode::XorInteger
+            | Bytecode::EqualInteger
+            | Bytecode::ShiftLeftInteger
+            | Bytecode::ShiftRightInteger
+            | Bytecode::AddFloat
+            | Bytecode::SubFloat
+            | Bytecode::MulFloat
+            | Bytecode::DivFloat => (2, 1),
+            Bytecode::IsNone
+            | Bytecode::NegateInteger
+            | Bytecode::NotInteger
+            | Bytecode::NegateFloat
+            | Bytecode::AddConstInteger(_) => (1, 1),
+        });
+    }
+
+    // follows every path, each instruction has to be reached with the same depth from all of
+    // them. When the start is not known the depths are relative to it instead
+    let mut depths: Vec<Option<i64>> = vec![None; bytecode.len()];
+    let mut check_underflow = start_depth.is_some();
+    let mut reaches_end = false;
+    let mut pending = vec![(0, start_depth.unwrap_or(0) as i64)];
+    while let Some((index, depth)) = pending.pop() {
+        let Some(instruction) = bytecode.get(index) else {
+            reaches_end = true;
+            continue;
+        };
+        match depths[index] {
+            Some(previous) => {
+                if previous != depth {
+                    push_error(
+                        errors,
+                        path,
+                        index,
+                        format!(
+                            "Reached with {} values on one path and {} on another",
+                            previous, depth
+                        ),
+                    );
+                }
+                continue;
+            }
+            None => depths[index] = Some(depth),
+        }
+
+        let (pops, pushes) = effects[index];
+        if check_underflow && depth < pops as i64 {
+            push_error(
+                errors,
+                path,
+                index,
+                format!("Needs {} values but the stack only has {}", pops, depth),
+            );
+            // only report the first underflow, everything after it would be wrong too
+            check_underflow = false;
+        }
+        let depth = depth + pushes as i64 - pops as i64;
+
+        match instruction {
+            Bytecode::Exit | Bytecode::Return => {}
+            Bytecode::Jump(target) => {
+                if *target < bytecode.len() {
+                    pending.push((*target, depth));
+                }
+            }
+            Bytecode::JumpIfZero(target) => {
+                if *target < bytecode.len() {
+                    pending.push((*target, depth));
+                }
+                pending.push((index + 1, depth));
+            }
+            _ => pending.push((index + 1, depth)),
+        }
+    }
+
+    // only the first one, the rest usually follow from it
+    if let Some(index) = depths.iter().position(Option::is_none) {
+        push_error(errors, path, index, "Unreachable instruction".to_string());
+    }
+    if reaches_end {
+        push_error(
+            errors,
+            path,
+            bytecode.len(),
+            "Reaches the end without an exit or return".to_string(),
+        );
+    }
+}
+
+/// The number of instructions, including the ones inside procedures
+pub fn count_instructions(bytecode: &[Bytecode]) -> usize {
+    bytecode
+        .iter()
+        .map(|instruction| match instruction {
+            Bytecode::Push(BytecodeValue::Procedure(procedure)) => {
+                1 + count_instructions(procedure)
+            }
+            _ => 1,
+        })
+        .sum()
+}
+
+fn check_target(
+    bytecode: &[Bytecode],
+    path: &[usize],
+    index: usize,
+    target: usize,
+    errors: &mut Vec<VerifyError>,
+) {
+    if target >= bytecode.len() {
+        push_error(
+            errors,
+            path,
+            index,
+            format!("Jumps to {} which is past the end", target),
+        );
+    }
+}
+
+// variables only live as long as the call that stored them, jumps only go forward in
+// compiled code so the order of the instructions is good enough
+fn check_stored(
+    stored: &HashSet<String>,
+    name: &String,
+    errors: &mut Vec<VerifyError>,
+    path: &[usize],
+    index: usize,
+) {
+    if !stored.contains(name) {
+        push_error(
+            errors,
+            path,
+            index,
+            format!("{} is loaded before it is stored", name),
+        );
+    }
+}
+
+fn push_error(errors: &mut Vec<VerifyError>, path: &[usize], index: usize, message: String) {
+    let mut path = path.to_vec();
+    path.push(index);
+    errors.push(VerifyError { path, message });
+}
@@ -0,0 +1,225 @@
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+fn write_source(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("lang_cli_{}_{}", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+fn run(name: &str, source: &str) -> Output {
+    let path = write_source(name, source);
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn success_exits_with_zero() {
+    let output = run("success.lang", "io.print_integer(1 + 2)\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn message_catalog_translates_errors() {
+    let path = write_source("catalog.lang", "io.print_integer(a)\n");
+    let catalog = write_source(
+        "catalog.txt",
+        "unresolved-name = Impossible de trouver {}\n",
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("run")
+        .arg(&path)
+        .arg("--message-catalog")
+        .arg(&catalog)
+        .output()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(catalog).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Compile Error: Impossible de trouver a"));
+}
+
+#[test]
+fn main_returns_the_exit_status() {
+    // builtins are the only procedures that can be exported, and the handles of channels
+    // count up from 0, so this `main` returns 3
+    let source = "io.print_integer(1)\n".to_string()
+        + &"tasks.channel()\n".repeat(3)
+        + "export main = tasks.channel\n";
+    let output = run("main.lang", &source);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+
+    // 256 would wrap around to a successful 0
+    let source = "tasks.channel()\n".repeat(256) + "export main = tasks.channel\n";
+    let output = run("main_too_big.lang", &source);
+    assert_eq!(output.status.code(), Some(70));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("main returned 256, but exit statuses have to be from 0 to 255"));
+}
+
+#[test]
+fn compile_error_exits_with_one() {
+    let output = run("compile_error.lang", "io.print_integer(a)\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Compile Error: Unable to find a"));
+}
+
+#[test]
+fn runtime_error_exits_with_seventy() {
+    let output = run(
+        "runtime_error.lang",
+        "let zero = 0\nio.print_integer(1)\nio.print_integer(1 / zero)\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Runtime Error: Unable to divide 1 by 0\n",
+    );
+}
+
+#[test]
+fn broken_pipe_is_not_a_crash() {
+    let path = write_source("broken_pipe.lang", &"io.print_integer(1)\n".repeat(100_000));
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("run")
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+
+    let mut stderr = String::new();
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    let status = child.wait().unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn resource_limits_exit_with_124() {
+    let path = write_source("timeout.lang", &"io.print_integer(1)\n".repeat(1000));
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("run")
+        .arg(&path)
+        .args(["--timeout", "0ms"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Runtime Error: Execution went past its timeout\n",
+    );
+
+    let path = write_source("max_output.lang", &"io.print_integer(1)\n".repeat(3));
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("run")
+        .arg(&path)
+        .args(["--max-output", "5"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n1\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Runtime Error: Execution printed more than its output limit\n",
+    );
+}
+
+#[test]
+fn dry_run_does_not_execute() {
+    let path = write_source("dry_run.lang", "let zero = 0\nio.print_integer(1 / zero)\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("run")
+        .arg(&path)
+        .arg("--dry-run")
+        .output()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .ends_with(" instructions\n"));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn fix_rewrites_the_file() {
+    let path = write_source("fix.lang", "let a = 1 io.print_integr(a)\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("fix")
+        .arg(&path)
+        .output()
+        .unwrap();
+    let fixed = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fixed, "let a = 1\nio.print_integer(a)\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(":1:11: Add a newline before a name\n"));
+    assert!(stdout.contains(":2:4: Did you mean print_integer?\n"));
+}
+
+#[test]
+fn lint_lists_metrics() {
+    let path = write_source("lint.lang", "let a = 1 + 2 * 3\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("lint")
+        .arg(&path)
+        .arg("--metrics")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(":1:5: 6 nodes, nesting 3, complexity 1\n"));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains(":1:5: Warning: a is never used\n"));
+}
+
+#[test]
+fn lint_directory_lints_every_file() {
+    let directory = std::env::temp_dir().join(format!("lang_cli_{}_lint_dir", std::process::id()));
+    std::fs::create_dir_all(directory.join("nested")).unwrap();
+    std::fs::write(directory.join("good.lang"), "io.print_integer(1)\n").unwrap();
+    std::fs::write(directory.join("nested/bad.lang"), "io.print_integer(a)\n").unwrap();
+    std::fs::write(directory.join("notes.txt"), "not a program").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg("lint")
+        .arg(&directory)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("bad.lang:1:18-1:19: Compile Error: Unable to find a"));
+    assert!(!stderr.contains("good.lang"));
+    assert!(stderr.ends_with("1 of 2 files failed\n"));
+}
@@ -0,0 +1,66 @@
+use std::{path::Path, process::Command};
+
+// runs every examples/*.lang file and compares what it prints against examples/<name>.out,
+// programs that are expected to fail also have their stderr in examples/<name>.err
+#[test]
+fn examples() {
+    let examples_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut paths: Vec<_> = std::fs::read_dir(&examples_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "lang")
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no examples were found");
+
+    let mut failures = vec![];
+    for path in &paths {
+        let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+            .arg("run")
+            .arg(path)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        let expected_stdout = std::fs::read_to_string(path.with_extension("out"))
+            .unwrap_or_else(|_| panic!("{} has no .out file", path.display()));
+        let expected_stderr = std::fs::read_to_string(path.with_extension("err")).ok();
+
+        if stdout != expected_stdout {
+            failures.push(format!(
+                "{}: expected stdout {:?} but got {:?}",
+                path.display(),
+                expected_stdout,
+                stdout,
+            ));
+        }
+        match &expected_stderr {
+            Some(expected_stderr) => {
+                if stderr != *expected_stderr || output.status.success() {
+                    failures.push(format!(
+                        "{}: expected to fail with {:?} but got {:?} and {}",
+                        path.display(),
+                        expected_stderr,
+                        stderr,
+                        output.status,
+                    ));
+                }
+            }
+            None => {
+                if !output.status.success() {
+                    failures.push(format!(
+                        "{}: failed with {} and {:?}",
+                        path.display(),
+                        output.status,
+                        stderr,
+                    ));
+                }
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
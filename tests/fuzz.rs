@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use lang::fuzz::fuzz_compile_and_run;
+
+// every file in tests/fuzz_corpus is an input that once crashed or came close, they only have
+// to get through the pipeline without panicking
+#[test]
+fn fuzz_corpus() {
+    let corpus_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fuzz_corpus");
+    let mut paths: Vec<_> = std::fs::read_dir(&corpus_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "the corpus is empty");
+
+    for path in &paths {
+        let bytes = std::fs::read(path).unwrap();
+        println!("{}", path.display());
+        fuzz_compile_and_run(&bytes, 10_000);
+    }
+}
+
+#[test]
+fn fuel_runs_out() {
+    let source = "io.print_integer(1)\n".repeat(100);
+    let outcome = fuzz_compile_and_run(source.as_bytes(), 10);
+    assert!(
+        matches!(&outcome, lang::fuzz::FuzzOutcome::RuntimeError(error) if error.message == "Execution ran out of fuel"),
+        "{:?}",
+        outcome,
+    );
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, path::Path};
+
+use lang::{bytecode_text::parse_bytecode_module, execute::Vm, value::Value};
+
+struct Expectation {
+    output: String,
+    result: Option<String>,
+    error: Option<String>,
+}
+
+// the expected behaviour is written in comments at the top of each file:
+// `# output: <line>` for every printed line, `# result: <value>` for the returned value
+// and `# error: <message>` if the program should fail
+fn read_expectation(text: &str) -> Expectation {
+    let mut expectation = Expectation {
+        output: String::new(),
+        result: None,
+        error: None,
+    };
+    for line in text.lines() {
+        let comment = match line.trim().strip_prefix('#') {
+            Some(comment) => comment.trim(),
+            None => continue,
+        };
+        if let Some(output) = comment.strip_prefix("output:") {
+            expectation.output += output.trim();
+            expectation.output.push('\n');
+        } else if let Some(result) = comment.strip_prefix("result:") {
+            expectation.result = Some(result.trim().to_string());
+        } else if let Some(error) = comment.strip_prefix("error:") {
+            expectation.error = Some(error.trim().to_string());
+        }
+    }
+    expectation
+}
+
+fn run_test(path: &Path) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).unwrap();
+    let expectation = read_expectation(&text);
+    let bytecode = parse_bytecode_module(&path.display().to_string(), &text)
+        .map_err(|error| format!("line {}: {}", error.line, error.message))?;
+
+    let mut vm = Vm::new();
+    vm.captured_output = Some(String::new());
+    let result = vm.execute(&bytecode, Vec::new(), &mut HashMap::new());
+    let output = vm.captured_output.unwrap();
+    if output != expectation.output {
+        return Err(format!(
+            "expected output {:?} but got {:?}",
+            expectation.output, output,
+        ));
+    }
+
+    match (result, &expectation.error) {
+        (Ok(result), None) => {
+            let result = result.map(|value| Value::from(&value).to_string());
+            if result != expectation.result {
+                return Err(format!(
+                    "expected result {:?} but got {:?}",
+                    expectation.result, result,
+                ));
+            }
+        }
+        (Ok(_), Some(error)) => return Err(format!("expected the error {:?}", error)),
+        (Err(error), None) => return Err(format!("unexpected error {:?}", error.message)),
+        (Err(error), Some(expected)) => {
+            if error.message != *expected {
+                return Err(format!(
+                    "expected the error {:?} but got {:?}",
+                    expected, error.message,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn vm_conformance() {
+    let tests_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("vm");
+    let mut paths: Vec<_> = std::fs::read_dir(&tests_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bc"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no vm tests were found");
+
+    let failures: Vec<_> = paths
+        .iter()
+        .filter_map(|path| {
+            run_test(path)
+                .err()
+                .map(|message| format!("{}: {}", path.display(), message))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}